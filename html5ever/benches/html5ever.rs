@@ -5,8 +5,9 @@ extern crate html5ever;
 use std::fs;
 use std::path::PathBuf;
 
-use criterion::{black_box, Criterion};
+use criterion::{black_box, Criterion, Throughput};
 
+use html5ever::small_char_set;
 use html5ever::tendril::*;
 use html5ever::tokenizer::{BufferQueue, Token, TokenSink, TokenSinkResult, Tokenizer};
 
@@ -68,6 +69,31 @@ fn run_bench(c: &mut Criterion, name: &str) {
     });
 }
 
+fn run_small_char_set_bench(c: &mut Criterion, name: &str) {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("data/bench/");
+    path.push(name);
+    let input = fs::read_to_string(&path).expect("can't read file");
+
+    // Split the input at the characters the tokenizer stops at in the data
+    // state, as `pop_except_from` does.
+    let set = small_char_set!('\r' '\0' '&' '<' '\n');
+    let mut group = c.benchmark_group("small char set");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function(name, move |b| {
+        b.iter(|| {
+            let mut rest = black_box(&*input);
+            while !rest.is_empty() {
+                let n = set.nonmember_prefix_len(rest) as usize;
+                // Members are ASCII, so skipping one byte stays on a
+                // character boundary.
+                rest = &rest[rest.len().min(n + 1)..];
+            }
+        })
+    });
+    group.finish();
+}
+
 fn html5ever_benchmark(c: &mut Criterion) {
     run_bench(c, "lipsum.html");
     run_bench(c, "lipsum-zh.html");
@@ -75,6 +101,10 @@ fn html5ever_benchmark(c: &mut Criterion) {
     run_bench(c, "small-fragment.html");
     run_bench(c, "tiny-fragment.html");
     run_bench(c, "strong.html");
+    run_small_char_set_bench(c, "lipsum.html");
+    run_small_char_set_bench(c, "lipsum-zh.html");
+    run_small_char_set_bench(c, "medium-fragment.html");
+    run_small_char_set_bench(c, "strong.html");
 }
 
 criterion_group!(benches, html5ever_benchmark);
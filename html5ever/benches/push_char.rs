@@ -0,0 +1,101 @@
+#[macro_use]
+extern crate criterion;
+extern crate html5ever;
+
+use std::borrow::Cow;
+use std::fs;
+use std::path::PathBuf;
+
+use criterion::Criterion;
+
+use html5ever::tendril::*;
+use html5ever::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use html5ever::{parse_document, Attribute, ExpandedName, LocalName, Namespace, QualName};
+
+/// A sink that only remembers element names.
+struct Sink {
+    names: Vec<QualName>,
+}
+
+impl TreeSink for Sink {
+    type Handle = usize;
+    type Output = ();
+
+    fn finish(self) {}
+    fn parse_error(&mut self, _msg: Cow<'static, str>) {}
+    fn get_document(&mut self) -> usize {
+        0
+    }
+    fn elem_name(&self, target: &usize) -> ExpandedName<'_> {
+        // Handle 0 is the document; the even handles are template contents.
+        self.names[(target - 1) / 2].expanded()
+    }
+    fn create_element(&mut self, name: QualName, _: Vec<Attribute>, _: ElementFlags) -> usize {
+        self.names.push(name);
+        self.names.len() * 2 - 1
+    }
+    fn create_comment(&mut self, _text: StrTendril) -> usize {
+        let name = QualName::new(None, Namespace::from(""), LocalName::from(""));
+        self.create_element(name, vec![], Default::default())
+    }
+    fn create_pi(&mut self, _target: StrTendril, _data: StrTendril) -> usize {
+        self.create_comment(StrTendril::new())
+    }
+    fn append(&mut self, _parent: &usize, _child: NodeOrText<usize>) {}
+    fn append_based_on_parent_node(&mut self, _: &usize, _: &usize, _: NodeOrText<usize>) {}
+    fn append_doctype_to_document(&mut self, _: StrTendril, _: StrTendril, _: StrTendril) {}
+    fn get_template_contents(&mut self, target: &usize) -> usize {
+        target + 1
+    }
+    fn same_node(&self, x: &usize, y: &usize) -> bool {
+        x == y
+    }
+    fn set_quirks_mode(&mut self, _mode: QuirksMode) {}
+    fn append_before_sibling(&mut self, _sibling: &usize, _new_node: NodeOrText<usize>) {}
+    fn add_attrs_if_missing(&mut self, _target: &usize, _attrs: Vec<Attribute>) {}
+    fn remove_from_parent(&mut self, _target: &usize) {}
+    fn reparent_children(&mut self, _node: &usize, _new_parent: &usize) {}
+}
+
+fn new_parser() -> html5ever::Parser<Sink> {
+    parse_document(Sink { names: vec![] }, Default::default())
+}
+
+fn run_bench(c: &mut Criterion, name: &str) {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("data/bench/");
+    path.push(name);
+    let input = fs::read_to_string(&path).expect("can't read file");
+
+    let test_name = format!("html parsing {} per char with process", name);
+    let chars = input.clone();
+    c.bench_function(&test_name, move |b| {
+        b.iter(|| {
+            let mut parser = new_parser();
+            for c in chars.chars() {
+                parser.process(StrTendril::from_char(c));
+            }
+            parser.finish()
+        })
+    });
+
+    let test_name = format!("html parsing {} per char with push_char", name);
+    c.bench_function(&test_name, move |b| {
+        b.iter(|| {
+            let mut parser = new_parser();
+            for c in input.chars() {
+                parser.push_char(c);
+            }
+            parser.finish()
+        })
+    });
+}
+
+fn push_char_benchmark(c: &mut Criterion) {
+    run_bench(c, "lipsum.html");
+    run_bench(c, "medium-fragment.html");
+    run_bench(c, "strong.html");
+}
+
+criterion_group!(benches, push_char_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,45 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Print each change of the tokenizer's state while it tokenizes
+//! `<a href="x">`, or the input given as the first argument.
+
+extern crate html5ever;
+
+use std::default::Default;
+use std::env;
+
+use html5ever::tendril::*;
+use html5ever::tokenizer::BufferQueue;
+use html5ever::tokenizer::{Token, TokenSink, TokenSinkResult, Tokenizer};
+
+struct NoopSink;
+
+impl TokenSink for NoopSink {
+    type Handle = ();
+
+    fn process_token(&mut self, _token: Token, _line_number: u64) -> TokenSinkResult<()> {
+        TokenSinkResult::Continue
+    }
+}
+
+fn main() {
+    let source = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "<a href=\"x\">".to_string());
+
+    let mut tok = Tokenizer::new(NoopSink, Default::default());
+    tok.set_on_state_change(Some(Box::new(|old, new, c| {
+        println!("{:?} -> {:?} on {:?}", old, new, c);
+    })));
+    let mut input = BufferQueue::new();
+    input.push_back(StrTendril::from(source));
+    let _ = tok.feed(&mut input);
+    tok.end();
+}
@@ -0,0 +1,384 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Collecting the elements of a document that match a predicate, without
+//! keeping the rest of the tree.
+//!
+//! The predicate sees each element as the tree builder creates it, with
+//! its namespace, so `<svg><a>` gives an SVG `a`.  Elements that the tree
+//! builder implies, like the `<body>` in `<p>x`, are passed to it too.  A
+//! formatting element that the tree builder copies, like the `<b>` in
+//! `<p><b>x</p>y`, is collected once.
+//!
+//! ```
+//! use html5ever::collect::{parse_collecting, CollectOpts};
+//! use html5ever::local_name;
+//!
+//! let html = b"<meta charset=utf-8><meta name=description content='A page'><p>Hi";
+//! let metas = parse_collecting(html, CollectOpts::default(), |name, _| {
+//!     name.local == local_name!("meta")
+//! });
+//! assert_eq!(metas.len(), 2);
+//! assert_eq!(&*metas[1].attrs[1].value, "A page");
+//! ```
+
+use crate::driver::{parse_document, ParseOpts};
+use crate::interface::{Attribute, ExpandedName, QualName};
+use crate::tendril::StrTendril;
+use crate::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use crate::util::bytes::parse_bytes;
+use crate::util::names::Names;
+
+use std::borrow::Cow;
+
+/// An element found by `parse_collecting`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CollectedElement {
+    /// The element's name.
+    pub name: QualName,
+
+    /// The element's attributes, in source order.
+    pub attrs: Vec<Attribute>,
+
+    /// With `CollectOpts::text`, all of the text inside the element, in
+    /// document order, as the tree builder inserted it: nested elements
+    /// are left out but their text is kept, so `<p>a <b>b</b>` gives
+    /// `"a b"`, and text that the tree builder moves out of the element,
+    /// like text in a `<table>`, is not included.  The contents of a
+    /// `<template>` are not part of it.  None without `CollectOpts::text`.
+    pub text: Option<StrTendril>,
+}
+
+/// Options for `parse_collecting`, with an impl for Default.
+#[derive(Clone, Default)]
+pub struct CollectOpts {
+    /// Options for the parser.
+    pub parse: ParseOpts,
+
+    /// Collect the text inside each element that is collected.
+    /// Default: false
+    pub text: bool,
+}
+
+/// Parse `input` and return the elements for which `pred` returns true,
+/// given their name and attributes, in the order they were created.
+///
+/// The encoding is taken from a byte order mark, for UTF-8 or UTF-16.
+/// Without one the input is decoded as UTF-8, since html5ever has no
+/// decoders for legacy encodings.  Invalid sequences are replaced.
+pub fn parse_collecting<F>(input: &[u8], opts: CollectOpts, pred: F) -> Vec<CollectedElement>
+where
+    F: Fn(&QualName, &[Attribute]) -> bool,
+{
+    let sink = Collector {
+        pred,
+        text: opts.text,
+        names: Names::new(),
+        collected: vec![],
+    };
+    parse_bytes(parse_document(sink, opts.parse), input)
+}
+
+/// A sink that keeps the elements that match, and just enough about the
+/// open ones to attribute text to them.
+struct Collector<F> {
+    pred: F,
+    text: bool,
+    /// The open elements, with their index in `collected` if they matched.
+    names: Names<Option<usize>>,
+    /// The elements that matched, with None for the copies of an element.
+    collected: Vec<Option<CollectedElement>>,
+}
+
+impl<F> Collector<F> {
+    /// Add `text` to every collected element that contains `parent`.
+    fn append_text(&mut self, parent: usize, text: &str) {
+        if !self.text {
+            return;
+        }
+        let mut seen = vec![];
+        for (_, node) in self.names.ancestors(parent) {
+            if let Some(index) = node.data {
+                // An element and a copy of it can both be ancestors.
+                if !seen.contains(&index) {
+                    seen.push(index);
+                    let element = self.collected[index]
+                        .as_mut()
+                        .expect("no collected element");
+                    if let Some(ref mut collected) = element.text {
+                        collected.push_slice(text);
+                    }
+                }
+            }
+        }
+    }
+
+    fn append_child(&mut self, parent: usize, child: NodeOrText<usize>) {
+        match child {
+            NodeOrText::AppendNode(node) => self.names.append(parent, node),
+            NodeOrText::AppendText(text) => self.append_text(parent, &text),
+        }
+    }
+}
+
+impl<F> TreeSink for Collector<F>
+where
+    F: Fn(&QualName, &[Attribute]) -> bool,
+{
+    type Handle = usize;
+    type Output = Vec<CollectedElement>;
+
+    fn finish(self) -> Vec<CollectedElement> {
+        self.collected.into_iter().flatten().collect()
+    }
+
+    fn parse_error(&mut self, _msg: Cow<'static, str>) {}
+
+    fn get_document(&mut self) -> usize {
+        0
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a usize) -> ExpandedName<'a> {
+        self.names.elem_name(*target)
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> usize {
+        let collected = if (self.pred)(&name, &attrs) {
+            self.collected.push(Some(CollectedElement {
+                name: name.clone(),
+                attrs,
+                text: if self.text {
+                    Some(StrTendril::new())
+                } else {
+                    None
+                },
+            }));
+            Some(self.collected.len() - 1)
+        } else {
+            None
+        };
+        self.names.create_element(name, &flags, collected)
+    }
+
+    fn element_cloned(&mut self, original: &usize, clone: &usize) {
+        let original = self.names.get(*original).and_then(|node| node.data);
+        if let Some(clone) = self.names.get_mut(*clone) {
+            if let Some(index) = clone.data {
+                self.collected[index] = None;
+            }
+            clone.data = original;
+        }
+    }
+
+    fn element_closed(&mut self, node: &usize) {
+        self.names.close(*node);
+    }
+
+    fn create_comment(&mut self, _text: StrTendril) -> usize {
+        self.names.new_id()
+    }
+
+    fn create_pi(&mut self, _target: StrTendril, _data: StrTendril) -> usize {
+        self.names.new_id()
+    }
+
+    fn append(&mut self, parent: &usize, child: NodeOrText<usize>) {
+        self.append_child(*parent, child);
+    }
+
+    fn append_before_sibling(&mut self, sibling: &usize, new_node: NodeOrText<usize>) {
+        if let Some(parent) = self.names.parent(*sibling) {
+            self.append_child(parent, new_node);
+        }
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &usize,
+        prev_element: &usize,
+        child: NodeOrText<usize>,
+    ) {
+        match self.names.parent(*element) {
+            Some(parent) => self.append_child(parent, child),
+            None => self.append_child(*prev_element, child),
+        }
+    }
+
+    fn append_doctype_to_document(&mut self, _: StrTendril, _: StrTendril, _: StrTendril) {}
+
+    fn get_template_contents(&mut self, target: &usize) -> usize {
+        target + 1
+    }
+
+    fn same_node(&self, x: &usize, y: &usize) -> bool {
+        x == y
+    }
+
+    fn set_quirks_mode(&mut self, _mode: QuirksMode) {}
+
+    fn add_attrs_if_missing(&mut self, _target: &usize, _attrs: Vec<Attribute>) {}
+
+    fn remove_from_parent(&mut self, target: &usize) {
+        self.names.remove_from_parent(*target);
+    }
+
+    fn reparent_children(&mut self, node: &usize, new_parent: &usize) {
+        self.names.reparent_children(*node, *new_parent);
+    }
+
+    fn is_mathml_annotation_xml_integration_point(&self, handle: &usize) -> bool {
+        self.names.is_integration_point(*handle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_collecting, CollectOpts, Collector};
+    use crate::driver::parse_document;
+    use crate::interface::{Attribute, QualName};
+    use crate::tendril::TendrilSink;
+    use crate::util::names::Names;
+    use markup5ever::{local_name, namespace_url, ns};
+
+    fn with_text() -> CollectOpts {
+        CollectOpts {
+            text: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn meta() {
+        let html = b"<!DOCTYPE html><meta charset=utf-8><title>x</title>\
+                     <meta name=viewport content='width=device-width'>\
+                     <body><meta property=og:title content=y>";
+        let metas: Vec<_> = parse_collecting(html, CollectOpts::default(), |name, _| {
+            name.ns == ns!(html) && name.local == local_name!("meta")
+        })
+        .into_iter()
+        .map(|element| {
+            assert_eq!(element.text, None);
+            element
+                .attrs
+                .iter()
+                .map(|attr| format!("{}={}", attr.name.local, attr.value))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+        assert_eq!(
+            metas,
+            vec![
+                "charset=utf-8",
+                "name=viewport content=width=device-width",
+                "property=og:title content=y",
+            ]
+        );
+    }
+
+    #[test]
+    fn data_attributes() {
+        let html = b"<div data-id=1><p>one <b>two</b></p></div>\
+                     <span class=x>three</span><svg data-icon=star><text>four</text></svg>\
+                     <ul><li data-n=1>a<li data-n=2>b</ul>";
+        let found: Vec<_> = parse_collecting(html, with_text(), |_, attrs| {
+            attrs
+                .iter()
+                .any(|attr| attr.name.local.starts_with("data-"))
+        })
+        .into_iter()
+        .map(|element| {
+            (
+                element.name.ns.to_string(),
+                element.name.local.to_string(),
+                element.text.unwrap().to_string(),
+            )
+        })
+        .collect();
+        let svg = ns!(svg).to_string();
+        let html = ns!(html).to_string();
+        assert_eq!(
+            found,
+            vec![
+                (html.clone(), "div".into(), "one two".into()),
+                (svg, "svg".into(), "four".into()),
+                (html.clone(), "li".into(), "a".into()),
+                (html, "li".into(), "b".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn copies() {
+        // The <b> is reopened in the second <p>, and split by the <div>,
+        // but is one element in the source.
+        let found: Vec<_> = parse_collecting(
+            b"<p><b data-x>one</p><p>two<div>three</b>four</div>",
+            with_text(),
+            |name, _| name.local == local_name!("b"),
+        )
+        .into_iter()
+        .map(|element| element.text.unwrap().to_string())
+        .collect();
+        assert_eq!(found, vec!["onetwothree"]);
+    }
+
+    #[test]
+    fn moved_text() {
+        // Text in a table is moved in front of it, out of the <table>.
+        let found: Vec<_> = parse_collecting(
+            b"<div><table>a<tr><td>b</td></tr></table></div><template>c</template>",
+            with_text(),
+            |name, _| {
+                name.local == local_name!("table") ||
+                    name.local == local_name!("div") ||
+                    name.local == local_name!("template")
+            },
+        )
+        .into_iter()
+        .map(|element| {
+            (
+                element.name.local.to_string(),
+                element.text.unwrap().to_string(),
+            )
+        })
+        .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("div".into(), "ab".into()),
+                ("table".into(), "b".into()),
+                ("template".into(), "".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn frees_closed_elements() {
+        let sink = Collector {
+            pred: |name: &QualName, _: &[Attribute]| name.local == local_name!("p"),
+            text: true,
+            names: Names::new(),
+            collected: vec![],
+        };
+        let mut parser = parse_document(sink, Default::default());
+        for _ in 0..1000 {
+            parser.process("<p>text<!-- comment --><template>x</template>".into());
+        }
+        parser.process("<p>".into());
+        // The document, <html>, <head>, <body> and the last <p>.
+        assert_eq!(parser.tokenizer.sink.sink.names.len(), 5);
+        assert_eq!(parser.finish().len(), 1001);
+    }
+}
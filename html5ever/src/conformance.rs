@@ -0,0 +1,518 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Check a document for parse errors, without building a tree or while
+//! building one.
+
+use crate::driver::{parse_document, ParseOpts};
+use crate::interface::{AmbiguousConstruct, NextParserState, ParseStats};
+use crate::tendril::{StrTendril, TendrilSink};
+use crate::tokenizer::TokenizerOpts;
+use crate::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeBuilderOpts, TreeSink};
+use crate::{Attribute, ExpandedName, QualName};
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// A parse error found by `check_document` or `StrictSink`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConformanceError {
+    /// The line on which the error was found, starting at 1.
+    pub line: u64,
+
+    /// Description of the error.
+    pub message: Cow<'static, str>,
+}
+
+/// The result of `check_document`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConformanceReport {
+    /// Every parse error, in document order.
+    pub errors: Vec<ConformanceError>,
+
+    /// The quirks mode the document was parsed in.
+    pub quirks_mode: QuirksMode,
+}
+
+impl ConformanceReport {
+    /// Did the document parse without errors?
+    pub fn is_conforming(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Parse `input` and report every parse error described in the spec.
+///
+/// The input is decoded as UTF-8; invalid sequences are replaced and reported
+/// as errors.  There is no encoding sniffing.  The tree sink is only told
+/// the line, so errors carry no column or byte offset.
+///
+/// ```
+/// use html5ever::conformance::check_document;
+///
+/// let report = check_document(b"<!DOCTYPE html><title>x</title><p>ok");
+/// assert!(report.is_conforming());
+/// ```
+pub fn check_document(input: &[u8]) -> ConformanceReport {
+    let opts = ParseOpts {
+        tokenizer: TokenizerOpts {
+            exact_errors: true,
+            named_comment_errors: true,
+            ..Default::default()
+        },
+        tree_builder: TreeBuilderOpts {
+            exact_errors: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let sink = Checker {
+        next_id: 1,
+        names: HashMap::new(),
+        line: 1,
+        report: ConformanceReport {
+            errors: vec![],
+            quirks_mode: QuirksMode::NoQuirks,
+        },
+    };
+    parse_document(sink, opts).from_utf8().one(input)
+}
+
+/// A sink that keeps only element names, for the tree builder's queries.
+struct Checker {
+    next_id: usize,
+    names: HashMap<usize, QualName>,
+    line: u64,
+    report: ConformanceReport,
+}
+
+impl Checker {
+    fn get_id(&mut self) -> usize {
+        // Odd ids are nodes; the following even id is a template's contents.
+        let id = self.next_id;
+        self.next_id += 2;
+        id
+    }
+}
+
+impl TreeSink for Checker {
+    type Handle = usize;
+    type Output = ConformanceReport;
+
+    fn finish(self) -> ConformanceReport {
+        self.report
+    }
+
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.report.errors.push(ConformanceError {
+            line: self.line,
+            message: msg,
+        });
+    }
+
+    fn set_current_line(&mut self, line_number: u64) {
+        self.line = line_number;
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.report.quirks_mode = mode;
+    }
+
+    fn get_document(&mut self) -> usize {
+        0
+    }
+
+    fn get_template_contents(&mut self, target: &usize) -> usize {
+        target + 1
+    }
+
+    fn same_node(&self, x: &usize, y: &usize) -> bool {
+        x == y
+    }
+
+    fn elem_name(&self, target: &usize) -> ExpandedName<'_> {
+        self.names.get(target).expect("not an element").expanded()
+    }
+
+    fn create_element(&mut self, name: QualName, _: Vec<Attribute>, _: ElementFlags) -> usize {
+        let id = self.get_id();
+        self.names.insert(id, name);
+        id
+    }
+
+    fn create_comment(&mut self, _text: StrTendril) -> usize {
+        self.get_id()
+    }
+
+    fn create_pi(&mut self, _target: StrTendril, _data: StrTendril) -> usize {
+        self.get_id()
+    }
+
+    fn append(&mut self, _parent: &usize, _child: NodeOrText<usize>) {}
+
+    fn append_before_sibling(&mut self, _sibling: &usize, _new_node: NodeOrText<usize>) {}
+
+    fn append_based_on_parent_node(
+        &mut self,
+        _element: &usize,
+        _prev_element: &usize,
+        _new_node: NodeOrText<usize>,
+    ) {
+    }
+
+    fn append_doctype_to_document(&mut self, _: StrTendril, _: StrTendril, _: StrTendril) {}
+
+    fn add_attrs_if_missing(&mut self, _target: &usize, _attrs: Vec<Attribute>) {}
+
+    fn remove_from_parent(&mut self, _target: &usize) {}
+
+    fn reparent_children(&mut self, _node: &usize, _new_parent: &usize) {}
+}
+
+/// A `TreeSink` that stops parsing at the first parse error, for using the
+/// parser as a gate on generated HTML.
+///
+/// Everything is passed on to another sink until a parse error is
+/// reported.  The error is not passed on, and the tokenizer stops after
+/// the token that caused it, so the rest of the input is ignored.  The
+/// output of the parse is the output of the other sink, or the first
+/// error.  Which errors are reported depends on the parser's options; set
+/// `exact_errors` on the tokenizer and the tree builder for all of the
+/// spec's errors, as `check_document` does.  Invalid UTF-8 from
+/// `Parser::from_utf8` is an error too.
+///
+/// ```
+/// use html5ever::conformance::StrictSink;
+/// use html5ever::driver::{parse_document, ParseOpts};
+/// use html5ever::immutable::ImmutableDom;
+/// use html5ever::tendril::TendrilSink;
+///
+/// let parse = |input| {
+///     let sink = StrictSink::new(ImmutableDom::default());
+///     parse_document(sink, ParseOpts::default()).one(input)
+/// };
+/// assert!(parse("<!DOCTYPE html><title>x</title><p>ok").is_ok());
+///
+/// let error = parse("<!DOCTYPE html><title>x</title>\n<p>not</div>ok").unwrap_err();
+/// assert_eq!(error.line, 2);
+/// ```
+pub struct StrictSink<Sink> {
+    sink: Sink,
+    line: u64,
+    error: Option<ConformanceError>,
+}
+
+impl<Sink: TreeSink> StrictSink<Sink> {
+    /// Pass everything on to `sink` until the first parse error.
+    pub fn new(sink: Sink) -> Self {
+        StrictSink {
+            sink,
+            line: 1,
+            error: None,
+        }
+    }
+}
+
+impl<Sink: TreeSink> TreeSink for StrictSink<Sink> {
+    type Handle = Sink::Handle;
+    type Output = Result<Sink::Output, ConformanceError>;
+
+    fn finish(self) -> Self::Output {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.sink.finish()),
+        }
+    }
+
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        if self.error.is_none() {
+            self.error = Some(ConformanceError {
+                line: self.line,
+                message: msg,
+            });
+        }
+    }
+
+    fn ambiguous_construct(&mut self, construct: AmbiguousConstruct, line_number: u64) {
+        self.sink.ambiguous_construct(construct, line_number)
+    }
+
+    fn duplicate_attribute(&mut self, first: &str, duplicate: &str, line_number: u64) {
+        self.sink.duplicate_attribute(first, duplicate, line_number)
+    }
+
+    fn set_current_line(&mut self, line_number: u64) {
+        self.line = line_number;
+        self.sink.set_current_line(line_number);
+    }
+
+    fn should_continue(&self) -> bool {
+        self.error.is_none() && self.sink.should_continue()
+    }
+
+    fn misnested_tags(&mut self, expected: &QualName, found: &QualName, expected_pos: Option<u64>) {
+        self.sink.misnested_tags(expected, found, expected_pos)
+    }
+
+    fn get_document(&mut self) -> Self::Handle {
+        self.sink.get_document()
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a Self::Handle) -> ExpandedName<'a> {
+        self.sink.elem_name(target)
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Self::Handle {
+        self.sink.create_element(name, attrs, flags)
+    }
+
+    fn create_element_raw_attrs(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Self::Handle {
+        self.sink.create_element_raw_attrs(name, attrs, flags)
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> Self::Handle {
+        self.sink.create_comment(text)
+    }
+
+    fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Self::Handle {
+        self.sink.create_pi(target, data)
+    }
+
+    fn append(&mut self, parent: &Self::Handle, child: NodeOrText<Self::Handle>) {
+        self.sink.append(parent, child)
+    }
+
+    fn append_text_with_flags(
+        &mut self,
+        parent: &Self::Handle,
+        text: StrTendril,
+        preserve_whitespace: bool,
+    ) {
+        self.sink
+            .append_text_with_flags(parent, text, preserve_whitespace)
+    }
+
+    fn wants_borrowed_text(&self) -> bool {
+        self.sink.wants_borrowed_text()
+    }
+
+    fn push_text(&mut self, parent: &Self::Handle, text: &str) {
+        self.sink.push_text(parent, text)
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &Self::Handle,
+        prev_element: &Self::Handle,
+        child: NodeOrText<Self::Handle>,
+    ) {
+        self.sink
+            .append_based_on_parent_node(element, prev_element, child)
+    }
+
+    fn foster_parent_text_with_flags(
+        &mut self,
+        element: &Self::Handle,
+        prev_element: &Self::Handle,
+        text: StrTendril,
+        preserve_whitespace: bool,
+    ) {
+        self.sink
+            .foster_parent_text_with_flags(element, prev_element, text, preserve_whitespace)
+    }
+
+    fn append_doctype_to_document(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.sink
+            .append_doctype_to_document(name, public_id, system_id)
+    }
+
+    fn append_doctype_full(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+        force_quirks: bool,
+    ) {
+        self.sink
+            .append_doctype_full(name, public_id, system_id, force_quirks)
+    }
+
+    fn append_doctype_raw(
+        &mut self,
+        raw: StrTendril,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.sink
+            .append_doctype_raw(raw, name, public_id, system_id)
+    }
+
+    fn obsolete_element(&mut self, name: &QualName) {
+        self.sink.obsolete_element(name)
+    }
+
+    fn select_content_dropped(&mut self, name: &QualName) {
+        self.sink.select_content_dropped(name)
+    }
+
+    fn stray_template_close(&mut self) {
+        self.sink.stray_template_close()
+    }
+
+    fn tag_rewritten(&mut self, from: &QualName, to: &QualName) {
+        self.sink.tag_rewritten(from, to)
+    }
+
+    fn unacknowledged_self_closing(&mut self, name: &QualName) {
+        self.sink.unacknowledged_self_closing(name)
+    }
+
+    fn leading_text_relocated(&mut self, text: StrTendril) {
+        self.sink.leading_text_relocated(text)
+    }
+
+    fn leading_newline_stripped(&mut self, element: &Self::Handle) {
+        self.sink.leading_newline_stripped(element)
+    }
+
+    fn misplaced_doctype(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.sink.misplaced_doctype(name, public_id, system_id)
+    }
+
+    fn mark_script_already_started(&mut self, node: &Self::Handle) {
+        self.sink.mark_script_already_started(node)
+    }
+
+    fn element_finalized(&mut self, element: &Self::Handle) {
+        self.sink.element_finalized(element)
+    }
+
+    fn element_synthesized(&mut self, element: &Self::Handle, name: &QualName) {
+        self.sink.element_synthesized(element, name)
+    }
+
+    fn element_cloned(&mut self, original: &Self::Handle, clone: &Self::Handle) {
+        self.sink.element_cloned(original, clone)
+    }
+
+    fn head_opened(&mut self, head: &Self::Handle) {
+        self.sink.head_opened(head)
+    }
+
+    fn head_closed(&mut self, head: &Self::Handle) {
+        self.sink.head_closed(head)
+    }
+
+    fn parse_stats(&mut self, stats: ParseStats) {
+        self.sink.parse_stats(stats)
+    }
+
+    fn eof_state(&mut self, clean: bool, open_elements: usize) {
+        self.sink.eof_state(clean, open_elements)
+    }
+
+    fn pop(&mut self, node: &Self::Handle) {
+        self.sink.pop(node)
+    }
+
+    fn element_closed(&mut self, node: &Self::Handle) {
+        self.sink.element_closed(node)
+    }
+
+    fn get_template_contents(&mut self, target: &Self::Handle) -> Self::Handle {
+        self.sink.get_template_contents(target)
+    }
+
+    fn same_node(&self, x: &Self::Handle, y: &Self::Handle) -> bool {
+        self.sink.same_node(x, y)
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.sink.set_quirks_mode(mode)
+    }
+
+    fn quirks_behavior_applied(&mut self, description: &'static str) {
+        self.sink.quirks_behavior_applied(description)
+    }
+
+    fn frameset_ok_changed(&mut self, ok: bool, reason: &'static str) {
+        self.sink.frameset_ok_changed(ok, reason)
+    }
+
+    fn set_document_metadata(&mut self, lang: Option<StrTendril>, dir: Option<StrTendril>) {
+        self.sink.set_document_metadata(lang, dir)
+    }
+
+    fn append_before_sibling(
+        &mut self,
+        sibling: &Self::Handle,
+        new_node: NodeOrText<Self::Handle>,
+    ) {
+        self.sink.append_before_sibling(sibling, new_node)
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &Self::Handle, attrs: Vec<Attribute>) {
+        self.sink.add_attrs_if_missing(target, attrs)
+    }
+
+    fn merge_attributes(
+        &mut self,
+        target: &Self::Handle,
+        source_tag_name: &QualName,
+        merged: &[Attribute],
+    ) {
+        self.sink.merge_attributes(target, source_tag_name, merged)
+    }
+
+    fn associate_with_form(
+        &mut self,
+        target: &Self::Handle,
+        form: &Self::Handle,
+        nodes: (&Self::Handle, Option<&Self::Handle>),
+    ) {
+        self.sink.associate_with_form(target, form, nodes)
+    }
+
+    fn remove_from_parent(&mut self, target: &Self::Handle) {
+        self.sink.remove_from_parent(target)
+    }
+
+    fn reparent_children(&mut self, node: &Self::Handle, new_parent: &Self::Handle) {
+        self.sink.reparent_children(node, new_parent)
+    }
+
+    fn is_mathml_annotation_xml_integration_point(&self, handle: &Self::Handle) -> bool {
+        self.sink.is_mathml_annotation_xml_integration_point(handle)
+    }
+
+    fn complete_script(&mut self, node: &Self::Handle) -> NextParserState {
+        self.sink.complete_script(node)
+    }
+}
@@ -10,11 +10,25 @@
 //! High-level interface to the parser.
 
 use crate::buffer_queue::BufferQueue;
-use crate::tokenizer::{Tokenizer, TokenizerOpts, TokenizerResult};
-use crate::tree_builder::{create_element, TreeBuilder, TreeBuilderOpts, TreeSink};
+use crate::tokenizer::{
+    InvalidCharRefPolicy, NullHandling, Position, TokenTransform, Tokenizer, TokenizerOpts,
+};
+use crate::tokenizer::{PauseHandle, TokenSink, TokenizerResult, Transformed};
+#[cfg(feature = "unicode-normalization")]
+use crate::tree_builder::NormalizationForm;
+use crate::tree_builder::TreeSink;
+use crate::tree_builder::{
+    create_element, CommentFilter, InsertionPointCallback, ParseStats, QuirksMode, StepCallback,
+    TreeBuilder, TreeBuilderOpts,
+};
+#[cfg(feature = "flate2")]
+use crate::util::bytes::{parse_bytes, utf16_bom};
 use crate::{Attribute, QualName};
+use markup5ever::{local_name, namespace_url, ns};
 
-use std::borrow::Cow;
+use std::borrow::Cow::{self, Borrowed};
+use std::marker::PhantomData;
+use std::mem;
 
 use crate::tendril;
 use crate::tendril::stream::{TendrilSink, Utf8LossyDecoder};
@@ -28,6 +42,318 @@ pub struct ParseOpts {
 
     /// Tree builder options.
     pub tree_builder: TreeBuilderOpts,
+
+    /// Stop accepting input once this many bytes have been fed to the
+    /// parser.  Default: None
+    ///
+    /// The limit applies to the total size of all chunks passed to
+    /// `process`, measured in UTF-8, so the size of a chunk does not
+    /// matter.  With `Parser::from_utf8` the input is counted after
+    /// decoding, where each invalid byte sequence becomes a three-byte
+    /// `U+FFFD`.  A byte order mark counts towards the limit, even if the
+    /// tokenizer discards it.
+    ///
+    /// The chunk that crosses the limit is cut at the last character that
+    /// fits, a parse error is reported and any further input is ignored.
+    /// `finish` then completes the document from the input that was
+    /// accepted.
+    pub max_input_bytes: Option<u64>,
+
+    /// Where the input starts in a larger text, such as a file that embeds
+    /// HTML snippets.  Line numbers passed to the sink are counted from
+    /// here.  Default: line 1, column 1
+    pub position_offset: Position,
+
+    /// Advance the column at a tab to the next multiple of this many
+    /// columns, to match an editor.  Without it a tab is one column.
+    /// Default: None
+    pub tab_width: Option<usize>,
+}
+
+impl ParseOpts {
+    /// Start building a `ParseOpts` from the defaults.
+    ///
+    /// ```
+    /// use html5ever::ParseOpts;
+    ///
+    /// let opts = ParseOpts::builder()
+    ///     .scripting_enabled(false)
+    ///     .exact_errors(true)
+    ///     .build();
+    /// assert!(!opts.tree_builder.scripting_enabled);
+    /// assert!(opts.tokenizer.exact_errors);
+    /// ```
+    pub fn builder() -> ParseOptsBuilder {
+        ParseOptsBuilder {
+            opts: ParseOpts::default(),
+        }
+    }
+}
+
+/// Builder for `ParseOpts`, created by `ParseOpts::builder`.
+///
+/// Options without a dedicated setter can be reached by passing a complete
+/// `TokenizerOpts` or `TreeBuilderOpts`:
+///
+/// ```
+/// use html5ever::ParseOpts;
+/// use html5ever::tree_builder::TreeBuilderOpts;
+///
+/// let opts = ParseOpts::builder()
+///     .tree_builder(TreeBuilderOpts {
+///         iframe_srcdoc: true,
+///         ..Default::default()
+///     })
+///     .drop_doctype(true)
+///     .build();
+/// assert!(opts.tree_builder.iframe_srcdoc);
+/// assert!(opts.tree_builder.drop_doctype);
+/// ```
+#[derive(Clone, Default)]
+pub struct ParseOptsBuilder {
+    opts: ParseOpts,
+}
+
+impl ParseOptsBuilder {
+    /// Replace all tokenizer options.
+    pub fn tokenizer(mut self, tokenizer: TokenizerOpts) -> Self {
+        self.opts.tokenizer = tokenizer;
+        self
+    }
+
+    /// Replace all tree builder options.
+    pub fn tree_builder(mut self, tree_builder: TreeBuilderOpts) -> Self {
+        self.opts.tree_builder = tree_builder;
+        self
+    }
+
+    /// Report all parse errors described in the spec, in both the tokenizer
+    /// and the tree builder.
+    pub fn exact_errors(mut self, exact_errors: bool) -> Self {
+        self.opts.tokenizer.exact_errors = exact_errors;
+        self.opts.tree_builder.exact_errors = exact_errors;
+        self
+    }
+
+    /// Is scripting enabled?
+    pub fn scripting_enabled(mut self, scripting_enabled: bool) -> Self {
+        self.opts.tree_builder.scripting_enabled = scripting_enabled;
+        self
+    }
+
+    /// Is this an `iframe srcdoc` document?
+    pub fn iframe_srcdoc(mut self, iframe_srcdoc: bool) -> Self {
+        self.opts.tree_builder.iframe_srcdoc = iframe_srcdoc;
+        self
+    }
+
+    /// Should we drop the DOCTYPE (if any) from the tree?
+    pub fn drop_doctype(mut self, drop_doctype: bool) -> Self {
+        self.opts.tree_builder.drop_doctype = drop_doctype;
+        self
+    }
+
+    /// Initial tree builder quirks mode.
+    pub fn quirks_mode(mut self, quirks_mode: QuirksMode) -> Self {
+        self.opts.tree_builder.quirks_mode = quirks_mode;
+        self
+    }
+
+    /// Maximum length of the list of active formatting elements.
+    pub fn max_formatting_elements(mut self, max: Option<usize>) -> Self {
+        self.opts.tree_builder.max_formatting_elements = max;
+        self
+    }
+
+    /// Pass misplaced `DOCTYPE`s to `TreeSink::misplaced_doctype`.
+    pub fn preserve_misplaced_doctype(mut self, preserve: bool) -> Self {
+        self.opts.tree_builder.preserve_misplaced_doctype = preserve;
+        self
+    }
+
+    /// Skip the adoption agency algorithm for mis-nested formatting elements.
+    pub fn disable_adoption_agency(mut self, disable: bool) -> Self {
+        self.opts.tree_builder.disable_adoption_agency = disable;
+        self
+    }
+
+    /// Rewrite `<image>` start tags to `<img>`, as the spec requires.
+    pub fn rewrite_image_tag(mut self, rewrite: bool) -> Self {
+        self.opts.tree_builder.rewrite_image_tag = rewrite;
+        self
+    }
+
+    /// Report obsolete HTML elements to `TreeSink::obsolete_element`.
+    pub fn flag_obsolete_elements(mut self, flag: bool) -> Self {
+        self.opts.tree_builder.flag_obsolete_elements = flag;
+        self
+    }
+
+    /// Keep the case of unknown SVG and MathML element and attribute names.
+    pub fn preserve_foreign_case(mut self, preserve: bool) -> Self {
+        self.opts.tree_builder.preserve_foreign_case = preserve;
+        self
+    }
+
+    /// Sort the attributes of each element by namespace and local name.
+    pub fn sort_attributes(mut self, sort: bool) -> Self {
+        self.opts.tree_builder.sort_attributes = sort;
+        self
+    }
+
+    /// Remove URL attributes whose scheme is not one of `schemes`.
+    pub fn allowed_url_schemes(mut self, schemes: Option<&'static [&'static str]>) -> Self {
+        self.opts.tree_builder.allowed_url_schemes = schemes;
+        self
+    }
+
+    /// Close non-void elements whose start tags end in `/>`.
+    pub fn honor_self_closing(mut self, honor_self_closing: bool) -> Self {
+        self.opts.tree_builder.honor_self_closing = honor_self_closing;
+        self
+    }
+
+    /// Create the missing table around cells and rows outside a table.
+    pub fn wrap_orphaned_table_content(mut self, wrap: bool) -> Self {
+        self.opts.tree_builder.wrap_orphaned_table_content = wrap;
+        self
+    }
+
+    /// Create HTML elements in `ns` instead of the HTML namespace.
+    pub fn default_namespace(mut self, ns: Option<&'static str>) -> Self {
+        self.opts.tree_builder.default_namespace = ns;
+        self
+    }
+
+    /// Normalize text and comments to `form`.
+    #[cfg(feature = "unicode-normalization")]
+    pub fn normalize_unicode(mut self, form: Option<NormalizationForm>) -> Self {
+        self.opts.tree_builder.normalize_unicode = form;
+        self
+    }
+
+    /// Close the elements inside `<body>` at `</body>` and `</html>`.
+    pub fn strict_body_close(mut self, strict: bool) -> Self {
+        self.opts.tree_builder.strict_body_close = strict;
+        self
+    }
+
+    /// Discard a `U+FEFF BYTE ORDER MARK` if we see one at the beginning
+    /// of the stream?
+    pub fn discard_bom(mut self, discard_bom: bool) -> Self {
+        self.opts.tokenizer.discard_bom = discard_bom;
+        self
+    }
+
+    /// Drop every `U+FEFF` from text, not just a leading byte order mark.
+    /// This differs from the spec; see `TokenizerOpts::strip_all_bom`.
+    pub fn strip_all_bom(mut self, strip_all_bom: bool) -> Self {
+        self.opts.tokenizer.strip_all_bom = strip_all_bom;
+        self
+    }
+
+    /// Keep every attribute of a tag, including duplicates, and pass them to
+    /// `TreeSink::create_element_raw_attrs`.
+    pub fn preserve_all_attributes(mut self, preserve_all_attributes: bool) -> Self {
+        self.opts.tokenizer.preserve_all_attributes = preserve_all_attributes;
+        self.opts.tree_builder.preserve_all_attributes = preserve_all_attributes;
+        self
+    }
+
+    /// Pass the `DOCTYPE` declaration as written to
+    /// `TreeSink::append_doctype_raw`.
+    pub fn preserve_raw_doctype(mut self, preserve_raw_doctype: bool) -> Self {
+        self.opts.tokenizer.preserve_raw_doctype = preserve_raw_doctype;
+        self
+    }
+
+    /// Choose how to handle numeric character references that the spec
+    /// replaces with another character.
+    pub fn invalid_charref_policy(mut self, policy: InvalidCharRefPolicy) -> Self {
+        self.opts.tokenizer.invalid_charref_policy = policy;
+        self
+    }
+
+    /// Stop decoding character references after `max` of them.
+    pub fn max_entity_expansions(mut self, max: Option<usize>) -> Self {
+        self.opts.tokenizer.max_entity_expansions = max;
+        self
+    }
+
+    /// Parse `<?target data?>` as a processing instruction instead of a
+    /// bogus comment.
+    pub fn pi_as_processing_instruction(mut self, pi_as_processing_instruction: bool) -> Self {
+        self.opts.tokenizer.pi_as_processing_instruction = pi_as_processing_instruction;
+        self
+    }
+
+    /// Replace `\r\n` and `\r` in the input with `\n`, as the spec does.
+    pub fn normalize_newlines(mut self, normalize_newlines: bool) -> Self {
+        self.opts.tokenizer.normalize_newlines = normalize_newlines;
+        self
+    }
+
+    /// Choose how to handle `U+0000 NULL` characters; see `NullHandling`.
+    /// Only the tokenizer is configured; the tree builder's null rules
+    /// apply just under `NullHandling::Spec`.
+    pub fn null_handling(mut self, null_handling: NullHandling) -> Self {
+        self.opts.tokenizer.null_handling = null_handling;
+        self
+    }
+
+    /// Report errors in comments where the current spec does, by its names.
+    pub fn named_comment_errors(mut self, named_comment_errors: bool) -> Self {
+        self.opts.tokenizer.named_comment_errors = named_comment_errors;
+        self
+    }
+
+    /// Report markup that parsers are known to disagree on to
+    /// `TreeSink::ambiguous_construct`.
+    pub fn flag_ambiguous_constructs(mut self, flag: bool) -> Self {
+        self.opts.tokenizer.flag_ambiguous_constructs = flag;
+        self
+    }
+
+    /// Keep at most this many bytes of each comment.
+    pub fn max_comment_length(mut self, max: Option<usize>) -> Self {
+        self.opts.tokenizer.max_comment_length = max;
+        self
+    }
+
+    /// Keep at most this many bytes of each attribute value.
+    pub fn max_attribute_value_length(mut self, max: Option<usize>) -> Self {
+        self.opts.tokenizer.max_attribute_value_length = max;
+        self
+    }
+
+    /// Collect `ParseStats` while parsing.
+    pub fn collect_stats(mut self, collect_stats: bool) -> Self {
+        self.opts.tree_builder.collect_stats = collect_stats;
+        self
+    }
+
+    /// Stop accepting input after this many bytes.
+    pub fn max_input_bytes(mut self, max: Option<u64>) -> Self {
+        self.opts.max_input_bytes = max;
+        self
+    }
+
+    /// Count positions from where the input starts in a larger text.
+    pub fn position_offset(mut self, position: Position) -> Self {
+        self.opts.position_offset = position;
+        self
+    }
+
+    /// Advance tabs to the next multiple of this many columns.
+    pub fn tab_width(mut self, tab_width: Option<usize>) -> Self {
+        self.opts.tab_width = tab_width;
+        self
+    }
+
+    /// Finish building.
+    pub fn build(self) -> ParseOpts {
+        self.opts
+    }
 }
 
 /// Parse an HTML document
@@ -42,13 +368,82 @@ where
     Sink: TreeSink,
 {
     let tb = TreeBuilder::new(sink, opts.tree_builder);
-    let tok = Tokenizer::new(tb, opts.tokenizer);
+    let mut tok = Tokenizer::new(tb, opts.tokenizer);
+    tok.set_position(opts.position_offset);
+    tok.set_tab_width(opts.tab_width);
     Parser {
         tokenizer: tok,
         input_buffer: BufferQueue::new(),
+        pending: StrTendril::new(),
+        written: StrTendril::new(),
+        input_limit: InputLimit::new(opts.max_input_bytes),
+        sink: PhantomData,
     }
 }
 
+/// Continue parsing a document from the middle, at a point where
+/// `Parser::at_restart_point` was true.
+///
+/// The sink must hold the document parsed up to that point, and `html`,
+/// `head` and `body` are its `<html>`, `<head>` and `<body>` elements.  The
+/// rest of the input is parsed as if it had followed the earlier input in
+/// one parser, with these exceptions:
+///
+/// * Line numbers start at `opts.position_offset`, not where the earlier
+///   input left off.
+/// * The quirks mode is `opts.tree_builder.quirks_mode`; set it to the
+///   document's.
+/// * `<html>` and `<body>` start tags still add their attributes to the
+///   elements from the earlier parse, so undoing a parse back to the
+///   restart point must not rely on those elements being unchanged.
+///
+/// This is a building block for re-parsing a document after an edit,
+/// without starting over; see `markup5ever_rcdom::RcDom::reparse_from`.
+pub fn resume_in_body<Sink>(
+    sink: Sink,
+    opts: ParseOpts,
+    html: Sink::Handle,
+    head: Option<Sink::Handle>,
+    body: Sink::Handle,
+) -> Parser<Sink>
+where
+    Sink: TreeSink,
+{
+    let tb = TreeBuilder::new_in_body(sink, opts.tree_builder, html, head, body);
+    let mut tok = Tokenizer::new(tb, opts.tokenizer);
+    tok.set_position(opts.position_offset);
+    tok.set_tab_width(opts.tab_width);
+    Parser {
+        tokenizer: tok,
+        input_buffer: BufferQueue::new(),
+        pending: StrTendril::new(),
+        written: StrTendril::new(),
+        input_limit: InputLimit::new(opts.max_input_bytes),
+        sink: PhantomData,
+    }
+}
+
+/// Parse only the `<head>` of an HTML document.
+///
+/// This is like `parse_document`, but parsing stops where the `<body>` or
+/// `<frameset>` element would be created, whether by a start tag or
+/// implied by other content.  The sink sees the document up to that point:
+/// the doctype, the `<html>` and `<head>` elements with the head's
+/// contents, and any comments and whitespace after the head.  Head elements
+/// misplaced after `</head>` are still moved into the head, as usual.  The
+/// rest of the input is ignored, and its parse errors are not reported.
+///
+/// The open elements are not popped when parsing stops; `finish` is
+/// called on the sink directly.
+pub fn parse_head_only<Sink>(sink: Sink, opts: ParseOpts) -> Parser<Sink>
+where
+    Sink: TreeSink,
+{
+    let mut parser = parse_document(sink, opts);
+    parser.tokenizer.sink.set_stop_at_body();
+    parser
+}
+
 /// Parse an HTML fragment
 ///
 /// The returned value implements `tendril::TendrilSink`
@@ -56,6 +451,21 @@ where
 /// or all at once with the `one` method.
 ///
 /// If your input is bytes, use `Parser::from_utf8`.
+///
+/// The context element is usually an HTML element, named with
+/// `QualName::html`:
+///
+/// ```
+/// use html5ever::driver::{parse_fragment, ParseOpts};
+/// use html5ever::immutable::ImmutableDom;
+/// use html5ever::tendril::TendrilSink;
+/// use html5ever::QualName;
+///
+/// let context = QualName::html("tr");
+/// let dom = parse_fragment(ImmutableDom::default(), ParseOpts::default(), context, vec![])
+///     .one("<td>cell");
+/// assert_eq!(dom.text_contents(dom.document()), "cell");
+/// ```
 pub fn parse_fragment<Sink>(
     mut sink: Sink,
     opts: ParseOpts,
@@ -69,6 +479,82 @@ where
     parse_fragment_for_element(sink, opts, context_elem, None)
 }
 
+/// Parse an HTML fragment into its context element.
+///
+/// `parse_fragment` parses the fragment into an `<html>` element in the
+/// sink's document, as the spec does, and the caller collects the nodes
+/// from there; the context element is only used to decide how to parse,
+/// and is not part of the tree.  This creates the context element from
+/// `context_name` and `context_attrs` and moves the parsed nodes into it
+/// when parsing finishes, so the output is the sink's output and the
+/// context element, with the fragment as its children.  The `<html>`
+/// element is removed from the document.
+///
+/// The sink's handles must stay valid after `finish`, as with `RcDom`.
+///
+/// ```
+/// use html5ever::driver::{parse_fragment_keep_context, ParseOpts};
+/// use html5ever::tendril::TendrilSink;
+/// use html5ever::tree_builder::TreeSink;
+/// use html5ever::QualName;
+///
+/// fn parse_row<Sink: TreeSink>(sink: Sink) -> (Sink::Output, Sink::Handle) {
+///     let context = QualName::html("tr");
+///     parse_fragment_keep_context(sink, ParseOpts::default(), context, vec![]).one("<td>cell")
+/// }
+/// ```
+pub fn parse_fragment_keep_context<Sink>(
+    mut sink: Sink,
+    opts: ParseOpts,
+    context_name: QualName,
+    context_attrs: Vec<Attribute>,
+) -> KeepContextParser<Sink>
+where
+    Sink: TreeSink,
+{
+    let context = create_element(&mut sink, context_name, context_attrs);
+    let parser = parse_fragment_for_element(sink, opts, context.clone(), None);
+    let root = parser.tokenizer.sink.html_elem().clone();
+    KeepContextParser {
+        parser,
+        root,
+        context,
+    }
+}
+
+/// Parse a fragment of SVG, as if it were the contents of an `<svg>`
+/// element in an HTML document.
+///
+/// This is `parse_fragment` with an `<svg>` context element.  Elements are
+/// created in the SVG namespace, with the case of their names adjusted as
+/// in SVG, except inside `<foreignObject>`, `<desc>` and `<title>`, which
+/// contain HTML.  Outside of those, HTML elements like `<p>` are created in
+/// the SVG namespace too, as there is no HTML element to break out to.
+pub fn parse_svg_fragment<Sink>(sink: Sink, opts: ParseOpts) -> Parser<Sink>
+where
+    Sink: TreeSink,
+{
+    let context_name = QualName::new(None, ns!(svg), local_name!("svg"));
+    parse_fragment(sink, opts, context_name, vec![])
+}
+
+/// Parse a fragment of MathML, as if it were the contents of a `<math>`
+/// element in an HTML document.
+///
+/// This is `parse_fragment` with a `<math>` context element.  Elements are
+/// created in the MathML namespace, except inside token elements like
+/// `<mi>` and `<mtext>`, and `<annotation-xml>` with an HTML encoding,
+/// which contain HTML.  Outside of those, HTML elements like `<p>` are
+/// created in the MathML namespace too, as there is no HTML element to
+/// break out to.
+pub fn parse_mathml_fragment<Sink>(sink: Sink, opts: ParseOpts) -> Parser<Sink>
+where
+    Sink: TreeSink,
+{
+    let context_name = QualName::new(None, ns!(mathml), local_name!("math"));
+    parse_fragment(sink, opts, context_name, vec![])
+}
+
 /// Like `parse_fragment`, but with an existing context element
 /// and optionally a form element.
 pub fn parse_fragment_for_element<Sink>(
@@ -85,47 +571,455 @@ where
         initial_state: Some(tb.tokenizer_state_for_context_elem()),
         ..opts.tokenizer
     };
-    let tok = Tokenizer::new(tb, tok_opts);
+    let mut tok = Tokenizer::new(tb, tok_opts);
+    tok.set_position(opts.position_offset);
+    tok.set_tab_width(opts.tab_width);
     Parser {
         tokenizer: tok,
         input_buffer: BufferQueue::new(),
+        pending: StrTendril::new(),
+        written: StrTendril::new(),
+        input_limit: InputLimit::new(opts.max_input_bytes),
+        sink: PhantomData,
     }
 }
 
 /// An HTML parser,
 /// ready to receive Unicode input through the `tendril::TendrilSink` trait’s methods.
-pub struct Parser<Sink>
+///
+/// The tokenizer usually passes tokens straight to the tree builder.  `Tok`
+/// is something else, like the `Transformed` of a `TransformParser`, when
+/// they go through it first.
+pub struct Parser<Sink, Tok = TreeBuilder<<Sink as TreeSink>::Handle, Sink>>
 where
     Sink: TreeSink,
 {
-    pub tokenizer: Tokenizer<TreeBuilder<Sink::Handle, Sink>>,
+    pub tokenizer: Tokenizer<Tok>,
     pub input_buffer: BufferQueue,
+    /// Characters from `push_char` that haven't been tokenized yet.
+    pending: StrTendril,
+    /// Text from `document_write`, to go in front of `input_buffer` once
+    /// tokenizing resumes.
+    written: StrTendril,
+    input_limit: InputLimit,
+    sink: PhantomData<Sink>,
+}
+
+/// A `TokenSink` that passes tokens on to a tree builder, which a `Parser`
+/// reaches through it.
+pub trait ParserSink: TokenSink {
+    type TreeSink: TreeSink;
+
+    fn tree_builder(&self) -> &TreeBuilder<<Self::TreeSink as TreeSink>::Handle, Self::TreeSink>;
+
+    fn tree_builder_mut(
+        &mut self,
+    ) -> &mut TreeBuilder<<Self::TreeSink as TreeSink>::Handle, Self::TreeSink>;
+
+    fn into_tree_builder(self)
+        -> TreeBuilder<<Self::TreeSink as TreeSink>::Handle, Self::TreeSink>;
+}
+
+impl<Sink: TreeSink> ParserSink for TreeBuilder<Sink::Handle, Sink> {
+    type TreeSink = Sink;
+
+    fn tree_builder(&self) -> &TreeBuilder<Sink::Handle, Sink> {
+        self
+    }
+
+    fn tree_builder_mut(&mut self) -> &mut TreeBuilder<Sink::Handle, Sink> {
+        self
+    }
+
+    fn into_tree_builder(self) -> TreeBuilder<Sink::Handle, Sink> {
+        self
+    }
+}
+
+impl<T: TokenTransform, Tok: ParserSink> ParserSink for Transformed<T, Tok> {
+    type TreeSink = Tok::TreeSink;
+
+    fn tree_builder(&self) -> &TreeBuilder<<Self::TreeSink as TreeSink>::Handle, Self::TreeSink> {
+        self.sink.tree_builder()
+    }
+
+    fn tree_builder_mut(
+        &mut self,
+    ) -> &mut TreeBuilder<<Self::TreeSink as TreeSink>::Handle, Self::TreeSink> {
+        self.sink.tree_builder_mut()
+    }
+
+    fn into_tree_builder(
+        self,
+    ) -> TreeBuilder<<Self::TreeSink as TreeSink>::Handle, Self::TreeSink> {
+        self.sink.into_tree_builder()
+    }
 }
 
-impl<Sink: TreeSink> TendrilSink<tendril::fmt::UTF8> for Parser<Sink> {
+impl<Sink, Tok> TendrilSink<tendril::fmt::UTF8> for Parser<Sink, Tok>
+where
+    Sink: TreeSink,
+    Tok: ParserSink<TreeSink = Sink>,
+{
     fn process(&mut self, t: StrTendril) {
-        self.input_buffer.push_back(t);
+        if !self.tokenizer.sink.tree_builder().sink.should_continue() || self.input_limit.exceeded {
+            return;
+        }
+        if !self.pending.is_empty() {
+            let pending = mem::replace(&mut self.pending, StrTendril::new());
+            self.push_input(pending);
+        }
+        self.push_input(t);
+        self.push_written();
         // FIXME: Properly support </script> somehow.
         while let TokenizerResult::Script(_) = self.tokenizer.feed(&mut self.input_buffer) {}
+        self.tokenizer
+            .sink
+            .tree_builder_mut()
+            .process_pending_text();
     }
 
     // FIXME: Is it too noisy to report every character decoding error?
     fn error(&mut self, desc: Cow<'static, str>) {
-        self.tokenizer.sink.sink.parse_error(desc)
+        self.tokenizer
+            .sink
+            .tree_builder_mut()
+            .sink
+            .parse_error(desc)
     }
 
     type Output = Sink::Output;
 
     fn finish(mut self) -> Self::Output {
-        // FIXME: Properly support </script> somehow.
-        while let TokenizerResult::Script(_) = self.tokenizer.feed(&mut self.input_buffer) {}
-        assert!(self.input_buffer.is_empty());
-        self.tokenizer.end();
-        self.tokenizer.sink.sink.finish()
+        self.end();
+        self.tokenizer.sink.into_tree_builder().sink.finish()
+    }
+}
+
+impl<Sink, Tok> Parser<Sink, Tok>
+where
+    Sink: TreeSink,
+    Tok: ParserSink<TreeSink = Sink>,
+{
+    /// Parse the rest of the input and end parsing, short of finishing the
+    /// sink.
+    fn end(&mut self) {
+        if self.tokenizer.sink.tree_builder().sink.should_continue() {
+            if !self.pending.is_empty() {
+                let pending = mem::replace(&mut self.pending, StrTendril::new());
+                self.push_input(pending);
+            }
+            self.push_written();
+            // FIXME: Properly support </script> somehow.
+            while let TokenizerResult::Script(_) = self.tokenizer.feed(&mut self.input_buffer) {}
+            assert!(self.input_buffer.is_empty());
+            self.tokenizer.end();
+        }
+        self.tokenizer.sink.tree_builder_mut().report_stats();
+    }
+
+    /// Add one character of input.
+    ///
+    /// Characters are collected in a buffer and only handed to the tokenizer
+    /// once they may complete a token: at a `>`, before a `<`, and at
+    /// whitespace outside of a tag.  Until then nothing is emitted to the
+    /// sink, so a renderer may see a tag some characters after it was pushed.
+    /// Calling `process` or `finish` tokenizes the buffered characters first.
+    ///
+    /// This avoids allocating a tendril per character and feeding the
+    /// tokenizer a partial tag one character at a time.
+    pub fn push_char(&mut self, c: char) {
+        if c == '<' && !self.pending.is_empty() {
+            self.flush_pending();
+        }
+        self.pending.push_char(c);
+        if c == '>' || (c.is_ascii_whitespace() && !self.pending.starts_with('<')) {
+            self.flush_pending();
+        }
+    }
+
+    /// Insert `text` at the tokenizer's position, as `document.write` does
+    /// from a script.
+    ///
+    /// The text is tokenized before any input that has not been tokenized
+    /// yet: input left over while a `PausableParser` is paused and
+    /// characters buffered by `push_char`.  This is the difference from
+    /// `process`, which adds input after everything given so far.  Nothing
+    /// is tokenized until the next call to `process`, `resume` or
+    /// `finish`, and the text does not count towards
+    /// `ParseOpts::max_input_bytes`.
+    ///
+    /// Text written more than once without tokenizing in between is
+    /// tokenized in the order it was written.
+    pub fn document_write(&mut self, text: StrTendril) {
+        if !self.pending.is_empty() {
+            let pending = mem::replace(&mut self.pending, StrTendril::new());
+            self.push_input(pending);
+        }
+        self.written.push_tendril(&text);
+    }
+
+    /// Could parsing stop here and continue later with `resume_in_body`?
+    ///
+    /// This is true between tokens, with no input left over, when the tree
+    /// builder is at the top level of the `<body>`: the only open elements
+    /// are `<html>` and `<body>`, with no formatting elements to reopen,
+    /// no open form and no pending table text.  A document with content in
+    /// its body usually reaches such a point before each of its top-level
+    /// elements.
+    pub fn at_restart_point(&self) -> bool {
+        self.pending.is_empty() &&
+            self.written.is_empty() &&
+            self.input_buffer.is_empty() &&
+            self.tokenizer.between_tokens() &&
+            self.tokenizer.sink.tree_builder().at_body_top_level()
+    }
+
+    /// The counters collected so far, if `TreeBuilderOpts::collect_stats` is set.
+    ///
+    /// Characters buffered by `push_char` are not counted until they are
+    /// tokenized.
+    pub fn stats(&self) -> Option<&ParseStats> {
+        self.tokenizer.sink.tree_builder().stats()
+    }
+
+    /// Call `on_step` after each token the tree builder processes.  See
+    /// `TreeBuilder::set_on_step`.
+    pub fn set_on_step(&mut self, on_step: Option<StepCallback>) {
+        self.tokenizer.sink.tree_builder_mut().set_on_step(on_step);
+    }
+
+    /// Call `on_insertion_point` before each insertion, with where the node
+    /// is going.  See `TreeBuilder::set_on_insertion_point`.
+    pub fn set_on_insertion_point(&mut self, on_insertion_point: Option<InsertionPointCallback>) {
+        self.tokenizer
+            .sink
+            .tree_builder_mut()
+            .set_on_insertion_point(on_insertion_point);
+    }
+
+    /// Drop comments for which `comment_filter` returns false.  See
+    /// `TreeBuilder::set_comment_filter`.
+    pub fn set_comment_filter(&mut self, comment_filter: Option<CommentFilter>) {
+        self.tokenizer
+            .sink
+            .tree_builder_mut()
+            .set_comment_filter(comment_filter);
+    }
+
+    fn flush_pending(&mut self) {
+        let pending = mem::replace(&mut self.pending, StrTendril::new());
+        self.process(pending);
+    }
+
+    // Put the text from `document_write` before the input left over.
+    fn push_written(&mut self) {
+        if !self.written.is_empty() {
+            let written = mem::replace(&mut self.written, StrTendril::new());
+            self.input_buffer.push_front(written);
+        }
+    }
+
+    fn push_input(&mut self, t: StrTendril) {
+        if self.input_limit.exceeded {
+            return;
+        }
+        match self.input_limit.admit(t) {
+            Ok(t) => self.input_buffer.push_back(t),
+            Err(t) => {
+                self.input_buffer.push_back(t);
+                self.error(Borrowed("Input exceeds the maximum size"));
+            },
+        }
+    }
+
+    /// Wrap this parser into a `TendrilSink` that accepts UTF-8 bytes.
+    ///
+    /// Use this when your input is bytes that are known to be in the UTF-8 encoding.
+    /// Decoding is lossy, like `String::from_utf8_lossy`.
+    ///
+    /// A byte order mark at the start of the input is discarded, even if it
+    /// is split across chunks, unless `TokenizerOpts::discard_bom` is false.
+    ///
+    /// `read_from` feeds the parser as the bytes arrive from any `Read`.
+    /// Multi-byte characters split across reads are decoded correctly.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_utf8(self) -> Utf8LossyDecoder<Self> {
+        Utf8LossyDecoder::new(self)
+    }
+
+    /// Parse gzip-compressed HTML read from `reader`, decompressing it and
+    /// feeding the parser as the bytes arrive.
+    ///
+    /// The encoding is taken from a byte order mark at the start of the
+    /// decompressed input, for UTF-8 or UTF-16; without one the input is
+    /// decoded as UTF-8, lossily.  UTF-16 input is decompressed in full
+    /// before it is parsed.
+    ///
+    /// Requires the `flate2` feature.
+    #[cfg(feature = "flate2")]
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_gzip<R: std::io::Read>(self, reader: R) -> std::io::Result<Sink::Output> {
+        use std::io::Read;
+
+        let mut decoder = flate2::read::GzDecoder::new(reader);
+        let mut start = Vec::with_capacity(2);
+        (&mut decoder).take(2).read_to_end(&mut start)?;
+        if utf16_bom(&start).is_some() {
+            decoder.read_to_end(&mut start)?;
+            Ok(parse_bytes(self, &start))
+        } else {
+            self.from_utf8().read_from(&mut (&start[..]).chain(decoder))
+        }
     }
 }
 
 impl<Sink: TreeSink> Parser<Sink> {
+    /// Let the sink pause parsing, by calling `PauseHandle::pause` on a
+    /// clone of `pause`.  See `PausableParser`.
+    pub fn pausable(mut self, pause: PauseHandle) -> PausableParser<Sink> {
+        self.tokenizer.set_pause_handle(Some(pause.clone()));
+        PausableParser {
+            parser: self,
+            pause,
+        }
+    }
+}
+
+/// A fragment parser that keeps the context element, created by
+/// `parse_fragment_keep_context`.
+pub struct KeepContextParser<Sink>
+where
+    Sink: TreeSink,
+{
+    pub parser: Parser<Sink>,
+    /// The `<html>` element that the fragment is parsed into.
+    root: Sink::Handle,
+    context: Sink::Handle,
+}
+
+impl<Sink: TreeSink> TendrilSink<tendril::fmt::UTF8> for KeepContextParser<Sink> {
+    fn process(&mut self, t: StrTendril) {
+        self.parser.process(t)
+    }
+
+    fn error(&mut self, desc: Cow<'static, str>) {
+        self.parser.error(desc)
+    }
+
+    type Output = (Sink::Output, Sink::Handle);
+
+    fn finish(mut self) -> Self::Output {
+        self.parser.end();
+        let sink = &mut self.parser.tokenizer.sink.sink;
+        sink.reparent_children(&self.root, &self.context);
+        sink.remove_from_parent(&self.root);
+        (self.parser.tokenizer.sink.sink.finish(), self.context)
+    }
+}
+
+impl<Sink: TreeSink> KeepContextParser<Sink> {
+    /// Wrap this parser into a `TendrilSink` that accepts UTF-8 bytes.
+    ///
+    /// Use this when your input is bytes that are known to be in the UTF-8 encoding.
+    /// Decoding is lossy, like `String::from_utf8_lossy`.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_utf8(self) -> Utf8LossyDecoder<Self> {
+        Utf8LossyDecoder::new(self)
+    }
+}
+
+/// A parser that stops between tokens when its sink asks it to, created by
+/// `Parser::pausable`.
+///
+/// The sink keeps a clone of the `PauseHandle` and calls `pause` from a
+/// callback, such as `create_element`.  Parsing stops after the token being
+/// processed, with the exceptions described at `Tokenizer::set_pause_handle`,
+/// and `process` returns with the rest of its input buffered.  Call `resume`
+/// to parse on until the sink pauses again or the input runs out, and
+/// `process` to add more input, which is only parsed once resumed.  This
+/// hands control back to the caller after each token the sink is interested
+/// in, without threads.
+///
+/// The sink must not resume from inside a callback: a pause that is undone
+/// in the same callback is lost.  `finish` parses the rest of the input
+/// without pausing.
+///
+/// ```
+/// use html5ever::driver::parse_document;
+/// use html5ever::select::SelectorSink;
+/// use html5ever::tendril::TendrilSink;
+/// use html5ever::tokenizer::PauseHandle;
+///
+/// let pause = PauseHandle::default();
+/// let handle = pause.clone();
+/// let sink = SelectorSink::new("p".parse().unwrap(), move |_| handle.pause());
+/// let mut parser = parse_document(sink, Default::default()).pausable(pause);
+/// parser.process("<p>a<p>b<p>c".into());
+/// let mut pauses = 0;
+/// while parser.is_paused() {
+///     pauses += 1;
+///     parser.resume();
+/// }
+/// assert_eq!(pauses, 2);
+/// ```
+pub struct PausableParser<Sink>
+where
+    Sink: TreeSink,
+{
+    parser: Parser<Sink>,
+    pause: PauseHandle,
+}
+
+impl<Sink: TreeSink> TendrilSink<tendril::fmt::UTF8> for PausableParser<Sink> {
+    fn process(&mut self, t: StrTendril) {
+        self.parser.process(t)
+    }
+
+    fn error(&mut self, desc: Cow<'static, str>) {
+        self.parser.error(desc)
+    }
+
+    type Output = Sink::Output;
+
+    fn finish(mut self) -> Self::Output {
+        self.parser.tokenizer.set_pause_handle(None);
+        self.parser.finish()
+    }
+}
+
+impl<Sink: TreeSink> PausableParser<Sink> {
+    /// Has the sink paused parsing?
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_paused()
+    }
+
+    /// Continue parsing the input given so far, until the sink pauses
+    /// again or the input runs out.
+    pub fn resume(&mut self) {
+        self.pause.resume();
+        if self.parser.tokenizer.sink.should_continue() {
+            let parser = &mut self.parser;
+            parser.push_written();
+            // FIXME: Properly support </script> somehow.
+            while let TokenizerResult::Script(_) = parser.tokenizer.feed(&mut parser.input_buffer) {
+            }
+            parser.tokenizer.sink.process_pending_text();
+        }
+    }
+
+    /// Insert `text` at the tokenizer's position; see
+    /// `Parser::document_write`.  Call this while paused after a script,
+    /// then `resume`.
+    pub fn document_write(&mut self, text: StrTendril) {
+        self.parser.document_write(text)
+    }
+
+    /// The sink, to look at what it has seen while parsing is paused.
+    pub fn sink(&self) -> &Sink {
+        &self.parser.tokenizer.sink.sink
+    }
+
     /// Wrap this parser into a `TendrilSink` that accepts UTF-8 bytes.
     ///
     /// Use this when your input is bytes that are known to be in the UTF-8 encoding.
@@ -135,3 +1029,222 @@ impl<Sink: TreeSink> Parser<Sink> {
         Utf8LossyDecoder::new(self)
     }
 }
+
+/// Parse an HTML document, passing every token through `transform` on its
+/// way from the tokenizer to the tree builder.
+///
+/// See `TokenTransform` for how transformed tokens interact with the
+/// tokenizer's state.  Otherwise this behaves like `parse_document`.
+pub fn parse_document_with_transform<Sink, T>(
+    sink: Sink,
+    opts: ParseOpts,
+    transform: T,
+) -> TransformParser<Sink, T>
+where
+    Sink: TreeSink,
+    T: TokenTransform,
+{
+    let tb = TreeBuilder::new(sink, opts.tree_builder);
+    let mut tok = Tokenizer::new(Transformed::new(transform, tb), opts.tokenizer);
+    tok.set_position(opts.position_offset);
+    tok.set_tab_width(opts.tab_width);
+    Parser {
+        tokenizer: tok,
+        input_buffer: BufferQueue::new(),
+        pending: StrTendril::new(),
+        written: StrTendril::new(),
+        input_limit: InputLimit::new(opts.max_input_bytes),
+        sink: PhantomData,
+    }
+}
+
+/// An HTML parser with a token transform, created by
+/// `parse_document_with_transform`.
+pub type TransformParser<Sink, T> =
+    Parser<Sink, Transformed<T, TreeBuilder<<Sink as TreeSink>::Handle, Sink>>>;
+
+/// Counts the input fed to a parser, for `ParseOpts::max_input_bytes`.
+struct InputLimit {
+    remaining: Option<u64>,
+    exceeded: bool,
+}
+
+impl InputLimit {
+    fn new(max: Option<u64>) -> InputLimit {
+        InputLimit {
+            remaining: max,
+            exceeded: false,
+        }
+    }
+
+    /// Count `t` against the limit.  If it does not fit, return `Err` with
+    /// the longest prefix of `t` that does.
+    fn admit(&mut self, mut t: StrTendril) -> Result<StrTendril, StrTendril> {
+        let remaining = unwrap_or_return!(self.remaining.as_mut(), Ok(t));
+        let len = t.len() as u64;
+        if len <= *remaining {
+            *remaining -= len;
+            return Ok(t);
+        }
+        let mut end = *remaining as usize;
+        while !t.is_char_boundary(end) {
+            end -= 1;
+        }
+        t.pop_back(t.len() as u32 - end as u32);
+        *remaining = 0;
+        self.exceeded = true;
+        Err(t)
+    }
+}
+
+/// Parse an HTML document from an asynchronous stream of UTF-8 byte chunks.
+///
+/// Chunks are decoded and fed to the parser as they arrive; a code point
+/// split across two chunks is handled correctly.  Decoding is lossy, like
+/// `Parser::from_utf8`.  The first error yielded by the stream aborts parsing
+/// and is returned.
+///
+/// Requires the `async` feature.
+///
+/// ```ignore
+/// let response = reqwest::get("https://example.com/").await?;
+/// let dom = parse_document_async(RcDom::default(), Default::default(), response.bytes_stream())
+///     .await?;
+/// ```
+#[cfg(feature = "async")]
+pub async fn parse_document_async<Sink, S, B, E>(
+    sink: Sink,
+    opts: ParseOpts,
+    stream: S,
+) -> Result<Sink::Output, E>
+where
+    Sink: TreeSink,
+    S: futures_util::stream::Stream<Item = Result<B, E>>,
+    B: AsRef<[u8]>,
+{
+    use futures_util::stream::StreamExt;
+
+    futures_util::pin_mut!(stream);
+    let mut decoder = parse_document(sink, opts).from_utf8();
+    while let Some(chunk) = stream.next().await {
+        decoder.process(tendril::ByteTendril::from_slice(chunk?.as_ref()));
+    }
+    Ok(decoder.finish())
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "async")]
+    mod stream {
+        use crate::driver::parse_document_async;
+        use crate::select::SelectorSink;
+        use futures_util::stream;
+        use futures_util::task::noop_waker;
+        use std::future::Future;
+        use std::task::{Context, Poll};
+
+        /// Run a future that never has to wait, like one reading `stream::iter`.
+        fn run<F: Future>(future: F) -> F::Output {
+            futures_util::pin_mut!(future);
+            let waker = noop_waker();
+            match future.poll(&mut Context::from_waker(&waker)) {
+                Poll::Ready(output) => output,
+                Poll::Pending => panic!("future is pending"),
+            }
+        }
+
+        fn paragraphs(
+            chunks: Vec<Result<&'static [u8], &'static str>>,
+        ) -> Result<Vec<String>, &'static str> {
+            let mut found = vec![];
+            let sink = SelectorSink::new("p".parse().unwrap(), |m| found.push(m.text.to_string()));
+            run(parse_document_async(
+                sink,
+                Default::default(),
+                stream::iter(chunks),
+            ))?;
+            Ok(found)
+        }
+
+        #[test]
+        fn chunks() {
+            let chunks = vec![
+                Ok(&b"<p>caf\xc3"[..]),
+                Ok(&b"\xa9</p><p>"[..]),
+                Ok(&b"\xffx"[..]),
+            ];
+            assert_eq!(
+                paragraphs(chunks),
+                Ok(vec!["café".to_string(), "\u{fffd}x".to_string()])
+            );
+        }
+
+        #[test]
+        fn stream_error() {
+            let chunks = vec![Ok(&b"<p>a"[..]), Err("reset"), Ok(&b"<p>b"[..])];
+            assert_eq!(paragraphs(chunks), Err("reset"));
+        }
+    }
+
+    #[cfg(feature = "flate2")]
+    mod gzip {
+        use crate::driver::parse_document;
+        use crate::select::SelectorSink;
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::{self, Read, Write};
+
+        /// Returns a few bytes per read, like a socket.
+        struct Chunked(Vec<u8>, usize);
+
+        impl Read for Chunked {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let rest = &self.0[self.1..];
+                let n = buf.len().min(rest.len()).min(3);
+                buf[..n].copy_from_slice(&rest[..n]);
+                self.1 += n;
+                Ok(n)
+            }
+        }
+
+        fn gzip(input: &[u8]) -> Vec<u8> {
+            let mut encoder = GzEncoder::new(vec![], Compression::default());
+            encoder.write_all(input).unwrap();
+            encoder.finish().unwrap()
+        }
+
+        fn paragraphs(compressed: Vec<u8>) -> io::Result<Vec<String>> {
+            let mut found = vec![];
+            let sink = SelectorSink::new("p".parse().unwrap(), |m| found.push(m.text.to_string()));
+            parse_document(sink, Default::default()).from_gzip(Chunked(compressed, 0))?;
+            Ok(found)
+        }
+
+        #[test]
+        fn utf8() {
+            let input = "<title>x</title><p>caf\u{e9}<p>\u{2603}\u{1F600}".repeat(100);
+            let found = paragraphs(gzip(input.as_bytes())).unwrap();
+            assert_eq!(found.len(), 200);
+            assert_eq!(found[0], "caf\u{e9}");
+            assert_eq!(found[199], "\u{2603}\u{1F600}");
+            let found = paragraphs(gzip(b"\xEF\xBB\xBF<p>\xC3\xA9")).unwrap();
+            assert_eq!(found, vec!["\u{e9}"]);
+        }
+
+        #[test]
+        fn utf16() {
+            let mut input = vec![0xFF, 0xFE];
+            for unit in "<p>caf\u{e9}".encode_utf16() {
+                input.extend_from_slice(&unit.to_le_bytes());
+            }
+            assert_eq!(paragraphs(gzip(&input)).unwrap(), vec!["caf\u{e9}"]);
+        }
+
+        #[test]
+        fn corrupt() {
+            let mut compressed = gzip(b"<p>a");
+            compressed.truncate(12);
+            assert!(paragraphs(compressed).is_err());
+        }
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Counting the tags and attributes in a document, for profiling content.
+//!
+//! The counts come from the tokenizer alone, without a tree builder, so
+//! they are of the tags as written: tags that the tree builder would imply
+//! are not counted, and markup in `<script>`, `<style>` and the like is
+//! counted as tags.  Names are lowercase, as the tokenizer gives them, and
+//! have no namespace, which is decided by the tree builder.
+//!
+//! ```
+//! use html5ever::frequencies::tag_frequencies;
+//! use html5ever::local_name;
+//!
+//! let counts = tag_frequencies(b"<ul><li>one<li>two</ul><P>three");
+//! assert_eq!(counts[&local_name!("li")], 2);
+//! assert_eq!(counts[&local_name!("p")], 1);
+//! assert!(!counts.contains_key(&local_name!("body")));
+//! ```
+
+use crate::tokenizer::{tokenize, StartTag, Tag, TagToken};
+use crate::util::bytes::decode_bytes;
+use crate::LocalName;
+
+use std::collections::HashMap;
+
+/// Count the start tags in `input`, by element name.
+///
+/// The encoding is taken from a byte order mark, for UTF-8 or UTF-16.
+/// Without one the input is decoded as UTF-8, since html5ever has no
+/// decoders for legacy encodings.  Invalid sequences are replaced.
+pub fn tag_frequencies(input: &[u8]) -> HashMap<LocalName, usize> {
+    count_start_tags(input, |tag, counts| {
+        *counts.entry(tag.name.clone()).or_insert(0) += 1;
+    })
+}
+
+/// Count the attributes on the start tags in `input`, by attribute name.
+///
+/// An attribute that is repeated on one tag is counted once, as the
+/// tokenizer drops the duplicates.  The input is decoded as for
+/// `tag_frequencies`.
+pub fn attribute_frequencies(input: &[u8]) -> HashMap<LocalName, usize> {
+    count_start_tags(input, |tag, counts| {
+        for attr in &tag.attrs {
+            *counts.entry(attr.name.local.clone()).or_insert(0) += 1;
+        }
+    })
+}
+
+fn count_start_tags<F>(input: &[u8], mut count: F) -> HashMap<LocalName, usize>
+where
+    F: FnMut(&Tag, &mut HashMap<LocalName, usize>),
+{
+    let mut counts = HashMap::new();
+    for token in tokenize(&decode_bytes(input), Default::default()) {
+        if let TagToken(ref tag) = token {
+            if tag.kind == StartTag {
+                count(tag, &mut counts);
+            }
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod test {
+    use super::{attribute_frequencies, tag_frequencies};
+    use crate::LocalName;
+
+    use std::collections::HashMap;
+
+    const DOCUMENT: &str = "<!DOCTYPE html>\n\
+        <html lang=en>\n\
+        <head><meta charset=utf-8><title>Fruit</title>\n\
+        <link rel=stylesheet href=style.css></head>\n\
+        <body class=page>\n\
+        <h1 id=top class=title>Fruit</h1>\n\
+        <ul class=list>\n\
+        <li><a href=/apple class=link>Apple</a>\n\
+        <li><a href=/pear class=link CLASS=dup>Pear</a>\n\
+        <li><img src=plum.png alt=Plum>\n\
+        </ul>\n\
+        <svg viewBox='0 0 1 1'><circle r=1 /></svg>\n\
+        </body></html>\n";
+
+    fn sorted(counts: HashMap<LocalName, usize>) -> Vec<(String, usize)> {
+        let mut counts: Vec<_> = counts
+            .into_iter()
+            .map(|(name, count)| (name.to_string(), count))
+            .collect();
+        counts.sort();
+        counts
+    }
+
+    #[test]
+    fn tags() {
+        assert_eq!(
+            sorted(tag_frequencies(DOCUMENT.as_bytes())),
+            vec![
+                ("a".into(), 2),
+                ("body".into(), 1),
+                ("circle".into(), 1),
+                ("h1".into(), 1),
+                ("head".into(), 1),
+                ("html".into(), 1),
+                ("img".into(), 1),
+                ("li".into(), 3),
+                ("link".into(), 1),
+                ("meta".into(), 1),
+                ("svg".into(), 1),
+                ("title".into(), 1),
+                ("ul".into(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn attributes() {
+        assert_eq!(
+            sorted(attribute_frequencies(DOCUMENT.as_bytes())),
+            vec![
+                ("alt".into(), 1),
+                ("charset".into(), 1),
+                ("class".into(), 5),
+                ("href".into(), 3),
+                ("id".into(), 1),
+                ("lang".into(), 1),
+                ("r".into(), 1),
+                ("rel".into(), 1),
+                ("src".into(), 1),
+                ("viewbox".into(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn utf16() {
+        let mut input = vec![0xFF, 0xFE];
+        for unit in "<p>caf\u{e9}<p>".encode_utf16() {
+            input.extend_from_slice(&unit.to_le_bytes());
+        }
+        assert_eq!(sorted(tag_frequencies(&input)), vec![("p".into(), 2)]);
+    }
+}
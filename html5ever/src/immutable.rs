@@ -0,0 +1,645 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A read-only tree, for documents that are not changed after parsing.
+//!
+//! `ImmutableDom` is a sink that builds the tree in a `Vec` of nodes linked
+//! by index, and freezes it into a `FrozenDom` when parsing finishes.  The
+//! frozen tree has no `Rc` or `RefCell`, and keeps its text as `String`
+//! rather than `StrTendril`, so it is `Send` and `Sync` and can be shared
+//! between threads.
+//!
+//! The nodes of the document are numbered in document order, so that the
+//! descendants of a node are the nodes that follow it up to the end of its
+//! subtree.  The contents of each `<template>` come after the document,
+//! in the order the templates appear, and have no parent.  Nodes that the
+//! tree builder removed from the tree are dropped.
+//!
+//! ```
+//! use html5ever::driver::parse_document;
+//! use html5ever::immutable::{ImmutableDom, NodeData};
+//! use html5ever::tendril::TendrilSink;
+//!
+//! let dom = parse_document(ImmutableDom::default(), Default::default())
+//!     .one("<p>a<b>b</b><p>c");
+//! let names: Vec<_> = dom
+//!     .descendants(dom.document())
+//!     .filter_map(|id| match dom[id].data {
+//!         NodeData::Element { ref name, .. } => Some(name.local.to_string()),
+//!         _ => None,
+//!     })
+//!     .collect();
+//! assert_eq!(names, ["html", "head", "body", "p", "b", "p"]);
+//!
+//! let dom = std::sync::Arc::new(dom);
+//! let shared = dom.clone();
+//! let text = std::thread::spawn(move || shared.text_contents(shared.document()))
+//!     .join()
+//!     .unwrap();
+//! assert_eq!(text, "abc");
+//! ```
+
+use crate::interface::{self, ExpandedName, QualName};
+use crate::tendril::StrTendril;
+use crate::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+
+use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
+use std::mem;
+use std::ops::Index;
+
+/// The index of a node in a `FrozenDom`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    /// The position of the node in the tree, in document order.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// An attribute of an element, with its value as a `String`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attribute {
+    /// The attribute's name.
+    pub name: QualName,
+
+    /// The attribute's value.
+    pub value: String,
+}
+
+/// The different kinds of nodes in the tree, as in `markup5ever_rcdom`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NodeData {
+    /// The document itself, or the contents of a `<template>`.
+    Document,
+
+    /// A `DOCTYPE` with name, public id, and system id.
+    Doctype {
+        name: String,
+        public_id: String,
+        system_id: String,
+    },
+
+    /// A text node.
+    Text { contents: String },
+
+    /// A comment.
+    Comment { contents: String },
+
+    /// An element with attributes.
+    Element {
+        name: QualName,
+        attrs: Vec<Attribute>,
+
+        /// For HTML `<template>` elements, the template contents.
+        template_contents: Option<NodeId>,
+
+        /// Whether the node is a HTML integration point.
+        mathml_annotation_xml_integration_point: bool,
+    },
+
+    /// A processing instruction.
+    ProcessingInstruction { target: String, contents: String },
+}
+
+/// A node of a `FrozenDom`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    /// The node's contents.
+    pub data: NodeData,
+
+    /// The parent node, None for the document and for template contents.
+    pub parent: Option<NodeId>,
+
+    /// The child nodes, in order.
+    pub children: Vec<NodeId>,
+
+    /// The index after the last descendant.
+    subtree_end: usize,
+}
+
+/// The read-only tree built by `ImmutableDom`.
+#[derive(Clone, Debug)]
+pub struct FrozenDom {
+    nodes: Vec<Node>,
+
+    /// Errors that occurred during parsing.
+    pub errors: Vec<Cow<'static, str>>,
+
+    /// The document's quirks mode.
+    pub quirks_mode: QuirksMode,
+}
+
+impl FrozenDom {
+    /// The document node, the root of the tree.
+    pub fn document(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// The number of nodes, including template contents.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether the tree has no nodes.  It always has the document.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Iterate over all of the nodes, in the order of their ids.
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (NodeId(index), node))
+    }
+
+    /// Iterate over the descendants of a node in document order, without
+    /// the node itself.  Template contents are not included.
+    pub fn descendants(&self, id: NodeId) -> impl Iterator<Item = NodeId> {
+        (id.0 + 1..self[id].subtree_end).map(NodeId)
+    }
+
+    /// Iterate over the ancestors of a node, from its parent up.
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self[id].parent, move |&parent| self[parent].parent)
+    }
+
+    /// Whether `descendant` is inside `ancestor`, within the same document
+    /// or template contents.
+    pub fn contains(&self, ancestor: NodeId, descendant: NodeId) -> bool {
+        ancestor < descendant && descendant.0 < self[ancestor].subtree_end
+    }
+
+    /// The text of the text nodes inside a node, in document order.
+    pub fn text_contents(&self, id: NodeId) -> String {
+        let mut text = String::new();
+        for node in self.descendants(id) {
+            if let NodeData::Text { ref contents } = self[node].data {
+                text.push_str(contents);
+            }
+        }
+        text
+    }
+}
+
+impl Index<NodeId> for FrozenDom {
+    type Output = Node;
+
+    fn index(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+}
+
+/// A node of an `ImmutableDom` that is still being built.
+struct BuildNode {
+    /// The node's contents, with the builder's indices for template
+    /// contents.
+    data: NodeData,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+/// A sink that builds a `FrozenDom`.
+pub struct ImmutableDom {
+    /// The nodes, indexed by handle.  The document is node 0.
+    nodes: Vec<BuildNode>,
+    errors: Vec<Cow<'static, str>>,
+    quirks_mode: QuirksMode,
+}
+
+impl Default for ImmutableDom {
+    fn default() -> ImmutableDom {
+        ImmutableDom {
+            nodes: vec![BuildNode {
+                data: NodeData::Document,
+                parent: None,
+                children: vec![],
+            }],
+            errors: vec![],
+            quirks_mode: QuirksMode::NoQuirks,
+        }
+    }
+}
+
+impl ImmutableDom {
+    fn new_node(&mut self, data: NodeData) -> usize {
+        self.nodes.push(BuildNode {
+            data,
+            parent: None,
+            children: vec![],
+        });
+        self.nodes.len() - 1
+    }
+
+    fn detach(&mut self, node: usize) {
+        if let Some(parent) = self.nodes[node].parent.take() {
+            self.nodes[parent].children.retain(|&child| child != node);
+        }
+    }
+
+    /// Insert `child` into `parent` at `index`, merging text with a text
+    /// node before it.
+    fn insert(&mut self, parent: usize, index: usize, child: NodeOrText<usize>) {
+        match child {
+            NodeOrText::AppendText(text) => self.insert_text(parent, index, &text),
+            NodeOrText::AppendNode(node) => {
+                self.nodes[node].parent = Some(parent);
+                self.nodes[parent].children.insert(index, node);
+            },
+        }
+    }
+
+    fn insert_text(&mut self, parent: usize, index: usize, text: &str) {
+        if let Some(&prev) = index
+            .checked_sub(1)
+            .and_then(|i| self.nodes[parent].children.get(i))
+        {
+            if let NodeData::Text { ref mut contents } = self.nodes[prev].data {
+                contents.push_str(text);
+                return;
+            }
+        }
+        let node = self.new_node(NodeData::Text {
+            contents: text.to_string(),
+        });
+        self.insert(parent, index, NodeOrText::AppendNode(node));
+    }
+
+    fn element_data(&mut self, target: usize) -> &mut NodeData {
+        let data = &mut self.nodes[target].data;
+        match *data {
+            NodeData::Element { .. } => data,
+            _ => panic!("not an element!"),
+        }
+    }
+
+    /// Number the nodes that are still in the tree and move them into a
+    /// `FrozenDom`.
+    fn freeze(self) -> FrozenDom {
+        let mut nodes: Vec<Option<BuildNode>> = self.nodes.into_iter().map(Some).collect();
+
+        // The builder's index of each node in the new order, and the new
+        // index of each node that is kept.
+        let mut order = vec![];
+        let mut ids = vec![None; nodes.len()];
+        let mut roots = VecDeque::new();
+        roots.push_back(0);
+        while let Some(root) = roots.pop_front() {
+            let mut stack = vec![root];
+            while let Some(node) = stack.pop() {
+                let node_ref = nodes[node].as_ref().expect("node numbered twice");
+                ids[node] = Some(NodeId(order.len()));
+                order.push(node);
+                if let NodeData::Element {
+                    template_contents: Some(contents),
+                    ..
+                } = node_ref.data
+                {
+                    roots.push_back(contents.0);
+                }
+                stack.extend(node_ref.children.iter().rev());
+            }
+        }
+
+        let id = |node: usize| ids[node].expect("node not in the tree");
+        let mut frozen: Vec<Node> = order
+            .iter()
+            .map(|&node| {
+                let BuildNode {
+                    mut data,
+                    parent,
+                    children,
+                } = nodes[node].take().expect("node moved twice");
+                if let NodeData::Element {
+                    ref mut template_contents,
+                    ..
+                } = data
+                {
+                    *template_contents = template_contents.map(|contents| id(contents.0));
+                }
+                Node {
+                    data,
+                    parent: parent.map(id),
+                    children: children.into_iter().map(id).collect(),
+                    subtree_end: 0,
+                }
+            })
+            .collect();
+
+        // Children come after their parent, so each subtree's end is known
+        // by the time its root is reached.
+        for index in (0..frozen.len()).rev() {
+            let end = match frozen[index].children.last() {
+                Some(&last) => frozen[last.0].subtree_end,
+                None => index + 1,
+            };
+            frozen[index].subtree_end = end;
+        }
+
+        FrozenDom {
+            nodes: frozen,
+            errors: self.errors,
+            quirks_mode: self.quirks_mode,
+        }
+    }
+}
+
+fn to_string(tendril: StrTendril) -> String {
+    String::from(&*tendril)
+}
+
+fn convert_attrs(attrs: Vec<interface::Attribute>) -> Vec<Attribute> {
+    attrs
+        .into_iter()
+        .map(|attr| Attribute {
+            name: attr.name,
+            value: attr.value.to_string(),
+        })
+        .collect()
+}
+
+impl TreeSink for ImmutableDom {
+    type Handle = usize;
+    type Output = FrozenDom;
+
+    fn finish(self) -> FrozenDom {
+        self.freeze()
+    }
+
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.errors.push(msg);
+    }
+
+    fn get_document(&mut self) -> usize {
+        0
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a usize) -> ExpandedName<'a> {
+        match self.nodes[*target].data {
+            NodeData::Element { ref name, .. } => name.expanded(),
+            _ => panic!("not an element!"),
+        }
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<interface::Attribute>,
+        flags: ElementFlags,
+    ) -> usize {
+        let template_contents = if flags.template {
+            Some(NodeId(self.new_node(NodeData::Document)))
+        } else {
+            None
+        };
+        self.new_node(NodeData::Element {
+            name,
+            attrs: convert_attrs(attrs),
+            template_contents,
+            mathml_annotation_xml_integration_point: flags.mathml_annotation_xml_integration_point,
+        })
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> usize {
+        self.new_node(NodeData::Comment {
+            contents: to_string(text),
+        })
+    }
+
+    fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> usize {
+        self.new_node(NodeData::ProcessingInstruction {
+            target: to_string(target),
+            contents: to_string(data),
+        })
+    }
+
+    fn append(&mut self, parent: &usize, child: NodeOrText<usize>) {
+        if let NodeOrText::AppendNode(node) = child {
+            self.detach(node);
+        }
+        let index = self.nodes[*parent].children.len();
+        self.insert(*parent, index, child);
+    }
+
+    fn wants_borrowed_text(&self) -> bool {
+        true
+    }
+
+    fn push_text(&mut self, parent: &usize, text: &str) {
+        let index = self.nodes[*parent].children.len();
+        self.insert_text(*parent, index, text);
+    }
+
+    fn append_before_sibling(&mut self, sibling: &usize, child: NodeOrText<usize>) {
+        if let NodeOrText::AppendNode(node) = child {
+            self.detach(node);
+        }
+        let parent = self.nodes[*sibling]
+            .parent
+            .expect("append_before_sibling called on node without parent");
+        let index = self.nodes[parent]
+            .children
+            .iter()
+            .position(|&child| child == *sibling)
+            .expect("have parent but couldn't find in parent's children!");
+        self.insert(parent, index, child);
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &usize,
+        prev_element: &usize,
+        child: NodeOrText<usize>,
+    ) {
+        if self.nodes[*element].parent.is_some() {
+            self.append_before_sibling(element, child);
+        } else {
+            self.append(prev_element, child);
+        }
+    }
+
+    fn append_doctype_to_document(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        let doctype = self.new_node(NodeData::Doctype {
+            name: to_string(name),
+            public_id: to_string(public_id),
+            system_id: to_string(system_id),
+        });
+        self.append(&0, NodeOrText::AppendNode(doctype));
+    }
+
+    fn get_template_contents(&mut self, target: &usize) -> usize {
+        match *self.element_data(*target) {
+            NodeData::Element {
+                template_contents: Some(contents),
+                ..
+            } => contents.0,
+            _ => panic!("not a template element!"),
+        }
+    }
+
+    fn same_node(&self, x: &usize, y: &usize) -> bool {
+        x == y
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.quirks_mode = mode;
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &usize, attrs: Vec<interface::Attribute>) {
+        if let NodeData::Element {
+            attrs: ref mut existing,
+            ..
+        } = *self.element_data(*target)
+        {
+            let existing_names = existing
+                .iter()
+                .map(|attr| attr.name.clone())
+                .collect::<HashSet<_>>();
+            existing.extend(
+                convert_attrs(attrs)
+                    .into_iter()
+                    .filter(|attr| !existing_names.contains(&attr.name)),
+            );
+        }
+    }
+
+    fn remove_from_parent(&mut self, target: &usize) {
+        self.detach(*target);
+    }
+
+    fn reparent_children(&mut self, node: &usize, new_parent: &usize) {
+        let children = mem::take(&mut self.nodes[*node].children);
+        for &child in &children {
+            self.nodes[child].parent = Some(*new_parent);
+        }
+        self.nodes[*new_parent].children.extend(children);
+    }
+
+    fn is_mathml_annotation_xml_integration_point(&self, target: &usize) -> bool {
+        match self.nodes[*target].data {
+            NodeData::Element {
+                mathml_annotation_xml_integration_point,
+                ..
+            } => mathml_annotation_xml_integration_point,
+            _ => panic!("not an element!"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FrozenDom, ImmutableDom, NodeData, NodeId};
+    use crate::driver::parse_document;
+    use crate::tendril::TendrilSink;
+
+    fn parse(input: &str) -> FrozenDom {
+        parse_document(ImmutableDom::default(), Default::default()).one(input)
+    }
+
+    fn describe(dom: &FrozenDom, id: NodeId) -> String {
+        match dom[id].data {
+            NodeData::Document => "#document".to_string(),
+            NodeData::Doctype { ref name, .. } => format!("!{}", name),
+            NodeData::Text { ref contents } => format!("\"{}\"", contents),
+            NodeData::Comment { ref contents } => format!("<!--{}-->", contents),
+            NodeData::Element { ref name, .. } => name.local.to_string(),
+            NodeData::ProcessingInstruction { ref target, .. } => format!("?{}", target),
+        }
+    }
+
+    fn find(dom: &FrozenDom, name: &str) -> NodeId {
+        dom.nodes()
+            .map(|(id, _)| id)
+            .find(|&id| describe(dom, id) == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<FrozenDom>();
+    }
+
+    #[test]
+    fn document_order() {
+        let dom = parse("<!DOCTYPE html><p>a<b>b</b>c</p><!--d--><template>e<i>f</i></template>");
+        let all: Vec<_> = dom.nodes().map(|(id, _)| describe(&dom, id)).collect();
+        assert_eq!(
+            all,
+            [
+                "#document",
+                "!html",
+                "html",
+                "head",
+                "body",
+                "p",
+                "\"a\"",
+                "b",
+                "\"b\"",
+                "\"c\"",
+                "<!--d-->",
+                "template",
+                // The template contents, after the document.
+                "#document",
+                "\"e\"",
+                "i",
+                "\"f\"",
+            ]
+        );
+
+        let p = find(&dom, "p");
+        let descendants: Vec<_> = dom.descendants(p).map(|id| describe(&dom, id)).collect();
+        assert_eq!(descendants, ["\"a\"", "b", "\"b\"", "\"c\""]);
+        assert_eq!(dom.text_contents(dom.document()), "abc");
+
+        let template = find(&dom, "template");
+        assert_eq!(dom.descendants(template).count(), 0);
+        match dom[template].data {
+            NodeData::Element {
+                template_contents: Some(contents),
+                ..
+            } => {
+                assert_eq!(dom[contents].parent, None);
+                assert_eq!(dom.text_contents(contents), "ef");
+            },
+            _ => panic!("no template contents"),
+        }
+    }
+
+    #[test]
+    fn ancestors() {
+        let dom = parse("<table><tr><td>a</table>x");
+        let td = find(&dom, "td");
+        let ancestors: Vec<_> = dom.ancestors(td).map(|id| describe(&dom, id)).collect();
+        assert_eq!(
+            ancestors,
+            ["tr", "tbody", "table", "body", "html", "#document"]
+        );
+        assert!(dom.contains(dom.document(), td));
+        assert!(!dom.contains(td, dom.document()));
+        assert!(!dom.contains(td, find(&dom, "\"x\"")));
+    }
+
+    #[test]
+    fn removed_nodes() {
+        // The <frameset> replaces the <body>, which is dropped with the
+        // <div> in it.
+        let dom = parse("<div></div><frameset><frame>");
+        let all: Vec<_> = dom.nodes().map(|(id, _)| describe(&dom, id)).collect();
+        assert_eq!(all, ["#document", "html", "head", "frameset", "frame"]);
+    }
+}
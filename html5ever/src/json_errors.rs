@@ -0,0 +1,457 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Log parse errors as newline-delimited JSON.
+
+use crate::interface::{AmbiguousConstruct, NextParserState, ParseStats};
+use crate::tendril::StrTendril;
+use crate::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use crate::{Attribute, ExpandedName, QualName};
+
+use serde_json::json;
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+/// A `TreeSink` that writes a line of JSON for each parse error and
+/// ambiguous construct, then passes it on to another sink.
+///
+/// Each line is an object with these fields:
+///
+/// * `kind`: `"parse-error"` or `"ambiguous-construct"`.
+/// * `severity`: `"error"` for parse errors, `"warning"` for ambiguous
+///   constructs that are not parse errors by themselves.
+/// * `line`: the line the parser was on, starting at 1.
+/// * `message`: for parse errors, their description.
+/// * `construct`: for ambiguous constructs, the name of the
+///   `AmbiguousConstruct` variant.
+///
+/// Ambiguous constructs are only reported with the tokenizer's
+/// `flag_ambiguous_constructs` option, and each of them usually comes with
+/// a parse error of its own.  Errors are only as detailed as the parser's
+/// options make them; set `exact_errors` for the spec's full set.
+///
+/// ```
+/// use html5ever::driver::{parse_document, ParseOpts};
+/// use html5ever::immutable::ImmutableDom;
+/// use html5ever::json_errors::JsonErrorSink;
+/// use html5ever::tendril::TendrilSink;
+///
+/// let sink = JsonErrorSink::new(ImmutableDom::default(), Vec::new());
+/// let (_, log) = parse_document(sink, ParseOpts::default()).one("<p>\n</div>");
+/// let log = String::from_utf8(log.unwrap()).unwrap();
+/// assert!(log.lines().any(|line| line.contains(r#""line":2"#)));
+/// ```
+pub struct JsonErrorSink<Sink, W> {
+    sink: Sink,
+    writer: W,
+    line: u64,
+    result: io::Result<()>,
+}
+
+impl<Sink: TreeSink, W: Write> JsonErrorSink<Sink, W> {
+    /// Log to `writer` while passing everything on to `sink`.
+    ///
+    /// The output of the parse is the output of `sink` and the writer, or
+    /// the first error writing to it.  Nothing more is written after an
+    /// error.
+    pub fn new(sink: Sink, writer: W) -> Self {
+        JsonErrorSink {
+            sink,
+            writer,
+            line: 1,
+            result: Ok(()),
+        }
+    }
+
+    fn write(&mut self, value: serde_json::Value) {
+        if self.result.is_ok() {
+            self.result = serde_json::to_writer(&mut self.writer, &value)
+                .map_err(io::Error::from)
+                .and_then(|()| self.writer.write_all(b"\n"));
+        }
+    }
+}
+
+impl<Sink: TreeSink, W: Write> TreeSink for JsonErrorSink<Sink, W> {
+    type Handle = Sink::Handle;
+    type Output = (Sink::Output, io::Result<W>);
+
+    fn finish(self) -> Self::Output {
+        let writer = self.writer;
+        (self.sink.finish(), self.result.map(|()| writer))
+    }
+
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.write(json!({
+            "kind": "parse-error",
+            "severity": "error",
+            "line": self.line,
+            "message": &*msg,
+        }));
+        self.sink.parse_error(msg);
+    }
+
+    fn ambiguous_construct(&mut self, construct: AmbiguousConstruct, line_number: u64) {
+        self.write(json!({
+            "kind": "ambiguous-construct",
+            "severity": "warning",
+            "line": line_number,
+            "construct": format!("{:?}", construct),
+        }));
+        self.sink.ambiguous_construct(construct, line_number);
+    }
+
+    fn duplicate_attribute(&mut self, first: &str, duplicate: &str, line_number: u64) {
+        self.sink.duplicate_attribute(first, duplicate, line_number)
+    }
+
+    fn set_current_line(&mut self, line_number: u64) {
+        self.line = line_number;
+        self.sink.set_current_line(line_number);
+    }
+
+    fn should_continue(&self) -> bool {
+        self.sink.should_continue()
+    }
+
+    fn misnested_tags(&mut self, expected: &QualName, found: &QualName, expected_pos: Option<u64>) {
+        self.sink.misnested_tags(expected, found, expected_pos)
+    }
+
+    fn get_document(&mut self) -> Self::Handle {
+        self.sink.get_document()
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a Self::Handle) -> ExpandedName<'a> {
+        self.sink.elem_name(target)
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Self::Handle {
+        self.sink.create_element(name, attrs, flags)
+    }
+
+    fn create_element_raw_attrs(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Self::Handle {
+        self.sink.create_element_raw_attrs(name, attrs, flags)
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> Self::Handle {
+        self.sink.create_comment(text)
+    }
+
+    fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Self::Handle {
+        self.sink.create_pi(target, data)
+    }
+
+    fn append(&mut self, parent: &Self::Handle, child: NodeOrText<Self::Handle>) {
+        self.sink.append(parent, child)
+    }
+
+    fn append_text_with_flags(
+        &mut self,
+        parent: &Self::Handle,
+        text: StrTendril,
+        preserve_whitespace: bool,
+    ) {
+        self.sink
+            .append_text_with_flags(parent, text, preserve_whitespace)
+    }
+
+    fn wants_borrowed_text(&self) -> bool {
+        self.sink.wants_borrowed_text()
+    }
+
+    fn push_text(&mut self, parent: &Self::Handle, text: &str) {
+        self.sink.push_text(parent, text)
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &Self::Handle,
+        prev_element: &Self::Handle,
+        child: NodeOrText<Self::Handle>,
+    ) {
+        self.sink
+            .append_based_on_parent_node(element, prev_element, child)
+    }
+
+    fn foster_parent_text_with_flags(
+        &mut self,
+        element: &Self::Handle,
+        prev_element: &Self::Handle,
+        text: StrTendril,
+        preserve_whitespace: bool,
+    ) {
+        self.sink
+            .foster_parent_text_with_flags(element, prev_element, text, preserve_whitespace)
+    }
+
+    fn append_doctype_to_document(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.sink
+            .append_doctype_to_document(name, public_id, system_id)
+    }
+
+    fn append_doctype_full(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+        force_quirks: bool,
+    ) {
+        self.sink
+            .append_doctype_full(name, public_id, system_id, force_quirks)
+    }
+
+    fn append_doctype_raw(
+        &mut self,
+        raw: StrTendril,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.sink
+            .append_doctype_raw(raw, name, public_id, system_id)
+    }
+
+    fn obsolete_element(&mut self, name: &QualName) {
+        self.sink.obsolete_element(name)
+    }
+
+    fn select_content_dropped(&mut self, name: &QualName) {
+        self.sink.select_content_dropped(name)
+    }
+
+    fn stray_template_close(&mut self) {
+        self.sink.stray_template_close()
+    }
+
+    fn tag_rewritten(&mut self, from: &QualName, to: &QualName) {
+        self.sink.tag_rewritten(from, to)
+    }
+
+    fn unacknowledged_self_closing(&mut self, name: &QualName) {
+        self.sink.unacknowledged_self_closing(name)
+    }
+
+    fn leading_text_relocated(&mut self, text: StrTendril) {
+        self.sink.leading_text_relocated(text)
+    }
+
+    fn leading_newline_stripped(&mut self, element: &Self::Handle) {
+        self.sink.leading_newline_stripped(element)
+    }
+
+    fn misplaced_doctype(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.sink.misplaced_doctype(name, public_id, system_id)
+    }
+
+    fn mark_script_already_started(&mut self, node: &Self::Handle) {
+        self.sink.mark_script_already_started(node)
+    }
+
+    fn element_finalized(&mut self, element: &Self::Handle) {
+        self.sink.element_finalized(element)
+    }
+
+    fn element_synthesized(&mut self, element: &Self::Handle, name: &QualName) {
+        self.sink.element_synthesized(element, name)
+    }
+
+    fn element_cloned(&mut self, original: &Self::Handle, clone: &Self::Handle) {
+        self.sink.element_cloned(original, clone)
+    }
+
+    fn head_opened(&mut self, head: &Self::Handle) {
+        self.sink.head_opened(head)
+    }
+
+    fn head_closed(&mut self, head: &Self::Handle) {
+        self.sink.head_closed(head)
+    }
+
+    fn parse_stats(&mut self, stats: ParseStats) {
+        self.sink.parse_stats(stats)
+    }
+
+    fn eof_state(&mut self, clean: bool, open_elements: usize) {
+        self.sink.eof_state(clean, open_elements)
+    }
+
+    fn pop(&mut self, node: &Self::Handle) {
+        self.sink.pop(node)
+    }
+
+    fn element_closed(&mut self, node: &Self::Handle) {
+        self.sink.element_closed(node)
+    }
+
+    fn get_template_contents(&mut self, target: &Self::Handle) -> Self::Handle {
+        self.sink.get_template_contents(target)
+    }
+
+    fn same_node(&self, x: &Self::Handle, y: &Self::Handle) -> bool {
+        self.sink.same_node(x, y)
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.sink.set_quirks_mode(mode)
+    }
+
+    fn quirks_behavior_applied(&mut self, description: &'static str) {
+        self.sink.quirks_behavior_applied(description)
+    }
+
+    fn frameset_ok_changed(&mut self, ok: bool, reason: &'static str) {
+        self.sink.frameset_ok_changed(ok, reason)
+    }
+
+    fn set_document_metadata(&mut self, lang: Option<StrTendril>, dir: Option<StrTendril>) {
+        self.sink.set_document_metadata(lang, dir)
+    }
+
+    fn append_before_sibling(
+        &mut self,
+        sibling: &Self::Handle,
+        new_node: NodeOrText<Self::Handle>,
+    ) {
+        self.sink.append_before_sibling(sibling, new_node)
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &Self::Handle, attrs: Vec<Attribute>) {
+        self.sink.add_attrs_if_missing(target, attrs)
+    }
+
+    fn merge_attributes(
+        &mut self,
+        target: &Self::Handle,
+        source_tag_name: &QualName,
+        merged: &[Attribute],
+    ) {
+        self.sink.merge_attributes(target, source_tag_name, merged)
+    }
+
+    fn associate_with_form(
+        &mut self,
+        target: &Self::Handle,
+        form: &Self::Handle,
+        nodes: (&Self::Handle, Option<&Self::Handle>),
+    ) {
+        self.sink.associate_with_form(target, form, nodes)
+    }
+
+    fn remove_from_parent(&mut self, target: &Self::Handle) {
+        self.sink.remove_from_parent(target)
+    }
+
+    fn reparent_children(&mut self, node: &Self::Handle, new_parent: &Self::Handle) {
+        self.sink.reparent_children(node, new_parent)
+    }
+
+    fn is_mathml_annotation_xml_integration_point(&self, handle: &Self::Handle) -> bool {
+        self.sink.is_mathml_annotation_xml_integration_point(handle)
+    }
+
+    fn complete_script(&mut self, node: &Self::Handle) -> NextParserState {
+        self.sink.complete_script(node)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::JsonErrorSink;
+    use crate::driver::{parse_document, ParseOpts};
+    use crate::immutable::ImmutableDom;
+    use crate::tendril::TendrilSink;
+    use serde_json::{json, Value};
+
+    fn log(input: &str, opts: ParseOpts) -> Vec<Value> {
+        let sink = JsonErrorSink::new(ImmutableDom::default(), Vec::new());
+        let (_, log) = parse_document(sink, opts).one(input);
+        String::from_utf8(log.unwrap())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parse_errors() {
+        let log = log("<!DOCTYPE html>\n<p>\n</div>", ParseOpts::default());
+        assert_eq!(
+            log,
+            vec![json!({
+                "kind": "parse-error",
+                "severity": "error",
+                "line": 3,
+                "message": "Unexpected token",
+            })]
+        );
+    }
+
+    #[test]
+    fn ambiguous_constructs() {
+        let opts = ParseOpts::builder().flag_ambiguous_constructs(true).build();
+        let log = log("<!DOCTYPE html>\n<div/></div>", opts);
+        assert_eq!(
+            log,
+            vec![
+                json!({
+                    "kind": "ambiguous-construct",
+                    "severity": "warning",
+                    "line": 2,
+                    "construct": "SelfClosingNonVoidElement",
+                }),
+                json!({
+                    "kind": "parse-error",
+                    "severity": "error",
+                    "line": 2,
+                    "message": "Unacknowledged self-closing tag",
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn write_error() {
+        struct Full;
+
+        impl std::io::Write for Full {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::WriteZero.into())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let sink = JsonErrorSink::new(ImmutableDom::default(), Full);
+        let (_, result) = parse_document(sink, ParseOpts::default()).one("</div>");
+        assert!(result.is_err());
+    }
+}
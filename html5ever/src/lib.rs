@@ -12,7 +12,11 @@
 #![cfg_attr(test, deny(warnings))]
 #![allow(unused_parens)]
 
-pub use driver::{parse_document, parse_fragment, ParseOpts, Parser};
+#[cfg(feature = "async")]
+pub use driver::parse_document_async;
+pub use driver::{parse_document, parse_fragment, parse_head_only};
+pub use driver::{parse_fragment_keep_context, parse_mathml_fragment, parse_svg_fragment};
+pub use driver::{ParseOpts, ParseOptsBuilder, Parser};
 pub use markup5ever::*;
 
 pub use serialize::serialize;
@@ -21,10 +25,22 @@ pub use serialize::serialize;
 mod macros;
 
 mod util {
+    pub mod bytes;
+    pub mod names;
     pub mod str;
 }
 
+pub mod collect;
+pub mod conformance;
 pub mod driver;
+pub mod frequencies;
+pub mod immutable;
+#[cfg(feature = "serde_json")]
+pub mod json_errors;
+pub mod links;
+pub mod mixed_content;
+pub mod select;
 pub mod serialize;
+pub mod tee;
 pub mod tokenizer;
 pub mod tree_builder;
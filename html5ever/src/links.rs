@@ -0,0 +1,360 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Extracting the links in a document without building a tree.
+//!
+//! Links are taken from start tags as they pass from the tokenizer to the
+//! tree builder, so each tag in the source gives its links once, in
+//! document order, even where the tree builder clones an element.  The
+//! tree builder still runs, so text in `<script>`, `<textarea>` and the
+//! like is not mistaken for markup.
+//!
+//! ```
+//! use html5ever::links::extract_links;
+//!
+//! let html = b"<link rel=icon href=/icon.png><p>See <a href='/docs'>the docs</a>.";
+//! let links: Vec<_> = extract_links(html)
+//!     .into_iter()
+//!     .map(|link| (link.element.to_string(), link.url.to_string(), link.text.to_string()))
+//!     .collect();
+//! assert_eq!(
+//!     links,
+//!     vec![
+//!         ("link".to_string(), "/icon.png".to_string(), "".to_string()),
+//!         ("a".to_string(), "/docs".to_string(), "the docs".to_string()),
+//!     ]
+//! );
+//! ```
+
+use crate::driver::parse_document_with_transform;
+use crate::interface::{Attribute, ExpandedName, QualName};
+use crate::tendril::StrTendril;
+use crate::tokenizer::{StartTag, Token, TokenTransform};
+use crate::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use crate::util::bytes::parse_bytes;
+use crate::util::names::Names;
+use crate::LocalName;
+use markup5ever::{local_name, namespace_url, ns};
+
+use std::borrow::Cow;
+
+/// A URL found by `extract_links`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    /// The element's name, like `a` or `img`.
+    pub element: LocalName,
+
+    /// The attribute holding the URL, like `href` or `src`.
+    pub attr: LocalName,
+
+    /// The URL, with character references replaced and leading and trailing
+    /// ASCII whitespace removed.  It is not resolved: relative URLs are
+    /// relative to the document, or to its `<base href>`, which is reported
+    /// as a link too by default.
+    pub url: StrTendril,
+
+    /// All of the element's attributes, in source order.
+    pub attrs: Vec<Attribute>,
+
+    /// For an `<a>`, the text between its start tag and the `</a>` or next
+    /// `<a>` that closes it, as written.  Empty for other elements.
+    pub text: StrTendril,
+}
+
+/// Options for `extract_links_with_opts`, with an impl for Default.
+#[derive(Clone, Debug)]
+pub struct LinkOpts {
+    /// The attributes that hold links, as pairs of an element name and an
+    /// attribute name, both lowercase.  An element gives a `Link` for each
+    /// pair that it has the attribute for, in this order.  Default: `href`
+    /// on `a`, `area`, `base` and `link`, and `src` on `iframe`, `img` and
+    /// `script`
+    pub attributes: Vec<(LocalName, LocalName)>,
+}
+
+impl Default for LinkOpts {
+    fn default() -> LinkOpts {
+        LinkOpts {
+            attributes: vec![
+                (local_name!("a"), local_name!("href")),
+                (local_name!("area"), local_name!("href")),
+                (local_name!("base"), local_name!("href")),
+                (local_name!("iframe"), local_name!("src")),
+                (local_name!("img"), local_name!("src")),
+                (local_name!("link"), local_name!("href")),
+                (local_name!("script"), local_name!("src")),
+            ],
+        }
+    }
+}
+
+/// Parse `input` and return its links in document order, using the default
+/// `LinkOpts`.
+pub fn extract_links(input: &[u8]) -> Vec<Link> {
+    extract_links_with_opts(input, &LinkOpts::default())
+}
+
+/// Parse `input` and return the links that `opts` asks for, in document
+/// order.
+///
+/// The encoding is taken from a byte order mark, for UTF-8 or UTF-16.
+/// Without one the input is decoded as UTF-8, since html5ever has no
+/// decoders for legacy encodings.  Invalid sequences are replaced.
+pub fn extract_links_with_opts(input: &[u8], opts: &LinkOpts) -> Vec<Link> {
+    let mut collector = Collector {
+        attributes: &opts.attributes,
+        links: vec![],
+        anchor: None,
+    };
+    let tree = Tree {
+        names: Names::new(),
+    };
+    let parser = parse_document_with_transform(tree, Default::default(), &mut collector);
+    parse_bytes(parser, input);
+    collector.links
+}
+
+/// Records the links of each start tag, and passes every token on.
+struct Collector<'a> {
+    attributes: &'a [(LocalName, LocalName)],
+    links: Vec<Link>,
+    /// The links of the open `<a>`, which collect its text.
+    anchor: Option<(usize, usize)>,
+}
+
+impl TokenTransform for &mut Collector<'_> {
+    fn transform(&mut self, token: Token, output: &mut Vec<Token>) {
+        match token {
+            Token::TagToken(ref tag) => {
+                if tag.name == local_name!("a") {
+                    self.anchor = None;
+                }
+                if tag.kind == StartTag {
+                    let start = self.links.len();
+                    for (element, attr) in self.attributes {
+                        if *element != tag.name {
+                            continue;
+                        }
+                        let value = tag
+                            .attrs
+                            .iter()
+                            .find(|a| a.name.ns == ns!() && a.name.local == *attr);
+                        if let Some(value) = value {
+                            let url = value.value.trim_matches(|c: char| c.is_ascii_whitespace());
+                            self.links.push(Link {
+                                element: tag.name.clone(),
+                                attr: attr.clone(),
+                                url: StrTendril::from_slice(url),
+                                attrs: tag.attrs.clone(),
+                                text: StrTendril::new(),
+                            });
+                        }
+                    }
+                    if tag.name == local_name!("a") && self.links.len() > start {
+                        self.anchor = Some((start, self.links.len()));
+                    }
+                }
+            },
+            Token::CharacterTokens(ref text) => {
+                if let Some((start, end)) = self.anchor {
+                    for link in &mut self.links[start..end] {
+                        link.text.push_tendril(text);
+                    }
+                }
+            },
+            _ => (),
+        }
+        output.push(token);
+    }
+}
+
+/// A sink that keeps only the names of open elements, for the tree
+/// builder's queries.
+struct Tree {
+    names: Names<()>,
+}
+
+impl TreeSink for Tree {
+    type Handle = usize;
+    type Output = ();
+
+    fn finish(self) {}
+
+    fn parse_error(&mut self, _msg: Cow<'static, str>) {}
+
+    fn get_document(&mut self) -> usize {
+        0
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a usize) -> ExpandedName<'a> {
+        self.names.elem_name(*target)
+    }
+
+    fn create_element(&mut self, name: QualName, _: Vec<Attribute>, flags: ElementFlags) -> usize {
+        self.names.create_element(name, &flags, ())
+    }
+
+    fn create_comment(&mut self, _text: StrTendril) -> usize {
+        self.names.new_id()
+    }
+
+    fn create_pi(&mut self, _target: StrTendril, _data: StrTendril) -> usize {
+        self.names.new_id()
+    }
+
+    fn append(&mut self, _parent: &usize, _child: NodeOrText<usize>) {}
+
+    fn append_before_sibling(&mut self, _sibling: &usize, _new_node: NodeOrText<usize>) {}
+
+    fn append_based_on_parent_node(
+        &mut self,
+        _element: &usize,
+        _prev_element: &usize,
+        _new_node: NodeOrText<usize>,
+    ) {
+    }
+
+    fn append_doctype_to_document(&mut self, _: StrTendril, _: StrTendril, _: StrTendril) {}
+
+    fn element_closed(&mut self, node: &usize) {
+        self.names.close(*node);
+    }
+
+    fn get_template_contents(&mut self, target: &usize) -> usize {
+        target + 1
+    }
+
+    fn same_node(&self, x: &usize, y: &usize) -> bool {
+        x == y
+    }
+
+    fn set_quirks_mode(&mut self, _mode: QuirksMode) {}
+
+    fn add_attrs_if_missing(&mut self, _target: &usize, _attrs: Vec<Attribute>) {}
+
+    fn remove_from_parent(&mut self, _target: &usize) {}
+
+    fn reparent_children(&mut self, _node: &usize, _new_parent: &usize) {}
+
+    fn is_mathml_annotation_xml_integration_point(&self, handle: &usize) -> bool {
+        self.names.is_integration_point(*handle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{extract_links, extract_links_with_opts, LinkOpts};
+    use markup5ever::local_name;
+
+    fn links(input: &[u8]) -> Vec<(String, String, String, String)> {
+        extract_links(input)
+            .into_iter()
+            .map(|link| {
+                (
+                    link.element.to_string(),
+                    link.attr.to_string(),
+                    link.url.to_string(),
+                    link.text.to_string(),
+                )
+            })
+            .collect()
+    }
+
+    fn link(element: &str, attr: &str, url: &str, text: &str) -> (String, String, String, String) {
+        (element.into(), attr.into(), url.into(), text.into())
+    }
+
+    #[test]
+    fn document_order() {
+        let html = b"<!DOCTYPE html><base href='http://example.com/dir/'>\
+                     <script src=a.js></script><img src=' b.png\n'>\
+                     <a href=c?x=1&amp;y=2>one <b>two</b></a> <area href=d>\
+                     <iframe src=e></iframe><a name=top>no link</a><img alt=no-src>";
+        assert_eq!(
+            links(html),
+            vec![
+                link("base", "href", "http://example.com/dir/", ""),
+                link("script", "src", "a.js", ""),
+                link("img", "src", "b.png", ""),
+                link("a", "href", "c?x=1&y=2", "one two"),
+                link("area", "href", "d", ""),
+                link("iframe", "src", "e", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn anchor_text() {
+        // The adoption agency algorithm clones the <a>, but it is one link.
+        assert_eq!(
+            links(b"<p><a href=x>1</p>2<a href=y>3<a href=z>4</a>5"),
+            vec![
+                link("a", "href", "x", "12"),
+                link("a", "href", "y", "3"),
+                link("a", "href", "z", "4"),
+            ]
+        );
+        // Markup in raw text is not parsed.
+        assert_eq!(
+            links(b"<a href=x><textarea><a href=y></textarea></a><script>'<img src=z>'</script>"),
+            vec![link("a", "href", "x", "<a href=y>")]
+        );
+    }
+
+    #[test]
+    fn sniffing() {
+        let expected = vec![link("a", "href", "\u{e9}", "\u{e9}")];
+        assert_eq!(links("<a href=\u{e9}>\u{e9}</a>".as_bytes()), expected);
+        assert_eq!(
+            links(b"\xEF\xBB\xBF<a href=\xC3\xA9>\xC3\xA9</a>"),
+            expected
+        );
+        let utf16: Vec<u16> = "<a href=\u{e9}>\u{e9}</a>".encode_utf16().collect();
+        let mut be = vec![0xFE, 0xFF];
+        let mut le = vec![0xFF, 0xFE];
+        for unit in utf16 {
+            be.extend_from_slice(&unit.to_be_bytes());
+            le.extend_from_slice(&unit.to_le_bytes());
+        }
+        assert_eq!(links(&be), expected);
+        assert_eq!(links(&le), expected);
+    }
+
+    #[test]
+    fn opts() {
+        let opts = LinkOpts {
+            attributes: vec![
+                (local_name!("img"), local_name!("longdesc")),
+                (local_name!("img"), local_name!("src")),
+                (local_name!("video"), local_name!("poster")),
+            ],
+        };
+        let found: Vec<_> = extract_links_with_opts(
+            b"<a href=x>x</a><img src=y longdesc=z><video poster=w>",
+            &opts,
+        )
+        .into_iter()
+        .map(|link| {
+            (
+                link.attr.to_string(),
+                link.url.to_string(),
+                link.attrs.len(),
+            )
+        })
+        .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("longdesc".into(), "z".into(), 2),
+                ("src".into(), "y".into(), 2),
+                ("poster".into(), "w".into(), 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,419 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Finding resources that an `https:` page would load over plain `http:`.
+
+use crate::interface::{AmbiguousConstruct, NextParserState, ParseStats};
+use crate::tendril::StrTendril;
+use crate::tree_builder::{url_scheme, ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use crate::{Attribute, ExpandedName, QualName};
+use markup5ever::{expanded_name, local_name, namespace_url, ns};
+
+use std::borrow::Cow;
+
+/// A URL found by `MixedContentSink`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MixedContent {
+    /// The element's name, like `img` or `script`.
+    pub element: QualName,
+
+    /// The attribute holding the URL, `src` or `href`.
+    pub attr: QualName,
+
+    /// The URL, as the tree builder gave it to the sink.
+    pub url: StrTendril,
+}
+
+/// A `TreeSink` that passes everything on to another sink, and collects the
+/// `src` and `href` attributes that would load a resource over `http:` into
+/// a page served over `https:`.
+///
+/// Every `src` attribute is checked, as is every `href` except on `<a>`
+/// and `<area>`, which are followed rather than loaded, and on `<base>`.
+/// Only the scheme of each URL is needed, so URLs are not resolved in
+/// full: one without a scheme, like `/x.png` or `//cdn.example/x.png`,
+/// takes the scheme of the document's URL.  The first `<base href>` with
+/// a scheme replaces that for the elements after it, though a browser
+/// would also apply it to those before.  If the document's URL is not
+/// `https:` nothing is collected.
+///
+/// The output of the parse is the output of the wrapped sink and the URLs
+/// found, in the order their elements were created.
+///
+/// ```
+/// use html5ever::driver::{parse_document, ParseOpts};
+/// use html5ever::immutable::ImmutableDom;
+/// use html5ever::mixed_content::MixedContentSink;
+/// use html5ever::tendril::TendrilSink;
+///
+/// let sink = MixedContentSink::new(ImmutableDom::default(), "https://example.com/").unwrap();
+/// let html = "<img src=/logo.png><script src='http://cdn.example/app.js'></script>";
+/// let (_, found) = parse_document(sink, ParseOpts::default()).one(html);
+/// assert_eq!(found.len(), 1);
+/// assert_eq!(&*found[0].element.local, "script");
+/// assert_eq!(&*found[0].url, "http://cdn.example/app.js");
+/// ```
+pub struct MixedContentSink<Sink> {
+    sink: Sink,
+    /// Whether the document's URL is `https:`.
+    secure: bool,
+    /// The scheme that URLs without one take.
+    base_scheme: String,
+    /// Whether the first `<base href>` with a scheme has been seen.
+    base_seen: bool,
+    found: Vec<MixedContent>,
+}
+
+impl<Sink: TreeSink> MixedContentSink<Sink> {
+    /// Pass everything on to `sink`, checking URLs against the document's
+    /// URL, `base_url`.  Returns None if `base_url` has no scheme.
+    pub fn new(sink: Sink, base_url: &str) -> Option<Self> {
+        let scheme = url_scheme(base_url)?;
+        Some(MixedContentSink {
+            sink,
+            secure: scheme == "https",
+            base_scheme: scheme,
+            base_seen: false,
+            found: vec![],
+        })
+    }
+
+    /// Collect the insecure URLs in the attributes of a new element.  As
+    /// in the DOM, only the first attribute with each name counts.
+    fn check(&mut self, name: &QualName, attrs: &[Attribute]) {
+        if !self.secure {
+            return;
+        }
+        if name.expanded() == expanded_name!(html "base") {
+            if !self.base_seen {
+                let href = attrs
+                    .iter()
+                    .find(|attr| attr.name.expanded() == expanded_name!("", "href"));
+                if let Some(scheme) = href.and_then(|attr| url_scheme(&attr.value)) {
+                    self.base_scheme = scheme;
+                    self.base_seen = true;
+                }
+            }
+            return;
+        }
+        let followed = name.expanded() == expanded_name!(html "a") ||
+            name.expanded() == expanded_name!(html "area") ||
+            name.expanded() == expanded_name!(svg "a");
+        for (i, attr) in attrs.iter().enumerate() {
+            let loads = match attr.name.local {
+                local_name!("src") => true,
+                local_name!("href") => !followed,
+                _ => false,
+            };
+            if !loads || attrs[..i].iter().any(|a| a.name == attr.name) {
+                continue;
+            }
+            let scheme = url_scheme(&attr.value);
+            let scheme = scheme.as_ref().unwrap_or(&self.base_scheme);
+            if scheme == "http" {
+                self.found.push(MixedContent {
+                    element: name.clone(),
+                    attr: attr.name.clone(),
+                    url: StrTendril::from_slice(&attr.value),
+                });
+            }
+        }
+    }
+}
+
+impl<Sink: TreeSink> TreeSink for MixedContentSink<Sink> {
+    type Handle = Sink::Handle;
+    type Output = (Sink::Output, Vec<MixedContent>);
+
+    fn finish(self) -> Self::Output {
+        (self.sink.finish(), self.found)
+    }
+
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.sink.parse_error(msg)
+    }
+
+    fn ambiguous_construct(&mut self, construct: AmbiguousConstruct, line_number: u64) {
+        self.sink.ambiguous_construct(construct, line_number)
+    }
+
+    fn duplicate_attribute(&mut self, first: &str, duplicate: &str, line_number: u64) {
+        self.sink.duplicate_attribute(first, duplicate, line_number)
+    }
+
+    fn set_current_line(&mut self, line_number: u64) {
+        self.sink.set_current_line(line_number)
+    }
+
+    fn should_continue(&self) -> bool {
+        self.sink.should_continue()
+    }
+
+    fn misnested_tags(&mut self, expected: &QualName, found: &QualName, expected_pos: Option<u64>) {
+        self.sink.misnested_tags(expected, found, expected_pos)
+    }
+
+    fn get_document(&mut self) -> Self::Handle {
+        self.sink.get_document()
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a Self::Handle) -> ExpandedName<'a> {
+        self.sink.elem_name(target)
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Self::Handle {
+        self.check(&name, &attrs);
+        self.sink.create_element(name, attrs, flags)
+    }
+
+    fn create_element_raw_attrs(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Self::Handle {
+        self.check(&name, &attrs);
+        self.sink.create_element_raw_attrs(name, attrs, flags)
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> Self::Handle {
+        self.sink.create_comment(text)
+    }
+
+    fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Self::Handle {
+        self.sink.create_pi(target, data)
+    }
+
+    fn append(&mut self, parent: &Self::Handle, child: NodeOrText<Self::Handle>) {
+        self.sink.append(parent, child)
+    }
+
+    fn append_text_with_flags(
+        &mut self,
+        parent: &Self::Handle,
+        text: StrTendril,
+        preserve_whitespace: bool,
+    ) {
+        self.sink
+            .append_text_with_flags(parent, text, preserve_whitespace)
+    }
+
+    fn wants_borrowed_text(&self) -> bool {
+        self.sink.wants_borrowed_text()
+    }
+
+    fn push_text(&mut self, parent: &Self::Handle, text: &str) {
+        self.sink.push_text(parent, text)
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &Self::Handle,
+        prev_element: &Self::Handle,
+        child: NodeOrText<Self::Handle>,
+    ) {
+        self.sink
+            .append_based_on_parent_node(element, prev_element, child)
+    }
+
+    fn foster_parent_text_with_flags(
+        &mut self,
+        element: &Self::Handle,
+        prev_element: &Self::Handle,
+        text: StrTendril,
+        preserve_whitespace: bool,
+    ) {
+        self.sink
+            .foster_parent_text_with_flags(element, prev_element, text, preserve_whitespace)
+    }
+
+    fn append_doctype_to_document(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.sink
+            .append_doctype_to_document(name, public_id, system_id)
+    }
+
+    fn append_doctype_full(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+        force_quirks: bool,
+    ) {
+        self.sink
+            .append_doctype_full(name, public_id, system_id, force_quirks)
+    }
+
+    fn append_doctype_raw(
+        &mut self,
+        raw: StrTendril,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.sink
+            .append_doctype_raw(raw, name, public_id, system_id)
+    }
+
+    fn obsolete_element(&mut self, name: &QualName) {
+        self.sink.obsolete_element(name)
+    }
+
+    fn select_content_dropped(&mut self, name: &QualName) {
+        self.sink.select_content_dropped(name)
+    }
+
+    fn stray_template_close(&mut self) {
+        self.sink.stray_template_close()
+    }
+
+    fn tag_rewritten(&mut self, from: &QualName, to: &QualName) {
+        self.sink.tag_rewritten(from, to)
+    }
+
+    fn unacknowledged_self_closing(&mut self, name: &QualName) {
+        self.sink.unacknowledged_self_closing(name)
+    }
+
+    fn leading_text_relocated(&mut self, text: StrTendril) {
+        self.sink.leading_text_relocated(text)
+    }
+
+    fn leading_newline_stripped(&mut self, element: &Self::Handle) {
+        self.sink.leading_newline_stripped(element)
+    }
+
+    fn misplaced_doctype(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.sink.misplaced_doctype(name, public_id, system_id)
+    }
+
+    fn mark_script_already_started(&mut self, node: &Self::Handle) {
+        self.sink.mark_script_already_started(node)
+    }
+
+    fn element_finalized(&mut self, element: &Self::Handle) {
+        self.sink.element_finalized(element)
+    }
+
+    fn element_synthesized(&mut self, element: &Self::Handle, name: &QualName) {
+        self.sink.element_synthesized(element, name)
+    }
+
+    fn element_cloned(&mut self, original: &Self::Handle, clone: &Self::Handle) {
+        self.sink.element_cloned(original, clone)
+    }
+
+    fn head_opened(&mut self, head: &Self::Handle) {
+        self.sink.head_opened(head)
+    }
+
+    fn head_closed(&mut self, head: &Self::Handle) {
+        self.sink.head_closed(head)
+    }
+
+    fn parse_stats(&mut self, stats: ParseStats) {
+        self.sink.parse_stats(stats)
+    }
+
+    fn eof_state(&mut self, clean: bool, open_elements: usize) {
+        self.sink.eof_state(clean, open_elements)
+    }
+
+    fn pop(&mut self, node: &Self::Handle) {
+        self.sink.pop(node)
+    }
+
+    fn element_closed(&mut self, node: &Self::Handle) {
+        self.sink.element_closed(node)
+    }
+
+    fn get_template_contents(&mut self, target: &Self::Handle) -> Self::Handle {
+        self.sink.get_template_contents(target)
+    }
+
+    fn same_node(&self, x: &Self::Handle, y: &Self::Handle) -> bool {
+        self.sink.same_node(x, y)
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.sink.set_quirks_mode(mode)
+    }
+
+    fn quirks_behavior_applied(&mut self, description: &'static str) {
+        self.sink.quirks_behavior_applied(description)
+    }
+
+    fn frameset_ok_changed(&mut self, ok: bool, reason: &'static str) {
+        self.sink.frameset_ok_changed(ok, reason)
+    }
+
+    fn set_document_metadata(&mut self, lang: Option<StrTendril>, dir: Option<StrTendril>) {
+        self.sink.set_document_metadata(lang, dir)
+    }
+
+    fn append_before_sibling(
+        &mut self,
+        sibling: &Self::Handle,
+        new_node: NodeOrText<Self::Handle>,
+    ) {
+        self.sink.append_before_sibling(sibling, new_node)
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &Self::Handle, attrs: Vec<Attribute>) {
+        self.sink.add_attrs_if_missing(target, attrs)
+    }
+
+    fn merge_attributes(
+        &mut self,
+        target: &Self::Handle,
+        source_tag_name: &QualName,
+        merged: &[Attribute],
+    ) {
+        self.sink.merge_attributes(target, source_tag_name, merged)
+    }
+
+    fn associate_with_form(
+        &mut self,
+        target: &Self::Handle,
+        form: &Self::Handle,
+        nodes: (&Self::Handle, Option<&Self::Handle>),
+    ) {
+        self.sink.associate_with_form(target, form, nodes)
+    }
+
+    fn remove_from_parent(&mut self, target: &Self::Handle) {
+        self.sink.remove_from_parent(target)
+    }
+
+    fn reparent_children(&mut self, node: &Self::Handle, new_parent: &Self::Handle) {
+        self.sink.reparent_children(node, new_parent)
+    }
+
+    fn is_mathml_annotation_xml_integration_point(&self, handle: &Self::Handle) -> bool {
+        self.sink.is_mathml_annotation_xml_integration_point(handle)
+    }
+
+    fn complete_script(&mut self, node: &Self::Handle) -> NextParserState {
+        self.sink.complete_script(node)
+    }
+}
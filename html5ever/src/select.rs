@@ -0,0 +1,430 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Extracting the elements that match a simple selector while parsing,
+//! without building a tree.
+//!
+//! A `Selector` is an element name, or `*`, followed by any number of
+//! attribute predicates: `[name]` requires the attribute and
+//! `[name=value]` also requires its value, which may be quoted.  There are
+//! no combinators.
+//!
+//! `SelectorSink` is a `TreeSink` that calls a closure with each element
+//! that matches, once the element is closed.  It only keeps the elements
+//! that are still open, with their names and parents, plus the attributes
+//! and text of matches not reported yet.  Closed formatting elements such
+//! as `<b>` are kept too, since the tree builder may reopen them.
+//!
+//! Collect the targets of all links:
+//!
+//! ```
+//! use html5ever::parse_document;
+//! use html5ever::select::SelectorSink;
+//! use html5ever::tendril::TendrilSink;
+//!
+//! let html = "<p>See <a href=/docs>the docs</a> or <a name=top>the top</a>.";
+//! let mut links = Vec::new();
+//! let sink = SelectorSink::new("a[href]".parse().unwrap(), |m| {
+//!     links.push((m.attr("href").unwrap().to_string(), m.text.to_string()))
+//! });
+//! parse_document(sink, Default::default()).one(html);
+//! assert_eq!(links, vec![("/docs".to_string(), "the docs".to_string())]);
+//! ```
+//!
+//! Read a page's description:
+//!
+//! ```
+//! use html5ever::parse_document;
+//! use html5ever::select::SelectorSink;
+//! use html5ever::tendril::TendrilSink;
+//!
+//! let html = r#"<meta name=author content=Me>
+//!               <meta name="description" content="All about parsing">"#;
+//! let mut description = None;
+//! let sink = SelectorSink::new("meta[name=description]".parse().unwrap(), |m| {
+//!     description = m.attr("content").map(|c| c.to_string())
+//! });
+//! parse_document(sink, Default::default()).one(html);
+//! assert_eq!(description.as_deref(), Some("All about parsing"));
+//! ```
+
+use crate::interface::{Attribute, ExpandedName, QualName};
+use crate::tendril::StrTendril;
+use crate::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use crate::util::names::Names;
+use crate::LocalName;
+use markup5ever::{namespace_url, ns};
+
+use std::borrow::Cow::{self, Borrowed};
+use std::mem;
+use std::str::FromStr;
+
+/// An element name and attribute predicates, like `meta[name=description]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selector {
+    name: Option<LocalName>,
+    attrs: Vec<(LocalName, Option<String>)>,
+}
+
+impl Selector {
+    /// Parse a selector.  Element and attribute names are ASCII
+    /// case-insensitive, attribute values are not.
+    pub fn parse(selector: &str) -> Result<Selector, Cow<'static, str>> {
+        let mut input = selector.trim();
+        if input.is_empty() {
+            return Err(Borrowed("Empty selector"));
+        }
+
+        let name = if input.starts_with('*') {
+            input = &input[1..];
+            None
+        } else {
+            let (name, rest) = split_name(input);
+            input = rest;
+            if name.is_empty() {
+                None
+            } else {
+                Some(LocalName::from(name.to_ascii_lowercase()))
+            }
+        };
+
+        let mut attrs = vec![];
+        while !input.is_empty() {
+            if !input.starts_with('[') {
+                return Err(Borrowed("Expected '[' in selector"));
+            }
+            let (name, rest) = split_name(input[1..].trim_start());
+            if name.is_empty() {
+                return Err(Borrowed("Expected an attribute name in selector"));
+            }
+            input = rest.trim_start();
+
+            let mut value = None;
+            if input.starts_with('=') {
+                let (v, rest) = split_value(input[1..].trim_start())?;
+                value = Some(v.to_string());
+                input = rest.trim_start();
+            }
+            if !input.starts_with(']') {
+                return Err(Borrowed("Expected ']' in selector"));
+            }
+            input = &input[1..];
+            attrs.push((LocalName::from(name.to_ascii_lowercase()), value));
+        }
+
+        Ok(Selector { name, attrs })
+    }
+
+    /// Does an element with this name and these attributes match?
+    pub fn matches(&self, name: &QualName, attrs: &[Attribute]) -> bool {
+        if let Some(ref local) = self.name {
+            if name.local != *local {
+                return false;
+            }
+        }
+        self.attrs.iter().all(|&(ref local, ref value)| {
+            attrs.iter().any(|attr| {
+                attr.name.ns == ns!() &&
+                    attr.name.local == *local &&
+                    value.as_ref().map_or(true, |v| *attr.value == **v)
+            })
+        })
+    }
+}
+
+impl FromStr for Selector {
+    type Err = Cow<'static, str>;
+
+    fn from_str(selector: &str) -> Result<Selector, Cow<'static, str>> {
+        Selector::parse(selector)
+    }
+}
+
+fn split_name(input: &str) -> (&str, &str) {
+    let end = input
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        .unwrap_or(input.len());
+    input.split_at(end)
+}
+
+fn split_value(input: &str) -> Result<(&str, &str), Cow<'static, str>> {
+    match input.chars().next() {
+        Some(quote @ '"') | Some(quote @ '\'') => {
+            let end = input[1..]
+                .find(quote)
+                .ok_or(Borrowed("Unterminated string in selector"))?;
+            Ok((&input[1..end + 1], &input[end + 2..]))
+        },
+        _ => {
+            let (value, rest) = split_name(input);
+            if value.is_empty() {
+                return Err(Borrowed("Expected an attribute value in selector"));
+            }
+            Ok((value, rest))
+        },
+    }
+}
+
+/// An element that matched a `Selector`.
+#[derive(Clone, Debug)]
+pub struct Match {
+    pub name: QualName,
+    pub attrs: Vec<Attribute>,
+    /// The text inserted into the element or its descendants, in the order
+    /// it was parsed.  Where the adoption agency algorithm moves nodes around
+    /// this can differ from the final tree's text content.
+    pub text: StrTendril,
+}
+
+impl Match {
+    /// Get the value of an attribute that has no namespace.
+    pub fn attr(&self, name: &str) -> Option<&StrTendril> {
+        self.attrs
+            .iter()
+            .find(|attr| attr.name.ns == ns!() && &*attr.name.local == name)
+            .map(|attr| &attr.value)
+    }
+}
+
+/// A `TreeSink` that calls `callback` with each element matching a
+/// `Selector`, when the element is closed.  A match inside another match is
+/// reported first.
+pub struct SelectorSink<F> {
+    selector: Selector,
+    callback: F,
+    /// The open elements, and whether each is a match not reported yet.
+    names: Names<bool>,
+    pending: Vec<(usize, Match)>,
+}
+
+impl<F: FnMut(Match)> SelectorSink<F> {
+    pub fn new(selector: Selector, callback: F) -> SelectorSink<F> {
+        SelectorSink {
+            selector,
+            callback,
+            names: Names::new(),
+            pending: vec![],
+        }
+    }
+
+    fn append_text(&mut self, parent: usize, text: &StrTendril) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let pending = &mut self.pending;
+        for (id, node) in self.names.ancestors(parent) {
+            if node.data {
+                if let Some(&mut (_, ref mut m)) = pending.iter_mut().find(|p| p.0 == id) {
+                    m.text.push_tendril(text);
+                }
+            }
+        }
+    }
+}
+
+impl<F: FnMut(Match)> TreeSink for SelectorSink<F> {
+    type Handle = usize;
+    type Output = ();
+
+    fn finish(mut self) {
+        for (_, m) in self.pending.drain(..) {
+            (self.callback)(m);
+        }
+    }
+
+    fn parse_error(&mut self, _msg: Cow<'static, str>) {}
+
+    fn get_document(&mut self) -> usize {
+        0
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a usize) -> ExpandedName<'a> {
+        self.names.elem_name(*target)
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> usize {
+        let matched = self.selector.matches(&name, &attrs);
+        let id = self.names.create_element(name.clone(), &flags, matched);
+        if matched {
+            self.pending.push((
+                id,
+                Match {
+                    name,
+                    attrs,
+                    text: StrTendril::new(),
+                },
+            ));
+        }
+        id
+    }
+
+    fn create_comment(&mut self, _text: StrTendril) -> usize {
+        self.names.new_id()
+    }
+
+    fn create_pi(&mut self, _target: StrTendril, _data: StrTendril) -> usize {
+        self.names.new_id()
+    }
+
+    fn append(&mut self, parent: &usize, child: NodeOrText<usize>) {
+        match child {
+            NodeOrText::AppendNode(node) => self.names.append(*parent, node),
+            NodeOrText::AppendText(text) => self.append_text(*parent, &text),
+        }
+    }
+
+    fn append_before_sibling(&mut self, sibling: &usize, child: NodeOrText<usize>) {
+        if let Some(parent) = self.names.parent(*sibling) {
+            self.append(&parent, child);
+        }
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &usize,
+        prev_element: &usize,
+        child: NodeOrText<usize>,
+    ) {
+        if self.names.parent(*element).is_some() {
+            self.append_before_sibling(element, child);
+        } else {
+            self.append(prev_element, child);
+        }
+    }
+
+    fn append_doctype_to_document(&mut self, _: StrTendril, _: StrTendril, _: StrTendril) {}
+
+    fn element_closed(&mut self, node: &usize) {
+        let matched = match self.names.get_mut(*node) {
+            Some(closed) => mem::replace(&mut closed.data, false),
+            None => return,
+        };
+        if matched {
+            if let Some(position) = self.pending.iter().position(|p| p.0 == *node) {
+                let (_, m) = self.pending.remove(position);
+                (self.callback)(m);
+            }
+        }
+        self.names.close(*node);
+    }
+
+    fn get_template_contents(&mut self, target: &usize) -> usize {
+        target + 1
+    }
+
+    fn same_node(&self, x: &usize, y: &usize) -> bool {
+        x == y
+    }
+
+    fn set_quirks_mode(&mut self, _mode: QuirksMode) {}
+
+    fn add_attrs_if_missing(&mut self, target: &usize, attrs: Vec<Attribute>) {
+        if let Some(&mut (_, ref mut m)) = self.pending.iter_mut().find(|p| p.0 == *target) {
+            for attr in attrs {
+                if !m.attrs.iter().any(|a| a.name == attr.name) {
+                    m.attrs.push(attr);
+                }
+            }
+        }
+    }
+
+    fn remove_from_parent(&mut self, target: &usize) {
+        self.names.remove_from_parent(*target);
+    }
+
+    fn reparent_children(&mut self, node: &usize, new_parent: &usize) {
+        self.names.reparent_children(*node, *new_parent);
+    }
+
+    fn is_mathml_annotation_xml_integration_point(&self, handle: &usize) -> bool {
+        self.names.is_integration_point(*handle)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Selector, SelectorSink};
+    use crate::driver::parse_document;
+    use crate::tendril::TendrilSink;
+
+    fn select(selector: &str, html: &str) -> Vec<(String, String)> {
+        let mut found = vec![];
+        let sink = SelectorSink::new(selector.parse().unwrap(), |m| {
+            found.push((m.name.local.to_string(), m.text.to_string()))
+        });
+        parse_document(sink, Default::default()).one(html);
+        found
+    }
+
+    #[test]
+    fn parse() {
+        assert!(Selector::parse("a[href]").is_ok());
+        assert!(Selector::parse(" *[ data-x = 'a]b' ][y=\"\"]").is_ok());
+        assert_eq!(Selector::parse("A[HREF]"), Selector::parse("a[href]"));
+        assert!(Selector::parse("").is_err());
+        assert!(Selector::parse("a b").is_err());
+        assert!(Selector::parse("a[href").is_err());
+        assert!(Selector::parse("a[=x]").is_err());
+        assert!(Selector::parse("a[href='x]").is_err());
+    }
+
+    #[test]
+    fn attributes() {
+        let html = "<a href=x>1</a><a>2</a><a href=y rel=next>3</a><A HREF=z>4</a>";
+        assert_eq!(select("a", html).len(), 4);
+        assert_eq!(select("a[href]", html).len(), 3);
+        assert_eq!(
+            select("[rel=next][href]", html),
+            vec![("a".into(), "3".into())]
+        );
+        assert_eq!(select("a[href=X]", html), vec![]);
+    }
+
+    #[test]
+    fn text_and_order() {
+        assert_eq!(
+            select("p", "<p>one<p>two<div>three</div><p>four"),
+            vec![
+                ("p".into(), "one".into()),
+                ("p".into(), "two".into()),
+                ("p".into(), "four".into()),
+            ]
+        );
+        assert_eq!(
+            select("div", "<div>a<div>b</div>c</div>"),
+            vec![("div".into(), "b".into()), ("div".into(), "abc".into())]
+        );
+        // The adoption agency algorithm clones the <b>.
+        assert_eq!(select("b", "<b>1<p>2</b>3").len(), 2);
+        // ... and moves the <p>'s children into the clone.
+        assert_eq!(
+            select("p", "<b><p>1<i>2</b>3"),
+            vec![("p".into(), "123".into())]
+        );
+    }
+
+    #[test]
+    fn frees_closed_elements() {
+        let mut parser = parse_document(
+            SelectorSink::new("p".parse().unwrap(), |_| ()),
+            Default::default(),
+        );
+        for _ in 0..1000 {
+            parser.process("<p>text<!-- comment --><template>x</template>".into());
+        }
+        parser.process("<p>".into());
+        // The document, <html>, <head>, <body> and the last <p>.
+        assert_eq!(parser.tokenizer.sink.sink.names.len(), 5);
+    }
+}
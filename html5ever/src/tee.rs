@@ -0,0 +1,85 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Keep the tokens that a tree was built from.
+
+use crate::tokenizer::{Token, TokenTransform};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A `TokenTransform` that keeps a copy of every token the tree builder
+/// receives, and passes it on unchanged.
+///
+/// Pass a clone of the log to `parse_document_with_transform`, and take
+/// the tokens from the original when parsing is finished, to compare what
+/// was tokenized with the tree it produced.  The clones share the tokens.
+///
+/// Every token is cloned and kept until it is taken, so this costs time
+/// and memory in proportion to the input.  Use it for debugging.
+///
+/// ```
+/// use html5ever::driver::{parse_document_with_transform, ParseOpts};
+/// use html5ever::immutable::ImmutableDom;
+/// use html5ever::tee::TokenLog;
+/// use html5ever::tendril::TendrilSink;
+/// use html5ever::tokenizer::TagToken;
+///
+/// let log = TokenLog::new();
+/// let sink = ImmutableDom::default();
+/// let _dom = parse_document_with_transform(sink, ParseOpts::default(), log.clone()).one("<p>x");
+/// match log.take()[0] {
+///     TagToken(ref tag) => assert_eq!(&*tag.name, "p"),
+///     ref token => panic!("unexpected token {:?}", token),
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct TokenLog {
+    tokens: Rc<RefCell<Vec<Token>>>,
+}
+
+impl TokenLog {
+    /// An empty log.
+    pub fn new() -> Self {
+        TokenLog::default()
+    }
+
+    /// The tokens recorded so far, in order, leaving the log empty.
+    pub fn take(&self) -> Vec<Token> {
+        self.tokens.replace(Vec::new())
+    }
+}
+
+impl TokenTransform for TokenLog {
+    fn transform(&mut self, token: Token, output: &mut Vec<Token>) {
+        self.tokens.borrow_mut().push(token.clone());
+        output.push(token);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TokenLog;
+    use crate::driver::{parse_document_with_transform, ParseOpts};
+    use crate::immutable::ImmutableDom;
+    use crate::tendril::TendrilSink;
+    use crate::tokenizer::tokenize;
+
+    #[test]
+    fn matches_tokenizer() {
+        let input = "<!DOCTYPE html><p class=a>x &amp; y<!--c--><br/></div>";
+        let log = TokenLog::new();
+        let sink = ImmutableDom::default();
+        parse_document_with_transform(sink, ParseOpts::default(), log.clone()).one(input);
+        assert_eq!(
+            log.take(),
+            tokenize(input, Default::default()).collect::<Vec<_>>()
+        );
+    }
+}
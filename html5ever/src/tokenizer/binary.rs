@@ -0,0 +1,499 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A compact binary encoding of the token stream, for caching, and of the
+//! tokenizer's state.
+//!
+//! Each token is a kind byte followed by its fields.  Strings are a
+//! little-endian `u32` byte length and UTF-8 bytes; optional strings have a
+//! leading `0` or `1` byte.  Atoms are written as their strings.  The format
+//! may change between versions of html5ever.
+
+use super::states::State;
+use super::{CharRefTokenizer, TokenizerState};
+use super::{CharacterTokens, CommentToken, DoctypeToken, EOFToken, NullCharacterToken};
+use super::{Doctype, EndTag, ParseError, StartTag, Tag, TagKind, TagToken, Token};
+use crate::interface::Attribute;
+use crate::tendril::StrTendril;
+use crate::{LocalName, Namespace, Prefix, QualName};
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+const DOCTYPE: u8 = 0;
+const START_TAG: u8 = 1;
+const END_TAG: u8 = 2;
+const COMMENT: u8 = 3;
+const CHARACTERS: u8 = 4;
+const NULL_CHARACTER: u8 = 5;
+const EOF: u8 = 6;
+const PARSE_ERROR: u8 = 7;
+
+/// Write `tokens` to `w`, to be read back by `deserialize_tokens`.
+pub fn serialize_tokens<I, W>(tokens: I, w: &mut W) -> io::Result<()>
+where
+    I: IntoIterator<Item = Token>,
+    W: Write,
+{
+    for token in tokens {
+        match token {
+            DoctypeToken(doctype) => {
+                w.write_all(&[DOCTYPE])?;
+                write_doctype(w, &doctype)?;
+            },
+            TagToken(tag) => {
+                write_tag_kind(w, tag.kind)?;
+                write_str(w, &tag.name)?;
+                write_bool(w, tag.self_closing)?;
+                write_attrs(w, &tag.attrs)?;
+            },
+            CommentToken(text) => {
+                w.write_all(&[COMMENT])?;
+                write_str(w, &text)?;
+            },
+            CharacterTokens(text) => {
+                w.write_all(&[CHARACTERS])?;
+                write_str(w, &text)?;
+            },
+            NullCharacterToken => w.write_all(&[NULL_CHARACTER])?,
+            EOFToken => w.write_all(&[EOF])?,
+            ParseError(msg) => {
+                w.write_all(&[PARSE_ERROR])?;
+                write_str(w, &msg)?;
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Read back tokens written by `serialize_tokens`.
+///
+/// The iterator ends at the end of the input.  Malformed or truncated input
+/// yields an error, after which the iterator ends.
+pub fn deserialize_tokens<R: Read>(r: R) -> DeserializeTokens<R> {
+    DeserializeTokens {
+        reader: r,
+        failed: false,
+    }
+}
+
+/// Iterator over the tokens in a binary stream.  Created by
+/// `deserialize_tokens`.
+pub struct DeserializeTokens<R> {
+    reader: R,
+    failed: bool,
+}
+
+impl<R: Read> Iterator for DeserializeTokens<R> {
+    type Item = io::Result<Token>;
+
+    fn next(&mut self) -> Option<io::Result<Token>> {
+        if self.failed {
+            return None;
+        }
+        let mut kind = [0];
+        loop {
+            match self.reader.read(&mut kind) {
+                Ok(0) => return None,
+                Ok(_) => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                },
+            }
+        }
+        let token = read_token(&mut self.reader, kind[0]);
+        self.failed = token.is_err();
+        Some(token)
+    }
+}
+
+fn read_token<R: Read>(r: &mut R, kind: u8) -> io::Result<Token> {
+    Ok(match kind {
+        DOCTYPE => DoctypeToken(read_doctype(r)?),
+        START_TAG | END_TAG => TagToken(Tag {
+            kind: if kind == START_TAG { StartTag } else { EndTag },
+            name: LocalName::from(&*read_str(r)?),
+            self_closing: read_bool(r)?,
+            attrs: read_attrs(r)?,
+        }),
+        COMMENT => CommentToken(read_str(r)?),
+        CHARACTERS => CharacterTokens(read_str(r)?),
+        NULL_CHARACTER => NullCharacterToken,
+        EOF => EOFToken,
+        PARSE_ERROR => ParseError(Cow::Owned(read_str(r)?.into())),
+        _ => return Err(invalid_data("unknown token kind")),
+    })
+}
+
+/// Write a tokenizer state saved by `Tokenizer::save_state` to `w`, to be
+/// read back by `deserialize_tokenizer_state`.
+pub fn serialize_tokenizer_state<W: Write>(state: &TokenizerState, w: &mut W) -> io::Result<()> {
+    write_state(w, state.state)?;
+    match state.char_ref_tokenizer {
+        None => write_bool(w, false)?,
+        Some(ref tok) => {
+            write_bool(w, true)?;
+            tok.serialize(w)?;
+        },
+    }
+    write_char(w, state.current_char)?;
+    write_bool(w, state.reconsume)?;
+    write_bool(w, state.ignore_lf)?;
+    write_bool(w, state.discard_bom)?;
+    write_tag_kind(w, state.current_tag_kind)?;
+    write_str(w, &state.current_tag_name)?;
+    write_bool(w, state.current_tag_self_closing)?;
+    write_bool(w, state.keep_tag_case)?;
+    write_attrs(w, &state.current_tag_attrs)?;
+    write_len(w, state.current_tag_raw_attr_names.len())?;
+    for name in &state.current_tag_raw_attr_names {
+        write_str(w, name)?;
+    }
+    write_str(w, &state.current_attr_name)?;
+    write_str(w, &state.current_attr_raw_name)?;
+    write_str(w, &state.current_attr_value)?;
+    write_str(w, &state.current_comment)?;
+    write_doctype(w, &state.current_doctype)?;
+    write_opt_str(w, state.raw_doctype.as_deref())?;
+    write_opt_str(w, state.last_start_tag_name.as_deref())?;
+    write_bool(w, state.comment_truncated)?;
+    write_bool(w, state.attr_value_truncated)?;
+    write_u64(w, state.char_ref_count as u64)?;
+    write_str(w, &state.temp_buf)?;
+    write_u64(w, state.current_line)?;
+    write_u64(w, state.current_column)?;
+    write_u64(w, state.last_column)
+}
+
+/// Read back a tokenizer state written by `serialize_tokenizer_state`.
+pub fn deserialize_tokenizer_state<R: Read>(mut r: R) -> io::Result<TokenizerState> {
+    let r = &mut r;
+    Ok(TokenizerState {
+        state: read_state(r)?,
+        char_ref_tokenizer: if read_bool(r)? {
+            Some(Box::new(CharRefTokenizer::deserialize(r)?))
+        } else {
+            None
+        },
+        current_char: read_char(r)?,
+        reconsume: read_bool(r)?,
+        ignore_lf: read_bool(r)?,
+        discard_bom: read_bool(r)?,
+        current_tag_kind: match read_u8(r)? {
+            START_TAG => StartTag,
+            END_TAG => EndTag,
+            _ => return Err(invalid_data("unknown tag kind")),
+        },
+        current_tag_name: read_str(r)?,
+        current_tag_self_closing: read_bool(r)?,
+        keep_tag_case: read_bool(r)?,
+        current_tag_attrs: read_attrs(r)?,
+        current_tag_raw_attr_names: (0..read_len(r)?)
+            .map(|_| read_str(r))
+            .collect::<io::Result<_>>()?,
+        current_attr_name: read_str(r)?,
+        current_attr_raw_name: read_str(r)?,
+        current_attr_value: read_str(r)?,
+        current_comment: read_str(r)?,
+        current_doctype: read_doctype(r)?,
+        raw_doctype: read_opt_str(r)?,
+        last_start_tag_name: read_opt_str(r)?.map(|name| LocalName::from(&*name)),
+        comment_truncated: read_bool(r)?,
+        attr_value_truncated: read_bool(r)?,
+        char_ref_count: read_u64(r)? as usize,
+        temp_buf: read_str(r)?,
+        current_line: read_u64(r)?,
+        current_column: read_u64(r)?,
+        last_column: read_u64(r)?,
+    })
+}
+
+/// Every tokenizer state, in the order of their encodings.
+fn all_states() -> Vec<State> {
+    use super::states::*;
+
+    let escapes = [Escaped, DoubleEscaped];
+    let raw_kinds = [
+        Rcdata,
+        Rawtext,
+        ScriptData,
+        ScriptDataEscaped(Escaped),
+        ScriptDataEscaped(DoubleEscaped),
+    ];
+    let id_kinds = [Public, System];
+
+    let mut all = vec![Data, Plaintext, TagOpen, EndTagOpen, TagName];
+    for &kind in raw_kinds.iter() {
+        all.extend_from_slice(&[
+            RawData(kind),
+            RawLessThanSign(kind),
+            RawEndTagOpen(kind),
+            RawEndTagName(kind),
+        ]);
+    }
+    for &kind in escapes.iter() {
+        all.extend_from_slice(&[
+            ScriptDataEscapeStart(kind),
+            ScriptDataEscapedDash(kind),
+            ScriptDataEscapedDashDash(kind),
+        ]);
+    }
+    all.extend_from_slice(&[
+        ScriptDataEscapeStartDash,
+        ScriptDataDoubleEscapeEnd,
+        BeforeAttributeName,
+        AttributeName,
+        AfterAttributeName,
+        BeforeAttributeValue,
+        AttributeValue(Unquoted),
+        AttributeValue(SingleQuoted),
+        AttributeValue(DoubleQuoted),
+        AfterAttributeValueQuoted,
+        SelfClosingStartTag,
+        BogusComment,
+        MarkupDeclarationOpen,
+        CommentStart,
+        CommentStartDash,
+        Comment,
+        CommentLessThanSign,
+        CommentLessThanSignBang,
+        CommentLessThanSignBangDash,
+        CommentLessThanSignBangDashDash,
+        CommentEndDash,
+        CommentEnd,
+        CommentEndBang,
+        Doctype,
+        BeforeDoctypeName,
+        DoctypeName,
+        AfterDoctypeName,
+    ]);
+    for &kind in id_kinds.iter() {
+        all.extend_from_slice(&[
+            AfterDoctypeKeyword(kind),
+            BeforeDoctypeIdentifier(kind),
+            DoctypeIdentifierDoubleQuoted(kind),
+            DoctypeIdentifierSingleQuoted(kind),
+            AfterDoctypeIdentifier(kind),
+        ]);
+    }
+    all.extend_from_slice(&[
+        BetweenDoctypePublicAndSystemIdentifiers,
+        BogusDoctype,
+        CdataSection,
+        CdataSectionBracket,
+        CdataSectionEnd,
+        ProcessingInstruction,
+        ProcessingInstructionQuestion,
+    ]);
+    all
+}
+
+fn write_state<W: Write>(w: &mut W, state: State) -> io::Result<()> {
+    let index = all_states()
+        .iter()
+        .position(|&s| s == state)
+        .expect("tokenizer state missing from all_states");
+    w.write_all(&[index as u8])
+}
+
+fn read_state<R: Read>(r: &mut R) -> io::Result<State> {
+    let index = read_u8(r)? as usize;
+    all_states()
+        .get(index)
+        .cloned()
+        .ok_or_else(|| invalid_data("unknown tokenizer state"))
+}
+
+fn write_tag_kind<W: Write>(w: &mut W, kind: TagKind) -> io::Result<()> {
+    w.write_all(&[match kind {
+        StartTag => START_TAG,
+        EndTag => END_TAG,
+    }])
+}
+
+fn write_attrs<W: Write>(w: &mut W, attrs: &[Attribute]) -> io::Result<()> {
+    write_len(w, attrs.len())?;
+    for attr in attrs.iter() {
+        write_opt_str(w, attr.name.prefix.as_deref())?;
+        write_str(w, &attr.name.ns)?;
+        write_str(w, &attr.name.local)?;
+        write_str(w, &attr.value)?;
+    }
+    Ok(())
+}
+
+fn read_attrs<R: Read>(r: &mut R) -> io::Result<Vec<Attribute>> {
+    let mut attrs = vec![];
+    for _ in 0..read_len(r)? {
+        let prefix = read_opt_str(r)?.map(|p| Prefix::from(&*p));
+        let ns = Namespace::from(&*read_str(r)?);
+        let local = LocalName::from(&*read_str(r)?);
+        attrs.push(Attribute {
+            name: QualName::new(prefix, ns, local),
+            value: read_str(r)?,
+        });
+    }
+    Ok(attrs)
+}
+
+fn write_doctype<W: Write>(w: &mut W, doctype: &Doctype) -> io::Result<()> {
+    write_opt_str(w, doctype.name.as_deref())?;
+    write_opt_str(w, doctype.public_id.as_deref())?;
+    write_opt_str(w, doctype.system_id.as_deref())?;
+    write_bool(w, doctype.force_quirks)?;
+    write_opt_str(w, doctype.raw.as_deref())
+}
+
+fn read_doctype<R: Read>(r: &mut R) -> io::Result<Doctype> {
+    Ok(Doctype {
+        name: read_opt_str(r)?,
+        public_id: read_opt_str(r)?,
+        system_id: read_opt_str(r)?,
+        force_quirks: read_bool(r)?,
+        raw: read_opt_str(r)?,
+    })
+}
+
+pub(super) fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub(super) fn write_len<W: Write>(w: &mut W, len: usize) -> io::Result<()> {
+    let len = u32::try_from(len)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "token too long"))?;
+    write_u32(w, len)
+}
+
+pub(super) fn write_bool<W: Write>(w: &mut W, b: bool) -> io::Result<()> {
+    w.write_all(&[b as u8])
+}
+
+pub(super) fn write_u32<W: Write>(w: &mut W, n: u32) -> io::Result<()> {
+    w.write_all(&n.to_le_bytes())
+}
+
+fn write_u64<W: Write>(w: &mut W, n: u64) -> io::Result<()> {
+    w.write_all(&n.to_le_bytes())
+}
+
+pub(super) fn write_char<W: Write>(w: &mut W, c: char) -> io::Result<()> {
+    write_u32(w, c as u32)
+}
+
+pub(super) fn write_opt_char<W: Write>(w: &mut W, c: Option<char>) -> io::Result<()> {
+    match c {
+        None => write_bool(w, false),
+        Some(c) => {
+            write_bool(w, true)?;
+            write_char(w, c)
+        },
+    }
+}
+
+pub(super) fn write_str<W: Write>(w: &mut W, s: &str) -> io::Result<()> {
+    write_len(w, s.len())?;
+    w.write_all(s.as_bytes())
+}
+
+pub(super) fn write_opt_str<W: Write>(w: &mut W, s: Option<&str>) -> io::Result<()> {
+    match s {
+        None => write_bool(w, false),
+        Some(s) => {
+            write_bool(w, true)?;
+            write_str(w, s)
+        },
+    }
+}
+
+pub(super) fn read_len<R: Read>(r: &mut R) -> io::Result<usize> {
+    read_u32(r).map(|n| n as usize)
+}
+
+pub(super) fn read_u8<R: Read>(r: &mut R) -> io::Result<u8> {
+    let mut byte = [0];
+    r.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+pub(super) fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub(super) fn read_bool<R: Read>(r: &mut R) -> io::Result<bool> {
+    match read_u8(r)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(invalid_data("invalid flag")),
+    }
+}
+
+pub(super) fn read_char<R: Read>(r: &mut R) -> io::Result<char> {
+    std::char::from_u32(read_u32(r)?).ok_or_else(|| invalid_data("invalid character"))
+}
+
+pub(super) fn read_opt_char<R: Read>(r: &mut R) -> io::Result<Option<char>> {
+    if read_bool(r)? {
+        read_char(r).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+pub(super) fn read_str<R: Read>(r: &mut R) -> io::Result<StrTendril> {
+    let len = read_len(r)?;
+    let mut bytes = vec![];
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let s = String::from_utf8(bytes).map_err(|_| invalid_data("invalid UTF-8"))?;
+    Ok(StrTendril::from(s))
+}
+
+pub(super) fn read_opt_str<R: Read>(r: &mut R) -> io::Result<Option<StrTendril>> {
+    if read_bool(r)? {
+        read_str(r).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{deserialize_tokens, serialize_tokens};
+    use crate::tokenizer::{tokenize, Token};
+
+    #[test]
+    fn round_trip() {
+        let input = "<!DOCTYPE html PUBLIC \"x\"><p class=a id='b'>one &amp; \0two\
+                     <svg><a xlink:href=c /></svg><!-- three --></p></x y>";
+        let tokens: Vec<Token> = tokenize(input, Default::default()).collect();
+
+        let mut bytes = vec![];
+        serialize_tokens(tokens.clone(), &mut bytes).unwrap();
+        let read: Vec<Token> = deserialize_tokens(&bytes[..])
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read, tokens);
+
+        let truncated: Vec<_> = deserialize_tokens(&bytes[..bytes.len() - 5]).collect();
+        assert!(truncated.last().unwrap().is_err());
+    }
+}
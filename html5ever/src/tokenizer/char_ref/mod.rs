@@ -7,7 +7,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use super::{TokenSink, Tokenizer};
+use super::{InvalidCharRefPolicy, TokenSink, Tokenizer};
 use crate::buffer_queue::BufferQueue;
 use crate::data;
 use crate::tendril::StrTendril;
@@ -16,11 +16,135 @@ use log::debug;
 use mac::format_if;
 use std::borrow::Cow::Borrowed;
 use std::char::from_u32;
+#[cfg(feature = "binary_tokens")]
+use std::io::{self, Read, Write};
+
+#[cfg(feature = "binary_tokens")]
+use super::binary;
 
 use self::State::*;
 pub use self::Status::*;
 
+/// The character that the spec substitutes for a numeric character
+/// reference to `n`, if it substitutes one.
+///
+/// Zero, surrogates and values above U+10FFFF become U+FFFD REPLACEMENT
+/// CHARACTER.  Most C1 controls, U+0080 to U+009F, become the character at
+/// that position in Windows-1252.  Other values, including the C1 controls
+/// that Windows-1252 leaves undefined, are used as they are.
+///
+/// ```
+/// use html5ever::tokenizer::numeric_char_ref_replacement;
+///
+/// assert_eq!(numeric_char_ref_replacement(0x80), Some('\u{20ac}'));
+/// assert_eq!(numeric_char_ref_replacement(0xD800), Some('\u{fffd}'));
+/// assert_eq!(numeric_char_ref_replacement(0x81), None);
+/// assert_eq!(numeric_char_ref_replacement(0x41), None);
+/// ```
+pub fn numeric_char_ref_replacement(n: u32) -> Option<char> {
+    match n {
+        0x00 | 0xD800..=0xDFFF => Some('\u{fffd}'),
+        n if n > 0x10FFFF => Some('\u{fffd}'),
+        0x80..=0x9F => data::C1_REPLACEMENTS[(n - 0x80) as usize],
+        _ => None,
+    }
+}
+
+/// Replace the character references in `raw`, an attribute value as it
+/// would be written in the source, the way the tokenizer does with the
+/// default options.  No parse errors are reported.
+///
+/// The rules are those for attributes, which differ from those for text
+/// in one way: a named reference without a semicolon, like `&amp`, is left
+/// as it is when followed by `=` or an ASCII letter or digit, so the query
+/// string in `href="?a=1&copy=2"` is kept.  Numeric references are decoded
+/// the same way in both.
+///
+/// ```
+/// use html5ever::tokenizer::decode_attribute_value;
+///
+/// assert_eq!(decode_attribute_value("a&amp;b&lt;c"), "a&b<c");
+/// assert_eq!(decode_attribute_value("?a=1&copy=2"), "?a=1&copy=2");
+/// assert_eq!(decode_attribute_value("&copy 2020"), "\u{a9} 2020");
+/// ```
+pub fn decode_attribute_value(raw: &str) -> String {
+    let mut value = String::new();
+    let mut rest = raw;
+    while let Some(amp) = rest.find('&') {
+        value.push_str(&rest[..amp]);
+        rest = &rest[amp + 1..];
+        match decode_char_ref(rest) {
+            Some((CharRef { chars, num_chars }, len)) => {
+                value.extend(chars[..num_chars as usize].iter().cloned());
+                rest = &rest[len..];
+            },
+            None => value.push('&'),
+        }
+    }
+    value.push_str(rest);
+    value
+}
+
+/// Decode the character reference at the start of `input`, which follows an
+/// `&` in an attribute value.  Returns the characters and the length of
+/// `input` they replace.
+fn decode_char_ref(input: &str) -> Option<(CharRef, usize)> {
+    let mut chars = input.chars();
+    if chars.next() == Some('#') {
+        let digits = chars.as_str();
+        let (base, digits) = match chars.next() {
+            Some('x') | Some('X') => (16, chars.as_str()),
+            _ => (10, digits),
+        };
+        let len = digits
+            .find(|c: char| !c.is_digit(base))
+            .unwrap_or(digits.len());
+        if len == 0 {
+            return None;
+        }
+        let num = digits[..len].chars().fold(0u32, |num, c| {
+            num.saturating_mul(base)
+                .saturating_add(c.to_digit(base).unwrap())
+        });
+        let c = numeric_char_ref_replacement(num)
+            .unwrap_or_else(|| from_u32(num).expect("invalid char missed by replacement"));
+        let semicolon = digits[len..].starts_with(';') as usize;
+        let char_ref = CharRef {
+            chars: [c, '\0'],
+            num_chars: 1,
+        };
+        return Some((char_ref, input.len() - digits.len() + len + semicolon));
+    }
+
+    let mut name_match = None;
+    for (i, c) in input.char_indices() {
+        let end = i + c.len_utf8();
+        match data::NAMED_ENTITIES.get(&input[..end]) {
+            Some(&(c1, c2)) if c1 != 0 => name_match = Some((c1, c2, end)),
+            Some(_) => (),
+            None => break,
+        }
+    }
+    let (c1, c2, len) = name_match?;
+
+    // As in `finish_named`, a reference without a semicolon that is followed
+    // by `=` or an alphanumeric character is left as it is.
+    if !input[..len].ends_with(';') {
+        if let Some(next) = input[len..].chars().next() {
+            if next == '=' || next.is_ascii_alphanumeric() {
+                return None;
+            }
+        }
+    }
+    let char_ref = CharRef {
+        chars: [from_u32(c1).unwrap(), from_u32(c2).unwrap()],
+        num_chars: if c2 == 0 { 1 } else { 2 },
+    };
+    Some((char_ref, len))
+}
+
 //§ tokenizing-character-references
+#[derive(Clone, Debug)]
 pub struct CharRef {
     /// The resulting character(s)
     pub chars: [char; 2],
@@ -35,7 +159,7 @@ pub enum Status {
     Done,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 enum State {
     Begin,
     Octothorpe,
@@ -45,6 +169,7 @@ enum State {
     BogusName,
 }
 
+#[derive(Clone, Debug)]
 pub struct CharRefTokenizer {
     state: State,
     addnl_allowed: Option<char>,
@@ -54,6 +179,8 @@ pub struct CharRefTokenizer {
     num_too_big: bool,
     seen_digit: bool,
     hex_marker: Option<char>,
+    /// The digits as written, kept for `InvalidCharRefPolicy::Keep`.
+    digits: StrTendril,
 
     name_buf_opt: Option<StrTendril>,
     name_match: Option<(u32, u32)>,
@@ -72,6 +199,7 @@ impl CharRefTokenizer {
             num_too_big: false,
             seen_digit: false,
             hex_marker: None,
+            digits: StrTendril::new(),
             name_buf_opt: None,
             name_match: None,
             name_len: 0,
@@ -96,7 +224,13 @@ impl CharRefTokenizer {
             .expect("name_buf missing in named character reference")
     }
 
+    /// There is no character reference, so the `&` is just text.
     fn finish_none(&mut self) -> Status {
+        self.finish_one('&')
+    }
+
+    /// Emit nothing for the character reference.
+    fn finish_empty(&mut self) -> Status {
         self.result = Some(CharRef {
             chars: ['\0', '\0'],
             num_chars: 0,
@@ -140,7 +274,7 @@ impl CharRefTokenizer {
         input: &mut BufferQueue,
     ) -> Status {
         match unwrap_or_return!(tokenizer.peek(input), Stuck) {
-            '\t' | '\n' | '\x0C' | ' ' | '<' | '&' => self.finish_none(),
+            '\t' | '\n' | '\r' | '\x0C' | ' ' | '<' | '&' => self.finish_none(),
             c if Some(c) == self.addnl_allowed => self.finish_none(),
 
             '#' => {
@@ -188,6 +322,9 @@ impl CharRefTokenizer {
         match c.to_digit(base) {
             Some(n) => {
                 tokenizer.discard_char(input);
+                if tokenizer.opts.invalid_charref_policy == InvalidCharRefPolicy::Keep {
+                    self.digits.push_char(c);
+                }
                 self.num = self.num.wrapping_mul(base);
                 if self.num > 0x10FFFF {
                     // We might overflow, and the character is definitely invalid.
@@ -213,13 +350,19 @@ impl CharRefTokenizer {
         tokenizer: &mut Tokenizer<Sink>,
         input: &mut BufferQueue,
     ) -> Status {
-        match unwrap_or_return!(tokenizer.peek(input), Stuck) {
-            ';' => tokenizer.discard_char(input),
-            _ => tokenizer.emit_error(Borrowed(
-                "Semicolon missing after numeric character reference",
-            )),
+        let semicolon = match unwrap_or_return!(tokenizer.peek(input), Stuck) {
+            ';' => {
+                tokenizer.discard_char(input);
+                true
+            },
+            _ => {
+                tokenizer.emit_error(Borrowed(
+                    "Semicolon missing after numeric character reference",
+                ));
+                false
+            },
         };
-        self.finish_numeric(tokenizer)
+        self.finish_numeric(tokenizer, input, semicolon)
     }
 
     fn unconsume_numeric<Sink: TokenSink>(
@@ -233,30 +376,34 @@ impl CharRefTokenizer {
             None => (),
         }
 
-        input.push_front(unconsume);
+        tokenizer.unconsume(input, unconsume);
         tokenizer.emit_error(Borrowed("Numeric character reference without digits"));
         self.finish_none()
     }
 
-    fn finish_numeric<Sink: TokenSink>(&mut self, tokenizer: &mut Tokenizer<Sink>) -> Status {
+    fn finish_numeric<Sink: TokenSink>(
+        &mut self,
+        tokenizer: &mut Tokenizer<Sink>,
+        input: &mut BufferQueue,
+        semicolon: bool,
+    ) -> Status {
         fn conv(n: u32) -> char {
             from_u32(n).expect("invalid char missed by error handling cases")
         }
 
-        let (c, error) = match self.num {
-            n if (n > 0x10FFFF) || self.num_too_big => ('\u{fffd}', true),
-            0x00 | 0xD800..=0xDFFF => ('\u{fffd}', true),
-
-            0x80..=0x9F => match data::C1_REPLACEMENTS[(self.num - 0x80) as usize] {
-                Some(c) => (c, true),
-                None => (conv(self.num), true),
-            },
+        let num = if self.num_too_big { 0x110000 } else { self.num };
+        let (c, error) = match numeric_char_ref_replacement(num) {
+            Some(c) => (c, true),
 
-            0x01..=0x08 | 0x0B | 0x0D..=0x1F | 0x7F | 0xFDD0..=0xFDEF => (conv(self.num), true),
+            None => match num {
+                0x80..=0x9F | 0x01..=0x08 | 0x0B | 0x0D..=0x1F | 0x7F | 0xFDD0..=0xFDEF => {
+                    (conv(num), true)
+                },
 
-            n if (n & 0xFFFE) == 0xFFFE => (conv(n), true),
+                n if (n & 0xFFFE) == 0xFFFE => (conv(n), true),
 
-            n => (conv(n), false),
+                n => (conv(n), false),
+            },
         };
 
         if error {
@@ -269,6 +416,29 @@ impl CharRefTokenizer {
             tokenizer.emit_error(msg);
         }
 
+        if numeric_char_ref_replacement(num).is_some() {
+            match tokenizer.opts.invalid_charref_policy {
+                InvalidCharRefPolicy::SpecReplace => (),
+                InvalidCharRefPolicy::Error => {
+                    tokenizer.invalid_char_ref = Some(num);
+                    return self.finish_empty();
+                },
+                InvalidCharRefPolicy::Keep => {
+                    let mut text = StrTendril::from_char('#');
+                    if let Some(x) = self.hex_marker {
+                        text.push_char(x);
+                    }
+                    text.push_tendril(&self.digits);
+                    if semicolon {
+                        text.push_char(';');
+                    }
+                    tokenizer.unconsume(input, text);
+                    return self.finish_none();
+                },
+                InvalidCharRefPolicy::Drop => return self.finish_empty(),
+            }
+        }
+
         self.finish_one(c)
     }
 
@@ -306,8 +476,12 @@ impl CharRefTokenizer {
         tokenizer.emit_error(msg);
     }
 
-    fn unconsume_name(&mut self, input: &mut BufferQueue) {
-        input.push_front(self.name_buf_opt.take().unwrap());
+    fn unconsume_name<Sink: TokenSink>(
+        &mut self,
+        tokenizer: &mut Tokenizer<Sink>,
+        input: &mut BufferQueue,
+    ) {
+        tokenizer.unconsume(input, self.name_buf_opt.take().unwrap());
     }
 
     fn finish_named<Sink: TokenSink>(
@@ -331,7 +505,7 @@ impl CharRefTokenizer {
 
                     _ => (),
                 }
-                self.unconsume_name(input);
+                self.unconsume_name(tokenizer, input);
                 self.finish_none()
             },
 
@@ -384,10 +558,11 @@ impl CharRefTokenizer {
                 };
 
                 if unconsume_all {
-                    self.unconsume_name(input);
+                    self.unconsume_name(tokenizer, input);
                     self.finish_none()
                 } else {
-                    input.push_front(StrTendril::from_slice(&self.name_buf()[name_len..]));
+                    let rest = StrTendril::from_slice(&self.name_buf()[name_len..]);
+                    tokenizer.unconsume(input, rest);
                     self.result = Some(CharRef {
                         chars: [from_u32(c1).unwrap(), from_u32(c2).unwrap()],
                         num_chars: if c2 == 0 { 1 } else { 2 },
@@ -410,7 +585,7 @@ impl CharRefTokenizer {
             ';' => self.emit_name_error(tokenizer),
             _ => (),
         }
-        self.unconsume_name(input);
+        self.unconsume_name(tokenizer, input);
         self.finish_none()
     }
 
@@ -427,18 +602,18 @@ impl CharRefTokenizer {
 
                 Numeric(_) | NumericSemicolon => {
                     tokenizer.emit_error(Borrowed("EOF in numeric character reference"));
-                    self.finish_numeric(tokenizer);
+                    self.finish_numeric(tokenizer, input, false);
                 },
 
                 Named => drop(self.finish_named(tokenizer, input, None)),
 
                 BogusName => {
-                    self.unconsume_name(input);
+                    self.unconsume_name(tokenizer, input);
                     self.finish_none();
                 },
 
                 Octothorpe => {
-                    input.push_front(StrTendril::from_slice("#"));
+                    tokenizer.unconsume(input, StrTendril::from_slice("#"));
                     tokenizer.emit_error(Borrowed("EOF after '#' in character reference"));
                     self.finish_none();
                 },
@@ -446,3 +621,69 @@ impl CharRefTokenizer {
         }
     }
 }
+
+#[cfg(feature = "binary_tokens")]
+impl CharRefTokenizer {
+    /// Write the state of a character reference in progress, for
+    /// `serialize_tokenizer_state`.
+    pub(super) fn serialize<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        debug_assert!(self.result.is_none());
+        match self.state {
+            Begin => w.write_all(&[0])?,
+            Octothorpe => w.write_all(&[1])?,
+            Numeric(base) => {
+                w.write_all(&[2])?;
+                binary::write_u32(w, base)?;
+            },
+            NumericSemicolon => w.write_all(&[3])?,
+            Named => w.write_all(&[4])?,
+            BogusName => w.write_all(&[5])?,
+        }
+        binary::write_opt_char(w, self.addnl_allowed)?;
+        binary::write_u32(w, self.num)?;
+        binary::write_bool(w, self.num_too_big)?;
+        binary::write_bool(w, self.seen_digit)?;
+        binary::write_opt_char(w, self.hex_marker)?;
+        binary::write_str(w, &self.digits)?;
+        binary::write_opt_str(w, self.name_buf_opt.as_deref())?;
+        match self.name_match {
+            None => binary::write_bool(w, false)?,
+            Some((c1, c2)) => {
+                binary::write_bool(w, true)?;
+                binary::write_u32(w, c1)?;
+                binary::write_u32(w, c2)?;
+            },
+        }
+        binary::write_len(w, self.name_len)
+    }
+
+    /// Read back a character reference written by `serialize`.
+    pub(super) fn deserialize<R: Read>(r: &mut R) -> io::Result<CharRefTokenizer> {
+        let state = match binary::read_u8(r)? {
+            0 => Begin,
+            1 => Octothorpe,
+            2 => Numeric(binary::read_u32(r)?),
+            3 => NumericSemicolon,
+            4 => Named,
+            5 => BogusName,
+            _ => return Err(binary::invalid_data("unknown character reference state")),
+        };
+        Ok(CharRefTokenizer {
+            state,
+            addnl_allowed: binary::read_opt_char(r)?,
+            result: None,
+            num: binary::read_u32(r)?,
+            num_too_big: binary::read_bool(r)?,
+            seen_digit: binary::read_bool(r)?,
+            hex_marker: binary::read_opt_char(r)?,
+            digits: binary::read_str(r)?,
+            name_buf_opt: binary::read_opt_str(r)?,
+            name_match: if binary::read_bool(r)? {
+                Some((binary::read_u32(r)?, binary::read_u32(r)?))
+            } else {
+                None
+            },
+            name_len: binary::read_len(r)?,
+        })
+    }
+}
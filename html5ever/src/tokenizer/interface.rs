@@ -7,7 +7,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use crate::interface::Attribute;
+use crate::interface::{AmbiguousConstruct, Attribute};
 use crate::tendril::StrTendril;
 use crate::tokenizer::states;
 use crate::LocalName;
@@ -25,6 +25,9 @@ pub struct Doctype {
     pub public_id: Option<StrTendril>,
     pub system_id: Option<StrTendril>,
     pub force_quirks: bool,
+    /// The declaration as written, from `<!` up to and including the `>`,
+    /// if `TokenizerOpts::preserve_raw_doctype` is set.
+    pub raw: Option<StrTendril>,
 }
 
 impl Doctype {
@@ -34,6 +37,7 @@ impl Doctype {
             public_id: None,
             system_id: None,
             force_quirks: false,
+            raw: None,
         }
     }
 }
@@ -70,7 +74,19 @@ impl Tag {
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+/// A processing instruction, `<?target data?>`.  Only produced with
+/// `TokenizerOpts::pi_as_processing_instruction`, and passed to
+/// `TokenSink::process_pi` rather than as a `Token`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Pi {
+    /// The name after the `<?`, up to the first whitespace.
+    pub target: StrTendril,
+
+    /// The rest, after the whitespace, up to the `?>`.
+    pub data: StrTendril,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
 pub enum Token {
     DoctypeToken(Doctype),
     TagToken(Tag),
@@ -81,6 +97,36 @@ pub enum Token {
     ParseError(Cow<'static, str>),
 }
 
+/// The kind of a `Token`, without its contents.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TokenKind {
+    Doctype,
+    StartTag,
+    EndTag,
+    Comment,
+    Pi,
+    Characters,
+    NullCharacter,
+    Eof,
+}
+
+impl Token {
+    /// The kind of this token, or `None` for a `ParseError`, which is not
+    /// part of the document.
+    pub fn kind(&self) -> Option<TokenKind> {
+        Some(match *self {
+            DoctypeToken(_) => TokenKind::Doctype,
+            TagToken(Tag { kind: StartTag, .. }) => TokenKind::StartTag,
+            TagToken(Tag { kind: EndTag, .. }) => TokenKind::EndTag,
+            CommentToken(_) => TokenKind::Comment,
+            CharacterTokens(_) => TokenKind::Characters,
+            NullCharacterToken => TokenKind::NullCharacter,
+            EOFToken => TokenKind::Eof,
+            ParseError(_) => return None,
+        })
+    }
+}
+
 #[derive(Debug, PartialEq)]
 #[must_use]
 pub enum TokenSinkResult<Handle> {
@@ -97,9 +143,34 @@ pub trait TokenSink {
     /// Process a token.
     fn process_token(&mut self, token: Token, line_number: u64) -> TokenSinkResult<Self::Handle>;
 
+    /// Process a processing instruction, when the
+    /// `pi_as_processing_instruction` tokenizer option is set.  By default,
+    /// it is processed as the comment `?target data?`.
+    fn process_pi(&mut self, pi: Pi, line_number: u64) -> TokenSinkResult<Self::Handle> {
+        let mut text = StrTendril::from_char('?');
+        text.push_tendril(&pi.target);
+        if !pi.data.is_empty() {
+            text.push_char(' ');
+            text.push_tendril(&pi.data);
+        }
+        text.push_char('?');
+        self.process_token(CommentToken(text), line_number)
+    }
+
     // Signal sink that tokenization reached the end.
     fn end(&mut self) {}
 
+    /// Called when the input ends in the middle of a token, as in `<a
+    /// href=`, before the tokens for the end of the input are processed.
+    fn eof_in_token(&mut self) {}
+
+    /// Called before the tokenizer asks about the tree with
+    /// `adjusted_current_node_present_but_not_in_html_namespace` or
+    /// `keep_tag_case`.  A sink that holds back tokens, as the tree builder
+    /// does with character tokens, should process them here so that the
+    /// answer takes them into account.
+    fn flush_pending(&mut self) {}
+
     /// Used in the markup declaration open state. By default, this always
     /// returns false and thus all CDATA sections are tokenized as bogus
     /// comments.
@@ -107,4 +178,27 @@ pub trait TokenSink {
     fn adjusted_current_node_present_but_not_in_html_namespace(&self) -> bool {
         false
     }
+
+    /// Called when a tag starts in the data state.  If this returns true,
+    /// the tag's name and attribute names keep their case instead of being
+    /// lowercased.  By default, this always returns false.
+    fn keep_tag_case(&self) -> bool {
+        false
+    }
+
+    /// Receive markup that parsers are known to disagree on, when the
+    /// `flag_ambiguous_constructs` tokenizer option is set.
+    fn ambiguous_construct(&mut self, _construct: AmbiguousConstruct, _line_number: u64) {}
+
+    /// Receive the names of an attribute and of a later duplicate of it on
+    /// the same tag, as written before case folding, when the
+    /// `flag_ambiguous_constructs` tokenizer option is set.  Called after
+    /// `AmbiguousConstruct::DuplicateAttribute` is reported.
+    fn duplicate_attribute(&mut self, _first: &str, _duplicate: &str, _line_number: u64) {}
+
+    /// Checked between tokens.  Once this returns false, the tokenizer stops
+    /// consuming input, and drops whatever it is fed.
+    fn should_continue(&self) -> bool {
+        true
+    }
 }
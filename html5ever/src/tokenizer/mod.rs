@@ -10,9 +10,19 @@
 //! The HTML5 tokenizer.
 
 pub use self::interface::{CharacterTokens, EOFToken, NullCharacterToken, ParseError};
-pub use self::interface::{CommentToken, DoctypeToken, TagToken, Token};
-pub use self::interface::{Doctype, EndTag, StartTag, Tag, TagKind};
+pub use self::interface::{CommentToken, DoctypeToken, TagToken, Token, TokenKind};
+pub use self::interface::{Doctype, EndTag, Pi, StartTag, Tag, TagKind};
 pub use self::interface::{TokenSink, TokenSinkResult};
+pub use self::tokens::{tokenize, Tokens};
+pub use self::transform::{TokenTransform, Transformed};
+pub use crate::interface::AmbiguousConstruct;
+
+pub use self::char_ref::{decode_attribute_value, numeric_char_ref_replacement};
+
+#[cfg(feature = "binary_tokens")]
+pub use self::binary::{deserialize_tokenizer_state, serialize_tokenizer_state};
+#[cfg(feature = "binary_tokens")]
+pub use self::binary::{deserialize_tokens, serialize_tokens, DeserializeTokens};
 
 use self::states::{DoctypeIdKind, Public, System};
 use self::states::{DoubleEscaped, Escaped};
@@ -25,19 +35,25 @@ use crate::util::str::lower_ascii_letter;
 
 use log::debug;
 use mac::{_tt_as_expr_hack, format_if, matches};
-use markup5ever::{namespace_url, ns, small_char_set};
+use markup5ever::{local_name, namespace_url, ns, small_char_set};
 use std::borrow::Cow::{self, Borrowed};
+use std::cell::Cell;
 use std::collections::BTreeMap;
 use std::default::Default;
 use std::mem::replace;
+use std::rc::Rc;
 
 pub use crate::buffer_queue::{BufferQueue, FromSet, NotFromSet, SetResult};
 use crate::tendril::StrTendril;
 use crate::{Attribute, LocalName, QualName, SmallCharSet};
 
+#[cfg(feature = "binary_tokens")]
+mod binary;
 mod char_ref;
 mod interface;
 pub mod states;
+mod tokens;
+mod transform;
 
 pub enum ProcessResult<Handle> {
     Continue,
@@ -58,6 +74,62 @@ fn option_push(opt_str: &mut Option<StrTendril>, c: char) {
     }
 }
 
+// Push `s` to `buf`, keeping at most `max` bytes.  Returns true when this
+// is the first push that doesn't fit, and sets `truncated` so that later
+// pushes are dropped.
+fn push_limited(buf: &mut StrTendril, s: &str, max: Option<usize>, truncated: &mut bool) -> bool {
+    let max = match max {
+        Some(max) => max,
+        None => {
+            buf.push_slice(s);
+            return false;
+        },
+    };
+    if *truncated {
+        return false;
+    }
+    let room = max.saturating_sub(buf.len());
+    if s.len() <= room {
+        buf.push_slice(s);
+        return false;
+    }
+    let mut end = room;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    buf.push_slice(&s[..end]);
+    *truncated = true;
+    true
+}
+
+// Elements whose `/>` is not ambiguous: void elements, which have no end
+// tag anyway, and `<svg>` and `<math>`, where it does close the element.
+fn is_void_or_foreign_root(name: &LocalName) -> bool {
+    matches!(
+        *name,
+        local_name!("area") |
+            local_name!("base") |
+            local_name!("basefont") |
+            local_name!("bgsound") |
+            local_name!("br") |
+            local_name!("col") |
+            local_name!("embed") |
+            local_name!("frame") |
+            local_name!("hr") |
+            local_name!("img") |
+            local_name!("input") |
+            local_name!("keygen") |
+            local_name!("link") |
+            local_name!("meta") |
+            local_name!("param") |
+            local_name!("source") |
+            local_name!("track") |
+            local_name!("wbr") |
+            local_name!("svg") |
+            local_name!("math")
+    )
+}
+
 /// Tokenizer options, with an impl for `Default`.
 #[derive(Clone)]
 pub struct TokenizerOpts {
@@ -65,14 +137,97 @@ pub struct TokenizerOpts {
     /// performance penalty?  Default: false
     pub exact_errors: bool,
 
+    /// Report errors in comments where the current spec does, by its
+    /// names: "Abrupt closing of empty comment", "Nested comment" and
+    /// "Incorrectly closed comment".  Otherwise they are reported where the
+    /// older spec the rest of the tokenizer follows does, as bad
+    /// characters, like after the `--` of `<!--a--b-->`.  The comments
+    /// themselves are the same either way.  Default: false
+    pub named_comment_errors: bool,
+
     /// Discard a `U+FEFF BYTE ORDER MARK` if we see one at the beginning
     /// of the stream?  Default: true
     pub discard_bom: bool,
 
+    /// Drop every `U+FEFF` from text, not just a byte order mark at the
+    /// start of the stream?  This is not what the spec does: after the
+    /// start, `U+FEFF` is a zero width no-break space and is kept like any
+    /// other character.  It is for documents with stray byte order marks
+    /// left over from concatenating files.  Only character data is changed,
+    /// including `U+FEFF` from character references; attribute values and
+    /// comments keep theirs.  Default: false
+    pub strip_all_bom: bool,
+
     /// Keep a record of how long we spent in each state?  Printed
     /// when `end()` is called.  Default: false
     pub profile: bool,
 
+    /// Keep duplicate attributes on a tag, in source order, instead of
+    /// dropping all but the first?  The duplicates reach the sink through
+    /// `TreeSink::create_element_raw_attrs`, with the tree builder's option
+    /// of the same name.  Default: false
+    pub preserve_all_attributes: bool,
+
+    /// Keep the text of the `DOCTYPE` declaration as written, in
+    /// `Doctype::raw`?  Newlines are normalized as in the rest of the
+    /// input.  Default: false
+    pub preserve_raw_doctype: bool,
+
+    /// What to do with numeric character references that the spec replaces
+    /// with another character, like `&#0;` or `&#x80;`.
+    /// Default: `InvalidCharRefPolicy::SpecReplace`
+    pub invalid_charref_policy: InvalidCharRefPolicy,
+
+    /// Report markup that parsers are known to disagree on to
+    /// `TokenSink::ambiguous_construct`, and the names of duplicate
+    /// attributes as written to `TokenSink::duplicate_attribute`?  See
+    /// `AmbiguousConstruct` for the constructs reported.  Default: false
+    pub flag_ambiguous_constructs: bool,
+
+    /// What to do with `U+0000 NULL` characters in the input.
+    /// Default: `NullHandling::Spec`
+    pub null_handling: NullHandling,
+
+    /// Stop decoding character references after this many, as a bound on
+    /// the work a hostile document can cause.  Every `&` in text or an
+    /// attribute value counts, whether or not it starts a valid reference.
+    /// Past the limit a parse error is reported once, and each `&` is left
+    /// as text.  Default: `None`, no limit
+    pub max_entity_expansions: Option<usize>,
+
+    /// Tokenize `<?target data?>` as a processing instruction, as XML
+    /// does, instead of as a bogus comment ending at the first `>`.  The
+    /// tree builder puts it where a comment would go, through
+    /// `TreeSink::create_pi`.  Not conforming HTML.  Default: false
+    pub pi_as_processing_instruction: bool,
+
+    /// Replace each `\r\n` and lone `\r` in the input with `\n`, as the
+    /// spec's input stream preprocessing does?  Turning this off keeps
+    /// every `\r` in text, attribute values and comments, so they can be
+    /// mapped back to the source byte for byte, but the tokens are then no
+    /// longer what a browser would see.  A `\r` still separates attributes
+    /// like other whitespace, and a `\r\n` still counts as one line.
+    /// Default: true
+    pub normalize_newlines: bool,
+
+    /// Keep at most this many bytes of a comment, measured in UTF-8, as a
+    /// bound on the memory an unterminated comment can take.  The rest of
+    /// the comment is still read up to its end, without being kept, so
+    /// parsing goes on after the `-->` as usual.  The comment is cut at the
+    /// last character that fits and a parse error is reported.  Bogus
+    /// comments and processing instructions are limited the same way.
+    /// Default: `None`, no limit
+    pub max_comment_length: Option<usize>,
+
+    /// Keep at most this many bytes of an attribute value, measured in
+    /// UTF-8 after replacing character references, as a bound on the
+    /// memory one value like a large `data:` URL can take.  The rest of
+    /// the value is still read up to its end, without being kept, so the
+    /// attributes after it are parsed as usual.  The value is cut at the
+    /// last character that fits and a parse error is reported.
+    /// Default: `None`, no limit
+    pub max_attribute_value_length: Option<usize>,
+
     /// Initial state override.  Only the test runner should use
     /// a non-`None` value!
     pub initial_state: Option<states::State>,
@@ -89,14 +244,154 @@ impl Default for TokenizerOpts {
     fn default() -> TokenizerOpts {
         TokenizerOpts {
             exact_errors: false,
+            named_comment_errors: false,
             discard_bom: true,
+            strip_all_bom: false,
             profile: false,
+            preserve_all_attributes: false,
+            preserve_raw_doctype: false,
+            invalid_charref_policy: InvalidCharRefPolicy::SpecReplace,
+            null_handling: NullHandling::Spec,
+            flag_ambiguous_constructs: false,
+            max_entity_expansions: None,
+            pi_as_processing_instruction: false,
+            normalize_newlines: true,
+            max_comment_length: None,
+            max_attribute_value_length: None,
             initial_state: None,
             last_start_tag_name: None,
         }
     }
 }
 
+/// How the tokenizer handles a numeric character reference whose value the
+/// spec replaces: zero, a surrogate, a value above U+10FFFF, or one of the
+/// C1 controls that `numeric_char_ref_replacement` maps to a Windows-1252
+/// character.  A parse error is reported in each case.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvalidCharRefPolicy {
+    /// Replace the reference as the spec says.
+    SpecReplace,
+
+    /// Stop tokenizing at the reference, for pipelines that must reject
+    /// such input.  The reference and the rest of the input are dropped,
+    /// as if the sink's `should_continue` had returned false, and
+    /// `Tokenizer::invalid_char_ref` returns the reference's value.  `end`
+    /// still ends the document there.
+    Error,
+
+    /// Leave the reference in the text as written.
+    Keep,
+
+    /// Remove the reference from the text.
+    Drop,
+}
+
+/// How `U+0000 NULL` characters in the input are handled.  A parse error is
+/// reported for each of them in every case.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NullHandling {
+    /// Follow the spec, which handles them in two layers.  The tokenizer
+    /// replaces them with `U+FFFD REPLACEMENT CHARACTER` in most places,
+    /// such as attribute values, comments, `<textarea>`, `<script>` and
+    /// `<style>`.  In text it emits them as `NullCharacterToken`s instead,
+    /// and the tree builder drops those in HTML content but replaces them
+    /// with `U+FFFD` in SVG and MathML.  So `<p>a\0b` gives the text `ab`
+    /// while `<textarea>a\0b` gives `a\u{FFFD}b`.
+    Spec,
+
+    /// Replace them with `U+FFFD` everywhere, before tokenizing.
+    ///
+    /// This is done by the tokenizer alone.  The tree builder never sees a
+    /// null, so its own null rules don't apply.
+    Replace,
+
+    /// Drop them everywhere, before tokenizing.
+    ///
+    /// As with `Replace`, this is done by the tokenizer alone.
+    Drop,
+}
+
+/// A position in the input.  Lines and columns count from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    /// The line number.
+    pub line: u64,
+
+    /// The column, in characters.
+    pub column: u64,
+}
+
+impl Default for Position {
+    fn default() -> Position {
+        Position { line: 1, column: 1 }
+    }
+}
+
+/// A flag that pauses a tokenizer between tokens, set with
+/// `Tokenizer::set_pause_handle`.  Clones share the flag, so a sink can keep
+/// one and call `pause` from inside a callback.
+#[derive(Clone, Debug, Default)]
+pub struct PauseHandle(Rc<Cell<bool>>);
+
+impl PauseHandle {
+    /// Stop tokenizing once the current step of the tokenizer is done.
+    pub fn pause(&self) {
+        self.0.set(true);
+    }
+
+    /// Let tokenizing continue the next time the tokenizer is fed.
+    pub fn resume(&self) {
+        self.0.set(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0.get()
+    }
+}
+
+/// A callback for `Tokenizer::set_on_state_change`, called with the old
+/// state, the new state and the character that caused the change.
+pub type StateChangeCallback = Box<dyn FnMut(states::State, states::State, char)>;
+
+/// The state of a `Tokenizer` between two inputs, saved by
+/// `Tokenizer::save_state` and restored by `Tokenizer::load_state`.
+///
+/// This covers the tokenizer only.  A tree builder cannot be saved, so when
+/// shards of a document are tokenized on different machines, the tokens
+/// must still be sent to a single tree builder, in order.  With the
+/// `binary_tokens` feature the state can be written and read with
+/// `serialize_tokenizer_state` and `deserialize_tokenizer_state`.
+#[derive(Clone, Debug)]
+pub struct TokenizerState {
+    state: states::State,
+    char_ref_tokenizer: Option<Box<CharRefTokenizer>>,
+    current_char: char,
+    reconsume: bool,
+    ignore_lf: bool,
+    discard_bom: bool,
+    current_tag_kind: TagKind,
+    current_tag_name: StrTendril,
+    current_tag_self_closing: bool,
+    keep_tag_case: bool,
+    current_tag_attrs: Vec<Attribute>,
+    current_tag_raw_attr_names: Vec<StrTendril>,
+    current_attr_name: StrTendril,
+    current_attr_raw_name: StrTendril,
+    current_attr_value: StrTendril,
+    current_comment: StrTendril,
+    current_doctype: Doctype,
+    raw_doctype: Option<StrTendril>,
+    last_start_tag_name: Option<LocalName>,
+    temp_buf: StrTendril,
+    comment_truncated: bool,
+    attr_value_truncated: bool,
+    char_ref_count: usize,
+    current_line: u64,
+    current_column: u64,
+    last_column: u64,
+}
+
 /// The HTML tokenizer.
 pub struct Tokenizer<Sink> {
     /// Options controlling the behavior of the tokenizer.
@@ -116,6 +411,14 @@ pub struct Tokenizer<Sink> {
     /// one at the moment.
     char_ref_tokenizer: Option<Box<CharRefTokenizer>>,
 
+    /// Number of character references we have started to decode.  Counts
+    /// one past `max_entity_expansions` once the limit is reported.
+    char_ref_count: usize,
+
+    /// The value of the numeric character reference that stopped tokenizing
+    /// with `InvalidCharRefPolicy::Error`.
+    invalid_char_ref: Option<u32>,
+
     /// Current input character.  Just consumed, may reconsume.
     current_char: char,
 
@@ -139,12 +442,23 @@ pub struct Tokenizer<Sink> {
     /// Current tag is self-closing?
     current_tag_self_closing: bool,
 
+    /// Keep the case of the current tag's name and attribute names?
+    keep_tag_case: bool,
+
     /// Current tag attributes.
     current_tag_attrs: Vec<Attribute>,
 
+    /// The names of the current tag's attributes as written, before case
+    /// folding, if `flag_ambiguous_constructs` is set.
+    current_tag_raw_attr_names: Vec<StrTendril>,
+
     /// Current attribute name.
     current_attr_name: StrTendril,
 
+    /// Current attribute name as written, if `flag_ambiguous_constructs` is
+    /// set.
+    current_attr_raw_name: StrTendril,
+
     /// Current attribute value.
     current_attr_value: StrTendril,
 
@@ -154,9 +468,18 @@ pub struct Tokenizer<Sink> {
     /// Current doctype token.
     current_doctype: Doctype,
 
+    /// Source text of the current doctype, if `preserve_raw_doctype` is set.
+    raw_doctype: Option<StrTendril>,
+
     /// Last start tag name, for use in checking "appropriate end tag".
     last_start_tag_name: Option<LocalName>,
 
+    /// Was the current comment cut at `max_comment_length`?
+    comment_truncated: bool,
+
+    /// Was the current attribute value cut at `max_attribute_value_length`?
+    attr_value_truncated: bool,
+
     /// The "temporary buffer" mentioned in the spec.
     temp_buf: StrTendril,
 
@@ -168,6 +491,19 @@ pub struct Tokenizer<Sink> {
 
     /// Track current line
     current_line: u64,
+
+    /// Track current column, and the column before the last character.
+    current_column: u64,
+    last_column: u64,
+
+    /// Advance tabs to the next multiple of this many columns, if set.
+    tab_width: Option<usize>,
+
+    /// Stops the tokenizer between tokens when paused, if set.
+    pause: Option<PauseHandle>,
+
+    /// Called when the state changes, if set.
+    on_state_change: Option<StateChangeCallback>,
 }
 
 impl<Sink: TokenSink> Tokenizer<Sink> {
@@ -184,6 +520,8 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             sink,
             state,
             char_ref_tokenizer: None,
+            char_ref_count: 0,
+            invalid_char_ref: None,
             at_eof: false,
             current_char: '\0',
             reconsume: false,
@@ -192,27 +530,44 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             current_tag_kind: StartTag,
             current_tag_name: StrTendril::new(),
             current_tag_self_closing: false,
+            keep_tag_case: false,
             current_tag_attrs: vec![],
+            current_tag_raw_attr_names: vec![],
             current_attr_name: StrTendril::new(),
+            current_attr_raw_name: StrTendril::new(),
             current_attr_value: StrTendril::new(),
             current_comment: StrTendril::new(),
             current_doctype: Doctype::new(),
+            raw_doctype: None,
             last_start_tag_name: start_tag_name,
+            comment_truncated: false,
+            attr_value_truncated: false,
             temp_buf: StrTendril::new(),
             state_profile: BTreeMap::new(),
             time_in_sink: 0,
             current_line: 1,
+            current_column: 1,
+            last_column: 1,
+            tab_width: None,
+            pause: None,
+            on_state_change: None,
         }
     }
 
     /// Feed an input string into the tokenizer.
     pub fn feed(&mut self, input: &mut BufferQueue) -> TokenizerResult<Sink::Handle> {
-        if input.is_empty() {
+        if input.is_empty() || self.is_paused() {
+            return TokenizerResult::Done;
+        }
+        if self.invalid_char_ref.is_some() {
+            input.clear();
             return TokenizerResult::Done;
         }
 
         if self.discard_bom {
             if let Some(c) = input.peek() {
+                // Only the first character of the stream can be a BOM.
+                self.discard_bom = false;
                 if c == '\u{feff}' {
                     input.next();
                 }
@@ -221,13 +576,162 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             }
         };
 
-        self.run(input)
+        let result = self.run(input);
+        if !self.should_continue() {
+            // The rest of the input will never be tokenized.
+            input.clear();
+        }
+        result
     }
 
     pub fn set_plaintext_state(&mut self) {
         self.state = states::Plaintext;
     }
 
+    /// Count lines from `position` instead of the start of the input, for
+    /// input taken from the middle of a larger text.  Call this before
+    /// feeding any input.
+    pub fn set_position(&mut self, position: Position) {
+        self.current_line = position.line;
+        self.current_column = position.column;
+        self.last_column = position.column;
+    }
+
+    /// Advance tabs to the next multiple of `tab_width` columns, as an
+    /// editor would, instead of counting them as one column.
+    pub fn set_tab_width(&mut self, tab_width: Option<usize>) {
+        self.tab_width = tab_width;
+    }
+
+    /// The column after the last character consumed, counting from 1.
+    ///
+    /// Each character is one column, except for tabs with `set_tab_width`.
+    /// A line starts after a `\n` or `\r`, as for line numbers.
+    pub fn current_column(&self) -> u64 {
+        self.current_column
+    }
+
+    /// Call `on_state_change` each time the tokenizer moves from one state
+    /// to another, with the old state, the new state and the input
+    /// character that it was processing.
+    ///
+    /// This is for debugging and for visualizing the state machine.  It
+    /// checks the state after every step, which makes tokenizing much
+    /// slower.  Without a callback there is no extra work.  Changes made
+    /// at the end of the input, by `end`, are not reported, as there is no
+    /// character to report them with.
+    pub fn set_on_state_change(&mut self, on_state_change: Option<StateChangeCallback>) {
+        self.on_state_change = on_state_change;
+    }
+
+    /// Stop tokenizing whenever `pause` is paused, as if the input had run
+    /// out.  The tokenizer checks it after each step of the state machine,
+    /// which usually emits a single token, but a step that emits several,
+    /// such as the characters of a `</x` that turned out not to be an end
+    /// tag in a `<script>`, emits all of them first.  `feed` returns right
+    /// away while paused, keeping its input for the next `feed` after
+    /// `PauseHandle::resume`.  `end` ignores the pause.
+    pub fn set_pause_handle(&mut self, pause: Option<PauseHandle>) {
+        self.pause = pause;
+    }
+
+    /// The value of the numeric character reference that stopped tokenizing,
+    /// if one did with `InvalidCharRefPolicy::Error`.
+    pub fn invalid_char_ref(&self) -> Option<u32> {
+        self.invalid_char_ref
+    }
+
+    fn should_continue(&self) -> bool {
+        self.invalid_char_ref.is_none() && self.sink.should_continue()
+    }
+
+    fn is_paused(&self) -> bool {
+        match self.pause {
+            Some(ref pause) => pause.is_paused(),
+            None => false,
+        }
+    }
+
+    /// Is the tokenizer in the data state, between tokens, with nothing
+    /// left over from the input so far?
+    pub(crate) fn between_tokens(&self) -> bool {
+        self.state == states::Data &&
+            self.char_ref_tokenizer.is_none() &&
+            !self.reconsume &&
+            !self.at_eof
+    }
+
+    /// Save the tokenizer's state, to continue tokenizing later with
+    /// `load_state`, possibly on another machine.
+    ///
+    /// Call this between calls to `feed`, not after `end`.  Input that
+    /// `feed` left in the `BufferQueue`, because the tokenizer needs more of
+    /// it to decide what to do, is not part of the state: pass it on along
+    /// with the state, ahead of the rest of the input.
+    pub fn save_state(&self) -> TokenizerState {
+        TokenizerState {
+            state: self.state,
+            char_ref_tokenizer: self.char_ref_tokenizer.clone(),
+            current_char: self.current_char,
+            reconsume: self.reconsume,
+            ignore_lf: self.ignore_lf,
+            discard_bom: self.discard_bom,
+            current_tag_kind: self.current_tag_kind,
+            current_tag_name: self.current_tag_name.clone(),
+            current_tag_self_closing: self.current_tag_self_closing,
+            keep_tag_case: self.keep_tag_case,
+            current_tag_attrs: self.current_tag_attrs.clone(),
+            current_tag_raw_attr_names: self.current_tag_raw_attr_names.clone(),
+            current_attr_name: self.current_attr_name.clone(),
+            current_attr_raw_name: self.current_attr_raw_name.clone(),
+            current_attr_value: self.current_attr_value.clone(),
+            current_comment: self.current_comment.clone(),
+            current_doctype: self.current_doctype.clone(),
+            raw_doctype: self.raw_doctype.clone(),
+            last_start_tag_name: self.last_start_tag_name.clone(),
+            comment_truncated: self.comment_truncated,
+            attr_value_truncated: self.attr_value_truncated,
+            char_ref_count: self.char_ref_count,
+            temp_buf: self.temp_buf.clone(),
+            current_line: self.current_line,
+            current_column: self.current_column,
+            last_column: self.last_column,
+        }
+    }
+
+    /// Continue from a state saved by `save_state`, replacing this
+    /// tokenizer's state.  The options are not part of the state; use the
+    /// same options as the tokenizer that saved it.
+    pub fn load_state(&mut self, state: TokenizerState) {
+        self.state = state.state;
+        self.at_eof = false;
+        self.char_ref_tokenizer = state.char_ref_tokenizer;
+        self.current_char = state.current_char;
+        self.reconsume = state.reconsume;
+        self.ignore_lf = state.ignore_lf;
+        self.discard_bom = state.discard_bom;
+        self.current_tag_kind = state.current_tag_kind;
+        self.current_tag_name = state.current_tag_name;
+        self.current_tag_self_closing = state.current_tag_self_closing;
+        self.keep_tag_case = state.keep_tag_case;
+        self.current_tag_attrs = state.current_tag_attrs;
+        self.current_tag_raw_attr_names = state.current_tag_raw_attr_names;
+        self.current_attr_name = state.current_attr_name;
+        self.current_attr_raw_name = state.current_attr_raw_name;
+        self.current_attr_value = state.current_attr_value;
+        self.current_comment = state.current_comment;
+        self.current_doctype = state.current_doctype;
+        self.raw_doctype = state.raw_doctype;
+        self.last_start_tag_name = state.last_start_tag_name;
+        self.comment_truncated = state.comment_truncated;
+        self.attr_value_truncated = state.attr_value_truncated;
+        self.char_ref_count = state.char_ref_count;
+        self.temp_buf = state.temp_buf;
+        self.current_line = state.current_line;
+        self.current_column = state.current_column;
+        self.last_column = state.last_column;
+    }
+
     fn process_token(&mut self, token: Token) -> TokenSinkResult<Sink::Handle> {
         if self.opts.profile {
             let (ret, dt) = time!(self.sink.process_token(token, self.current_line));
@@ -249,20 +753,45 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
     // Get the next input character, which might be the character
     // 'c' that we already consumed from the buffers.
     fn get_preprocessed_char(&mut self, mut c: char, input: &mut BufferQueue) -> Option<char> {
-        if self.ignore_lf {
-            self.ignore_lf = false;
+        loop {
+            if self.ignore_lf {
+                self.ignore_lf = false;
+                if c == '\n' {
+                    if !self.opts.normalize_newlines {
+                        // The line was counted at the '\r'.
+                        self.current_char = c;
+                        return Some(c);
+                    }
+                    c = unwrap_or_return!(input.next(), None);
+                }
+            }
+
+            if c == '\r' {
+                self.ignore_lf = true;
+                if self.opts.normalize_newlines {
+                    c = '\n';
+                } else {
+                    self.current_line += 1;
+                }
+            }
+
             if c == '\n' {
-                c = unwrap_or_return!(input.next(), None);
+                self.current_line += 1;
             }
-        }
 
-        if c == '\r' {
-            self.ignore_lf = true;
-            c = '\n';
-        }
+            if c == '\0' && self.opts.null_handling != NullHandling::Spec {
+                self.emit_error(Borrowed("Unexpected null character"));
+                if self.opts.null_handling == NullHandling::Drop {
+                    // Loop rather than recurse, so a long run of nulls
+                    // can't overflow the stack.
+                    self.advance_column(c);
+                    c = unwrap_or_return!(input.next(), None);
+                    continue;
+                }
+                c = '\u{fffd}';
+            }
 
-        if c == '\n' {
-            self.current_line += 1;
+            break;
         }
 
         if self.opts.exact_errors &&
@@ -277,10 +806,33 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
         }
 
         debug!("got character {}", c);
+        self.advance_column(c);
         self.current_char = c;
         Some(c)
     }
 
+    fn advance_column(&mut self, c: char) {
+        self.last_column = self.current_column;
+        self.current_column = match (c, self.tab_width) {
+            ('\n', _) | ('\r', _) => 1,
+            ('\t', Some(width)) if width > 0 => {
+                let width = width as u64;
+                (self.current_column - 1) / width * width + width + 1
+            },
+            _ => self.current_column + 1,
+        };
+    }
+
+    // Push back characters that were consumed, ending with the last one.
+    // Only the last character may be a tab or a newline.
+    fn unconsume(&mut self, input: &mut BufferQueue, buf: StrTendril) {
+        let len = buf.chars().count() as u64;
+        if len > 0 {
+            self.current_column = self.last_column - (len - 1);
+            input.push_front(buf);
+        }
+    }
+
     //§ tokenization
     // Get the next input character, if one is available.
     fn get_char(&mut self, input: &mut BufferQueue) -> Option<char> {
@@ -288,9 +840,13 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             self.reconsume = false;
             Some(self.current_char)
         } else {
-            input
+            let c = input
                 .next()
-                .and_then(|c| self.get_preprocessed_char(c, input))
+                .and_then(|c| self.get_preprocessed_char(c, input));
+            if let (Some(c), Some(raw)) = (c, self.raw_doctype.as_mut()) {
+                raw.push_char(c);
+            }
+            c
         }
     }
 
@@ -311,7 +867,13 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             // NB: We don't set self.current_char for a run of characters not
             // in the set.  It shouldn't matter for the codepaths that use
             // this.
-            _ => d,
+            Some(NotFromSet(ref run)) => {
+                for c in run.chars() {
+                    self.advance_column(c);
+                }
+                d
+            },
+            None => None,
         }
     }
 
@@ -335,10 +897,49 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
                 }
                 None
             },
-            Some(matched) => Some(matched),
+            Some(matched) => {
+                if matched {
+                    self.current_column += pat.len() as u64;
+                }
+                Some(matched)
+            },
         }
     }
 
+    // Check for one of the doctype keywords, with an ASCII case-insensitive
+    // `eat`.  If the raw doctype is preserved, add the keyword to it as
+    // written; `DOCTYPE` starts it.
+    fn eat_keyword(&mut self, input: &mut BufferQueue, pat: &str) -> Option<bool> {
+        if !self.opts.preserve_raw_doctype {
+            return self.eat(input, pat, u8::eq_ignore_ascii_case);
+        }
+        input.push_front(replace(&mut self.temp_buf, StrTendril::new()));
+        let mut keyword = StrTendril::new();
+        for pat in pat.chars() {
+            match input.next() {
+                Some(c) if c.eq_ignore_ascii_case(&pat) => keyword.push_char(c),
+                Some(c) => {
+                    input.push_front(StrTendril::from_char(c));
+                    input.push_front(keyword);
+                    return Some(false);
+                },
+                None if self.at_eof => {
+                    input.push_front(keyword);
+                    return Some(false);
+                },
+                None => {
+                    self.temp_buf = keyword;
+                    return None;
+                },
+            }
+        }
+        self.current_column += keyword.len() as u64;
+        self.raw_doctype
+            .get_or_insert_with(|| StrTendril::from_slice("<!"))
+            .push_tendril(&keyword);
+        Some(true)
+    }
+
     /// Run the state machine for as long as we can.
     fn run(&mut self, input: &mut BufferQueue) -> TokenizerResult<Sink::Handle> {
         if self.opts.profile {
@@ -359,6 +960,25 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
                     self.state_profile.insert(state, dt);
                 }
                 match run {
+                    ProcessResult::Continue if !self.should_continue() => break,
+                    ProcessResult::Continue if self.is_paused() && !self.at_eof => break,
+                    ProcessResult::Continue => (),
+                    ProcessResult::Suspend => break,
+                    ProcessResult::Script(node) => return TokenizerResult::Script(node),
+                }
+            }
+        } else if self.on_state_change.is_some() {
+            loop {
+                let state = self.state;
+                let run = self.step(input);
+                if self.state != state {
+                    if let Some(ref mut on_state_change) = self.on_state_change {
+                        on_state_change(state, self.state, self.current_char);
+                    }
+                }
+                match run {
+                    ProcessResult::Continue if !self.should_continue() => break,
+                    ProcessResult::Continue if self.is_paused() && !self.at_eof => break,
                     ProcessResult::Continue => (),
                     ProcessResult::Suspend => break,
                     ProcessResult::Script(node) => return TokenizerResult::Script(node),
@@ -367,6 +987,8 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
         } else {
             loop {
                 match self.step(input) {
+                    ProcessResult::Continue if !self.should_continue() => break,
+                    ProcessResult::Continue if self.is_paused() && !self.at_eof => break,
                     ProcessResult::Continue => (),
                     ProcessResult::Suspend => break,
                     ProcessResult::Script(node) => return TokenizerResult::Script(node),
@@ -387,6 +1009,28 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
         self.emit_error(msg);
     }
 
+    /// A comment error by the current spec, with `named_comment_errors`.
+    fn comment_error(&mut self, name: &'static str) {
+        if self.opts.named_comment_errors {
+            let msg = format_if!(
+                self.opts.exact_errors,
+                name,
+                "{}: saw {} in state {:?}",
+                name,
+                self.current_char,
+                self.state
+            );
+            self.emit_error(msg);
+        }
+    }
+
+    /// A comment error by the older spec, without `named_comment_errors`.
+    fn legacy_comment_error(&mut self) {
+        if !self.opts.named_comment_errors {
+            self.bad_char_error();
+        }
+    }
+
     fn bad_eof_error(&mut self) {
         let msg = format_if!(
             self.opts.exact_errors,
@@ -398,6 +1042,9 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
     }
 
     fn emit_char(&mut self, c: char) {
+        if c == '\u{feff}' && self.opts.strip_all_bom {
+            return;
+        }
         self.process_token_and_continue(match c {
             '\0' => NullCharacterToken,
             _ => CharacterTokens(StrTendril::from_char(c)),
@@ -406,6 +1053,13 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
 
     // The string must not contain '\0'!
     fn emit_chars(&mut self, b: StrTendril) {
+        if self.opts.strip_all_bom && b.contains('\u{feff}') {
+            let stripped = b.replace('\u{feff}', "");
+            if !stripped.is_empty() {
+                self.process_token_and_continue(CharacterTokens(stripped.into()));
+            }
+            return;
+        }
         self.process_token_and_continue(CharacterTokens(b));
     }
 
@@ -417,11 +1071,27 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
 
         match self.current_tag_kind {
             StartTag => {
-                self.last_start_tag_name = Some(name.clone());
+                if self.current_tag_self_closing &&
+                    self.opts.flag_ambiguous_constructs &&
+                    !is_void_or_foreign_root(&name) &&
+                    !self
+                        .sink
+                        .adjusted_current_node_present_but_not_in_html_namespace()
+                {
+                    self.flag(AmbiguousConstruct::SelfClosingNonVoidElement);
+                }
+                // End tags in raw text are lowercased, so compare them with
+                // the start tag's name lowercased.
+                self.last_start_tag_name = Some(if self.keep_tag_case {
+                    LocalName::from(name.to_ascii_lowercase())
+                } else {
+                    name.clone()
+                });
             },
             EndTag => {
                 if !self.current_tag_attrs.is_empty() {
                     self.emit_error(Borrowed("Attributes on an end tag"));
+                    self.flag(AmbiguousConstruct::EndTagWithAttributes);
                 }
                 if self.current_tag_self_closing {
                     self.emit_error(Borrowed("Self-closing end tag"));
@@ -435,6 +1105,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             self_closing: self.current_tag_self_closing,
             attrs: replace(&mut self.current_tag_attrs, vec![]),
         });
+        self.current_tag_raw_attr_names.clear();
 
         match self.process_token(token) {
             TokenSinkResult::Continue => ProcessResult::Continue,
@@ -464,23 +1135,75 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
         self.temp_buf.clear();
     }
 
+    fn push_comment(&mut self, s: &str) {
+        let (max, truncated) = (self.opts.max_comment_length, &mut self.comment_truncated);
+        if push_limited(&mut self.current_comment, s, max, truncated) {
+            self.emit_error(Borrowed("Comment longer than the maximum length"));
+        }
+    }
+
+    fn clear_comment(&mut self) {
+        self.current_comment.clear();
+        self.comment_truncated = false;
+    }
+
     fn emit_current_comment(&mut self) {
+        self.comment_truncated = false;
         let comment = replace(&mut self.current_comment, StrTendril::new());
         self.process_token_and_continue(CommentToken(comment));
     }
 
+    /// Emit the text of a processing instruction, gathered in
+    /// `current_comment`, split into its target and data.
+    fn emit_current_pi(&mut self) {
+        self.comment_truncated = false;
+        let mut data = replace(&mut self.current_comment, StrTendril::new());
+        let target_len = data
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(data.len());
+        let data_start = data[target_len..]
+            .find(|c: char| !c.is_ascii_whitespace())
+            .map_or(data.len(), |i| target_len + i);
+        let target = data.subtendril(0, target_len as u32);
+        data.pop_front(data_start as u32);
+        let pi = Pi { target, data };
+        assert!(matches!(
+            self.sink.process_pi(pi, self.current_line),
+            TokenSinkResult::Continue
+        ));
+    }
+
     fn discard_tag(&mut self) {
         self.current_tag_name.clear();
         self.current_tag_self_closing = false;
         self.current_tag_attrs = vec![];
+        self.current_tag_raw_attr_names.clear();
     }
 
     fn create_tag(&mut self, kind: TagKind, c: char) {
         self.discard_tag();
+        self.keep_tag_case = false;
         self.current_tag_name.push_char(c);
         self.current_tag_kind = kind;
     }
 
+    // Start a tag in the tag open or end tag open state, where the sink
+    // decides whether to keep the case of its names.
+    fn create_data_tag(&mut self, kind: TagKind, c: char) {
+        self.sink.flush_pending();
+        let keep_case = self.sink.keep_tag_case();
+        self.create_tag(kind, if keep_case { c } else { c.to_ascii_lowercase() });
+        self.keep_tag_case = keep_case;
+    }
+
+    fn fold_case(&self, c: char) -> char {
+        if self.keep_tag_case {
+            c
+        } else {
+            c.to_ascii_lowercase()
+        }
+    }
+
     fn have_appropriate_end_tag(&self) -> bool {
         match self.last_start_tag_name.as_ref() {
             Some(last) => (self.current_tag_kind == EndTag) && (*self.current_tag_name == **last),
@@ -491,10 +1214,36 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
     fn create_attribute(&mut self, c: char) {
         self.finish_attribute();
 
-        self.current_attr_name.push_char(c);
+        self.push_attribute_name(c);
+    }
+
+    /// Append `c` to the current attribute's name, lowercasing it unless the
+    /// tag keeps its case.
+    fn push_attribute_name(&mut self, c: char) {
+        if self.opts.flag_ambiguous_constructs {
+            self.current_attr_raw_name.push_char(c);
+        }
+        self.current_attr_name.push_char(self.fold_case(c));
+    }
+
+    fn push_value(&mut self, s: &str) {
+        let max = self.opts.max_attribute_value_length;
+        let truncated = &mut self.attr_value_truncated;
+        if push_limited(&mut self.current_attr_value, s, max, truncated) {
+            self.emit_error(Borrowed("Attribute value longer than the maximum length"));
+        }
+    }
+
+    fn append_value(&mut self, t: &StrTendril) {
+        if self.opts.max_attribute_value_length.is_some() {
+            self.push_value(t);
+        } else {
+            self.current_attr_value.push_tendril(t);
+        }
     }
 
     fn finish_attribute(&mut self) {
+        self.attr_value_truncated = false;
         if self.current_attr_name.is_empty() {
             return;
         }
@@ -502,15 +1251,29 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
         // Check for a duplicate attribute.
         // FIXME: the spec says we should error as soon as the name is finished.
         // FIXME: linear time search, do we care?
-        let dup = {
+        let first = {
             let name = &*self.current_attr_name;
             self.current_tag_attrs
                 .iter()
-                .any(|a| &*a.name.local == name)
+                .position(|a| a.name.local.eq_str_ignore_ascii_case(name))
         };
+        let dup = first.is_some();
+        let raw_name = replace(&mut self.current_attr_raw_name, StrTendril::new());
 
-        if dup {
+        if let Some(first) = first {
             self.emit_error(Borrowed("Duplicate attribute"));
+            self.flag(AmbiguousConstruct::DuplicateAttribute);
+            if self.opts.flag_ambiguous_constructs {
+                let first = self.current_tag_raw_attr_names[first].clone();
+                self.sink
+                    .duplicate_attribute(&first, &raw_name, self.current_line);
+            }
+        }
+        if self.opts.flag_ambiguous_constructs && self.current_attr_value.contains('<') {
+            self.flag(AmbiguousConstruct::LessThanSignInAttributeValue);
+        }
+
+        if dup && !self.opts.preserve_all_attributes {
             self.current_attr_name.clear();
             self.current_attr_value.clear();
         } else {
@@ -522,11 +1285,21 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
                 name: QualName::new(None, ns!(), name),
                 value: replace(&mut self.current_attr_value, StrTendril::new()),
             });
+            if self.opts.flag_ambiguous_constructs {
+                self.current_tag_raw_attr_names.push(raw_name);
+            }
+        }
+    }
+
+    fn flag(&mut self, construct: AmbiguousConstruct) {
+        if self.opts.flag_ambiguous_constructs {
+            self.sink.ambiguous_construct(construct, self.current_line);
         }
     }
 
     fn emit_current_doctype(&mut self) {
-        let doctype = replace(&mut self.current_doctype, Doctype::new());
+        let mut doctype = replace(&mut self.current_doctype, Doctype::new());
+        doctype.raw = self.raw_doctype.take();
         self.process_token_and_continue(DoctypeToken(doctype));
     }
 
@@ -546,6 +1319,21 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
     }
 
     fn consume_char_ref(&mut self, addnl_allowed: Option<char>) {
+        if let Some(max) = self.opts.max_entity_expansions {
+            if self.char_ref_count >= max {
+                if self.char_ref_count == max {
+                    self.char_ref_count += 1;
+                    self.emit_error(Borrowed("Too many character references"));
+                }
+                self.process_char_ref(CharRef {
+                    chars: ['&', '\0'],
+                    num_chars: 1,
+                });
+                return;
+            }
+        }
+        self.char_ref_count += 1;
+
         // NB: The char ref tokenizer assumes we have an additional allowed
         // character iff we're tokenizing in an attribute value.
         self.char_ref_tokenizer = Some(Box::new(CharRefTokenizer::new(addnl_allowed)));
@@ -577,6 +1365,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
 macro_rules! shorthand (
     ( $me:ident : emit $c:expr                     ) => ( $me.emit_char($c);                                   );
     ( $me:ident : create_tag $kind:ident $c:expr   ) => ( $me.create_tag($kind, $c);                           );
+    ( $me:ident : create_data_tag $kind:ident $c:expr ) => ( $me.create_data_tag($kind, $c);                 );
     ( $me:ident : push_tag $c:expr                 ) => ( $me.current_tag_name.push_char($c);                  );
     ( $me:ident : discard_tag                      ) => ( $me.discard_tag();                                   );
     ( $me:ident : discard_char $input:expr         ) => ( $me.discard_char($input);                            );
@@ -584,13 +1373,14 @@ macro_rules! shorthand (
     ( $me:ident : emit_temp                        ) => ( $me.emit_temp_buf();                                 );
     ( $me:ident : clear_temp                       ) => ( $me.clear_temp_buf();                                );
     ( $me:ident : create_attr $c:expr              ) => ( $me.create_attribute($c);                            );
-    ( $me:ident : push_name $c:expr                ) => ( $me.current_attr_name.push_char($c);                 );
-    ( $me:ident : push_value $c:expr               ) => ( $me.current_attr_value.push_char($c);                );
-    ( $me:ident : append_value $c:expr             ) => ( $me.current_attr_value.push_tendril($c);             );
-    ( $me:ident : push_comment $c:expr             ) => ( $me.current_comment.push_char($c);                   );
-    ( $me:ident : append_comment $c:expr           ) => ( $me.current_comment.push_slice($c);                  );
+    ( $me:ident : push_name $c:expr                ) => ( $me.push_attribute_name($c);                         );
+    ( $me:ident : push_value $c:expr               ) => ( $me.push_value($c.encode_utf8(&mut [0; 4]));         );
+    ( $me:ident : append_value $c:expr             ) => ( $me.append_value($c);                                );
+    ( $me:ident : push_comment $c:expr             ) => ( $me.push_comment($c.encode_utf8(&mut [0; 4]));       );
+    ( $me:ident : append_comment $c:expr           ) => ( $me.push_comment($c);                                );
     ( $me:ident : emit_comment                     ) => ( $me.emit_current_comment();                          );
-    ( $me:ident : clear_comment                    ) => ( $me.current_comment.clear();                         );
+    ( $me:ident : clear_comment                    ) => ( $me.clear_comment();                                 );
+    ( $me:ident : emit_pi                          ) => ( $me.emit_current_pi();                               );
     ( $me:ident : create_doctype                   ) => ( $me.current_doctype = Doctype::new();                );
     ( $me:ident : push_doctype_name $c:expr        ) => ( option_push(&mut $me.current_doctype.name, $c);      );
     ( $me:ident : push_doctype_id $k:ident $c:expr ) => ( option_push($me.doctype_id($k), $c);                 );
@@ -599,6 +1389,9 @@ macro_rules! shorthand (
     ( $me:ident : emit_doctype                     ) => ( $me.emit_current_doctype();                          );
     ( $me:ident : error                            ) => ( $me.bad_char_error();                                );
     ( $me:ident : error_eof                        ) => ( $me.bad_eof_error();                                 );
+    ( $me:ident : comment_error $name:expr         ) => ( $me.comment_error($name);                            );
+    ( $me:ident : legacy_comment_error             ) => ( $me.legacy_comment_error();                          );
+    ( $me:ident : flag $c:ident                    ) => ( $me.flag(AmbiguousConstruct::$c);                    );
 );
 
 // Tracing of tokenizer actions.  This adds significant bloat and compile time,
@@ -672,7 +1465,7 @@ macro_rules! pop_except_from ( ($me:expr, $input:expr, $set:expr) => (
 ));
 
 macro_rules! eat ( ($me:expr, $input:expr, $pat:expr) => (
-    unwrap_or_return!($me.eat($input, $pat, u8::eq_ignore_ascii_case), ProcessResult::Suspend)
+    unwrap_or_return!($me.eat_keyword($input, $pat), ProcessResult::Suspend)
 ));
 
 macro_rules! eat_exact ( ($me:expr, $input:expr, $pat:expr) => (
@@ -771,9 +1564,12 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
                 match get_char!(self, input) {
                     '!' => go!(self: clear_temp; to MarkupDeclarationOpen),
                     '/' => go!(self: to EndTagOpen),
+                    '?' if self.opts.pi_as_processing_instruction => {
+                        go!(self: clear_comment; to ProcessingInstruction)
+                    },
                     '?' => go!(self: error; clear_comment; push_comment '?'; to BogusComment),
                     c => match lower_ascii_letter(c) {
-                        Some(cl) => go!(self: create_tag StartTag cl; to TagName),
+                        Some(_) => go!(self: create_data_tag StartTag c; to TagName),
                         None => go!(self: error; emit '<'; reconsume Data),
                     },
                 }
@@ -787,7 +1583,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
                         go!(self: error; clear_comment; push_comment '\u{fffd}'; to BogusComment)
                     },
                     c => match lower_ascii_letter(c) {
-                        Some(cl) => go!(self: create_tag EndTag cl; to TagName),
+                        Some(_) => go!(self: create_data_tag EndTag c; to TagName),
                         None => go!(self: error; clear_comment; push_comment c; to BogusComment),
                     },
                 }
@@ -796,11 +1592,11 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             //§ tag-name-state
             states::TagName => loop {
                 match get_char!(self, input) {
-                    '\t' | '\n' | '\x0C' | ' ' => go!(self: to BeforeAttributeName),
+                    '\t' | '\n' | '\r' | '\x0C' | ' ' => go!(self: to BeforeAttributeName),
                     '/' => go!(self: to SelfClosingStartTag),
                     '>' => go!(self: emit_tag Data),
                     '\0' => go!(self: error; push_tag '\u{fffd}'),
-                    c => go!(self: push_tag (c.to_ascii_lowercase())),
+                    c => go!(self: push_tag (self.fold_case(c))),
                 }
             },
 
@@ -850,7 +1646,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
                 let c = get_char!(self, input);
                 if self.have_appropriate_end_tag() {
                     match c {
-                        '\t' | '\n' | '\x0C' | ' ' => go!(self: to BeforeAttributeName),
+                        '\t' | '\n' | '\r' | '\x0C' | ' ' => go!(self: to BeforeAttributeName),
                         '/' => go!(self: to SelfClosingStartTag),
                         '>' => go!(self: emit_tag Data),
                         _ => (),
@@ -869,13 +1665,12 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             states::ScriptDataEscapeStart(DoubleEscaped) => loop {
                 let c = get_char!(self, input);
                 match c {
-                    '\t' | '\n' | '\x0C' | ' ' | '/' | '>' => {
-                        let esc = if &*self.temp_buf == "script" {
-                            DoubleEscaped
-                        } else {
-                            Escaped
-                        };
-                        go!(self: emit c; to RawData ScriptDataEscaped esc);
+                    '\t' | '\n' | '\r' | '\x0C' | ' ' | '/' | '>' => {
+                        if &*self.temp_buf == "script" {
+                            go!(self: emit c; flag NestedScriptStart;
+                                to RawData ScriptDataEscaped DoubleEscaped);
+                        }
+                        go!(self: emit c; to RawData ScriptDataEscaped Escaped);
                     },
                     _ => match lower_ascii_letter(c) {
                         Some(cl) => go!(self: push_temp cl; emit c),
@@ -895,7 +1690,10 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             //§ script-data-escape-start-dash-state
             states::ScriptDataEscapeStartDash => loop {
                 match get_char!(self, input) {
-                    '-' => go!(self: emit '-'; to ScriptDataEscapedDashDash Escaped),
+                    '-' => {
+                        go!(self: emit '-'; flag ScriptHtmlCommentLikeText;
+                            to ScriptDataEscapedDashDash Escaped)
+                    },
                     _ => go!(self: reconsume RawData ScriptData),
                 }
             },
@@ -935,7 +1733,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             states::ScriptDataDoubleEscapeEnd => loop {
                 let c = get_char!(self, input);
                 match c {
-                    '\t' | '\n' | '\x0C' | ' ' | '/' | '>' => {
+                    '\t' | '\n' | '\r' | '\x0C' | ' ' | '/' | '>' => {
                         let esc = if &*self.temp_buf == "script" {
                             Escaped
                         } else {
@@ -953,15 +1751,18 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             //§ before-attribute-name-state
             states::BeforeAttributeName => loop {
                 match get_char!(self, input) {
-                    '\t' | '\n' | '\x0C' | ' ' => (),
+                    '\t' | '\n' | '\r' | '\x0C' | ' ' => (),
                     '/' => go!(self: to SelfClosingStartTag),
                     '>' => go!(self: emit_tag Data),
                     '\0' => go!(self: error; create_attr '\u{fffd}'; to AttributeName),
+                    '=' => {
+                        go!(self: error; flag EqualsSignBeforeAttributeName; create_attr '='; to AttributeName)
+                    },
                     c => match lower_ascii_letter(c) {
-                        Some(cl) => go!(self: create_attr cl; to AttributeName),
+                        Some(_) => go!(self: create_attr c; to AttributeName),
                         None => {
                             go_match!(self: c,
-                            '"' , '\'' , '<' , '=' => error);
+                            '"' , '\'' , '<' => error);
                             go!(self: create_attr c; to AttributeName);
                         },
                     },
@@ -971,13 +1772,13 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             //§ attribute-name-state
             states::AttributeName => loop {
                 match get_char!(self, input) {
-                    '\t' | '\n' | '\x0C' | ' ' => go!(self: to AfterAttributeName),
+                    '\t' | '\n' | '\r' | '\x0C' | ' ' => go!(self: to AfterAttributeName),
                     '/' => go!(self: to SelfClosingStartTag),
                     '=' => go!(self: to BeforeAttributeValue),
                     '>' => go!(self: emit_tag Data),
                     '\0' => go!(self: error; push_name '\u{fffd}'),
                     c => match lower_ascii_letter(c) {
-                        Some(cl) => go!(self: push_name cl),
+                        Some(_) => go!(self: push_name c),
                         None => {
                             go_match!(self: c,
                             '"' , '\'' , '<' => error);
@@ -990,13 +1791,13 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             //§ after-attribute-name-state
             states::AfterAttributeName => loop {
                 match get_char!(self, input) {
-                    '\t' | '\n' | '\x0C' | ' ' => (),
+                    '\t' | '\n' | '\r' | '\x0C' | ' ' => (),
                     '/' => go!(self: to SelfClosingStartTag),
                     '=' => go!(self: to BeforeAttributeValue),
                     '>' => go!(self: emit_tag Data),
                     '\0' => go!(self: error; create_attr '\u{fffd}'; to AttributeName),
                     c => match lower_ascii_letter(c) {
-                        Some(cl) => go!(self: create_attr cl; to AttributeName),
+                        Some(_) => go!(self: create_attr c; to AttributeName),
                         None => {
                             go_match!(self: c,
                             '"' , '\'' , '<' => error);
@@ -1045,34 +1846,48 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             },
 
             //§ attribute-value-(unquoted)-state
-            states::AttributeValue(Unquoted) => loop {
-                match pop_except_from!(
-                    self,
-                    input,
-                    small_char_set!('\r' '\t' '\n' '\x0C' ' ' '&' '>' '\0')
-                ) {
-                    FromSet('\t') | FromSet('\n') | FromSet('\x0C') | FromSet(' ') => {
-                        go!(self: to BeforeAttributeName)
-                    },
-                    FromSet('&') => go!(self: consume_char_ref '>'),
-                    FromSet('>') => go!(self: emit_tag Data),
-                    FromSet('\0') => go!(self: error; push_value '\u{fffd}'),
-                    FromSet(c) => {
-                        go_match!(self: c,
+            states::AttributeValue(Unquoted) => {
+                loop {
+                    match pop_except_from!(
+                        self,
+                        input,
+                        small_char_set!('\r' '\t' '\n' '\x0C' ' ' '&' '>' '\0')
+                    ) {
+                        FromSet('\t') | FromSet('\n') | FromSet('\r') | FromSet('\x0C') |
+                        FromSet(' ') => {
+                            go!(self: to BeforeAttributeName)
+                        },
+                        FromSet('&') => go!(self: consume_char_ref '>'),
+                        FromSet('>') => go!(self: emit_tag Data),
+                        FromSet('\0') => go!(self: error; push_value '\u{fffd}'),
+                        FromSet(c) => {
+                            go_match!(self: c,
                             '"' , '\'' , '<' , '=' , '`' => error);
-                        go!(self: push_value c);
-                    },
-                    NotFromSet(ref b) => go!(self: append_value b),
+                            go_match!(self: c,
+                            '"' , '\'' , '=' , '`' => flag UnexpectedCharacterInUnquotedAttributeValue);
+                            go!(self: push_value c);
+                        },
+                        NotFromSet(ref b) => {
+                            if self.opts.flag_ambiguous_constructs {
+                                for _ in b.matches(|c| matches!(c, '"' | '\'' | '=' | '`')) {
+                                    self.flag(AmbiguousConstruct::UnexpectedCharacterInUnquotedAttributeValue);
+                                }
+                            }
+                            go!(self: append_value b)
+                        },
+                    }
                 }
             },
 
             //§ after-attribute-value-(quoted)-state
             states::AfterAttributeValueQuoted => loop {
                 match get_char!(self, input) {
-                    '\t' | '\n' | '\x0C' | ' ' => go!(self: to BeforeAttributeName),
+                    '\t' | '\n' | '\r' | '\x0C' | ' ' => go!(self: to BeforeAttributeName),
                     '/' => go!(self: to SelfClosingStartTag),
                     '>' => go!(self: emit_tag Data),
-                    _ => go!(self: error; reconsume BeforeAttributeName),
+                    _ => {
+                        go!(self: error; flag MissingWhitespaceBetweenAttributes; reconsume BeforeAttributeName)
+                    },
                 }
             },
 
@@ -1083,7 +1898,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
                         self.current_tag_self_closing = true;
                         go!(self: emit_tag Data);
                     },
-                    _ => go!(self: error; reconsume BeforeAttributeName),
+                    _ => go!(self: error; flag SolidusInTag; reconsume BeforeAttributeName),
                 }
             },
 
@@ -1092,8 +1907,10 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
                 match get_char!(self, input) {
                     '-' => go!(self: to CommentStartDash),
                     '\0' => go!(self: error; push_comment '\u{fffd}'; to Comment),
-                    '>' => go!(self: error; emit_comment; to Data),
-                    c => go!(self: push_comment c; to Comment),
+                    '>' => go!(self: comment_error "Abrupt closing of empty comment";
+                               legacy_comment_error; flag AbruptClosingOfEmptyComment;
+                               emit_comment; to Data),
+                    _ => go!(self: reconsume Comment),
                 }
             },
 
@@ -1102,26 +1919,62 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
                 match get_char!(self, input) {
                     '-' => go!(self: to CommentEnd),
                     '\0' => go!(self: error; append_comment "-\u{fffd}"; to Comment),
-                    '>' => go!(self: error; emit_comment; to Data),
-                    c => go!(self: push_comment '-'; push_comment c; to Comment),
+                    '>' => go!(self: comment_error "Abrupt closing of empty comment";
+                               legacy_comment_error; flag AbruptClosingOfEmptyComment;
+                               emit_comment; to Data),
+                    _ => go!(self: push_comment '-'; reconsume Comment),
                 }
             },
 
             //§ comment-state
             states::Comment => loop {
                 match get_char!(self, input) {
+                    '<' => go!(self: push_comment '<'; to CommentLessThanSign),
                     '-' => go!(self: to CommentEndDash),
                     '\0' => go!(self: error; push_comment '\u{fffd}'),
                     c => go!(self: push_comment c),
                 }
             },
 
+            //§ comment-less-than-sign-state
+            states::CommentLessThanSign => loop {
+                match get_char!(self, input) {
+                    '!' => go!(self: push_comment '!'; to CommentLessThanSignBang),
+                    '<' => go!(self: push_comment '<'),
+                    _ => go!(self: reconsume Comment),
+                }
+            },
+
+            //§ comment-less-than-sign-bang-state
+            states::CommentLessThanSignBang => loop {
+                match get_char!(self, input) {
+                    '-' => go!(self: to CommentLessThanSignBangDash),
+                    _ => go!(self: reconsume Comment),
+                }
+            },
+
+            //§ comment-less-than-sign-bang-dash-state
+            states::CommentLessThanSignBangDash => loop {
+                match get_char!(self, input) {
+                    '-' => go!(self: to CommentLessThanSignBangDashDash),
+                    _ => go!(self: reconsume CommentEndDash),
+                }
+            },
+
+            //§ comment-less-than-sign-bang-dash-dash-state
+            states::CommentLessThanSignBangDashDash => loop {
+                match get_char!(self, input) {
+                    '>' => go!(self: reconsume CommentEnd),
+                    _ => go!(self: comment_error "Nested comment"; flag NestedComment;
+                             reconsume CommentEnd),
+                }
+            },
+
             //§ comment-end-dash-state
             states::CommentEndDash => loop {
                 match get_char!(self, input) {
                     '-' => go!(self: to CommentEnd),
-                    '\0' => go!(self: error; append_comment "-\u{fffd}"; to Comment),
-                    c => go!(self: push_comment '-'; push_comment c; to Comment),
+                    _ => go!(self: push_comment '-'; reconsume Comment),
                 }
             },
 
@@ -1129,10 +1982,11 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             states::CommentEnd => loop {
                 match get_char!(self, input) {
                     '>' => go!(self: emit_comment; to Data),
-                    '\0' => go!(self: error; append_comment "--\u{fffd}"; to Comment),
-                    '!' => go!(self: error; to CommentEndBang),
-                    '-' => go!(self: error; push_comment '-'),
-                    c => go!(self: error; append_comment "--"; push_comment c; to Comment),
+                    '!' => go!(self: legacy_comment_error; to CommentEndBang),
+                    '-' => go!(self: legacy_comment_error; push_comment '-'),
+                    // The comment state reports the NULL.
+                    '\0' => go!(self: append_comment "--"; reconsume Comment),
+                    _ => go!(self: legacy_comment_error; append_comment "--"; reconsume Comment),
                 }
             },
 
@@ -1140,16 +1994,16 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             states::CommentEndBang => loop {
                 match get_char!(self, input) {
                     '-' => go!(self: append_comment "--!"; to CommentEndDash),
-                    '>' => go!(self: emit_comment; to Data),
-                    '\0' => go!(self: error; append_comment "--!\u{fffd}"; to Comment),
-                    c => go!(self: append_comment "--!"; push_comment c; to Comment),
+                    '>' => go!(self: comment_error "Incorrectly closed comment";
+                               flag IncorrectlyClosedComment; emit_comment; to Data),
+                    _ => go!(self: append_comment "--!"; reconsume Comment),
                 }
             },
 
             //§ doctype-state
             states::Doctype => loop {
                 match get_char!(self, input) {
-                    '\t' | '\n' | '\x0C' | ' ' => go!(self: to BeforeDoctypeName),
+                    '\t' | '\n' | '\r' | '\x0C' | ' ' => go!(self: to BeforeDoctypeName),
                     _ => go!(self: error; reconsume BeforeDoctypeName),
                 }
             },
@@ -1157,7 +2011,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             //§ before-doctype-name-state
             states::BeforeDoctypeName => loop {
                 match get_char!(self, input) {
-                    '\t' | '\n' | '\x0C' | ' ' => (),
+                    '\t' | '\n' | '\r' | '\x0C' | ' ' => (),
                     '\0' => {
                         go!(self: error; create_doctype; push_doctype_name '\u{fffd}'; to DoctypeName)
                     },
@@ -1170,7 +2024,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             //§ doctype-name-state
             states::DoctypeName => loop {
                 match get_char!(self, input) {
-                    '\t' | '\n' | '\x0C' | ' ' => go!(self: clear_temp; to AfterDoctypeName),
+                    '\t' | '\n' | '\r' | '\x0C' | ' ' => go!(self: clear_temp; to AfterDoctypeName),
                     '>' => go!(self: emit_doctype; to Data),
                     '\0' => go!(self: error; push_doctype_name '\u{fffd}'),
                     c => go!(self: push_doctype_name (c.to_ascii_lowercase())),
@@ -1185,7 +2039,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
                     go!(self: to AfterDoctypeKeyword System);
                 } else {
                     match get_char!(self, input) {
-                        '\t' | '\n' | '\x0C' | ' ' => (),
+                        '\t' | '\n' | '\r' | '\x0C' | ' ' => (),
                         '>' => go!(self: emit_doctype; to Data),
                         _ => go!(self: error; force_quirks; to BogusDoctype),
                     }
@@ -1195,7 +2049,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             //§ after-doctype-public-keyword-state after-doctype-system-keyword-state
             states::AfterDoctypeKeyword(kind) => loop {
                 match get_char!(self, input) {
-                    '\t' | '\n' | '\x0C' | ' ' => go!(self: to BeforeDoctypeIdentifier kind),
+                    '\t' | '\n' | '\r' | '\x0C' | ' ' => go!(self: to BeforeDoctypeIdentifier kind),
                     '"' => {
                         go!(self: error; clear_doctype_id kind; to DoctypeIdentifierDoubleQuoted kind)
                     },
@@ -1210,7 +2064,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             //§ before-doctype-public-identifier-state before-doctype-system-identifier-state
             states::BeforeDoctypeIdentifier(kind) => loop {
                 match get_char!(self, input) {
-                    '\t' | '\n' | '\x0C' | ' ' => (),
+                    '\t' | '\n' | '\r' | '\x0C' | ' ' => (),
                     '"' => go!(self: clear_doctype_id kind; to DoctypeIdentifierDoubleQuoted kind),
                     '\'' => go!(self: clear_doctype_id kind; to DoctypeIdentifierSingleQuoted kind),
                     '>' => go!(self: error; force_quirks; emit_doctype; to Data),
@@ -1241,7 +2095,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             //§ after-doctype-public-identifier-state
             states::AfterDoctypeIdentifier(Public) => loop {
                 match get_char!(self, input) {
-                    '\t' | '\n' | '\x0C' | ' ' => {
+                    '\t' | '\n' | '\r' | '\x0C' | ' ' => {
                         go!(self: to BetweenDoctypePublicAndSystemIdentifiers)
                     },
                     '>' => go!(self: emit_doctype; to Data),
@@ -1258,7 +2112,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             //§ after-doctype-system-identifier-state
             states::AfterDoctypeIdentifier(System) => loop {
                 match get_char!(self, input) {
-                    '\t' | '\n' | '\x0C' | ' ' => (),
+                    '\t' | '\n' | '\r' | '\x0C' | ' ' => (),
                     '>' => go!(self: emit_doctype; to Data),
                     _ => go!(self: error; to BogusDoctype),
                 }
@@ -1267,7 +2121,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             //§ between-doctype-public-and-system-identifiers-state
             states::BetweenDoctypePublicAndSystemIdentifiers => loop {
                 match get_char!(self, input) {
-                    '\t' | '\n' | '\x0C' | ' ' => (),
+                    '\t' | '\n' | '\r' | '\x0C' | ' ' => (),
                     '>' => go!(self: emit_doctype; to Data),
                     '"' => {
                         go!(self: clear_doctype_id System; to DoctypeIdentifierDoubleQuoted System)
@@ -1296,6 +2150,23 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
                 }
             },
 
+            // Not in the spec; see `pi_as_processing_instruction`.
+            states::ProcessingInstruction => loop {
+                match get_char!(self, input) {
+                    '?' => go!(self: to ProcessingInstructionQuestion),
+                    '\0' => go!(self: error; push_comment '\u{fffd}'),
+                    c => go!(self: push_comment c),
+                }
+            },
+
+            states::ProcessingInstructionQuestion => loop {
+                match get_char!(self, input) {
+                    '>' => go!(self: emit_pi; to Data),
+                    '?' => go!(self: push_comment '?'),
+                    _ => go!(self: push_comment '?'; reconsume ProcessingInstruction),
+                }
+            },
+
             //§ markup-declaration-open-state
             states::MarkupDeclarationOpen => loop {
                 if eat_exact!(self, input, "--") {
@@ -1303,6 +2174,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
                 } else if eat!(self, input, "doctype") {
                     go!(self: to Doctype);
                 } else {
+                    self.sink.flush_pending();
                     if self
                         .sink
                         .adjusted_current_node_present_but_not_in_html_namespace()
@@ -1363,15 +2235,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
     }
 
     fn process_char_ref(&mut self, char_ref: CharRef) {
-        let CharRef {
-            mut chars,
-            mut num_chars,
-        } = char_ref;
-
-        if num_chars == 0 {
-            chars[0] = '&';
-            num_chars = 1;
-        }
+        let CharRef { chars, num_chars } = char_ref;
 
         for i in 0..num_chars {
             let c = chars[i as usize];
@@ -1407,6 +2271,15 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
         assert!(matches!(self.run(&mut input), TokenizerResult::Done));
         assert!(input.is_empty());
 
+        match self.state {
+            states::Data |
+            states::RawData(Rcdata) |
+            states::RawData(Rawtext) |
+            states::RawData(ScriptData) |
+            states::Plaintext => (),
+            _ => self.sink.eof_in_token(),
+        }
+
         loop {
             match self.eof_step() {
                 ProcessResult::Continue => (),
@@ -1451,14 +2324,15 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             states::Plaintext => go!(self: eof),
 
             states::TagName |
-            states::RawData(ScriptDataEscaped(_)) |
             states::BeforeAttributeName |
             states::AttributeName |
             states::AfterAttributeName |
             states::BeforeAttributeValue |
             states::AttributeValue(_) |
             states::AfterAttributeValueQuoted |
-            states::SelfClosingStartTag |
+            states::SelfClosingStartTag => go!(self: error_eof; flag EofInTag; to Data),
+
+            states::RawData(ScriptDataEscaped(_)) |
             states::ScriptDataEscapedDash(_) |
             states::ScriptDataEscapedDashDash(_) => go!(self: error_eof; to Data),
 
@@ -1486,6 +2360,12 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
                 go!(self: to RawData ScriptDataEscaped DoubleEscaped)
             },
 
+            states::CommentLessThanSign | states::CommentLessThanSignBang => go!(self: to Comment),
+
+            states::CommentLessThanSignBangDash => go!(self: to CommentEndDash),
+
+            states::CommentLessThanSignBangDashDash => go!(self: to CommentEnd),
+
             states::CommentStart |
             states::CommentStartDash |
             states::Comment |
@@ -1512,6 +2392,12 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
 
             states::BogusComment => go!(self: emit_comment; to Data),
 
+            states::ProcessingInstruction => go!(self: error_eof; emit_pi; to Data),
+
+            states::ProcessingInstructionQuestion => {
+                go!(self: error_eof; push_comment '?'; emit_pi; to Data)
+            },
+
             states::MarkupDeclarationOpen => go!(self: error; to BogusComment),
 
             states::CdataSection => go!(self: emit_temp; error_eof; to Data),
@@ -1529,11 +2415,12 @@ mod test {
     use super::option_push; // private items
     use crate::tendril::{SliceExt, StrTendril};
 
-    use super::{TokenSink, TokenSinkResult, Tokenizer, TokenizerOpts};
+    use super::{InvalidCharRefPolicy, NullHandling, TokenSink, TokenSinkResult};
+    use super::{Tokenizer, TokenizerOpts};
 
     use super::interface::{CharacterTokens, EOFToken, NullCharacterToken, ParseError};
+    use super::interface::{CommentToken, DoctypeToken, TagToken, Token};
     use super::interface::{EndTag, StartTag, Tag, TagKind};
-    use super::interface::{TagToken, Token};
 
     use markup5ever::buffer_queue::BufferQueue;
     use std::mem::replace;
@@ -1665,8 +2552,20 @@ mod test {
     fn check_lines() {
         let opts = TokenizerOpts {
             exact_errors: false,
+            named_comment_errors: false,
             discard_bom: true,
+            strip_all_bom: false,
             profile: false,
+            preserve_all_attributes: false,
+            preserve_raw_doctype: false,
+            invalid_charref_policy: InvalidCharRefPolicy::SpecReplace,
+            null_handling: NullHandling::Spec,
+            flag_ambiguous_constructs: false,
+            max_entity_expansions: None,
+            pi_as_processing_instruction: false,
+            normalize_newlines: true,
+            max_comment_length: None,
+            max_attribute_value_length: None,
             initial_state: None,
             last_start_tag_name: None,
         };
@@ -1690,8 +2589,20 @@ mod test {
     fn check_lines_with_new_line() {
         let opts = TokenizerOpts {
             exact_errors: false,
+            named_comment_errors: false,
             discard_bom: true,
+            strip_all_bom: false,
             profile: false,
+            preserve_all_attributes: false,
+            preserve_raw_doctype: false,
+            invalid_charref_policy: InvalidCharRefPolicy::SpecReplace,
+            null_handling: NullHandling::Spec,
+            flag_ambiguous_constructs: false,
+            max_entity_expansions: None,
+            pi_as_processing_instruction: false,
+            normalize_newlines: true,
+            max_comment_length: None,
+            max_attribute_value_length: None,
             initial_state: None,
             last_start_tag_name: None,
         };
@@ -1710,4 +2621,674 @@ mod test {
         let results = tokenize(vector, opts);
         assert_eq!(results, expected);
     }
+
+    #[test]
+    fn tokenize_across_chunks() {
+        let text = "\u{e9}".repeat(2000);
+        let input = format!("<p>{}</p>", text);
+        let mut chars = String::new();
+        let mut tokens = vec![];
+        for token in super::tokenize(&input, TokenizerOpts::default()) {
+            match token {
+                CharacterTokens(s) => chars.push_str(&s),
+                token => tokens.push(token),
+            }
+        }
+        assert_eq!(chars, text);
+        assert_eq!(
+            tokens,
+            vec![
+                create_tag(StrTendril::from("p"), StartTag),
+                create_tag(StrTendril::from("p"), EndTag),
+                EOFToken,
+            ]
+        );
+    }
+
+    #[test]
+    fn abnormal_comments() {
+        fn comment_and_errors(input: &str, named: bool) -> (String, Vec<String>) {
+            let opts = TokenizerOpts {
+                named_comment_errors: named,
+                ..Default::default()
+            };
+            let mut comment = String::new();
+            let mut errors = vec![];
+            for token in super::tokenize(input, opts) {
+                match token {
+                    CommentToken(s) => comment.push_str(&s),
+                    ParseError(e) => errors.push(e.into_owned()),
+                    _ => (),
+                }
+            }
+            (comment, errors)
+        }
+
+        let abrupt = "Abrupt closing of empty comment";
+        let nested = "Nested comment";
+        let incorrectly_closed = "Incorrectly closed comment";
+        let bad = "Bad character";
+        let cases: &[(&str, &str, &[&str], &[&str])] = &[
+            // Input, comment, errors with and without names.
+            ("<!-->", "", &[abrupt], &[bad]),
+            ("<!--->", "", &[abrupt], &[bad]),
+            ("<!-- <!-- -->", " <!-- ", &[nested], &[bad]),
+            ("<!--x--!>", "x", &[incorrectly_closed], &[bad]),
+            ("<!-- -- -->", " -- ", &[], &[bad]),
+            ("<!--x--->", "x-", &[], &[bad]),
+            ("<!--x--!-->", "x--!", &[], &[bad]),
+            ("<!--<!-->", "<!", &[], &[]),
+            ("<!--<!--x-->", "<!--x", &[nested], &[bad]),
+            ("<!--<!---->", "<!--", &[nested], &[bad, bad]),
+            ("<!--x--\0-->", "x--\u{fffd}", &[bad], &[bad]),
+        ];
+        for &(input, comment, named, legacy) in cases {
+            let expected = |errors: &[&str]| {
+                let errors = errors.iter().map(|e| e.to_string()).collect();
+                (comment.to_string(), errors)
+            };
+            assert_eq!(
+                comment_and_errors(input, true),
+                expected(named),
+                "{}",
+                input
+            );
+            assert_eq!(
+                comment_and_errors(input, false),
+                expected(legacy),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_charref_policy() {
+        fn text(input: &str, policy: InvalidCharRefPolicy) -> String {
+            let opts = TokenizerOpts {
+                invalid_charref_policy: policy,
+                ..Default::default()
+            };
+            let mut text = String::new();
+            for token in super::tokenize(input, opts) {
+                if let CharacterTokens(s) = token {
+                    text.push_str(&s);
+                }
+            }
+            text
+        }
+
+        for n in 0x80..=0x9F {
+            let expected = super::numeric_char_ref_replacement(n)
+                .unwrap_or_else(|| std::char::from_u32(n).unwrap());
+            let input = format!("&#x{:X};", n);
+            assert_eq!(
+                text(&input, InvalidCharRefPolicy::SpecReplace),
+                expected.to_string()
+            );
+        }
+        assert_eq!(
+            text("&#x80;&#x9f;", InvalidCharRefPolicy::SpecReplace),
+            "\u{20ac}\u{178}"
+        );
+        assert_eq!(text("&#x81;", InvalidCharRefPolicy::Keep), "\u{81}");
+        assert_eq!(text("&#x81;", InvalidCharRefPolicy::Error), "\u{81}");
+
+        let input = "a&#x110000;b&#xD800;c&#0;d&#x80";
+        assert_eq!(
+            text(input, InvalidCharRefPolicy::SpecReplace),
+            "a\u{fffd}b\u{fffd}c\u{fffd}d\u{20ac}"
+        );
+        assert_eq!(text(input, InvalidCharRefPolicy::Keep), input);
+        assert_eq!(text(input, InvalidCharRefPolicy::Drop), "abcd");
+        assert_eq!(text(input, InvalidCharRefPolicy::Error), "a");
+        assert_eq!(text("&#x41;&#65", InvalidCharRefPolicy::Drop), "AA");
+
+        // Input fed after the tokenizer stopped is dropped too.
+        struct Tokens(Vec<Token>);
+        impl TokenSink for Tokens {
+            type Handle = ();
+            fn process_token(&mut self, token: Token, _: u64) -> TokenSinkResult<()> {
+                self.0.push(token);
+                TokenSinkResult::Continue
+            }
+        }
+        let opts = TokenizerOpts {
+            invalid_charref_policy: InvalidCharRefPolicy::Error,
+            ..Default::default()
+        };
+        let mut tok = Tokenizer::new(Tokens(vec![]), opts);
+        let mut buffer = BufferQueue::new();
+        for &chunk in ["a&#x1", "10000;b", "<p>c"].iter() {
+            buffer.push_back(StrTendril::from(chunk));
+            let _ = tok.feed(&mut buffer);
+            assert!(buffer.is_empty());
+        }
+        tok.end();
+        assert_eq!(tok.invalid_char_ref(), Some(0x110000));
+        let tokens = tok.sink.0;
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[0], CharacterTokens("a".into()));
+        assert!(matches!(tokens[1], ParseError(_)));
+        assert_eq!(tokens[2], EOFToken);
+    }
+
+    #[test]
+    fn max_entity_expansions() {
+        fn tokens(input: &str, max: Option<usize>) -> (String, Vec<String>) {
+            let opts = TokenizerOpts {
+                max_entity_expansions: max,
+                ..Default::default()
+            };
+            let mut text = String::new();
+            let mut errors = vec![];
+            for token in super::tokenize(input, opts) {
+                match token {
+                    CharacterTokens(s) => text.push_str(&s),
+                    TagToken(tag) => {
+                        for attr in tag.attrs {
+                            text.push_str(&attr.value);
+                        }
+                    },
+                    ParseError(e) => errors.push(e.into_owned()),
+                    _ => (),
+                }
+            }
+            (text, errors)
+        }
+
+        let input = "&amp;&lt;<a title='&gt;&#65;'>&amp;&AMP;";
+        assert_eq!(tokens(input, None), ("&<>A&&".to_string(), vec![]));
+        assert_eq!(
+            tokens(input, Some(3)),
+            (
+                "&<>&#65;&amp;&AMP;".to_string(),
+                vec!["Too many character references".to_string()]
+            )
+        );
+        assert_eq!(
+            tokens(input, Some(0)),
+            (
+                "&amp;&lt;&gt;&#65;&amp;&AMP;".to_string(),
+                vec!["Too many character references".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn max_comment_length() {
+        fn tokens(input: &str, max: Option<usize>) -> Vec<Token> {
+            let opts = TokenizerOpts {
+                max_comment_length: max,
+                ..Default::default()
+            };
+            super::tokenize(input, opts).collect()
+        }
+
+        let error = || ParseError("Comment longer than the maximum length".into());
+        let comment = |s: &str| CommentToken(s.into());
+
+        // Only the start of a long comment is kept, and parsing resumes
+        // after its end.
+        let input = format!("<!--{}-->x<!--y-->", "- x ".repeat(1_000_000));
+        assert_eq!(
+            tokens(&input, Some(10)),
+            vec![
+                error(),
+                comment("- x - x - "),
+                CharacterTokens("x".into()),
+                comment("y"),
+                EOFToken,
+            ]
+        );
+
+        // The comment is cut at a character boundary.
+        assert_eq!(
+            tokens("<!--caf\u{e9}s-->", Some(4)),
+            vec![error(), comment("caf"), EOFToken]
+        );
+        assert_eq!(
+            tokens("<!--caf\u{e9}s-->", Some(5)),
+            vec![error(), comment("caf\u{e9}"), EOFToken]
+        );
+        assert_eq!(
+            tokens("<!--caf\u{e9}-->", Some(5)),
+            vec![comment("caf\u{e9}"), EOFToken]
+        );
+        assert_eq!(
+            tokens("<?x <!--y--> z", Some(3))[1..],
+            [
+                error(),
+                comment("?x "),
+                CharacterTokens(" z".into()),
+                EOFToken
+            ]
+        );
+    }
+
+    #[test]
+    fn max_attribute_value_length() {
+        fn attrs(input: &str, max: Option<usize>) -> (Vec<(String, String)>, Vec<String>) {
+            let opts = TokenizerOpts {
+                max_attribute_value_length: max,
+                ..Default::default()
+            };
+            let mut attrs = vec![];
+            let mut errors = vec![];
+            for token in super::tokenize(input, opts) {
+                match token {
+                    TagToken(tag) => {
+                        for attr in tag.attrs {
+                            attrs.push((attr.name.local.to_string(), attr.value.to_string()));
+                        }
+                    },
+                    ParseError(e) => errors.push(e.into_owned()),
+                    _ => (),
+                }
+            }
+            (attrs, errors)
+        }
+
+        let pair = |name: &str, value: &str| (name.to_string(), value.to_string());
+        let error = || "Attribute value longer than the maximum length".to_string();
+
+        // Only the start of a long value is kept, and the attributes after
+        // it are still parsed.
+        let input = format!(
+            "<img src=\"data:image/png;base64,{}\" alt=x><p title={}&amp; id=y>",
+            "QUJD".repeat(1_000_000),
+            "a".repeat(1_000_000)
+        );
+        assert_eq!(
+            attrs(&input, Some(16)),
+            (
+                vec![
+                    pair("src", "data:image/png;b"),
+                    pair("alt", "x"),
+                    pair("title", "aaaaaaaaaaaaaaaa"),
+                    pair("id", "y"),
+                ],
+                vec![error(), error()]
+            )
+        );
+
+        // Character references count as what they are replaced with.
+        assert_eq!(
+            attrs("<p title='&eacute;&eacute;&eacute;' id=&amp;>", Some(5)),
+            (
+                vec![pair("title", "\u{e9}\u{e9}"), pair("id", "&")],
+                vec![error()]
+            )
+        );
+        assert_eq!(
+            attrs("<p title='&eacute;&eacute;'>", Some(4)),
+            (vec![pair("title", "\u{e9}\u{e9}")], vec![])
+        );
+    }
+
+    #[test]
+    fn on_state_change() {
+        use super::states::*;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let changes = Rc::new(RefCell::new(vec![]));
+        let mut tok = Tokenizer::new(LinesMatch::new(), Default::default());
+        let log = changes.clone();
+        tok.set_on_state_change(Some(Box::new(move |old, new, c| {
+            log.borrow_mut().push((old, new, c))
+        })));
+        let mut input = BufferQueue::new();
+        input.push_back("<a href=\"x\">".to_tendril());
+        let _ = tok.feed(&mut input);
+        tok.end();
+        assert_eq!(
+            *changes.borrow(),
+            vec![
+                (Data, TagOpen, '<'),
+                (TagOpen, TagName, 'a'),
+                (TagName, BeforeAttributeName, ' '),
+                (BeforeAttributeName, AttributeName, 'h'),
+                (AttributeName, BeforeAttributeValue, '='),
+                (BeforeAttributeValue, AttributeValue(DoubleQuoted), '"'),
+                (AttributeValue(DoubleQuoted), AfterAttributeValueQuoted, '"'),
+                (AfterAttributeValueQuoted, Data, '>'),
+            ]
+        );
+    }
+
+    #[test]
+    fn normalize_newlines() {
+        fn run(normalize_newlines: bool) -> LinesMatch {
+            let opts = TokenizerOpts {
+                normalize_newlines,
+                ..Default::default()
+            };
+            let mut tok = Tokenizer::new(LinesMatch::new(), opts);
+            let mut buffer = BufferQueue::new();
+            for chunk in &["<a b='x\r", "\ny'\rc=z>t\r", "\nu\r", "<!--\r\n-->"] {
+                buffer.push_back(StrTendril::from(*chunk));
+                let _ = tok.feed(&mut buffer);
+            }
+            tok.end();
+            tok.sink
+        }
+
+        for &(normalize, crlf, cr) in &[(true, "\n", "\n"), (false, "\r\n", "\r")] {
+            let sink = run(normalize);
+            let lines: Vec<u64> = sink.lines.iter().map(|&(_, line)| line).collect();
+            assert_eq!(lines, vec![3, 6]);
+            match sink.lines[0].0 {
+                TagToken(ref tag) => {
+                    let values: Vec<&str> = tag.attrs.iter().map(|a| &*a.value).collect();
+                    assert_eq!(values, vec![format!("x{}y", crlf), "z".to_string()]);
+                },
+                ref token => panic!("expected a tag, got {:?}", token),
+            }
+            assert_eq!(sink.lines[1].0, CommentToken(StrTendril::from(crlf)));
+            assert_eq!(
+                sink.tokens,
+                vec![CharacterTokens(format!("t{}u{}", crlf, cr).into())]
+            );
+        }
+    }
+
+    #[test]
+    fn strip_all_bom() {
+        fn run(strip_all_bom: bool) -> (String, Vec<String>) {
+            let opts = TokenizerOpts {
+                strip_all_bom,
+                ..Default::default()
+            };
+            let input = "\u{feff}a\u{feff}b<p title='\u{feff}x'>&#xFEFF;c<!--\u{feff}-->\u{feff}";
+            let mut text = String::new();
+            let mut other = vec![];
+            for token in super::tokenize(input, opts) {
+                match token {
+                    CharacterTokens(s) => {
+                        assert!(!s.is_empty());
+                        text.push_str(&s);
+                    },
+                    TagToken(tag) => other.extend(tag.attrs.iter().map(|a| a.value.to_string())),
+                    CommentToken(s) => other.push(s.to_string()),
+                    _ => (),
+                }
+            }
+            (text, other)
+        }
+
+        // The leading byte order mark is dropped either way.  The attribute
+        // value and the comment keep theirs.
+        let kept = vec!["\u{feff}x".to_string(), "\u{feff}".to_string()];
+        assert_eq!(
+            run(false),
+            ("a\u{feff}b\u{feff}c\u{feff}".to_string(), kept.clone())
+        );
+        assert_eq!(run(true), ("abc".to_string(), kept));
+    }
+
+    #[test]
+    fn drop_long_run_of_nulls() {
+        let opts = TokenizerOpts {
+            null_handling: NullHandling::Drop,
+            ..Default::default()
+        };
+        let input = format!("a{}b", "\0".repeat(2_000_000));
+        let text: String = super::tokenize(&input, opts)
+            .filter_map(|token| match token {
+                CharacterTokens(s) => Some(s.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text, "ab");
+    }
+
+    #[test]
+    fn decode_attribute_value() {
+        fn tokenized(value: &str) -> String {
+            let input = format!("<a x='{}'>", value);
+            for token in super::tokenize(&input, Default::default()) {
+                if let TagToken(tag) = token {
+                    return tag.attrs[0].value.to_string();
+                }
+            }
+            panic!("no tag");
+        }
+
+        let cases = [
+            ("a&amp;b", "a&b"),
+            ("&amp", "&"),
+            ("&amp ", "& "),
+            // Without a semicolon, a reference followed by `=` or an ASCII
+            // alphanumeric is not decoded.  In text these would be.
+            ("&amp=", "&amp="),
+            ("&ampx", "&ampx"),
+            ("&copy2", "&copy2"),
+            ("?a=1&copy=2&not=3", "?a=1&copy=2&not=3"),
+            ("&notit;", "&notit;"),
+            // Other characters don't stop it.
+            ("&copy-", "\u{a9}-"),
+            ("&not\u{e9}", "\u{ac}\u{e9}"),
+            // With a semicolon the reference is always decoded.
+            ("&notin;x", "\u{2209}x"),
+            ("&amp;=", "&="),
+            // Only the legacy references may leave out the semicolon.
+            ("&hellip", "&hellip"),
+            ("&hellip;", "\u{2026}"),
+            // Numeric references are not affected.
+            ("&#65x", "Ax"),
+            ("&#x41=", "A="),
+            ("&#x80;&#0;", "\u{20ac}\u{fffd}"),
+            ("&", "&"),
+            ("&;&#;&#x;", "&;&#;&#x;"),
+        ];
+        for &(value, expected) in cases.iter() {
+            let decoded = super::decode_attribute_value(value);
+            assert_eq!(decoded, expected, "{:?}", value);
+            assert_eq!(tokenized(value), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn pi_as_processing_instruction() {
+        use super::interface::{Pi, TokenKind};
+
+        // Records processing instructions, text and errors.
+        struct Pis(Vec<String>);
+
+        impl TokenSink for Pis {
+            type Handle = ();
+
+            fn process_token(&mut self, token: Token, _line: u64) -> TokenSinkResult<()> {
+                match token {
+                    CharacterTokens(text) => self.0.push(text.to_string()),
+                    ParseError(_) => self.0.push("error".to_string()),
+                    _ => (),
+                }
+                TokenSinkResult::Continue
+            }
+
+            fn process_pi(&mut self, pi: Pi, _line: u64) -> TokenSinkResult<()> {
+                self.0.push(format!("{:?} {:?}", &*pi.target, &*pi.data));
+                TokenSinkResult::Continue
+            }
+        }
+
+        fn tokens(input: &str) -> Vec<String> {
+            let opts = TokenizerOpts {
+                pi_as_processing_instruction: true,
+                ..Default::default()
+            };
+            let mut tok = Tokenizer::new(Pis(vec![]), opts);
+            let mut buffer = BufferQueue::new();
+            buffer.push_back(input.to_tendril());
+            let _ = tok.feed(&mut buffer);
+            tok.end();
+            tok.sink.0
+        }
+
+        assert_eq!(tokens("<?php echo 1 ?>"), vec![r#""php" "echo 1 ""#]);
+        assert_eq!(
+            tokens("<?xml version='1.0'?>x"),
+            vec![r#""xml" "version='1.0'""#, "x"]
+        );
+        assert_eq!(tokens("<?a\n\tb>c??>"), vec![r#""a" "b>c?""#]);
+        assert_eq!(tokens("<?x?"), vec!["error", r#""x?" """#]);
+
+        // Without the option, and by default with it, a sink gets a comment.
+        for &pis in &[false, true] {
+            let opts = TokenizerOpts {
+                pi_as_processing_instruction: pis,
+                ..Default::default()
+            };
+            let comment = super::tokenize("<?php echo 1 ?>", opts)
+                .find(|token| token.kind() == Some(TokenKind::Comment));
+            assert_eq!(comment, Some(CommentToken("?php echo 1 ?".into())));
+        }
+    }
+
+    #[test]
+    fn preserve_raw_doctype() {
+        fn raw_doctype<I: IntoIterator<Item = Token>>(tokens: I) -> Option<String> {
+            tokens
+                .into_iter()
+                .find_map(|token| match token {
+                    DoctypeToken(d) => Some(d.raw.map(String::from)),
+                    _ => None,
+                })
+                .unwrap()
+        }
+        let opts = TokenizerOpts {
+            preserve_raw_doctype: true,
+            ..Default::default()
+        };
+
+        for raw in &[
+            "<!DOCTYPE html>",
+            "<!doctype HTML>",
+            "<!DocType html PUBLIC \"-//W3C//DTD HTML 4.01//EN\">",
+            "<!doctype html system 'about:legacy-compat'>",
+            "<!DOCTYPEhtml>",
+            "<!doctype html bogus>",
+            "<!DOCTYPE html",
+        ] {
+            let tokens = super::tokenize(raw, opts.clone());
+            assert_eq!(raw_doctype(tokens).as_deref(), Some(*raw));
+        }
+        let tokens = super::tokenize("<!doctype\r\nhtml>", opts.clone());
+        assert_eq!(raw_doctype(tokens).as_deref(), Some("<!doctype\nhtml>"));
+
+        let chunks = vec!["<!Doc".into(), "TYPE html Pub".into(), "lic \"x\">".into()];
+        let tokens = tokenize(chunks, opts).into_iter().map(|(token, _)| token);
+        assert_eq!(
+            raw_doctype(tokens).as_deref(),
+            Some("<!DocTYPE html Public \"x\">")
+        );
+
+        let tokens = super::tokenize("<!DOCTYPE html>", TokenizerOpts::default());
+        assert_eq!(raw_doctype(tokens), None);
+    }
+
+    #[test]
+    fn save_and_load_state() {
+        // Collects tokens with their line numbers, merging characters.
+        struct Collect(Vec<(Token, u64)>);
+
+        impl TokenSink for Collect {
+            type Handle = ();
+
+            fn process_token(&mut self, token: Token, line: u64) -> TokenSinkResult<()> {
+                match (self.0.last_mut(), token) {
+                    (Some(&mut (CharacterTokens(ref mut s), _)), CharacterTokens(t)) => {
+                        s.push_tendril(&t)
+                    },
+                    (_, token) => self.0.push((token, line)),
+                }
+                TokenSinkResult::Continue
+            }
+        }
+
+        fn opts() -> TokenizerOpts {
+            TokenizerOpts {
+                preserve_raw_doctype: true,
+                exact_errors: true,
+                max_entity_expansions: Some(4),
+                ..Default::default()
+            }
+        }
+
+        // Tokenize `input`, moving to another tokenizer after `split` bytes.
+        fn tokenize_split(input: &str, split: usize) -> Vec<(Token, u64)> {
+            let mut buffer = BufferQueue::new();
+            let mut first = Tokenizer::new(Collect(vec![]), opts());
+            buffer.push_back(StrTendril::from_slice(&input[..split]));
+            let _ = first.feed(&mut buffer);
+            let state = first.save_state();
+
+            #[cfg(feature = "binary_tokens")]
+            let state = {
+                let mut bytes = vec![];
+                super::serialize_tokenizer_state(&state, &mut bytes).unwrap();
+                super::deserialize_tokenizer_state(&bytes[..]).unwrap()
+            };
+
+            // The input the first tokenizer left in `buffer` goes along.
+            let mut second = Tokenizer::new(Collect(first.sink.0), opts());
+            second.load_state(state);
+            buffer.push_back(StrTendril::from_slice(&input[split..]));
+            let _ = second.feed(&mut buffer);
+            second.end();
+            second.sink.0
+        }
+
+        let input = "\u{feff}<!DoCtYpE html PUBLIC 'a'>\r\n<p class=x ID=\"y\" data-&amp;>\
+                     caf\u{e9} &amp &notin; &#x41; &#128; &#; &bogus;\r\r\n<!-- a -- b --!>\
+                     <a href='?x=1&lt=2'/><!x><![CDATA[c]]></p\0>\u{1}";
+        let expected = tokenize_split(input, input.len());
+        for (split, _) in input.char_indices() {
+            assert_eq!(tokenize_split(input, split), expected, "split at {}", split);
+        }
+    }
+    #[test]
+    fn tab_width() {
+        struct Ignore;
+
+        impl TokenSink for Ignore {
+            type Handle = ();
+
+            fn process_token(&mut self, _: Token, _: u64) -> TokenSinkResult<()> {
+                TokenSinkResult::Continue
+            }
+        }
+
+        fn column(input: &str, tab_width: Option<usize>) -> u64 {
+            let mut tok = Tokenizer::new(Ignore, TokenizerOpts::default());
+            tok.set_tab_width(tab_width);
+            let mut buffer = BufferQueue::new();
+            buffer.push_back(StrTendril::from_slice(input));
+            let _ = tok.feed(&mut buffer);
+            tok.end();
+            tok.current_column()
+        }
+
+        let cases = [
+            ("\t  \tx", [6, 10, 18]),
+            ("<p>\n \tab\t<b>", [9, 12, 20]),
+            ("<p title='\t'>\r\n\t\t", [3, 9, 17]),
+            // Characters after a character reference are read again.
+            ("&amp\tx", [7, 10, 10]),
+            ("&noti\tx", [8, 10, 10]),
+        ];
+        for &(input, columns) in cases.iter() {
+            for (&tab_width, &expected) in [None, Some(4), Some(8)].iter().zip(columns.iter()) {
+                assert_eq!(
+                    column(input, tab_width),
+                    expected,
+                    "{:?} with {:?}",
+                    input,
+                    tab_width
+                );
+            }
+        }
+    }
 }
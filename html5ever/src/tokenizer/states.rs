@@ -73,6 +73,10 @@ pub enum State {
     CommentStart,
     CommentStartDash,
     Comment,
+    CommentLessThanSign,
+    CommentLessThanSignBang,
+    CommentLessThanSignBangDash,
+    CommentLessThanSignBangDashDash,
     CommentEndDash,
     CommentEnd,
     CommentEndBang,
@@ -90,4 +94,6 @@ pub enum State {
     CdataSection,
     CdataSectionBracket,
     CdataSectionEnd,
+    ProcessingInstruction,
+    ProcessingInstructionQuestion,
 }
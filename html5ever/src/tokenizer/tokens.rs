@@ -0,0 +1,96 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An iterator over the tokens of a string, for consumers that don't need a tree.
+
+use super::{BufferQueue, Token, TokenSink, TokenSinkResult, Tokenizer, TokenizerOpts};
+use crate::tendril::StrTendril;
+
+use std::collections::VecDeque;
+
+/// How much input to feed the tokenizer at a time.
+const CHUNK_SIZE: usize = 1024;
+
+struct TokenQueue(VecDeque<Token>);
+
+impl TokenSink for TokenQueue {
+    type Handle = ();
+
+    fn process_token(&mut self, token: Token, _line_number: u64) -> TokenSinkResult<()> {
+        self.0.push_back(token);
+        TokenSinkResult::Continue
+    }
+}
+
+/// Iterator over the tokens of a string.  Created by `tokenize`.
+pub struct Tokens<'a> {
+    tokenizer: Tokenizer<TokenQueue>,
+    input: &'a str,
+    buffer: BufferQueue,
+    ended: bool,
+}
+
+/// Tokenize `input`, yielding tokens as they are produced.
+///
+/// Unlike the full parser, this does not build a tree, so there is no tree
+/// builder to switch the tokenizer into the raw text states.  The contents
+/// of elements like `<script>`, `<style>` and `<textarea>` are tokenized as
+/// ordinary markup.  Parse errors are yielded as `ParseError` tokens, and
+/// the last token is always `EOFToken`.
+///
+/// Tokens own their text; they don't borrow from `input`.
+///
+/// ```
+/// use html5ever::tokenizer::{tokenize, TagToken, StartTag};
+///
+/// let links = tokenize("<p><a href=x>one</a><a href=y>two</a>", Default::default())
+///     .filter(|token| match *token {
+///         TagToken(ref tag) => tag.kind == StartTag && &*tag.name == "a",
+///         _ => false,
+///     })
+///     .count();
+/// assert_eq!(links, 2);
+/// ```
+pub fn tokenize(input: &str, opts: TokenizerOpts) -> Tokens<'_> {
+    Tokens {
+        tokenizer: Tokenizer::new(TokenQueue(VecDeque::new()), opts),
+        input,
+        buffer: BufferQueue::new(),
+        ended: false,
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        loop {
+            if let Some(token) = self.tokenizer.sink.0.pop_front() {
+                return Some(token);
+            }
+            if self.input.is_empty() {
+                if self.ended {
+                    return None;
+                }
+                self.ended = true;
+                self.tokenizer.end();
+                continue;
+            }
+
+            let mut split = CHUNK_SIZE.min(self.input.len());
+            while !self.input.is_char_boundary(split) {
+                split += 1;
+            }
+            let (chunk, rest) = self.input.split_at(split);
+            self.input = rest;
+            self.buffer.push_back(StrTendril::from_slice(chunk));
+            let _ = self.tokenizer.feed(&mut self.buffer);
+        }
+    }
+}
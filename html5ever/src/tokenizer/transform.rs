@@ -0,0 +1,110 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Rewriting the token stream between the tokenizer and its sink.
+
+use super::{Pi, Token, TokenSink, TokenSinkResult};
+use crate::interface::AmbiguousConstruct;
+
+use std::mem;
+
+/// Sees each token before the sink does, and can drop, replace, or expand it.
+///
+/// The tokens that `transform` pushes to `output` are passed to the sink in
+/// order, all with the line number of the original token.
+///
+/// The tokenizer's state is driven by the sink, not by the transform: after
+/// a `<script>`, `<style>`, `<textarea>` or similar start tag reaches the tree
+/// builder, the following input is tokenized as text until the matching end
+/// tag.  A transform that emits such a start tag switches the tokenizer to
+/// text; one that hides such a start tag from the sink prevents the switch.
+/// If several of the tokens ask for a state change, only the last request
+/// takes effect.
+///
+/// Processing instructions are not tokens, and go to the sink unchanged.
+pub trait TokenTransform {
+    fn transform(&mut self, token: Token, output: &mut Vec<Token>);
+}
+
+/// A `TokenSink` that runs every token through a `TokenTransform` first.
+pub struct Transformed<T, Sink> {
+    pub transform: T,
+    pub sink: Sink,
+    /// Reused for the output of each call to `transform`.
+    output: Vec<Token>,
+}
+
+impl<T, Sink> Transformed<T, Sink> {
+    pub fn new(transform: T, sink: Sink) -> Self {
+        Transformed {
+            transform,
+            sink,
+            output: vec![],
+        }
+    }
+}
+
+impl<T, Sink> TokenSink for Transformed<T, Sink>
+where
+    T: TokenTransform,
+    Sink: TokenSink,
+{
+    type Handle = Sink::Handle;
+
+    fn process_token(&mut self, token: Token, line_number: u64) -> TokenSinkResult<Self::Handle> {
+        let mut output = mem::take(&mut self.output);
+        self.transform.transform(token, &mut output);
+        let mut result = TokenSinkResult::Continue;
+        for token in output.drain(..) {
+            match self.sink.process_token(token, line_number) {
+                TokenSinkResult::Continue => (),
+                other => result = other,
+            }
+        }
+        self.output = output;
+        result
+    }
+
+    fn process_pi(&mut self, pi: Pi, line_number: u64) -> TokenSinkResult<Self::Handle> {
+        self.sink.process_pi(pi, line_number)
+    }
+
+    fn end(&mut self) {
+        self.sink.end()
+    }
+
+    fn eof_in_token(&mut self) {
+        self.sink.eof_in_token()
+    }
+
+    fn flush_pending(&mut self) {
+        self.sink.flush_pending()
+    }
+
+    fn adjusted_current_node_present_but_not_in_html_namespace(&self) -> bool {
+        self.sink
+            .adjusted_current_node_present_but_not_in_html_namespace()
+    }
+
+    fn keep_tag_case(&self) -> bool {
+        self.sink.keep_tag_case()
+    }
+
+    fn ambiguous_construct(&mut self, construct: AmbiguousConstruct, line_number: u64) {
+        self.sink.ambiguous_construct(construct, line_number)
+    }
+
+    fn duplicate_attribute(&mut self, first: &str, duplicate: &str, line_number: u64) {
+        self.sink.duplicate_attribute(first, duplicate, line_number)
+    }
+
+    fn should_continue(&self) -> bool {
+        self.sink.should_continue()
+    }
+}
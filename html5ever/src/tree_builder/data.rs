@@ -11,6 +11,8 @@ use crate::interface::{LimitedQuirks, NoQuirks, Quirks, QuirksMode};
 use crate::tendril::StrTendril;
 use crate::tokenizer::Doctype;
 
+use mac::{_tt_as_expr_hack, matches};
+
 // These should all be lowercase, for ASCII-case-insensitive matching.
 static QUIRKY_PUBLIC_PREFIXES: &'static [&'static str] = &[
     "-//advasoft ltd//dtd html 3.0 aswedit + extensions//",
@@ -88,6 +90,82 @@ static HTML4_PUBLIC_PREFIXES: &'static [&'static str] = &[
     "-//w3c//dtd html 4.01 transitional//",
 ];
 
+/// Elements listed as obsolete and non-conforming by the spec, for the
+/// `flag_obsolete_elements` tree builder option.
+///
+/// https://html.spec.whatwg.org/multipage/#non-conforming-features
+pub static OBSOLETE_ELEMENTS: &'static [&'static str] = &[
+    "acronym",
+    "applet",
+    "basefont",
+    "bgsound",
+    "big",
+    "blink",
+    "center",
+    "dir",
+    "font",
+    "frame",
+    "frameset",
+    "image",
+    "isindex",
+    "keygen",
+    "listing",
+    "marquee",
+    "menuitem",
+    "multicol",
+    "nextid",
+    "nobr",
+    "noembed",
+    "noframes",
+    "plaintext",
+    "rb",
+    "rtc",
+    "spacer",
+    "strike",
+    "tt",
+    "xmp",
+];
+
+/// Attributes whose value is a URL, for the `allowed_url_schemes` tree
+/// builder option.  `xlink:href` is named as the tokenizer sees it, before
+/// the tree builder adjusts it in SVG and MathML.
+pub static URL_ATTRIBUTES: &'static [&'static str] = &[
+    "action",
+    "background",
+    "cite",
+    "codebase",
+    "data",
+    "formaction",
+    "href",
+    "icon",
+    "longdesc",
+    "manifest",
+    "poster",
+    "profile",
+    "src",
+    "usemap",
+    "xlink:href",
+];
+
+/// The scheme of `url`, lowercased, or `None` if it has none and so is
+/// relative.  As in the [URL parser], leading and trailing C0 controls and
+/// spaces are ignored, and so are tabs and newlines anywhere.
+///
+/// [URL parser]: https://url.spec.whatwg.org/#concept-basic-url-parser
+pub fn url_scheme(url: &str) -> Option<String> {
+    let mut scheme = String::new();
+    let url = url.trim_matches(|c| c <= ' ');
+    for c in url.chars().filter(|&c| !matches!(c, '\t' | '\n' | '\r')) {
+        match c {
+            ':' if !scheme.is_empty() => return Some(scheme),
+            'a'..='z' | 'A'..='Z' => scheme.push(c.to_ascii_lowercase()),
+            '0'..='9' | '+' | '-' | '.' if !scheme.is_empty() => scheme.push(c),
+            _ => return None,
+        }
+    }
+    None
+}
+
 pub fn doctype_error_and_quirks(doctype: &Doctype, iframe_srcdoc: bool) -> (bool, QuirksMode) {
     fn opt_string_as_slice<'t>(x: &'t Option<String>) -> Option<&'t str> {
         x.as_ref().map(|y| &y[..])
@@ -11,9 +11,18 @@
 
 //! The HTML5 tree builder.
 
-pub use crate::interface::{create_element, ElementFlags, NextParserState, Tracer, TreeSink};
+pub(crate) use self::data::url_scheme;
+pub use self::data::{OBSOLETE_ELEMENTS, URL_ATTRIBUTES};
+#[cfg(feature = "unicode-normalization")]
+pub use self::normalize::NormalizationForm;
+pub use self::tag_sets::{is_formatting_element, is_scope_terminator, is_special_element};
+pub use self::tag_sets::{is_html_integration_point, is_mathml_text_integration_point};
+pub use self::types::{InsertionMode, Scope};
+pub use crate::interface::{create_element, create_element_raw_attrs, ElementFlags};
+pub use crate::interface::{AmbiguousConstruct, ParseStats};
 pub use crate::interface::{AppendNode, AppendText, Attribute, NodeOrText};
 pub use crate::interface::{LimitedQuirks, NoQuirks, Quirks, QuirksMode};
+pub use crate::interface::{NextParserState, Tracer, TreeSink};
 
 use self::types::*;
 
@@ -22,9 +31,10 @@ use crate::{ExpandedName, LocalName, Namespace, QualName};
 
 use crate::tokenizer;
 use crate::tokenizer::states as tok_state;
-use crate::tokenizer::{Doctype, EndTag, StartTag, Tag, TokenSink, TokenSinkResult};
+use crate::tokenizer::{Doctype, EndTag, Pi, StartTag, Tag, TokenKind};
+use crate::tokenizer::{TokenSink, TokenSinkResult};
 
-use std::borrow::Cow::Borrowed;
+use std::borrow::Cow::{self, Borrowed};
 use std::collections::VecDeque;
 use std::default::Default;
 use std::iter::{Enumerate, Rev};
@@ -44,6 +54,8 @@ pub use self::PushFlag::*;
 mod tag_sets;
 
 mod data;
+#[cfg(feature = "unicode-normalization")]
+mod normalize;
 mod types;
 
 include!(concat!(env!("OUT_DIR"), "/rules.rs"));
@@ -69,8 +81,173 @@ pub struct TreeBuilderOpts {
 
     /// Initial TreeBuilder quirks mode. Default: NoQuirks
     pub quirks_mode: QuirksMode,
+
+    /// Maximum length of the list of active formatting elements after its
+    /// last marker, that is within the innermost table cell, caption,
+    /// template, `<object>`, `<applet>` or `<marquee>`.  When a new
+    /// formatting element would exceed it, the oldest entry after the marker
+    /// is dropped with a parse error.  With `Some(0)` the new element itself
+    /// is dropped, so formatting elements are never reconstructed.  This
+    /// bounds the work done reconstructing formatting elements on
+    /// adversarial input.
+    /// Default: None
+    pub max_formatting_elements: Option<usize>,
+
+    /// Pass a `DOCTYPE` that appears after the start of the document to
+    /// `TreeSink::misplaced_doctype` rather than ignoring it.  This is not
+    /// conforming: the spec drops such a `DOCTYPE`, and it is still reported
+    /// as a parse error.  Useful for faithful source transformation.
+    /// Default: false
+    pub preserve_misplaced_doctype: bool,
+
+    /// Skip the adoption agency algorithm.  The end tag of a mis-nested
+    /// formatting element then closes it along with every element opened
+    /// after it, with a parse error, instead of restructuring the tree.
+    /// This is faster on formatting-heavy input but produces a different
+    /// tree from the spec for mis-nested markup such as `<b>1<p>2</b>3`.
+    /// Default: false
+    pub disable_adoption_agency: bool,
+
+    /// Rewrite an `<image>` start tag to `<img>`, as the spec requires.
+    /// When false, an `<image>` element is created instead.  Either way it is
+    /// reported as a parse error.  Default: true
+    pub rewrite_image_tag: bool,
+
+    /// Call `TreeSink::obsolete_element` for each HTML element in
+    /// `OBSOLETE_ELEMENTS` created for a start tag in the source, and for
+    /// each `<image>` start tag rewritten to `<img>`.  SVG and MathML
+    /// elements of the same name, like `<svg><font>`, are not reported, nor
+    /// are formatting elements the tree builder reopens.  This is advisory
+    /// only; such elements are parsed as usual.  Default: false
+    pub flag_obsolete_elements: bool,
+
+    /// Keep the case of SVG and MathML element and attribute names as
+    /// written, instead of lowercasing them, so that `<svg><myShape
+    /// strokeStyle=x>` creates a `myShape` element with a `strokeStyle`
+    /// attribute.  Names in the spec's adjustment tables, like `viewBox`,
+    /// are still adjusted whatever their case.  Elements and attributes in
+    /// the HTML namespace are not affected, nor are the names on the `<svg>`
+    /// or `<math>` tag that starts foreign content, because the case is only
+    /// kept for tags inside a foreign element.
+    ///
+    /// This is not conforming: the spec lowercases every other name, so the
+    /// tree differs from a browser's for foreign content written in mixed
+    /// case.  In particular `<svg><RECT>` creates a `RECT` element, not an
+    /// SVG `rect`.  Default: false
+    pub preserve_foreign_case: bool,
+
+    /// Sort the attributes of each element by namespace and then local
+    /// name, comparing them as strings, before the element is created.
+    /// This gives a canonical form for comparing or caching documents, but
+    /// the attributes no longer appear in source order, so a serialized
+    /// document differs from its source.  Duplicate attributes keep their
+    /// relative order.  Default: false
+    pub sort_attributes: bool,
+
+    /// Create elements through `TreeSink::create_element_raw_attrs`, which
+    /// sees the duplicate attributes kept by the tokenizer's
+    /// `preserve_all_attributes`.  Set both, as
+    /// `ParseOptsBuilder::preserve_all_attributes` does.  Default: false
+    pub preserve_all_attributes: bool,
+
+    /// Close a non-void HTML element right away when its start tag ends in
+    /// `/>`, as in XML, by processing a matching end tag after it.  This is
+    /// not what browsers do, and each one is reported as a parse error.
+    /// Elements whose content is parsed as text, like `<script/>` or
+    /// `<title/>`, are not affected.  Default: false
+    pub honor_self_closing: bool,
+
+    /// Remove attributes that hold a URL, like `href`, `src` and `action`,
+    /// when the URL has a scheme not in this list, such as `javascript:`.
+    /// Schemes are compared ignoring ASCII case and don't include the
+    /// colon.  Whitespace and control characters that browsers ignore, as
+    /// in `java\tscript:`, don't hide the scheme, and URLs without one are
+    /// always kept.  Each removal is reported as a parse error.  The
+    /// attributes checked are those in `URL_ATTRIBUTES`, on any element.
+    /// Default: None, every URL is kept
+    pub allowed_url_schemes: Option<&'static [&'static str]>,
+
+    /// Keep a table cell, row or row group that is outside any table, as
+    /// in `<td>x`, by creating the `<table>`, `<tbody>` and `<tr>` it is
+    /// missing around it, instead of dropping its start tag.  Each wrapper
+    /// is reported with `TreeSink::element_synthesized`, and the tag is
+    /// still a parse error.  This is not conforming, but helps when
+    /// assembling a table from fragments.  Default: false
+    pub wrap_orphaned_table_content: bool,
+
+    /// Create elements in this namespace instead of the HTML namespace,
+    /// except inside SVG and MathML, for parsing HTML-like markup in
+    /// another vocabulary.  Tags are still parsed with the HTML rules, so
+    /// `<p>` in a custom namespace still closes at `<div>`, and `<svg>`
+    /// and `<math>` still switch to foreign content.  When asking the sink
+    /// for element names, the tree builder treats this namespace as HTML.
+    ///
+    /// This is not conforming, and the tree is not one that a browser
+    /// would build: serializers, selectors and other code that looks for
+    /// HTML elements by namespace won't recognize these elements, so
+    /// `<script>` or `<template>` lose their special meaning after
+    /// parsing.  Elements already in the tree, like the context element
+    /// of a fragment, are unaffected.  Default: None, the HTML namespace
+    pub default_namespace: Option<&'static str>,
+
+    /// Normalize each run of text and each comment to this Unicode
+    /// normalization form before the tree sink sees it, so that `é` written
+    /// as one code point and `e` followed by a combining accent compare
+    /// equal when indexing.
+    ///
+    /// The tree then no longer matches the source byte for byte.  A run of
+    /// text is normalized as a whole even when it arrives in several chunks
+    /// of input: at the end of a chunk, the characters that later input
+    /// could combine with are held back.  Attribute values and processing
+    /// instructions are not affected.
+    ///
+    /// Requires the `unicode-normalization` feature.  Default: None
+    #[cfg(feature = "unicode-normalization")]
+    pub normalize_unicode: Option<NormalizationForm>,
+
+    /// Close the elements still open inside `<body>` at `</body>` and
+    /// `</html>`.
+    ///
+    /// By the spec these end tags only check for unclosed elements and
+    /// switch the insertion mode; nothing is popped.  Content after them,
+    /// other than whitespace and comments, is a parse error that switches
+    /// back to "in body", so it is inserted wherever it would have gone
+    /// without the end tag: `<p>a</body>b` puts `b` in the `<p>`, and the
+    /// `<p>` is only popped at the end of the document.  With this option
+    /// the elements inside `<body>` are popped at the end tag, as they
+    /// would be by any other end tag, so `b` is appended to the `<body>`
+    /// after the `<p>`.  `<body>` and `<html>` stay open either way.
+    /// Formatting elements are reopened by later content as usual.
+    /// Default: false
+    pub strict_body_close: bool,
+
+    /// Count tokens, elements and other events while parsing, and pass
+    /// them to `TreeSink::parse_stats` before the sink is finished.
+    /// Default: false
+    pub collect_stats: bool,
 }
 
+/// Where a node is about to be inserted, as passed to the callback set
+/// with `TreeBuilder::set_on_insertion_point`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InsertionPointInfo {
+    /// The name of the element the node is inserted into.  With foster
+    /// parenting this is the `<table>` the node is inserted in front of,
+    /// or the `<template>` or `<html>` it is appended to; in a `<template>`
+    /// it is the template, though the node goes into its contents.
+    pub parent: QualName,
+
+    /// Whether the node is being foster-parented: inserted in front of a
+    /// table, or elsewhere, because it may not go inside one.
+    pub foster_parented: bool,
+}
+
+/// A callback for `TreeBuilder::set_on_insertion_point`.
+pub type InsertionPointCallback = Box<dyn FnMut(InsertionPointInfo)>;
+
+/// A filter for `TreeBuilder::set_comment_filter`.
+pub type CommentFilter = Box<dyn Fn(&str) -> bool>;
+
 impl Default for TreeBuilderOpts {
     fn default() -> TreeBuilderOpts {
         TreeBuilderOpts {
@@ -80,11 +257,51 @@ impl Default for TreeBuilderOpts {
             drop_doctype: false,
             ignore_missing_rules: false,
             quirks_mode: NoQuirks,
+            max_formatting_elements: None,
+            preserve_misplaced_doctype: false,
+            disable_adoption_agency: false,
+            rewrite_image_tag: true,
+            flag_obsolete_elements: false,
+            preserve_foreign_case: false,
+            sort_attributes: false,
+            preserve_all_attributes: false,
+            honor_self_closing: false,
+            allowed_url_schemes: None,
+            wrap_orphaned_table_content: false,
+            default_namespace: None,
+            #[cfg(feature = "unicode-normalization")]
+            normalize_unicode: None,
+            strict_body_close: false,
+            collect_stats: false,
         }
     }
 }
 
+/// The state of the tree builder after it has processed a token, as passed
+/// to the callback set with `TreeBuilder::set_on_step`.
+pub struct StepInfo<'a> {
+    /// The token, as it came from the tokenizer.
+    pub token: &'a tokenizer::Token,
+
+    /// The insertion mode after processing the token.
+    pub mode: InsertionMode,
+
+    /// The names of the open elements, outermost first.
+    pub open_elements: Vec<ExpandedName<'a>>,
+}
+
+/// A callback for `TreeBuilder::set_on_step`.
+pub type StepCallback = Box<dyn FnMut(StepInfo<'_>)>;
+
 /// The HTML tree builder.
+///
+/// Character tokens are not inserted as they arrive.  The tree builder
+/// holds them back until a token of another kind, the end of the input or a
+/// call to `process_pending_text`, and then inserts them as one run of text.
+/// `Parser` calls `process_pending_text` each time it runs out of input, but
+/// when you drive a `Tokenizer` with a tree builder sink yourself, the last
+/// text of each chunk is not in the tree, and not reflected in the tree
+/// builder's state, until you call it.
 pub struct TreeBuilder<Handle, Sink> {
     /// Options controlling the behavior of the tree builder.
     opts: TreeBuilderOpts,
@@ -114,6 +331,9 @@ pub struct TreeBuilder<Handle, Sink> {
     /// Stack of open elements, most recently added at end.
     open_elems: Vec<Handle>,
 
+    /// How many of the open elements preserve whitespace, like `<pre>`.
+    preserve_whitespace_depth: usize,
+
     /// List of active formatting elements.
     active_formatting: Vec<FormatEntry<Handle>>,
 
@@ -130,6 +350,10 @@ pub struct TreeBuilder<Handle, Sink> {
     /// Ignore a following U+000A LINE FEED?
     ignore_lf: bool,
 
+    /// Is the line feed to ignore the second half of a "\r\n", whose "\r"
+    /// was already dropped and reported?
+    ignore_lf_after_cr: bool,
+
     /// Is foster parenting enabled?
     foster_parenting: bool,
 
@@ -138,6 +362,51 @@ pub struct TreeBuilder<Handle, Sink> {
 
     /// Track current line
     current_line: u64,
+
+    /// The root element's `lang` and `dir` attributes, as last reported to
+    /// the sink.
+    root_lang: Option<StrTendril>,
+    root_dir: Option<StrTendril>,
+
+    /// Called after each token is processed, if set.
+    on_step: Option<StepCallback>,
+
+    /// Called before each insertion, if set.
+    on_insertion_point: Option<InsertionPointCallback>,
+
+    /// Decides which comments to keep, if set.
+    comment_filter: Option<CommentFilter>,
+
+    /// `TreeBuilderOpts::default_namespace`, as a namespace.
+    default_ns: Option<Namespace>,
+
+    /// Stop parsing instead of creating a `<body>` or `<frameset>`?
+    stop_at_body: bool,
+
+    /// Has parsing stopped at the body?
+    stopped_at_body: bool,
+
+    /// Counters for `TreeBuilderOpts::collect_stats`, if enabled.
+    stats: Option<ParseStats>,
+
+    /// The names of the current tag token as written, if they are not all
+    /// lowercase and `preserve_foreign_case` is set.
+    source_case: Option<(LocalName, Vec<LocalName>)>,
+
+    /// Character tokens received since the last token of another kind.
+    /// Consecutive runs of characters, as in text split by newlines or
+    /// character references, are processed as one.
+    pending_text: StrTendril,
+
+    /// The line of the first token in `pending_text`.
+    pending_text_line: u64,
+
+    /// The kind of the last token from the tokenizer, other than a parse
+    /// error.
+    last_token_kind: Option<TokenKind>,
+
+    /// Did the input end in the middle of a token?
+    eof_in_token: bool,
     // WARNING: If you add new fields that contain Handles, you
     // must add them to trace_handles() below to preserve memory
     // safety!
@@ -155,6 +424,13 @@ where
     /// The tree builder is also a `TokenSink`.
     pub fn new(mut sink: Sink, opts: TreeBuilderOpts) -> TreeBuilder<Handle, Sink> {
         let doc_handle = sink.get_document();
+        let quirks_mode = opts.quirks_mode;
+        let default_ns = opts.default_namespace.map(Namespace::from);
+        let stats = if opts.collect_stats {
+            Some(ParseStats::default())
+        } else {
+            None
+        };
         TreeBuilder {
             opts: opts,
             sink: sink,
@@ -162,20 +438,54 @@ where
             orig_mode: None,
             template_modes: vec![],
             pending_table_text: vec![],
-            quirks_mode: opts.quirks_mode,
+            quirks_mode: quirks_mode,
             doc_handle: doc_handle,
             open_elems: vec![],
+            preserve_whitespace_depth: 0,
             active_formatting: vec![],
             head_elem: None,
             form_elem: None,
             frameset_ok: true,
             ignore_lf: false,
+            ignore_lf_after_cr: false,
             foster_parenting: false,
             context_elem: None,
             current_line: 1,
+            root_lang: None,
+            root_dir: None,
+            on_step: None,
+            on_insertion_point: None,
+            comment_filter: None,
+            default_ns: default_ns,
+            stop_at_body: false,
+            stopped_at_body: false,
+            pending_text: StrTendril::new(),
+            pending_text_line: 1,
+            last_token_kind: None,
+            eof_in_token: false,
+            stats: stats,
+            source_case: None,
         }
     }
 
+    /// Create a tree builder that continues a document in the "in body"
+    /// insertion mode, with only `html` and `body` open, as
+    /// `at_body_top_level` describes.  Used by `resume_in_body`.
+    pub(crate) fn new_in_body(
+        sink: Sink,
+        opts: TreeBuilderOpts,
+        html: Handle,
+        head: Option<Handle>,
+        body: Handle,
+    ) -> TreeBuilder<Handle, Sink> {
+        let mut tb = TreeBuilder::new(sink, opts);
+        tb.mode = InBody;
+        tb.open_elems = vec![html, body];
+        tb.head_elem = head;
+        tb.frameset_ok = false;
+        tb
+    }
+
     /// Create a new tree builder which sends tree modifications to a particular `TreeSink`.
     /// This is for parsing fragments.
     ///
@@ -187,7 +497,15 @@ where
         opts: TreeBuilderOpts,
     ) -> TreeBuilder<Handle, Sink> {
         let doc_handle = sink.get_document();
-        let context_is_template = sink.elem_name(&context_elem) == expanded_name!(html "template");
+        let default_ns = opts.default_namespace.map(Namespace::from);
+        let context_is_template = as_html_name(sink.elem_name(&context_elem), &default_ns) ==
+            expanded_name!(html "template");
+        let quirks_mode = opts.quirks_mode;
+        let stats = if opts.collect_stats {
+            Some(ParseStats::default())
+        } else {
+            None
+        };
         let mut tb = TreeBuilder {
             opts: opts,
             sink: sink,
@@ -199,17 +517,33 @@ where
                 vec![]
             },
             pending_table_text: vec![],
-            quirks_mode: opts.quirks_mode,
+            quirks_mode: quirks_mode,
             doc_handle: doc_handle,
             open_elems: vec![],
+            preserve_whitespace_depth: 0,
             active_formatting: vec![],
             head_elem: None,
             form_elem: form_elem,
             frameset_ok: true,
             ignore_lf: false,
+            ignore_lf_after_cr: false,
             foster_parenting: false,
             context_elem: Some(context_elem),
             current_line: 1,
+            root_lang: None,
+            root_dir: None,
+            on_step: None,
+            on_insertion_point: None,
+            comment_filter: None,
+            default_ns: default_ns,
+            stop_at_body: false,
+            stopped_at_body: false,
+            pending_text: StrTendril::new(),
+            pending_text_line: 1,
+            last_token_kind: None,
+            eof_in_token: false,
+            stats: stats,
+            source_case: None,
         };
 
         // https://html.spec.whatwg.org/multipage/#parsing-html-fragments
@@ -227,7 +561,7 @@ where
     // Step 4. Set the state of the HTML parser's tokenization stage as follows:
     pub fn tokenizer_state_for_context_elem(&self) -> tok_state::State {
         let elem = self.context_elem.as_ref().expect("no context element");
-        let name = match self.sink.elem_name(elem) {
+        let name = match self.elem_name(elem) {
             ExpandedName {
                 ns: &ns!(html),
                 local,
@@ -268,7 +602,7 @@ where
         }
         for e in &self.active_formatting {
             match e {
-                &Element(ref h, _) => tracer.trace_handle(h),
+                &Element(ref h, _, _) => tracer.trace_handle(h),
                 _ => (),
             }
         }
@@ -282,7 +616,7 @@ where
         println!("dump_state on {}", label);
         print!("    open_elems:");
         for node in self.open_elems.iter() {
-            let name = self.sink.elem_name(node);
+            let name = self.elem_name(node);
             match *name.ns {
                 ns!(html) => print!(" {}", name.local),
                 _ => panic!(),
@@ -293,8 +627,8 @@ where
         for entry in self.active_formatting.iter() {
             match entry {
                 &Marker => print!(" Marker"),
-                &Element(ref h, _) => {
-                    let name = self.sink.elem_name(h);
+                &Element(ref h, _, _) => {
+                    let name = self.elem_name(h);
                     match *name.ns {
                         ns!(html) => print!(" {}", name.local),
                         _ => panic!(),
@@ -329,6 +663,12 @@ where
                     ..
                 })
             );
+            let self_closing = match token {
+                TagToken(ref tag) if should_have_acknowledged_self_closing_flag => {
+                    Some((tag.name.clone(), self.open_elems.last().cloned()))
+                },
+                _ => None,
+            };
             let result = if self.is_foreign(&token) {
                 self.step_foreign(token)
             } else {
@@ -337,9 +677,22 @@ where
             };
             match result {
                 Done => {
-                    if should_have_acknowledged_self_closing_flag {
-                        self.sink
-                            .parse_error(Borrowed("Unacknowledged self-closing tag"));
+                    if let Some((name, prev)) = self_closing {
+                        let qual_name = QualName::new(None, ns!(html), name.clone());
+                        self.sink.unacknowledged_self_closing(&qual_name);
+                        if self.opts.honor_self_closing && self.inserted_html_element(&name, prev) {
+                            self.parse_error(Borrowed(
+                                "Self-closing tag closed a non-void element",
+                            ));
+                            token = TagToken(Tag {
+                                kind: EndTag,
+                                name,
+                                self_closing: false,
+                                attrs: vec![],
+                            });
+                            continue;
+                        }
+                        self.parse_error(Borrowed("Unacknowledged self-closing tag"));
                     }
                     token = unwrap_or_return!(
                         more_tokens.pop_front(),
@@ -385,11 +738,233 @@ where
         }
     }
 
+    /// Is this a document in the "in body" insertion mode, with only `html`
+    /// and `body` open, no active formatting elements or templates, no form
+    /// element pointer and the frameset-ok flag unset?  Everything the tree
+    /// builder does next depends only on the tokens to come.
+    pub(crate) fn at_body_top_level(&self) -> bool {
+        self.mode == InBody &&
+            self.context_elem.is_none() &&
+            self.open_elems.len() == 2 &&
+            self.active_formatting.is_empty() &&
+            self.template_modes.is_empty() &&
+            self.pending_table_text.is_empty() &&
+            self.pending_text.is_empty() &&
+            self.form_elem.is_none() &&
+            !self.frameset_ok &&
+            !self.ignore_lf &&
+            !self.stopped_at_body
+    }
+
+    /// Is the current node an HTML element named `name` that was not the
+    /// current node before, when it was `prev`?
+    fn inserted_html_element(&self, name: &LocalName, prev: Option<Handle>) -> bool {
+        let current = unwrap_or_return!(self.open_elems.last(), false);
+        if let Some(prev) = prev {
+            if self.sink.same_node(current, &prev) {
+                return false;
+            }
+        }
+        self.elem_name(current) ==
+            ExpandedName {
+                ns: &ns!(html),
+                local: name,
+            }
+    }
+
     /// Are we parsing a HTML fragment?
     pub fn is_fragment(&self) -> bool {
         self.context_elem.is_some()
     }
 
+    /// The current insertion mode.  This is intended for tooling and debugging.
+    pub fn current_insertion_mode(&self) -> InsertionMode {
+        self.mode
+    }
+
+    /// The [form element pointer]: the `<form>` that form controls are
+    /// currently associated with, if any.
+    ///
+    /// [form element pointer]: https://html.spec.whatwg.org/multipage/#form-element-pointer
+    pub fn current_form_element(&self) -> Option<&Handle> {
+        self.form_elem.as_ref()
+    }
+
+    /// The namespace of the [adjusted current node], which decides whether
+    /// the next token is handled as foreign content.  This is the HTML
+    /// namespace when no element is open.
+    ///
+    /// [adjusted current node]: https://html.spec.whatwg.org/multipage/#adjusted-current-node
+    pub fn current_namespace(&self) -> Namespace {
+        if self.open_elems.is_empty() {
+            return ns!(html);
+        }
+        self.elem_name(self.adjusted_current_node()).ns.clone()
+    }
+
+    /// Is an element named `name` [in `scope`], given the stack of open
+    /// elements?  This is the check the tree builder makes before closing
+    /// elements, so tools that change the tree the way the parser would can
+    /// reuse it.
+    ///
+    /// [in `scope`]: https://html.spec.whatwg.org/multipage/#has-an-element-in-scope
+    pub fn has_element_in_scope(&self, name: &QualName, scope: Scope) -> bool {
+        let pred = |elem: Handle| self.elem_name(&elem) == name.expanded();
+        match scope {
+            Scope::Default => self.in_scope(default_scope, pred),
+            Scope::ListItem => self.in_scope(list_item_scope, pred),
+            Scope::Button => self.in_scope(button_scope, pred),
+            Scope::Table => self.in_scope(table_scope, pred),
+            Scope::Select => self.in_scope(select_scope, pred),
+        }
+    }
+
+    /// The kind of the last token from the tokenizer that was received,
+    /// or `None` before the first one.  Parse errors are skipped.
+    ///
+    /// This reflects the raw token stream, not the tree: after `<p>a<div>`
+    /// it is `StartTag` although the `<p>` was also closed, and elements
+    /// the tree builder implies or reconstructs have no token of their own.
+    pub fn last_token_kind(&self) -> Option<TokenKind> {
+        self.last_token_kind
+    }
+
+    /// Call `on_step` after each token from the tokenizer is processed.
+    ///
+    /// This is for debuggers and teaching tools that show the parse one
+    /// step at a time.  Consecutive character tokens are processed as one,
+    /// as `process_pending_text` describes, and give one step.  It clones
+    /// every token and collects the names of the open elements each time,
+    /// so it is much slower than parsing without it.  Without a callback
+    /// there is no extra work.  Processing instructions, from the
+    /// tokenizer's `pi_as_processing_instruction` option, are not reported.
+    pub fn set_on_step(&mut self, on_step: Option<StepCallback>) {
+        self.on_step = on_step;
+    }
+
+    /// Call `on_insertion_point` before each node or run of text is
+    /// inserted, with where it is going.
+    ///
+    /// This is meant for debugging how the tree builder handles misnested
+    /// markup, such as content in a `<table>` that is moved in front of it.
+    /// Nodes that the tree sink moves itself, as in `reparent_children`,
+    /// are not reported.
+    pub fn set_on_insertion_point(&mut self, on_insertion_point: Option<InsertionPointCallback>) {
+        self.on_insertion_point = on_insertion_point;
+    }
+
+    /// Drop each comment for which `comment_filter` returns false, before
+    /// the tree sink sees it.  It is passed the comment's text exactly as
+    /// written, without the `<!--` and `-->` but with any whitespace around
+    /// it, so `<!-- build:include -->` gives `" build:include "`.
+    /// Processing instructions are not affected.
+    pub fn set_comment_filter(&mut self, comment_filter: Option<CommentFilter>) {
+        self.comment_filter = comment_filter;
+    }
+
+    /// Stop parsing where the `<body>` or `<frameset>` element would be
+    /// created.  Used by `parse_head_only`.
+    pub(crate) fn set_stop_at_body(&mut self) {
+        self.stop_at_body = true;
+    }
+
+    /// The counters collected so far, if `TreeBuilderOpts::collect_stats` is set.
+    pub fn stats(&self) -> Option<&ParseStats> {
+        self.stats.as_ref()
+    }
+
+    /// Process the character tokens received since the last token of
+    /// another kind.
+    ///
+    /// Character tokens are not processed as they arrive, but joined and
+    /// processed as one run when a token of another kind arrives, or at
+    /// the end, so that a run of text split by newlines or character
+    /// references is inserted with one call to the sink.  The parser calls
+    /// this whenever it runs out of input; call it after feeding the
+    /// tokenizer directly to look at the tree, or at the tree builder's
+    /// state, before the next token.
+    ///
+    /// With `TreeBuilderOpts::normalize_unicode`, the end of the text that
+    /// characters still to come could combine with stays pending.
+    pub fn process_pending_text(&mut self) {
+        #[cfg(feature = "unicode-normalization")]
+        {
+            if let Some(form) = self.opts.normalize_unicode {
+                let len = form.stable_prefix_len(&self.pending_text) as u32;
+                let rest = self
+                    .pending_text
+                    .subtendril(len, self.pending_text.len32() - len);
+                self.pending_text.pop_back(rest.len32());
+                let rest_line =
+                    self.pending_text_line + self.pending_text.matches('\n').count() as u64;
+                self.flush_pending_text();
+                self.pending_text = rest;
+                self.pending_text_line = rest_line;
+                return;
+            }
+        }
+        self.flush_pending_text();
+    }
+
+    /// Process all of the pending text, at the end of a run of text.
+    fn flush_pending_text(&mut self) {
+        if self.pending_text.is_empty() {
+            return;
+        }
+        let text = replace(&mut self.pending_text, StrTendril::new());
+        let line_number = self.pending_text_line;
+        let result = self.process_step(tokenizer::CharacterTokens(text), line_number);
+        debug_assert!(matches!(result, tokenizer::TokenSinkResult::Continue));
+    }
+
+    /// Process a token, reporting it to `on_step` if that is set.
+    fn process_step(
+        &mut self,
+        token: tokenizer::Token,
+        line_number: u64,
+    ) -> TokenSinkResult<Handle> {
+        if self.on_step.is_none() {
+            return self.process_tokenizer_token(token, line_number);
+        }
+        let step_token = token.clone();
+        let result = self.process_tokenizer_token(token, line_number);
+        self.report_step(&step_token);
+        result
+    }
+
+    /// Pass the collected counters, if any, to `TreeSink::parse_stats`.
+    pub(crate) fn report_stats(&mut self) {
+        if let Some(stats) = self.stats {
+            self.sink.parse_stats(stats);
+        }
+    }
+
+    fn record_stats<F: FnOnce(&mut ParseStats)>(&mut self, record: F) {
+        if let Some(ref mut stats) = self.stats {
+            record(stats);
+        }
+    }
+
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.record_stats(|stats| stats.parse_errors += 1);
+        self.sink.parse_error(msg);
+    }
+
+    fn stop_before_body(&mut self) -> ProcessResult<Handle> {
+        self.stopped_at_body = true;
+        Done
+    }
+
+    fn report_step(&mut self, token: &tokenizer::Token) {
+        let on_step = unwrap_or_return!(self.on_step.as_mut(), ());
+        let sink = &self.sink;
+        on_step(StepInfo {
+            token,
+            mode: self.mode,
+            open_elements: self.open_elems.iter().map(|e| sink.elem_name(e)).collect(),
+        });
+    }
+
     /// https://html.spec.whatwg.org/multipage/#appropriate-place-for-inserting-a-node
     fn appropriate_place_for_insertion(
         &mut self,
@@ -402,29 +977,58 @@ where
         if !(self.foster_parenting && self.elem_in(&target, foster_target)) {
             if self.html_elem_named(&target, local_name!("template")) {
                 // No foster parenting (inside template).
+                self.report_insertion_point(&target, false);
                 let contents = self.sink.get_template_contents(&target);
                 return LastChild(contents);
             } else {
                 // No foster parenting (the common case).
+                self.report_insertion_point(&target, false);
                 return LastChild(target);
             }
         }
 
         // Foster parenting
-        let mut iter = self.open_elems.iter().rev().peekable();
-        while let Some(elem) = iter.next() {
-            if self.html_elem_named(&elem, local_name!("template")) {
-                let contents = self.sink.get_template_contents(&elem);
-                return LastChild(contents);
-            } else if self.html_elem_named(&elem, local_name!("table")) {
-                return TableFosterParenting {
-                    element: elem.clone(),
-                    prev_element: (*iter.peek().unwrap()).clone(),
-                };
+        self.record_stats(|stats| stats.foster_parented += 1);
+        let index = self.open_elems.iter().rposition(|elem| {
+            self.html_elem_named(elem, local_name!("template")) ||
+                self.html_elem_named(elem, local_name!("table"))
+        });
+        let index = match index {
+            Some(index) => index,
+            None => {
+                let html_elem = self.html_elem().clone();
+                self.report_insertion_point(&html_elem, true);
+                return LastChild(html_elem);
+            },
+        };
+        let elem = self.open_elems[index].clone();
+        self.report_insertion_point(&elem, true);
+        if self.html_elem_named(&elem, local_name!("template")) {
+            let contents = self.sink.get_template_contents(&elem);
+            LastChild(contents)
+        } else {
+            TableFosterParenting {
+                element: elem,
+                prev_element: self.open_elems[index - 1].clone(),
             }
         }
-        let html_elem = self.html_elem();
-        LastChild(html_elem.clone())
+    }
+
+    /// Pass the insertion point to `on_insertion_point`, if it is set.
+    /// `parent` is the element whose children the node joins, or the
+    /// template whose contents it joins, or the table it is placed before.
+    fn report_insertion_point(&mut self, parent: &Handle, foster_parented: bool) {
+        if self.on_insertion_point.is_none() {
+            return;
+        }
+        let name = self.elem_name(parent);
+        let parent = QualName::new(None, name.ns.clone(), name.local.clone());
+        if let Some(ref mut callback) = self.on_insertion_point {
+            callback(InsertionPointInfo {
+                parent,
+                foster_parented,
+            });
+        }
     }
 
     fn insert_at(&mut self, insertion_point: InsertionPoint<Handle>, child: NodeOrText<Handle>) {
@@ -441,27 +1045,30 @@ where
     }
 }
 
-impl<Handle, Sink> TokenSink for TreeBuilder<Handle, Sink>
+impl<Handle, Sink> TreeBuilder<Handle, Sink>
 where
     Handle: Clone,
     Sink: TreeSink<Handle = Handle>,
 {
-    type Handle = Handle;
-
-    fn process_token(
+    fn process_tokenizer_token(
         &mut self,
         token: tokenizer::Token,
         line_number: u64,
     ) -> TokenSinkResult<Handle> {
+        if self.stopped_at_body || !self.sink.should_continue() {
+            return tokenizer::TokenSinkResult::Continue;
+        }
         if line_number != self.current_line {
             self.sink.set_current_line(line_number);
+            self.current_line = line_number;
         }
         let ignore_lf = replace(&mut self.ignore_lf, false);
+        let ignore_lf_after_cr = replace(&mut self.ignore_lf_after_cr, false);
 
         // Handle `ParseError` and `DoctypeToken`; convert everything else to the local `Token` type.
         let token = match token {
             tokenizer::ParseError(e) => {
-                self.sink.parse_error(e);
+                self.parse_error(e);
                 return tokenizer::TokenSinkResult::Continue;
             },
 
@@ -469,7 +1076,7 @@ where
                 if self.mode == Initial {
                     let (err, quirk) = data::doctype_error_and_quirks(&dt, self.opts.iframe_srcdoc);
                     if err {
-                        self.sink.parse_error(format_if!(
+                        self.parse_error(format_if!(
                             self.opts.exact_errors,
                             "Bad DOCTYPE",
                             "Bad DOCTYPE: {:?}",
@@ -480,38 +1087,83 @@ where
                         name,
                         public_id,
                         system_id,
-                        force_quirks: _,
+                        force_quirks,
+                        raw,
                     } = dt;
                     if !self.opts.drop_doctype {
-                        self.sink.append_doctype_to_document(
-                            name.unwrap_or(StrTendril::new()),
-                            public_id.unwrap_or(StrTendril::new()),
-                            system_id.unwrap_or(StrTendril::new()),
-                        );
+                        let name = name.unwrap_or(StrTendril::new());
+                        let public_id = public_id.unwrap_or(StrTendril::new());
+                        let system_id = system_id.unwrap_or(StrTendril::new());
+                        match raw {
+                            Some(raw) => self
+                                .sink
+                                .append_doctype_raw(raw, name, public_id, system_id),
+                            None => self.sink.append_doctype_full(
+                                name,
+                                public_id,
+                                system_id,
+                                force_quirks,
+                            ),
+                        }
                     }
                     self.set_quirks_mode(quirk);
 
                     self.mode = BeforeHtml;
                     return tokenizer::TokenSinkResult::Continue;
                 } else {
-                    self.sink.parse_error(format_if!(
+                    self.parse_error(format_if!(
                         self.opts.exact_errors,
                         "DOCTYPE in body",
                         "DOCTYPE in insertion mode {:?}",
                         self.mode
                     ));
+                    if self.opts.preserve_misplaced_doctype {
+                        self.sink.misplaced_doctype(
+                            dt.name.unwrap_or(StrTendril::new()),
+                            dt.public_id.unwrap_or(StrTendril::new()),
+                            dt.system_id.unwrap_or(StrTendril::new()),
+                        );
+                    }
                     return tokenizer::TokenSinkResult::Continue;
                 }
             },
 
-            tokenizer::TagToken(x) => TagToken(x),
-            tokenizer::CommentToken(x) => CommentToken(x),
+            tokenizer::TagToken(mut x) => {
+                if self.opts.preserve_foreign_case {
+                    self.fold_tag_case(&mut x);
+                }
+                if x.kind == StartTag && self.opts.allowed_url_schemes.is_some() {
+                    self.remove_disallowed_urls(&mut x);
+                }
+                TagToken(x)
+            },
+            tokenizer::CommentToken(x) => match self.comment_filter {
+                Some(ref keep) if !keep(&x) => return tokenizer::TokenSinkResult::Continue,
+                _ => CommentToken(Comment::Text(x)),
+            },
             tokenizer::NullCharacterToken => NullCharacterToken,
-            tokenizer::EOFToken => EOFToken,
+            tokenizer::EOFToken => {
+                self.report_eof_state();
+                EOFToken
+            },
 
             tokenizer::CharacterTokens(mut x) => {
-                if ignore_lf && x.starts_with("\n") {
+                if ignore_lf && !ignore_lf_after_cr && x.starts_with("\r") {
+                    // Without `normalize_newlines`, the newline can be a
+                    // "\r\n", whose "\n" may come in the next token.
+                    x.pop_front(1);
+                    self.report_leading_newline_stripped();
+                    if x.is_empty() {
+                        self.ignore_lf = true;
+                        self.ignore_lf_after_cr = true;
+                    } else if x.starts_with("\n") {
+                        x.pop_front(1);
+                    }
+                } else if ignore_lf && x.starts_with("\n") {
                     x.pop_front(1);
+                    if !ignore_lf_after_cr {
+                        self.report_leading_newline_stripped();
+                    }
                 }
                 if x.is_empty() {
                     return tokenizer::TokenSinkResult::Continue;
@@ -522,16 +1174,110 @@ where
 
         self.process_to_completion(token)
     }
+}
+
+impl<Handle, Sink> TokenSink for TreeBuilder<Handle, Sink>
+where
+    Handle: Clone,
+    Sink: TreeSink<Handle = Handle>,
+{
+    type Handle = Handle;
+
+    fn process_token(
+        &mut self,
+        token: tokenizer::Token,
+        line_number: u64,
+    ) -> TokenSinkResult<Handle> {
+        if self.stopped_at_body || !self.sink.should_continue() {
+            return tokenizer::TokenSinkResult::Continue;
+        }
+        if let Some(kind) = token.kind() {
+            self.last_token_kind = Some(kind);
+            self.record_stats(|stats| stats.tokens += 1);
+        }
+        if let tokenizer::CharacterTokens(text) = token {
+            if self.pending_text.is_empty() {
+                // Keep the tendril, which may share the input's buffer.
+                self.pending_text = text;
+                self.pending_text_line = line_number;
+            } else {
+                self.pending_text.push_tendril(&text);
+            }
+            return tokenizer::TokenSinkResult::Continue;
+        }
+        self.flush_pending_text();
+        self.process_step(token, line_number)
+    }
+
+    fn process_pi(&mut self, pi: Pi, line_number: u64) -> TokenSinkResult<Handle> {
+        if self.stopped_at_body || !self.sink.should_continue() {
+            return tokenizer::TokenSinkResult::Continue;
+        }
+        self.last_token_kind = Some(TokenKind::Pi);
+        self.record_stats(|stats| stats.tokens += 1);
+        self.flush_pending_text();
+        if line_number != self.current_line {
+            self.sink.set_current_line(line_number);
+            self.current_line = line_number;
+        }
+        self.ignore_lf = false;
+        self.ignore_lf_after_cr = false;
+        self.process_to_completion(CommentToken(Comment::Pi(pi)))
+    }
+
+    fn eof_in_token(&mut self) {
+        self.eof_in_token = true;
+    }
 
     fn end(&mut self) {
+        if self.stopped_at_body {
+            return;
+        }
+        self.flush_pending_text();
         for elem in self.open_elems.drain(..).rev() {
             self.sink.pop(&elem);
+            self.sink.element_closed(&elem);
         }
+        self.preserve_whitespace_depth = 0;
+    }
+
+    fn flush_pending(&mut self) {
+        self.flush_pending_text();
     }
 
     fn adjusted_current_node_present_but_not_in_html_namespace(&self) -> bool {
-        !self.open_elems.is_empty() &&
-            self.sink.elem_name(self.adjusted_current_node()).ns != &ns!(html)
+        !self.open_elems.is_empty() && self.elem_name(self.adjusted_current_node()).ns != &ns!(html)
+    }
+
+    fn keep_tag_case(&self) -> bool {
+        self.opts.preserve_foreign_case &&
+            self.adjusted_current_node_present_but_not_in_html_namespace()
+    }
+
+    fn ambiguous_construct(&mut self, construct: AmbiguousConstruct, line_number: u64) {
+        self.sink.ambiguous_construct(construct, line_number)
+    }
+
+    fn duplicate_attribute(&mut self, first: &str, duplicate: &str, line_number: u64) {
+        self.sink.duplicate_attribute(first, duplicate, line_number)
+    }
+
+    fn should_continue(&self) -> bool {
+        !self.stopped_at_body && self.sink.should_continue()
+    }
+}
+
+static HTML_NAMESPACE: Namespace = ns!(html);
+
+/// An element name from the sink, with `default_namespace` replaced by the
+/// HTML namespace.
+fn as_html_name<'a>(name: ExpandedName<'a>, default_ns: &Option<Namespace>) -> ExpandedName<'a> {
+    match *default_ns {
+        Some(ref ns) if name.ns == ns => ExpandedName {
+            ns: &HTML_NAMESPACE,
+            local: name.local,
+        },
+        _ => name,
     }
 }
 
@@ -548,7 +1294,7 @@ impl<'a, Handle> Iterator for ActiveFormattingIter<'a, Handle> {
     fn next(&mut self) -> Option<(usize, &'a Handle, &'a Tag)> {
         match self.iter.next() {
             None | Some((_, &Marker)) => None,
-            Some((i, &Element(ref h, ref t))) => Some((i, h, t)),
+            Some((i, &Element(ref h, ref t, _))) => Some((i, h, t)),
         }
     }
 }
@@ -586,8 +1332,22 @@ where
     Handle: Clone,
     Sink: TreeSink<Handle = Handle>,
 {
+    /// Tell the sink that a start tag in a `<select>` was dropped.
+    fn report_select_content_dropped(&mut self, tag: &Tag) {
+        let name = QualName::new(None, ns!(html), tag.name.clone());
+        self.sink.select_content_dropped(&name);
+    }
+
+    /// Clear the frameset-ok flag, reporting the change to the sink.
+    fn frameset_not_ok(&mut self, reason: &'static str) {
+        if self.frameset_ok {
+            self.frameset_ok = false;
+            self.sink.frameset_ok_changed(false, reason);
+        }
+    }
+
     fn unexpected<T: fmt::Debug>(&mut self, _thing: &T) -> ProcessResult<Handle> {
-        self.sink.parse_error(format_if!(
+        self.parse_error(format_if!(
             self.opts.exact_errors,
             "Unexpected token",
             "Unexpected token {} in insertion mode {:?}",
@@ -612,7 +1372,7 @@ where
     fn position_in_active_formatting(&self, element: &Handle) -> Option<usize> {
         self.active_formatting.iter().position(|n| match n {
             &Marker => false,
-            &Element(ref handle, _) => self.sink.same_node(handle, element),
+            &Element(ref handle, _, _) => self.sink.same_node(handle, element),
         })
     }
 
@@ -660,7 +1420,7 @@ where
     where
         TagSet: Fn(ExpandedName) -> bool,
     {
-        set(self.sink.elem_name(self.current_node()))
+        set(self.elem_name(self.current_node()))
     }
 
     // Insert at the "appropriate place for inserting a node".
@@ -670,6 +1430,7 @@ where
     }
 
     fn adoption_agency(&mut self, subject: LocalName) {
+        self.record_stats(|stats| stats.adoption_agency_runs += 1);
         // 1.
         if self.current_node_named(subject.clone()) {
             if self
@@ -681,6 +1442,10 @@ where
             }
         }
 
+        if self.opts.disable_adoption_agency {
+            return self.close_formatting_element(subject);
+        }
+
         // 2. 3. 4.
         for _ in 0..8 {
             // 5.
@@ -700,28 +1465,42 @@ where
                 }
             );
 
+            let fmt_elem_line = match self.active_formatting[fmt_elem_index] {
+                Element(_, _, line) => line,
+                Marker => panic!("Found marker during adoption agency"),
+            };
+
             let fmt_elem_stack_index = unwrap_or_return!(
                 self.open_elems
                     .iter()
                     .rposition(|n| self.sink.same_node(n, &fmt_elem)),
                 {
-                    self.sink
-                        .parse_error(Borrowed("Formatting element not open"));
+                    self.parse_error(Borrowed("Formatting element not open"));
                     self.active_formatting.remove(fmt_elem_index);
                 }
             );
 
             // 7.
             if !self.in_scope(default_scope, |n| self.sink.same_node(&n, &fmt_elem)) {
-                self.sink
-                    .parse_error(Borrowed("Formatting element not in scope"));
+                self.parse_error(Borrowed("Formatting element not in scope"));
                 return;
             }
 
             // 8.
             if !self.sink.same_node(self.current_node(), &fmt_elem) {
-                self.sink
-                    .parse_error(Borrowed("Formatting element not current node"));
+                self.parse_error(Borrowed("Formatting element not current node"));
+                let expected = {
+                    let name = self.elem_name(self.current_node());
+                    QualName::new(None, name.ns.clone(), name.local.clone())
+                };
+                let expected_pos =
+                    self.position_in_active_formatting(self.current_node())
+                        .map(|i| match self.active_formatting[i] {
+                            Element(_, _, line) => line,
+                            Marker => panic!("Found marker during adoption agency"),
+                        });
+                let found = QualName::new(None, ns!(html), subject.clone());
+                self.sink.misnested_tags(&expected, &found, expected_pos);
             }
 
             // 9.
@@ -735,7 +1514,7 @@ where
                     .map(|(i, h)| (i, h.clone())),
                 // 10.
                 {
-                    self.open_elems.truncate(fmt_elem_stack_index);
+                    self.truncate_open_elems(fmt_elem_stack_index);
                     self.active_formatting.remove(fmt_elem_index);
                 }
             );
@@ -770,7 +1549,7 @@ where
                 if inner_counter > 3 {
                     self.position_in_active_formatting(&node)
                         .map(|position| self.active_formatting.remove(position));
-                    self.open_elems.remove(node_index);
+                    self.remove_open_elem(node_index);
                     continue;
                 }
 
@@ -778,28 +1557,30 @@ where
                     self.position_in_active_formatting(&node),
                     // 13.6.
                     {
-                        self.open_elems.remove(node_index);
+                        self.remove_open_elem(node_index);
                         continue;
                     }
                 );
 
                 // 13.7.
-                let tag = match self.active_formatting[node_formatting_index] {
-                    Element(ref h, ref t) => {
+                let (tag, line) = match self.active_formatting[node_formatting_index] {
+                    Element(ref h, ref t, line) => {
                         assert!(self.sink.same_node(h, &node));
-                        t.clone()
+                        (t.clone(), line)
                     },
                     Marker => panic!("Found marker during adoption agency"),
                 };
                 // FIXME: Is there a way to avoid cloning the attributes twice here (once on their
                 // own, once as part of t.clone() above)?
-                let new_element = create_element(
-                    &mut self.sink,
+                let new_element = self.create_element(
                     QualName::new(None, ns!(html), tag.name.clone()),
                     tag.attrs.clone(),
                 );
+                self.sink.element_finalized(&new_element);
+                self.sink.element_cloned(&node, &new_element);
                 self.open_elems[node_index] = new_element.clone();
-                self.active_formatting[node_formatting_index] = Element(new_element.clone(), tag);
+                self.active_formatting[node_formatting_index] =
+                    Element(new_element.clone(), tag, line);
                 node = new_element;
 
                 // 13.8.
@@ -824,12 +1605,13 @@ where
             // 15.
             // FIXME: Is there a way to avoid cloning the attributes twice here (once on their own,
             // once as part of t.clone() above)?
-            let new_element = create_element(
-                &mut self.sink,
+            let new_element = self.create_element(
                 QualName::new(None, ns!(html), fmt_elem_tag.name.clone()),
                 fmt_elem_tag.attrs.clone(),
             );
-            let new_entry = Element(new_element.clone(), fmt_elem_tag);
+            self.sink.element_finalized(&new_element);
+            self.sink.element_cloned(&fmt_elem, &new_element);
+            let new_entry = Element(new_element.clone(), fmt_elem_tag, fmt_elem_line);
 
             // 16.
             self.sink.reparent_children(&furthest_block, &new_element);
@@ -876,15 +1658,88 @@ where
     }
 
     fn push(&mut self, elem: &Handle) {
+        if self.preserves_whitespace(elem) {
+            self.preserve_whitespace_depth += 1;
+        }
         self.open_elems.push(elem.clone());
+        let depth = self.open_elems.len();
+        self.record_stats(|stats| stats.max_depth = stats.max_depth.max(depth));
+    }
+
+    // Close a formatting element without the adoption agency algorithm,
+    // for `disable_adoption_agency`.
+    fn close_formatting_element(&mut self, subject: LocalName) {
+        let (fmt_elem_index, fmt_elem) = unwrap_or_return!(
+            self.active_formatting_end_to_marker()
+                .filter(|&(_, _, tag)| tag.name == subject)
+                .next()
+                .map(|(i, h, _)| (i, h.clone())),
+            {
+                self.process_end_tag_in_body(Tag {
+                    kind: EndTag,
+                    name: subject,
+                    self_closing: false,
+                    attrs: vec![],
+                });
+            }
+        );
+
+        if !self
+            .open_elems
+            .iter()
+            .any(|n| self.sink.same_node(n, &fmt_elem))
+        {
+            self.parse_error(Borrowed("Formatting element not open"));
+            self.active_formatting.remove(fmt_elem_index);
+            return;
+        }
+
+        if !self.in_scope(default_scope, |n| self.sink.same_node(&n, &fmt_elem)) {
+            self.parse_error(Borrowed("Formatting element not in scope"));
+            return;
+        }
+
+        self.active_formatting.remove(fmt_elem_index);
+        if !self.sink.same_node(self.current_node(), &fmt_elem) {
+            self.parse_error(Borrowed("Formatting element not current node"));
+        }
+        loop {
+            let elem = self.pop();
+            if self.sink.same_node(&elem, &fmt_elem) {
+                break;
+            }
+        }
     }
 
     fn pop(&mut self) -> Handle {
         let elem = self.open_elems.pop().expect("no current element");
         self.sink.pop(&elem);
+        self.removed_from_stack(&elem);
         elem
     }
 
+    // Update `preserve_whitespace_depth` for an element removed from the
+    // stack of open elements, and tell the sink it was closed.
+    fn removed_from_stack(&mut self, elem: &Handle) {
+        if self.preserves_whitespace(elem) {
+            self.preserve_whitespace_depth -= 1;
+        }
+        self.sink.element_closed(elem);
+    }
+
+    // Remove elements from the stack until `len` are left.
+    fn truncate_open_elems(&mut self, len: usize) {
+        while self.open_elems.len() > len {
+            let elem = self.open_elems.pop().expect("no current element");
+            self.removed_from_stack(&elem);
+        }
+    }
+
+    fn remove_open_elem(&mut self, index: usize) {
+        let elem = self.open_elems.remove(index);
+        self.removed_from_stack(&elem);
+    }
+
     fn remove_from_stack(&mut self, elem: &Handle) {
         let sink = &mut self.sink;
         let position = self
@@ -893,14 +1748,15 @@ where
             .rposition(|x| sink.same_node(elem, &x));
         if let Some(position) = position {
             self.open_elems.remove(position);
-            sink.pop(elem);
+            self.sink.pop(elem);
+            self.removed_from_stack(elem);
         }
     }
 
     fn is_marker_or_open(&self, entry: &FormatEntry<Handle>) -> bool {
         match *entry {
             Marker => true,
-            Element(ref node, _) => self
+            Element(ref node, _, _) => self
                 .open_elems
                 .iter()
                 .rev()
@@ -930,8 +1786,8 @@ where
         }
 
         loop {
-            let tag = match self.active_formatting[entry_index] {
-                Element(_, ref t) => t.clone(),
+            let (original, tag, line) = match self.active_formatting[entry_index] {
+                Element(ref h, ref t, line) => (h.clone(), t.clone(), line),
                 Marker => panic!("Found marker during formatting element reconstruction"),
             };
 
@@ -939,7 +1795,8 @@ where
             // once as part of t.clone() above)?
             let new_element =
                 self.insert_element(Push, ns!(html), tag.name.clone(), tag.attrs.clone());
-            self.active_formatting[entry_index] = Element(new_element, tag);
+            self.sink.element_cloned(&original, &new_element);
+            self.active_formatting[entry_index] = Element(new_element, tag, line);
             if entry_index == self.active_formatting.len() - 1 {
                 break;
             }
@@ -948,7 +1805,7 @@ where
     }
 
     /// Get the first element on the stack, which will be the <html> element.
-    fn html_elem(&self) -> &Handle {
+    pub(crate) fn html_elem(&self) -> &Handle {
         &self.open_elems[0]
     }
 
@@ -966,17 +1823,21 @@ where
         }
     }
 
+    /// Pop the elements inside `<body>`, with
+    /// `TreeBuilderOpts::strict_body_close`.
+    fn close_body_contents(&mut self) {
+        if self.opts.strict_body_close {
+            self.pop_until_current(|name| name == expanded_name!(html "body"));
+        }
+    }
+
     /// Signal an error depending on the state of the stack of open elements at
     /// the end of the body.
     fn check_body_end(&mut self) {
-        declare_tag_set!(body_end_ok =
-            "dd" "dt" "li" "optgroup" "option" "p" "rp" "rt" "tbody" "td" "tfoot" "th"
-            "thead" "tr" "body" "html");
-
         for elem in self.open_elems.iter() {
             let error;
             {
-                let name = self.sink.elem_name(elem);
+                let name = self.elem_name(elem);
                 if body_end_ok(name) {
                     continue;
                 }
@@ -987,7 +1848,7 @@ where
                     name
                 );
             }
-            self.sink.parse_error(error);
+            self.parse_error(error);
             // FIXME: Do we keep checking after finding one bad tag?
             // The spec suggests not.
             return;
@@ -1003,7 +1864,7 @@ where
             if pred(node.clone()) {
                 return true;
             }
-            if scope(self.sink.elem_name(node)) {
+            if scope(self.elem_name(node)) {
                 return false;
             }
         }
@@ -1017,11 +1878,11 @@ where
     where
         TagSet: Fn(ExpandedName) -> bool,
     {
-        set(self.sink.elem_name(elem))
+        set(self.elem_name(elem))
     }
 
     fn html_elem_named(&self, elem: &Handle, name: LocalName) -> bool {
-        let expanded = self.sink.elem_name(elem);
+        let expanded = self.elem_name(elem);
         *expanded.ns == ns!(html) && *expanded.local == name
     }
 
@@ -1050,7 +1911,7 @@ where
         loop {
             {
                 let elem = unwrap_or_return!(self.open_elems.last(), ());
-                let nsname = self.sink.elem_name(elem);
+                let nsname = self.elem_name(elem);
                 if !set(nsname) {
                     return;
                 }
@@ -1079,7 +1940,8 @@ where
             if self.current_node_in(|x| pred(x)) {
                 break;
             }
-            self.open_elems.pop();
+            let elem = self.open_elems.pop().expect("no current element");
+            self.removed_from_stack(&elem);
         }
     }
 
@@ -1095,7 +1957,8 @@ where
             match self.open_elems.pop() {
                 None => break,
                 Some(elem) => {
-                    if pred(self.sink.elem_name(&elem)) {
+                    self.removed_from_stack(&elem);
+                    if pred(self.elem_name(&elem)) {
                         break;
                     }
                 },
@@ -1112,7 +1975,7 @@ where
     // Signal an error if it was not the first one.
     fn expect_to_close(&mut self, name: LocalName) {
         if self.pop_until_named(name.clone()) != 1 {
-            self.sink.parse_error(format_if!(
+            self.parse_error(format_if!(
                 self.opts.exact_errors,
                 "Unexpected open element",
                 "Unexpected open element while closing {:?}",
@@ -1161,7 +2024,7 @@ where
             self.orig_mode = Some(self.mode);
             Reprocess(InTableText, token)
         } else {
-            self.sink.parse_error(format_if!(
+            self.parse_error(format_if!(
                 self.opts.exact_errors,
                 "Unexpected characters in table",
                 "Unexpected characters {} in table",
@@ -1178,7 +2041,7 @@ where
             if let (true, Some(ctx)) = (last, self.context_elem.as_ref()) {
                 node = ctx;
             }
-            let name = match self.sink.elem_name(node) {
+            let name = match self.elem_name(node) {
                 ExpandedName {
                     ns: &ns!(html),
                     local,
@@ -1230,48 +2093,109 @@ where
     fn close_the_cell(&mut self) {
         self.generate_implied_end(cursory_implied_end);
         if self.pop_until(td_th) != 1 {
-            self.sink
-                .parse_error(Borrowed("expected to close <td> or <th> with cell"));
+            self.parse_error(Borrowed("expected to close <td> or <th> with cell"));
         }
         self.clear_active_formatting_to_marker();
     }
 
     fn append_text(&mut self, text: StrTendril) -> ProcessResult<Handle> {
-        self.insert_appropriately(AppendText(text), None);
+        let text = self.normalize_text(text);
+        let preserve = self.preserve_whitespace_depth > 0;
+        match self.appropriate_place_for_insertion(None) {
+            LastChild(parent) => self.sink.append_text_with_flags(&parent, text, preserve),
+            TableFosterParenting {
+                element,
+                prev_element,
+            } => self
+                .sink
+                .foster_parent_text_with_flags(&element, &prev_element, text, preserve),
+            insertion_point => self.insert_at(insertion_point, AppendText(text)),
+        }
         Done
     }
 
-    fn append_comment(&mut self, text: StrTendril) -> ProcessResult<Handle> {
-        let comment = self.sink.create_comment(text);
+    /// Does this element preserve whitespace, for `append_text_with_flags`?
+    fn preserves_whitespace(&self, elem: &Handle) -> bool {
+        declare_tag_set!(preserve_whitespace = "pre" "listing" "plaintext" "textarea" "xmp");
+        self.elem_in(elem, preserve_whitespace)
+    }
+
+    fn create_comment(&mut self, comment: Comment) -> Handle {
+        match comment {
+            Comment::Text(text) => {
+                let text = self.normalize_text(text);
+                self.sink.create_comment(text)
+            },
+            Comment::Pi(pi) => self.sink.create_pi(pi.target, pi.data),
+        }
+    }
+
+    /// Apply `TreeBuilderOpts::normalize_unicode`, if it is set.
+    fn normalize_text(&self, text: StrTendril) -> StrTendril {
+        #[cfg(feature = "unicode-normalization")]
+        {
+            if let Some(form) = self.opts.normalize_unicode {
+                return StrTendril::from(form.normalize(&text));
+            }
+        }
+        text
+    }
+
+    fn append_comment(&mut self, comment: Comment) -> ProcessResult<Handle> {
+        let comment = self.create_comment(comment);
         self.insert_appropriately(AppendNode(comment), None);
         Done
     }
 
-    fn append_comment_to_doc(&mut self, text: StrTendril) -> ProcessResult<Handle> {
-        let comment = self.sink.create_comment(text);
+    fn append_comment_to_doc(&mut self, comment: Comment) -> ProcessResult<Handle> {
+        let comment = self.create_comment(comment);
         self.sink.append(&self.doc_handle, AppendNode(comment));
         Done
     }
 
-    fn append_comment_to_html(&mut self, text: StrTendril) -> ProcessResult<Handle> {
+    fn append_comment_to_html(&mut self, comment: Comment) -> ProcessResult<Handle> {
+        let comment = self.create_comment(comment);
         let target = html_elem(&self.open_elems);
-        let comment = self.sink.create_comment(text);
         self.sink.append(target, AppendNode(comment));
         Done
     }
 
     //§ creating-and-inserting-nodes
     fn create_root(&mut self, attrs: Vec<Attribute>) {
-        let elem = create_element(
-            &mut self.sink,
-            QualName::new(None, ns!(html), local_name!("html")),
-            attrs,
-        );
+        self.note_root_attrs(&attrs);
+        let elem = self.create_element(QualName::new(None, ns!(html), local_name!("html")), attrs);
         self.push(&elem);
-        self.sink.append(&self.doc_handle, AppendNode(elem));
+        self.sink.append(&self.doc_handle, AppendNode(elem.clone()));
+        self.sink.element_finalized(&elem);
+        if self.context_elem.is_none() {
+            self.report_document_metadata();
+        }
         // FIXME: application cache selection algorithm
     }
 
+    /// Remember the root element's `lang` and `dir` attributes.  Like
+    /// `add_attrs_if_missing`, this keeps values that are already set.
+    fn note_root_attrs(&mut self, attrs: &[Attribute]) {
+        for attr in attrs {
+            if attr.name.ns != ns!() {
+                continue;
+            }
+            let slot = match attr.name.local {
+                local_name!("lang") => &mut self.root_lang,
+                local_name!("dir") => &mut self.root_dir,
+                _ => continue,
+            };
+            if slot.is_none() {
+                *slot = Some(attr.value.clone());
+            }
+        }
+    }
+
+    fn report_document_metadata(&mut self) {
+        self.sink
+            .set_document_metadata(self.root_lang.clone(), self.root_dir.clone());
+    }
+
     // https://html.spec.whatwg.org/multipage/#create-an-element-for-the-token
     fn insert_element(
         &mut self,
@@ -1288,7 +2212,10 @@ where
 
         // Step 7.
         let qname = QualName::new(None, ns, name);
-        let elem = create_element(&mut self.sink, qname.clone(), attrs.clone());
+        let has_form_attr = attrs
+            .iter()
+            .any(|a| a.name.expanded() == expanded_name!("", "form"));
+        let elem = self.create_element(qname.clone(), attrs);
 
         let insertion_point = self.appropriate_place_for_insertion(None);
         let (node1, node2) = match insertion_point {
@@ -1303,10 +2230,7 @@ where
         if form_associatable(qname.expanded()) &&
             self.form_elem.is_some() &&
             !self.in_html_elem_named(local_name!("template")) &&
-            !(listed(qname.expanded()) &&
-                attrs
-                    .iter()
-                    .any(|a| a.name.expanded() == expanded_name!("", "form")))
+            !(listed(qname.expanded()) && has_form_attr)
         {
             let form = self.form_elem.as_ref().unwrap().clone();
             let node2 = match node2 {
@@ -1317,6 +2241,7 @@ where
         }
 
         self.insert_at(insertion_point, AppendNode(elem.clone()));
+        self.sink.element_finalized(&elem);
 
         match push {
             Push => self.push(&elem),
@@ -1327,16 +2252,116 @@ where
     }
 
     fn insert_element_for(&mut self, tag: Tag) -> Handle {
+        self.flag_if_obsolete(&tag.name);
         self.insert_element(Push, ns!(html), tag.name, tag.attrs)
     }
 
     fn insert_and_pop_element_for(&mut self, tag: Tag) -> Handle {
+        self.flag_if_obsolete(&tag.name);
         self.insert_element(NoPush, ns!(html), tag.name, tag.attrs)
     }
 
+    /// Pass the name of an HTML element about to be created for a start
+    /// tag to `TreeSink::obsolete_element`, if it is obsolete and
+    /// `flag_obsolete_elements` is set.
+    fn flag_if_obsolete(&mut self, name: &LocalName) {
+        if self.opts.flag_obsolete_elements && OBSOLETE_ELEMENTS.contains(&&**name) {
+            self.sink
+                .obsolete_element(&QualName::new(None, ns!(html), name.clone()));
+        }
+    }
+
+    /// The name of an element, as the tree builder sees it: with
+    /// `default_namespace`, elements in that namespace are HTML elements.
+    fn elem_name<'a>(&'a self, elem: &'a Handle) -> ExpandedName<'a> {
+        as_html_name(self.sink.elem_name(elem), &self.default_ns)
+    }
+
+    /// Create an element with `create_element`, or `create_element_raw_attrs`
+    /// if `preserve_all_attributes` is set, counting it for `ParseStats` and
+    /// sorting its attributes if `sort_attributes` is set.  HTML elements
+    /// are created in `default_namespace`, if it is set.
+    fn create_element(&mut self, mut name: QualName, mut attrs: Vec<Attribute>) -> Handle {
+        self.record_stats(|stats| stats.elements_created += 1);
+        if let Some(ref ns) = self.default_ns {
+            if name.ns == ns!(html) {
+                name.ns = ns.clone();
+            }
+        }
+        if self.opts.sort_attributes {
+            attrs.sort_by(|a, b| (&*a.name.ns, &*a.name.local).cmp(&(&*b.name.ns, &*b.name.local)));
+        }
+        if self.opts.preserve_all_attributes {
+            create_element_raw_attrs(&mut self.sink, name, attrs)
+        } else {
+            create_element(&mut self.sink, name, attrs)
+        }
+    }
+
     fn insert_phantom(&mut self, name: LocalName) -> Handle {
         self.insert_element(Push, ns!(html), name, vec![])
     }
+
+    /// Create the `<table>`, and the `<tbody>` and `<tr>` as needed, that
+    /// a table part outside a table is missing, then reprocess its tag
+    /// inside them.  See `TreeBuilderOpts::wrap_orphaned_table_content`.
+    fn wrap_orphaned_table_part(&mut self, tag: Tag) -> ProcessResult<Handle> {
+        if self.quirks_mode != Quirks {
+            self.close_p_element_in_button_scope();
+        }
+        self.frameset_not_ok("<table>");
+        let table = self.insert_phantom(local_name!("table"));
+        self.report_synthesized(&table, local_name!("table"));
+        if let local_name!("tbody") | local_name!("tfoot") | local_name!("thead") = tag.name {
+            return Reprocess(InTable, TagToken(tag));
+        }
+        let tbody = self.insert_phantom(local_name!("tbody"));
+        self.report_synthesized(&tbody, local_name!("tbody"));
+        if tag.name == local_name!("tr") {
+            return Reprocess(InTableBody, TagToken(tag));
+        }
+        let tr = self.insert_phantom(local_name!("tr"));
+        self.report_synthesized(&tr, local_name!("tr"));
+        Reprocess(InRow, TagToken(tag))
+    }
+
+    /// Tell the sink that an implied element was created.
+    fn report_synthesized(&mut self, elem: &Handle, name: LocalName) {
+        let ns = match self.default_ns {
+            Some(ref ns) => ns.clone(),
+            None => ns!(html),
+        };
+        self.sink
+            .element_synthesized(elem, &QualName::new(None, ns, name));
+    }
+
+    /// Tell the sink that the newline after a `<pre>`, `<listing>` or
+    /// `<textarea>` start tag was dropped.
+    fn report_leading_newline_stripped(&mut self) {
+        let elem = self.current_node().clone();
+        self.sink.leading_newline_stripped(&elem);
+    }
+
+    /// Tell the sink whether the document looks complete, as the end of the
+    /// input is reached.
+    fn report_eof_state(&mut self) {
+        let open_elements = self
+            .open_elems
+            .iter()
+            .filter(|elem| !body_end_ok(self.elem_name(elem)))
+            .count();
+        let clean = open_elements == 0 && !self.eof_in_token;
+        self.sink.eof_state(clean, open_elements);
+    }
+
+    /// Tell the sink about text before `<html>`, which will end up in the
+    /// body.  Leading whitespace is split off and dropped first, so the text
+    /// is reported when it comes back without it.
+    fn report_leading_text(&mut self, text: &StrTendril) {
+        if text.starts_with(|c: char| !c.is_ascii_whitespace()) {
+            self.sink.leading_text_relocated(text.clone());
+        }
+    }
     //§ END
 
     fn create_formatting_element_for(&mut self, tag: Tag) -> Handle {
@@ -1355,8 +2380,25 @@ where
                 .remove(first_match.expect("matches with no index"));
         }
 
+        self.flag_if_obsolete(&tag.name);
         let elem = self.insert_element(Push, ns!(html), tag.name.clone(), tag.attrs.clone());
-        self.active_formatting.push(Element(elem.clone(), tag));
+        self.active_formatting
+            .push(Element(elem.clone(), tag, self.current_line));
+
+        if let Some(max) = self.opts.max_formatting_elements {
+            // Only the entries after the last marker count.  Those before it
+            // belong to the content around a table cell, template or the
+            // like, and are reconstructed again once it is closed.
+            let first = self
+                .active_formatting
+                .iter()
+                .rposition(|entry| matches!(*entry, Marker))
+                .map_or(0, |marker| marker + 1);
+            while self.active_formatting.len() - first > max {
+                self.parse_error(Borrowed("Too many active formatting elements"));
+                self.active_formatting.remove(first);
+            }
+        }
         elem
     }
 
@@ -1379,8 +2421,7 @@ where
             }
 
             if self.elem_in(elem, special_tag) {
-                self.sink
-                    .parse_error(Borrowed("Found special tag while closing generic tag"));
+                self.parse_error(Borrowed("Found special tag while closing generic tag"));
                 return;
             }
         }
@@ -1402,7 +2443,7 @@ where
             // mis-nested tags
             self.unexpected(&tag);
         }
-        self.open_elems.truncate(match_idx);
+        self.truncate_open_elems(match_idx);
     }
 
     fn handle_misnested_a_tags(&mut self, tag: &Tag) {
@@ -1431,7 +2472,7 @@ where
             return false;
         }
 
-        let name = self.sink.elem_name(self.adjusted_current_node());
+        let name = self.elem_name(self.adjusted_current_node());
         if let ns!(html) = *name.ns {
             return false;
         }
@@ -1485,6 +2526,7 @@ where
             _ => (),
         }
         self.adjust_foreign_attributes(&mut tag);
+        self.restore_source_case(&mut tag);
 
         if tag.self_closing {
             self.insert_element(NoPush, ns, tag.name, tag.attrs);
@@ -1495,6 +2537,58 @@ where
         }
     }
 
+    /// Remove the URL attributes of `tag` whose scheme is not in
+    /// `allowed_url_schemes`.
+    fn remove_disallowed_urls(&mut self, tag: &mut Tag) {
+        let before = tag.attrs.len();
+        if let Some(allowed) = self.opts.allowed_url_schemes {
+            let is_allowed = |url: &str| {
+                data::url_scheme(url).map_or(true, |scheme| {
+                    allowed.iter().any(|a| a.eq_ignore_ascii_case(&scheme))
+                })
+            };
+            tag.attrs.retain(|attr| {
+                !URL_ATTRIBUTES.contains(&&*attr.name.local) || is_allowed(&attr.value)
+            });
+        }
+        for _ in tag.attrs.len()..before {
+            self.parse_error(Borrowed("URL with a disallowed scheme"));
+        }
+    }
+
+    /// Lowercase the names of a tag whose case the tokenizer kept, for
+    /// `preserve_foreign_case`, and remember them as written.
+    fn fold_tag_case(&mut self, tag: &mut Tag) {
+        let name = tag.name.to_ascii_lowercase();
+        let mut attrs = Vec::with_capacity(tag.attrs.len());
+        let mut folded = name != tag.name;
+        for attr in tag.attrs.iter_mut() {
+            let local = attr.name.local.to_ascii_lowercase();
+            folded |= local != attr.name.local;
+            attrs.push(replace(&mut attr.name.local, local));
+        }
+        let name = replace(&mut tag.name, name);
+        self.source_case = if folded { Some((name, attrs)) } else { None };
+    }
+
+    /// Restore the case of names in a foreign element's tag, for
+    /// `preserve_foreign_case`.  Names that were adjusted stay as they are.
+    fn restore_source_case(&mut self, tag: &mut Tag) {
+        fn restore(name: &mut LocalName, source: LocalName) {
+            if *name == source.to_ascii_lowercase() {
+                *name = source;
+            }
+        }
+
+        let (name, attrs) = unwrap_or_return!(self.source_case.take(), ());
+        restore(&mut tag.name, name);
+        for (attr, source) in tag.attrs.iter_mut().zip(attrs) {
+            if attr.name.ns == ns!() {
+                restore(&mut attr.name.local, source);
+            }
+        }
+    }
+
     fn adjust_svg_tag_name(&mut self, tag: &mut Tag) {
         let Tag { ref mut name, .. } = *tag;
         match *name {
@@ -1640,7 +2734,7 @@ where
     }
 
     fn foreign_start_tag(&mut self, mut tag: Tag) -> ProcessResult<Handle> {
-        let current_ns = self.sink.elem_name(self.adjusted_current_node()).ns.clone();
+        let current_ns = self.elem_name(self.adjusted_current_node()).ns.clone();
         match current_ns {
             ns!(mathml) => self.adjust_mathml_attributes(&mut tag),
             ns!(svg) => {
@@ -1650,6 +2744,7 @@ where
             _ => (),
         }
         self.adjust_foreign_attributes(&mut tag);
+        self.restore_source_case(&mut tag);
         if tag.self_closing {
             // FIXME(#118): <script /> in SVG
             self.insert_element(NoPush, current_ns, tag.name, tag.attrs);
@@ -0,0 +1,65 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Unicode normalization of text and comments, for
+//! `TreeBuilderOpts::normalize_unicode`.
+
+use std::iter;
+
+use unicode_normalization::char::canonical_combining_class;
+use unicode_normalization::{is_nfc_quick, is_nfd_quick, is_nfkc_quick, is_nfkd_quick};
+use unicode_normalization::{IsNormalized, UnicodeNormalization};
+
+/// A Unicode normalization form, as defined in
+/// [UAX #15](https://www.unicode.org/reports/tr15/).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NormalizationForm {
+    /// Canonical decomposition, followed by canonical composition.
+    Nfc,
+
+    /// Canonical decomposition.
+    Nfd,
+
+    /// Compatibility decomposition, followed by canonical composition.
+    Nfkc,
+
+    /// Compatibility decomposition.
+    Nfkd,
+}
+
+impl NormalizationForm {
+    pub(crate) fn normalize(self, text: &str) -> String {
+        match self {
+            NormalizationForm::Nfc => text.nfc().collect(),
+            NormalizationForm::Nfd => text.nfd().collect(),
+            NormalizationForm::Nfkc => text.nfkc().collect(),
+            NormalizationForm::Nfkd => text.nfkd().collect(),
+        }
+    }
+
+    /// The length of the longest prefix of `text` that normalizes the same
+    /// whatever text follows it.  This ends in front of the last character
+    /// that stays as it is and that nothing before it combines with.
+    pub(crate) fn stable_prefix_len(self, text: &str) -> usize {
+        text.char_indices()
+            .rev()
+            .find(|&(_, c)| self.is_stable(c))
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn is_stable(self, c: char) -> bool {
+        let quick = match self {
+            NormalizationForm::Nfc => is_nfc_quick(iter::once(c)),
+            NormalizationForm::Nfd => is_nfd_quick(iter::once(c)),
+            NormalizationForm::Nfkc => is_nfkc_quick(iter::once(c)),
+            NormalizationForm::Nfkd => is_nfkd_quick(iter::once(c)),
+        };
+        canonical_combining_class(c) == 0 && quick == IsNormalized::Yes
+    }
+}
@@ -39,7 +39,10 @@ where
         match mode {
             //§ the-initial-insertion-mode
             Initial => match_token!(token {
-                CharacterTokens(NotSplit, text) => SplitWhitespace(text),
+                CharacterTokens(NotSplit, text) => {
+                    self.report_leading_text(&text);
+                    SplitWhitespace(text)
+                }
                 CharacterTokens(Whitespace, _) => Done,
                 CommentToken(text) => self.append_comment_to_doc(text),
                 token => {
@@ -53,7 +56,10 @@ where
 
             //§ the-before-html-insertion-mode
             BeforeHtml => match_token!(token {
-                CharacterTokens(NotSplit, text) => SplitWhitespace(text),
+                CharacterTokens(NotSplit, text) => {
+                    self.report_leading_text(&text);
+                    SplitWhitespace(text)
+                }
                 CharacterTokens(Whitespace, _) => Done,
                 CommentToken(text) => self.append_comment_to_doc(text),
 
@@ -65,10 +71,18 @@ where
 
                 </head> </body> </html> </br> => else,
 
+                tag @ </template> => {
+                    self.unexpected(&tag);
+                    self.sink.stray_template_close();
+                    Done
+                }
+
                 tag @ </_> => self.unexpected(&tag),
 
                 token => {
                     self.create_root(vec!());
+                    let html = self.html_elem().clone();
+                    self.report_synthesized(&html, local_name!("html"));
                     Reprocess(BeforeHead, token)
                 }
             }),
@@ -82,17 +96,28 @@ where
                 <html> => self.step(InBody, token),
 
                 tag @ <head> => {
-                    self.head_elem = Some(self.insert_element_for(tag));
+                    let head = self.insert_element_for(tag);
+                    self.sink.head_opened(&head);
+                    self.head_elem = Some(head);
                     self.mode = InHead;
                     Done
                 }
 
                 </head> </body> </html> </br> => else,
 
+                tag @ </template> => {
+                    self.unexpected(&tag);
+                    self.sink.stray_template_close();
+                    Done
+                }
+
                 tag @ </_> => self.unexpected(&tag),
 
                 token => {
-                    self.head_elem = Some(self.insert_phantom(local_name!("head")));
+                    let head = self.insert_phantom(local_name!("head"));
+                    self.report_synthesized(&head, local_name!("head"));
+                    self.sink.head_opened(&head);
+                    self.head_elem = Some(head);
                     Reprocess(InHead, token)
                 }
             }),
@@ -126,19 +151,21 @@ where
                 }
 
                 tag @ <script> => {
-                    let elem = create_element(
-                        &mut self.sink, QualName::new(None, ns!(html), local_name!("script")),
+                    let elem = self.create_element(
+                        QualName::new(None, ns!(html), local_name!("script")),
                         tag.attrs);
                     if self.is_fragment() {
                         self.sink.mark_script_already_started(&elem);
                     }
                     self.insert_appropriately(AppendNode(elem.clone()), None);
-                    self.open_elems.push(elem);
+                    self.sink.element_finalized(&elem);
+                    self.push(&elem);
                     self.to_raw_text_mode(ScriptData)
                 }
 
                 </head> => {
-                    self.pop();
+                    let head = self.pop();
+                    self.sink.head_closed(&head);
                     self.mode = AfterHead;
                     Done
                 }
@@ -148,7 +175,7 @@ where
                 tag @ <template> => {
                     self.insert_element_for(tag);
                     self.active_formatting.push(Marker);
-                    self.frameset_ok = false;
+                    self.frameset_not_ok("<template>");
                     self.mode = InTemplate;
                     self.template_modes.push(InTemplate);
                     Done
@@ -157,6 +184,7 @@ where
                 tag @ </template> => {
                     if !self.in_html_elem_named(local_name!("template")) {
                         self.unexpected(&tag);
+                        self.sink.stray_template_close();
                     } else {
                         self.generate_implied_end(thorough_implied_end);
                         self.expect_to_close(local_name!("template"));
@@ -171,7 +199,8 @@ where
                 tag @ </_> => self.unexpected(&tag),
 
                 token => {
-                    self.pop();
+                    let head = self.pop();
+                    self.sink.head_closed(&head);
                     Reprocess(AfterHead, token)
                 }
             }),
@@ -215,13 +244,19 @@ where
                 <html> => self.step(InBody, token),
 
                 tag @ <body> => {
+                    if self.stop_at_body {
+                        return self.stop_before_body();
+                    }
                     self.insert_element_for(tag);
-                    self.frameset_ok = false;
+                    self.frameset_not_ok("<body>");
                     self.mode = InBody;
                     Done
                 }
 
                 tag @ <frameset> => {
+                    if self.stop_at_body {
+                        return self.stop_before_body();
+                    }
                     self.insert_element_for(tag);
                     self.mode = InFrameset;
                     Done
@@ -245,7 +280,11 @@ where
                 tag @ </_> => self.unexpected(&tag),
 
                 token => {
-                    self.insert_phantom(local_name!("body"));
+                    if self.stop_at_body {
+                        return self.stop_before_body();
+                    }
+                    let body = self.insert_phantom(local_name!("body"));
+                    self.report_synthesized(&body, local_name!("body"));
                     Reprocess(InBody, token)
                 }
             }),
@@ -257,7 +296,7 @@ where
                 CharacterTokens(_, text) => {
                     self.reconstruct_formatting();
                     if any_not_whitespace(&text) {
-                        self.frameset_ok = false;
+                        self.frameset_not_ok("non-whitespace text");
                     }
                     self.append_text(text)
                 }
@@ -268,7 +307,10 @@ where
                     self.unexpected(&tag);
                     if !self.in_html_elem_named(local_name!("template")) {
                         let top = html_elem(&self.open_elems);
-                        self.sink.add_attrs_if_missing(top, tag.attrs);
+                        let name = QualName::new(None, ns!(html), tag.name);
+                        self.sink.merge_attributes(top, &name, &tag.attrs);
+                        self.note_root_attrs(&tag.attrs);
+                        self.report_document_metadata();
                     }
                     Done
                 }
@@ -283,8 +325,9 @@ where
                     match self.body_elem().cloned() {
                         Some(ref node) if self.open_elems.len() != 1 &&
                                           !self.in_html_elem_named(local_name!("template")) => {
-                            self.frameset_ok = false;
-                            self.sink.add_attrs_if_missing(node, tag.attrs)
+                            self.frameset_not_ok("<body>");
+                            let name = QualName::new(None, ns!(html), tag.name);
+                            self.sink.merge_attributes(node, &name, &tag.attrs)
                         },
                         _ => {}
                     }
@@ -300,7 +343,7 @@ where
 
                     // FIXME: can we get here in the fragment case?
                     // What to do with the first element then?
-                    self.open_elems.truncate(1);
+                    self.truncate_open_elems(1);
                     self.insert_element_for(tag);
                     self.mode = InFrameset;
                     Done
@@ -318,9 +361,10 @@ where
                 </body> => {
                     if self.in_scope_named(default_scope, local_name!("body")) {
                         self.check_body_end();
+                        self.close_body_contents();
                         self.mode = AfterBody;
                     } else {
-                        self.sink.parse_error(Borrowed("</body> with no <body> in scope"));
+                        self.parse_error(Borrowed("</body> with no <body> in scope"));
                     }
                     Done
                 }
@@ -328,9 +372,10 @@ where
                 </html> => {
                     if self.in_scope_named(default_scope, local_name!("body")) {
                         self.check_body_end();
+                        self.close_body_contents();
                         Reprocess(AfterBody, token)
                     } else {
-                        self.sink.parse_error(Borrowed("</html> with no <body> in scope"));
+                        self.parse_error(Borrowed("</html> with no <body> in scope"));
                         Done
                     }
                 }
@@ -352,7 +397,7 @@ where
                 tag @ <h1> <h2> <h3> <h4> <h5> <h6> => {
                     self.close_p_element_in_button_scope();
                     if self.current_node_in(heading_tag) {
-                        self.sink.parse_error(Borrowed("nested heading tags"));
+                        self.parse_error(Borrowed("nested heading tags"));
                         self.pop();
                     }
                     self.insert_element_for(tag);
@@ -363,14 +408,14 @@ where
                     self.close_p_element_in_button_scope();
                     self.insert_element_for(tag);
                     self.ignore_lf = true;
-                    self.frameset_ok = false;
+                    self.frameset_not_ok("<pre> or <listing>");
                     Done
                 }
 
                 tag @ <form> => {
                     if self.form_elem.is_some() &&
                        !self.in_html_elem_named(local_name!("template")) {
-                        self.sink.parse_error(Borrowed("nested forms"));
+                        self.parse_error(Borrowed("nested forms"));
                     } else {
                         self.close_p_element_in_button_scope();
                         let elem = self.insert_element_for(tag);
@@ -391,11 +436,11 @@ where
                         _ => unreachable!(),
                     };
 
-                    self.frameset_ok = false;
+                    self.frameset_not_ok("<li>, <dd> or <dt>");
 
                     let mut to_close = None;
                     for node in self.open_elems.iter().rev() {
-                        let name = self.sink.elem_name(node);
+                        let name = self.elem_name(node);
                         let can_close = if list {
                             close_list(name)
                         } else {
@@ -431,13 +476,13 @@ where
 
                 tag @ <button> => {
                     if self.in_scope_named(default_scope, local_name!("button")) {
-                        self.sink.parse_error(Borrowed("nested buttons"));
+                        self.parse_error(Borrowed("nested buttons"));
                         self.generate_implied_end(cursory_implied_end);
                         self.pop_until_named(local_name!("button"));
                     }
                     self.reconstruct_formatting();
                     self.insert_element_for(tag);
-                    self.frameset_ok = false;
+                    self.frameset_not_ok("<button>");
                     Done
                 }
 
@@ -459,29 +504,29 @@ where
                         // Can't use unwrap_or_return!() due to rust-lang/rust#16617.
                         let node = match self.form_elem.take() {
                             None => {
-                                self.sink.parse_error(Borrowed("Null form element pointer on </form>"));
+                                self.parse_error(Borrowed("Null form element pointer on </form>"));
                                 return Done;
                             }
                             Some(x) => x,
                         };
                         if !self.in_scope(default_scope, |n| self.sink.same_node(&node, &n)) {
-                            self.sink.parse_error(Borrowed("Form element not in scope on </form>"));
+                            self.parse_error(Borrowed("Form element not in scope on </form>"));
                             return Done;
                         }
                         self.generate_implied_end(cursory_implied_end);
                         let current = self.current_node().clone();
                         self.remove_from_stack(&node);
                         if !self.sink.same_node(&current, &node) {
-                            self.sink.parse_error(Borrowed("Bad open element on </form>"));
+                            self.parse_error(Borrowed("Bad open element on </form>"));
                         }
                     } else {
                         if !self.in_scope_named(default_scope, local_name!("form")) {
-                            self.sink.parse_error(Borrowed("Form element not in scope on </form>"));
+                            self.parse_error(Borrowed("Form element not in scope on </form>"));
                             return Done;
                         }
                         self.generate_implied_end(cursory_implied_end);
                         if !self.current_node_named(local_name!("form")) {
-                            self.sink.parse_error(Borrowed("Bad open element on </form>"));
+                            self.parse_error(Borrowed("Bad open element on </form>"));
                         }
                         self.pop_until_named(local_name!("form"));
                     }
@@ -490,7 +535,7 @@ where
 
                 </p> => {
                     if !self.in_scope_named(button_scope, local_name!("p")) {
-                        self.sink.parse_error(Borrowed("No <p> tag to close"));
+                        self.parse_error(Borrowed("No <p> tag to close"));
                         self.insert_phantom(local_name!("p"));
                     }
                     self.close_p_element();
@@ -507,7 +552,7 @@ where
                         self.generate_implied_end_except(tag.name.clone());
                         self.expect_to_close(tag.name);
                     } else {
-                        self.sink.parse_error(Borrowed("No matching tag to close"));
+                        self.parse_error(Borrowed("No matching tag to close"));
                     }
                     Done
                 }
@@ -516,11 +561,11 @@ where
                     if self.in_scope(default_scope, |n| self.elem_in(&n, heading_tag)) {
                         self.generate_implied_end(cursory_implied_end);
                         if !self.current_node_named(tag.name) {
-                            self.sink.parse_error(Borrowed("Closing wrong heading tag"));
+                            self.parse_error(Borrowed("Closing wrong heading tag"));
                         }
                         self.pop_until(heading_tag);
                     } else {
-                        self.sink.parse_error(Borrowed("No heading tag to close"));
+                        self.parse_error(Borrowed("No heading tag to close"));
                     }
                     Done
                 }
@@ -541,7 +586,7 @@ where
                 tag @ <nobr> => {
                     self.reconstruct_formatting();
                     if self.in_scope_named(default_scope, local_name!("nobr")) {
-                        self.sink.parse_error(Borrowed("Nested <nobr>"));
+                        self.parse_error(Borrowed("Nested <nobr>"));
                         self.adoption_agency(local_name!("nobr"));
                         self.reconstruct_formatting();
                     }
@@ -559,7 +604,7 @@ where
                     self.reconstruct_formatting();
                     self.insert_element_for(tag);
                     self.active_formatting.push(Marker);
-                    self.frameset_ok = false;
+                    self.frameset_not_ok("<applet>, <marquee> or <object>");
                     Done
                 }
 
@@ -577,9 +622,11 @@ where
                 tag @ <table> => {
                     if self.quirks_mode != Quirks {
                         self.close_p_element_in_button_scope();
+                    } else if self.in_scope_named(button_scope, local_name!("p")) {
+                        self.sink.quirks_behavior_applied("<table> does not close an open <p>");
                     }
                     self.insert_element_for(tag);
-                    self.frameset_ok = false;
+                    self.frameset_not_ok("<table>");
                     self.mode = InTable;
                     Done
                 }
@@ -601,7 +648,7 @@ where
                     self.reconstruct_formatting();
                     self.insert_and_pop_element_for(tag);
                     if !keep_frameset_ok {
-                        self.frameset_ok = false;
+                        self.frameset_not_ok("<area>, <br>, <embed>, <img>, <keygen>, <wbr> or <input>");
                     }
                     DoneAckSelfClosing
                 }
@@ -614,33 +661,43 @@ where
                 tag @ <hr> => {
                     self.close_p_element_in_button_scope();
                     self.insert_and_pop_element_for(tag);
-                    self.frameset_ok = false;
+                    self.frameset_not_ok("<hr>");
                     DoneAckSelfClosing
                 }
 
                 tag @ <image> => {
                     self.unexpected(&tag);
-                    self.step(InBody, TagToken(Tag {
-                        name: local_name!("img"),
-                        ..tag
-                    }))
+                    if self.opts.rewrite_image_tag {
+                        self.flag_if_obsolete(&tag.name);
+                        self.sink.tag_rewritten(
+                            &QualName::new(None, ns!(html), local_name!("image")),
+                            &QualName::new(None, ns!(html), local_name!("img")));
+                        self.step(InBody, TagToken(Tag {
+                            name: local_name!("img"),
+                            ..tag
+                        }))
+                    } else {
+                        self.reconstruct_formatting();
+                        self.insert_element_for(tag);
+                        Done
+                    }
                 }
 
                 tag @ <textarea> => {
                     self.ignore_lf = true;
-                    self.frameset_ok = false;
+                    self.frameset_not_ok("<textarea>");
                     self.parse_raw_data(tag, Rcdata)
                 }
 
                 tag @ <xmp> => {
                     self.close_p_element_in_button_scope();
                     self.reconstruct_formatting();
-                    self.frameset_ok = false;
+                    self.frameset_not_ok("<xmp>");
                     self.parse_raw_data(tag, Rawtext)
                 }
 
                 tag @ <iframe> => {
-                    self.frameset_ok = false;
+                    self.frameset_not_ok("<iframe>");
                     self.parse_raw_data(tag, Rawtext)
                 }
 
@@ -653,7 +710,7 @@ where
                 tag @ <select> => {
                     self.reconstruct_formatting();
                     self.insert_element_for(tag);
-                    self.frameset_ok = false;
+                    self.frameset_not_ok("<select>");
                     // NB: mode == InBody but possibly self.mode != mode, if
                     // we're processing "as in the rules for InBody".
                     self.mode = match self.mode {
@@ -699,12 +756,20 @@ where
 
                 tag @ <svg> => self.enter_foreign(tag, ns!(svg)),
 
-                <caption> <col> <colgroup> <frame> <head>
-                  <tbody> <td> <tfoot> <th> <thead> <tr> => {
+                <caption> <col> <colgroup> <frame> <head> => {
                     self.unexpected(&token);
                     Done
                 }
 
+                tag @ <tbody> <td> <tfoot> <th> <thead> <tr> => {
+                    self.unexpected(&tag);
+                    if self.opts.wrap_orphaned_table_content {
+                        self.wrap_orphaned_table_part(tag)
+                    } else {
+                        Done
+                    }
+                }
+
                 tag @ <_> => {
                     if self.opts.scripting_enabled && tag.name == local_name!("noscript") {
                         self.parse_raw_data(tag, Rawtext)
@@ -869,7 +934,7 @@ where
                     });
 
                     if contains_nonspace {
-                        self.sink.parse_error(Borrowed("Non-space table text"));
+                        self.parse_error(Borrowed("Non-space table text"));
                         for (split, text) in pending.into_iter() {
                             match self.foster_parent_in_body(CharacterTokens(split, text)) {
                                 Done => (),
@@ -1145,6 +1210,9 @@ where
                     if !in_scope || tag.kind == StartTag {
                         self.unexpected(&tag);
                     }
+                    if tag.kind == StartTag {
+                        self.report_select_content_dropped(&tag);
+                    }
 
                     if in_scope {
                         self.pop_until_named(local_name!("select"));
@@ -1153,12 +1221,13 @@ where
                     Done
                 }
 
-                <input> <keygen> <textarea> => {
-                    self.unexpected(&token);
+                tag @ <input> <keygen> <textarea> => {
+                    self.unexpected(&tag);
                     if self.in_scope_named(select_scope, local_name!("select")) {
                         self.pop_until_named(local_name!("select"));
-                        Reprocess(self.reset_insertion_mode(), token)
+                        Reprocess(self.reset_insertion_mode(), TagToken(tag))
                     } else {
+                        self.report_select_content_dropped(&tag);
                         Done
                     }
                 }
@@ -1167,6 +1236,12 @@ where
 
                 EOFToken => self.step(InBody, token),
 
+                tag @ <_> => {
+                    self.unexpected(&tag);
+                    self.report_select_content_dropped(&tag);
+                    Done
+                }
+
                 token => self.unexpected(&token),
             }),
 
@@ -1377,7 +1452,7 @@ where
 
             CharacterTokens(_, text) => {
                 if any_not_whitespace(&text) {
-                    self.frameset_ok = false;
+                    self.frameset_not_ok("non-whitespace text");
                 }
                 self.append_text(text)
             }
@@ -1419,7 +1494,7 @@ where
                     let html;
                     let eq;
                     {
-                        let node_name = self.sink.elem_name(&self.open_elems[stack_idx]);
+                        let node_name = self.elem_name(&self.open_elems[stack_idx]);
                         html = *node_name.ns == ns!(html);
                         eq = node_name.local.eq_ignore_ascii_case(&tag.name);
                     }
@@ -1429,7 +1504,7 @@ where
                     }
 
                     if eq {
-                        self.open_elems.truncate(stack_idx);
+                        self.truncate_open_elems(stack_idx);
                         return Done;
                     }
 
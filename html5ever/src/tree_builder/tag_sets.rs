@@ -9,7 +9,7 @@
 
 //! Various sets of HTML tag names, and macros for declaring them.
 
-use crate::ExpandedName;
+use crate::{Attribute, ExpandedName, QualName};
 use mac::{_tt_as_expr_hack, matches};
 use markup5ever::{expanded_name, local_name, namespace_prefix, namespace_url, ns};
 
@@ -73,6 +73,10 @@ declare_tag_set!(pub table_body_context = "tbody" "tfoot" "thead" "template" "ht
 declare_tag_set!(pub table_row_context = "tr" "template" "html");
 declare_tag_set!(pub td_th = "td" "th");
 
+declare_tag_set!(pub body_end_ok =
+    "dd" "dt" "li" "optgroup" "option" "p" "rp" "rt" "tbody" "td" "tfoot" "th"
+    "thead" "tr" "body" "html");
+
 declare_tag_set!(pub cursory_implied_end =
     "dd" "dt" "li" "option" "optgroup" "p" "rb" "rp" "rt" "rtc");
 
@@ -92,6 +96,9 @@ declare_tag_set!(pub special_tag =
     "ul" "wbr" "xmp");
 //§ END
 
+declare_tag_set!(pub formatting_tag =
+    "a" "b" "big" "code" "em" "font" "i" "nobr" "s" "small" "strike" "strong" "tt" "u");
+
 pub fn mathml_text_integration_point(p: ExpandedName) -> bool {
     matches!(
         p,
@@ -113,3 +120,59 @@ pub fn svg_html_integration_point(p: ExpandedName) -> bool {
             expanded_name!(svg "title")
     )
 }
+
+/// Is this element in the spec's [special] category?
+///
+/// Unlike the set used by the tree builder, this includes the MathML and
+/// SVG entries.
+///
+/// [special]: https://html.spec.whatwg.org/multipage/#special
+pub fn is_special_element(name: &QualName) -> bool {
+    let name = name.expanded();
+    special_tag(name) ||
+        mathml_text_integration_point(name) ||
+        svg_html_integration_point(name) ||
+        name == expanded_name!(mathml "annotation-xml")
+}
+
+/// Does this element bound the default scope, as used by
+/// [has an element in scope]?
+///
+/// [has an element in scope]: https://html.spec.whatwg.org/multipage/#has-an-element-in-scope
+pub fn is_scope_terminator(name: &QualName) -> bool {
+    let name = name.expanded();
+    default_scope(name) || name == expanded_name!(mathml "annotation-xml")
+}
+
+/// Is this element in the spec's [formatting] category?
+///
+/// [formatting]: https://html.spec.whatwg.org/multipage/#formatting
+pub fn is_formatting_element(name: &QualName) -> bool {
+    formatting_tag(name.expanded())
+}
+
+/// Is this element an [HTML integration point]?  These are `<foreignObject>`,
+/// `<desc>` and `<title>` in SVG, and `<annotation-xml>` in MathML with an
+/// `encoding` attribute of `text/html` or `application/xhtml+xml`, ignoring
+/// ASCII case.  Start tags and text in them are parsed as HTML.
+///
+/// [HTML integration point]: https://html.spec.whatwg.org/multipage/#html-integration-point
+pub fn is_html_integration_point(name: &QualName, attrs: &[Attribute]) -> bool {
+    match name.expanded() {
+        expanded_name!(mathml "annotation-xml") => attrs.iter().any(|attr| {
+            attr.name.expanded() == expanded_name!("", "encoding") &&
+                (attr.value.eq_ignore_ascii_case("text/html") ||
+                    attr.value.eq_ignore_ascii_case("application/xhtml+xml"))
+        }),
+        name => svg_html_integration_point(name),
+    }
+}
+
+/// Is this element a [MathML text integration point]?  These are `<mi>`,
+/// `<mo>`, `<mn>`, `<ms>` and `<mtext>` in MathML.  Text and start tags in
+/// them are parsed as HTML, except `<mglyph>` and `<malignmark>`.
+///
+/// [MathML text integration point]: https://html.spec.whatwg.org/multipage/#mathml-text-integration-point
+pub fn is_mathml_text_integration_point(name: &QualName) -> bool {
+    mathml_text_integration_point(name.expanded())
+}
@@ -7,10 +7,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-//! Types used within the tree builder code.  Not exported to users.
+//! Types used within the tree builder code.  Not exported to users,
+//! except for `InsertionMode` and `Scope`.
 
 use crate::tokenizer::states::RawKind;
-use crate::tokenizer::Tag;
+use crate::tokenizer::{Pi, Tag};
 
 use crate::tendril::StrTendril;
 
@@ -21,33 +22,76 @@ pub use self::ProcessResult::*;
 pub use self::SplitStatus::*;
 pub use self::Token::*;
 
+/// The tree builder's [insertion mode], one variant per mode named in the spec.
+///
+/// [insertion mode]: https://html.spec.whatwg.org/multipage/#the-insertion-mode
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum InsertionMode {
+    /// "initial"
     Initial,
+    /// "before html"
     BeforeHtml,
+    /// "before head"
     BeforeHead,
+    /// "in head"
     InHead,
+    /// "in head noscript"
     InHeadNoscript,
+    /// "after head"
     AfterHead,
+    /// "in body"
     InBody,
+    /// "text"
     Text,
+    /// "in table"
     InTable,
+    /// "in table text"
     InTableText,
+    /// "in caption"
     InCaption,
+    /// "in column group"
     InColumnGroup,
+    /// "in table body"
     InTableBody,
+    /// "in row"
     InRow,
+    /// "in cell"
     InCell,
+    /// "in select"
     InSelect,
+    /// "in select in table"
     InSelectInTable,
+    /// "in template"
     InTemplate,
+    /// "after body"
     AfterBody,
+    /// "in frameset"
     InFrameset,
+    /// "after frameset"
     AfterFrameset,
+    /// "after after body"
     AfterAfterBody,
+    /// "after after frameset"
     AfterAfterFrameset,
 }
 
+/// The kinds of scope used by the spec's [has an element in scope] checks.
+///
+/// [has an element in scope]: https://html.spec.whatwg.org/multipage/#has-an-element-in-scope
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Scope {
+    /// "in scope"
+    Default,
+    /// "in list item scope"
+    ListItem,
+    /// "in button scope"
+    Button,
+    /// "in table scope"
+    Table,
+    /// "in select scope"
+    Select,
+}
+
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum SplitStatus {
     NotSplit,
@@ -60,12 +104,21 @@ pub enum SplitStatus {
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub enum Token {
     TagToken(Tag),
-    CommentToken(StrTendril),
+    CommentToken(Comment),
     CharacterTokens(SplitStatus, StrTendril),
     NullCharacterToken,
     EOFToken,
 }
 
+/// The content of a `CommentToken`.  A processing instruction, from the
+/// tokenizer's `pi_as_processing_instruction` option, goes wherever a
+/// comment would.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Comment {
+    Text(StrTendril),
+    Pi(Pi),
+}
+
 pub enum ProcessResult<Handle> {
     Done,
     DoneAckSelfClosing,
@@ -78,7 +131,9 @@ pub enum ProcessResult<Handle> {
 }
 
 pub enum FormatEntry<Handle> {
-    Element(Handle, Tag),
+    /// An element, with the tag it was created from and the line the tag
+    /// is on.
+    Element(Handle, Tag, u64),
     Marker,
 }
 
@@ -87,7 +142,9 @@ pub enum InsertionPoint<Handle> {
     LastChild(Handle),
     /// Insert before this following sibling.
     BeforeSibling(Handle),
-    /// Insertion point is decided based on existence of element's parent node.
+    /// Foster parenting: before `element` (a table) if it has a parent,
+    /// otherwise as last child of `prev_element`.  See
+    /// `TreeSink::append_based_on_parent_node`.
     TableFosterParenting {
         element: Handle,
         prev_element: Handle,
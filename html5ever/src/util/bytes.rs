@@ -0,0 +1,57 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Decoding input given as bytes.  The encoding is taken from a byte order
+//! mark, for UTF-8 or UTF-16.  Without one the input is decoded as UTF-8,
+//! since html5ever has no decoders for legacy encodings.  Invalid sequences
+//! are replaced.
+
+use crate::tendril::fmt::UTF8;
+use crate::tendril::stream::Utf8LossyDecoder;
+use crate::tendril::TendrilSink;
+
+use std::borrow::Cow;
+
+/// Give all of `input` to `parser`, decoding it as UTF-16 if it starts with
+/// a UTF-16 byte order mark, and as UTF-8 otherwise.
+pub fn parse_bytes<P: TendrilSink<UTF8>>(parser: P, input: &[u8]) -> P::Output {
+    match utf16_bom(input) {
+        Some(big_endian) => parser.one(decode_utf16(&input[2..], big_endian)),
+        None => Utf8LossyDecoder::new(parser).one(input),
+    }
+}
+
+/// Decode `input` as `parse_bytes` does, for consumers that are not a
+/// `TendrilSink`.
+pub fn decode_bytes(input: &[u8]) -> Cow<'_, str> {
+    match utf16_bom(input) {
+        Some(big_endian) => Cow::Owned(decode_utf16(&input[2..], big_endian)),
+        None => String::from_utf8_lossy(input),
+    }
+}
+
+fn decode_utf16(input: &[u8], big_endian: bool) -> String {
+    let units = input.chunks(2).map(|pair| match (pair, big_endian) {
+        (&[a, b], true) => u16::from_be_bytes([a, b]),
+        (&[a, b], false) => u16::from_le_bytes([a, b]),
+        _ => 0xFFFD,
+    });
+    std::char::decode_utf16(units)
+        .map(|c| c.unwrap_or('\u{FFFD}'))
+        .collect()
+}
+
+/// Is there a UTF-16 byte order mark, and is it big-endian?
+pub fn utf16_bom(input: &[u8]) -> Option<bool> {
+    match input {
+        [0xFE, 0xFF, ..] => Some(true),
+        [0xFF, 0xFE, ..] => Some(false),
+        _ => None,
+    }
+}
@@ -0,0 +1,205 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! What a `TreeSink` that does not build a tree must still keep for the
+//! tree builder's queries: the names of elements, with their parents and
+//! children.  Elements are freed once they are closed.
+
+use crate::interface::{ExpandedName, QualName};
+use crate::tree_builder::{is_formatting_element, ElementFlags};
+use markup5ever::{expanded_name, local_name, namespace_url, ns};
+
+use std::collections::HashMap;
+use std::mem;
+
+/// An element, or the document or a template's contents, with some data
+/// of the sink's.
+pub struct Node<T> {
+    pub name: Option<QualName>,
+    pub parent: Option<usize>,
+    // May still list children that were freed, see `add_child`.
+    children: Vec<usize>,
+    template: bool,
+    integration_point: bool,
+    pub data: T,
+}
+
+impl<T> Node<T> {
+    fn new(name: Option<QualName>, data: T) -> Node<T> {
+        Node {
+            name,
+            parent: None,
+            children: vec![],
+            template: false,
+            integration_point: false,
+            data,
+        }
+    }
+}
+
+/// The open elements of a document, by handle.  The document is 0, and a
+/// template's contents follow the template.
+pub struct Names<T> {
+    nodes: HashMap<usize, Node<T>>,
+    next_id: usize,
+    // Closed elements, freed when the next one is created.  The tree
+    // builder can still ask for their names until then.
+    closed: Vec<usize>,
+}
+
+impl<T: Default> Names<T> {
+    pub fn new() -> Names<T> {
+        Names {
+            nodes: vec![(0, Node::new(None, T::default()))]
+                .into_iter()
+                .collect(),
+            next_id: 1,
+            closed: vec![],
+        }
+    }
+
+    pub fn create_element(&mut self, name: QualName, flags: &ElementFlags, data: T) -> usize {
+        for closed in self.closed.drain(..) {
+            if let Some(node) = self.nodes.remove(&closed) {
+                if node.template {
+                    self.nodes.remove(&(closed + 1));
+                }
+            }
+        }
+        let id = self.new_id();
+        let mut node = Node::new(Some(name), data);
+        node.template = flags.template;
+        node.integration_point = flags.mathml_annotation_xml_integration_point;
+        self.nodes.insert(id, node);
+        if flags.template {
+            let contents = self.new_id();
+            self.nodes.insert(contents, Node::new(None, T::default()));
+        }
+        id
+    }
+}
+
+impl<T> Names<T> {
+    /// A handle for a comment or processing instruction.  These are not
+    /// stored, since nothing asks about them again.
+    pub fn new_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Node<T>> {
+        self.nodes.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Node<T>> {
+        self.nodes.get_mut(&id)
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn elem_name(&self, id: usize) -> ExpandedName<'_> {
+        self.nodes[&id]
+            .name
+            .as_ref()
+            .expect("not an element")
+            .expanded()
+    }
+
+    pub fn parent(&self, id: usize) -> Option<usize> {
+        self.nodes.get(&id).and_then(|node| node.parent)
+    }
+
+    pub fn is_integration_point(&self, id: usize) -> bool {
+        self.nodes
+            .get(&id)
+            .map_or(false, |node| node.integration_point)
+    }
+
+    /// The node and its ancestors, up to the first one that was freed.
+    pub fn ancestors(&self, id: usize) -> impl Iterator<Item = (usize, &Node<T>)> {
+        let mut next = Some(id);
+        std::iter::from_fn(move || {
+            let id = next?;
+            let node = self.nodes.get(&id)?;
+            next = node.parent;
+            Some((id, node))
+        })
+    }
+
+    pub fn append(&mut self, parent: usize, child: usize) {
+        if let Some(node) = self.nodes.get_mut(&child) {
+            node.parent = Some(parent);
+            self.add_child(parent, child);
+        }
+    }
+
+    pub fn remove_from_parent(&mut self, target: usize) {
+        let parent = match self.nodes.get_mut(&target) {
+            Some(node) => node.parent.take(),
+            None => return,
+        };
+        if let Some(parent) = parent.and_then(|parent| self.nodes.get_mut(&parent)) {
+            parent.children.retain(|&child| child != target);
+        }
+    }
+
+    pub fn reparent_children(&mut self, node: usize, new_parent: usize) {
+        let children = match self.nodes.get_mut(&node) {
+            Some(node) => mem::take(&mut node.children),
+            None => return,
+        };
+        for child in children {
+            match self.nodes.get_mut(&child) {
+                Some(moved) if moved.parent == Some(node) => {
+                    moved.parent = Some(new_parent);
+                },
+                _ => continue,
+            }
+            self.add_child(new_parent, child);
+        }
+    }
+
+    /// Free an element that left the stack of open elements, unless the
+    /// tree builder may use it again: it can push `<head>` back onto the
+    /// stack, and its list of active formatting elements can outlive their
+    /// closing.
+    pub fn close(&mut self, id: usize) {
+        let keep = self
+            .nodes
+            .get(&id)
+            .and_then(|node| node.name.as_ref())
+            .map_or(true, |name| {
+                name.expanded() == expanded_name!(html "head") || is_formatting_element(name)
+            });
+        if !keep {
+            self.closed.push(id);
+        }
+    }
+
+    // Record `child` as a child of `parent`.  Freed children are only
+    // dropped from the list when it has to grow, so this is amortized
+    // constant time.
+    fn add_child(&mut self, parent: usize, child: usize) {
+        let mut children = match self.nodes.get_mut(&parent) {
+            Some(node) => mem::take(&mut node.children),
+            None => return,
+        };
+        if children.len() == children.capacity() {
+            let nodes = &self.nodes;
+            children.retain(|id| nodes.contains_key(id));
+        }
+        children.push(child);
+        if let Some(node) = self.nodes.get_mut(&parent) {
+            node.children = children;
+        }
+    }
+}
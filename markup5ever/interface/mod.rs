@@ -11,9 +11,11 @@
 use std::fmt;
 use tendril::StrTendril;
 
-pub use self::tree_builder::{create_element, AppendNode, AppendText, ElementFlags, NodeOrText};
+pub use self::tree_builder::AmbiguousConstruct;
+pub use self::tree_builder::{create_element, create_element_raw_attrs};
+pub use self::tree_builder::{AppendNode, AppendText, ElementFlags, NodeOrText};
 pub use self::tree_builder::{LimitedQuirks, NoQuirks, Quirks, QuirksMode};
-pub use self::tree_builder::{NextParserState, Tracer, TreeSink};
+pub use self::tree_builder::{NextParserState, ParseStats, Tracer, TreeSink};
 use super::{LocalName, Namespace, Prefix};
 
 /// An [expanded name], containing the tag and the namespace.
@@ -142,6 +144,17 @@ pub mod tree_builder;
 /// NOTE: `Prefix`, `LocalName` and `Prefix` are all derivative of
 /// `string_cache::atom::Atom` and `Atom` implements `Deref<str>`.
 ///
+/// For the name of an HTML, SVG or MathML element, which has no prefix,
+/// `QualName::html`, `QualName::svg` and `QualName::mathml` only need the
+/// local name as a string:
+///
+/// ```
+/// use markup5ever::QualName;
+///
+/// let div = QualName::html("div");
+/// assert_eq!(&*div.local, "div");
+/// ```
+///
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
 #[cfg_attr(feature = "heap_size", derive(HeapSizeOf))]
 pub struct QualName {
@@ -303,11 +316,40 @@ impl QualName {
     ///
     #[inline]
     pub fn new(prefix: Option<Prefix>, ns: Namespace, local: LocalName) -> QualName {
-        QualName {
-            prefix,
-            ns,
-            local,
-        }
+        QualName { prefix, ns, local }
+    }
+
+    /// The name of an element in the HTML namespace, without a prefix.
+    ///
+    /// This is the easiest way to name the context element for
+    /// `parse_fragment`.  The local name is used as it is, and the parser
+    /// only creates HTML elements with lowercase names.
+    ///
+    /// ```
+    /// #[macro_use] extern crate markup5ever;
+    /// use markup5ever::QualName;
+    ///
+    /// # fn main() {
+    /// assert_eq!(
+    ///     QualName::html("div"),
+    ///     QualName::new(None, ns!(html), local_name!("div"))
+    /// );
+    /// # }
+    /// ```
+    pub fn html(local: &str) -> QualName {
+        QualName::new(None, ns!(html), LocalName::from(local))
+    }
+
+    /// The name of an element in the SVG namespace, without a prefix.  The
+    /// local name is used as it is, so it should be in SVG's case, like
+    /// `foreignObject`.
+    pub fn svg(local: &str) -> QualName {
+        QualName::new(None, ns!(svg), LocalName::from(local))
+    }
+
+    /// The name of an element in the MathML namespace, without a prefix.
+    pub fn mathml(local: &str) -> QualName {
+        QualName::new(None, ns!(mathml), LocalName::from(local))
     }
 
     /// Take a reference of `self` as an `ExpandedName`, dropping the unresolved prefix.
@@ -344,7 +386,9 @@ impl QualName {
 /// The namespace on the attribute name is almost always ns!("").
 /// The tokenizer creates all attributes this way, but the tree
 /// builder will adjust certain attribute names inside foreign
-/// content (MathML, SVG).
+/// content (MathML, SVG).  An adjusted name keeps the prefix it was written
+/// with, so `xlink:href` gets the `xlink` prefix, the XLink namespace and
+/// the local name `href`, and a serializer can write it back as it was.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
 pub struct Attribute {
     /// The name of the attribute (e.g. the `class` in `<div class="test">`)
@@ -355,7 +399,7 @@ pub struct Attribute {
 
 #[cfg(test)]
 mod tests {
-    use super::Namespace;
+    use super::{LocalName, Namespace, QualName};
 
     #[test]
     fn ns_macro() {
@@ -374,4 +418,20 @@ mod tests {
             Namespace::from("http://www.w3.org/1998/Math/MathML")
         );
     }
+
+    #[test]
+    fn qual_name_constructors() {
+        let div = QualName::html("div");
+        assert_eq!(div, QualName::new(None, ns!(html), LocalName::from("div")));
+        assert_eq!(div.expanded(), expanded_name!(html "div"));
+
+        let svg = QualName::svg("foreignObject");
+        assert_eq!(svg.prefix, None);
+        assert_eq!(svg.ns, ns!(svg));
+        assert_eq!(svg.expanded(), expanded_name!(svg "foreignObject"));
+
+        let mathml = QualName::mathml("mi");
+        assert_eq!(mathml.ns, ns!(mathml));
+        assert_eq!(mathml.expanded(), expanded_name!(mathml "mi"));
+    }
 }
@@ -13,6 +13,7 @@
 
 use crate::interface::{Attribute, ExpandedName, QualName};
 use std::borrow::Cow;
+use std::collections::HashSet;
 use tendril::StrTendril;
 
 pub use self::NodeOrText::{AppendNode, AppendText};
@@ -75,6 +76,77 @@ pub struct ElementFlags {
     _private: (),
 }
 
+/// Counters collected while parsing, when enabled with
+/// `TreeBuilderOpts::collect_stats`.
+///
+/// Passed to `TreeSink::parse_stats` before the sink is finished.
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug, Default)]
+pub struct ParseStats {
+    /// Tokens received by the tree builder, not counting parse errors.  A
+    /// run of characters is a single token.
+    pub tokens: usize,
+    /// Elements created by the tree builder, including implied ones.
+    pub elements_created: usize,
+    /// Parse errors reported by the tokenizer and the tree builder.
+    pub parse_errors: usize,
+    /// Runs of the adoption agency algorithm, for end tags of formatting
+    /// elements.
+    pub adoption_agency_runs: usize,
+    /// Nodes inserted while foster parenting was in effect.
+    pub foster_parented: usize,
+    /// Largest size the stack of open elements reached.
+    pub max_depth: usize,
+}
+
+/// Markup that HTML parsers are known to disagree on, or that changes
+/// meaning when a parsed document is serialized and parsed again, as in
+/// mutation XSS.  Reported to `TreeSink::ambiguous_construct` when the
+/// tokenizer's `flag_ambiguous_constructs` option is set.
+///
+/// Each of these is also a parse error, except where noted.
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
+pub enum AmbiguousConstruct {
+    /// `/>` closing an HTML element that is not void, like `<div/>`.  HTML
+    /// ignores the `/`, while XML parsers close the element.  Not reported
+    /// in SVG or MathML, where it does close the element.  Not a parse
+    /// error.
+    SelfClosingNonVoidElement,
+    /// An attribute right after a quoted value, like `<a href="x"title=y>`.
+    MissingWhitespaceBetweenAttributes,
+    /// A `/` in a tag that is not followed by `>`, like
+    /// `<a href="x"/onclick=y>`, where it separates two attributes.
+    SolidusInTag,
+    /// An attribute name starting with `=`, like `<a =x>`.
+    EqualsSignBeforeAttributeName,
+    /// A `"`, `'`, `=` or `` ` `` in an unquoted attribute value, like
+    /// ``<a title=x`y>``.
+    UnexpectedCharacterInUnquotedAttributeValue,
+    /// A `<` in an attribute value, written or from a character reference,
+    /// like `<img alt="</noscript><b>">`.  Not a parse error.
+    LessThanSignInAttributeValue,
+    /// An attribute given more than once on a tag.  HTML keeps the first.
+    DuplicateAttribute,
+    /// Attributes on an end tag, like `</a href=x>`.
+    EndTagWithAttributes,
+    /// A comment closed by `<!-->` or `<!--->`.
+    AbruptClosingOfEmptyComment,
+    /// A comment closed by `--!>`.
+    IncorrectlyClosedComment,
+    /// `<!--` inside a comment.
+    NestedComment,
+    /// The input ends inside a tag, which is then dropped.
+    EofInTag,
+    /// `<!--` in the text of a `<script>`, which starts what the spec calls
+    /// script-html-comment-like text.  A `<script` after it hides the next
+    /// `</script>`.  Not a parse error.
+    ScriptHtmlCommentLikeText,
+    /// A `<script` start tag after `<!--` in the text of a `<script>`, like
+    /// `<script><!--<script></script>-->`.  The `</script>` that follows
+    /// does not end the element, which then runs on until `-->` or the
+    /// `</script>` after it.  Not a parse error.
+    NestedScriptStart,
+}
+
 /// A constructor for an element.
 ///
 /// # Examples
@@ -84,6 +156,26 @@ pub fn create_element<Sink>(sink: &mut Sink, name: QualName, attrs: Vec<Attribut
 where
     Sink: TreeSink,
 {
+    let flags = element_flags(&name, &attrs);
+    sink.create_element(name, attrs, flags)
+}
+
+/// A constructor for an element whose attributes may hold duplicates, as
+/// tokenized.  Like `create_element`, but goes through
+/// `TreeSink::create_element_raw_attrs`.
+pub fn create_element_raw_attrs<Sink>(
+    sink: &mut Sink,
+    name: QualName,
+    attrs: Vec<Attribute>,
+) -> Sink::Handle
+where
+    Sink: TreeSink,
+{
+    let flags = element_flags(&name, &attrs);
+    sink.create_element_raw_attrs(name, attrs, flags)
+}
+
+fn element_flags(name: &QualName, attrs: &[Attribute]) -> ElementFlags {
     let mut flags = ElementFlags::default();
     match name.expanded() {
         expanded_name!(html "template") => flags.template = true,
@@ -96,7 +188,7 @@ where
         },
         _ => {},
     }
-    sink.create_element(name, attrs, flags)
+    flags
 }
 
 /// Methods a parser can use to create the DOM. The DOM provider implements this trait.
@@ -125,6 +217,28 @@ pub trait TreeSink {
     /// Signal a parse error.
     fn parse_error(&mut self, msg: Cow<'static, str>);
 
+    /// Checked between tokens.  Return false to stop parsing: the rest of
+    /// the input is ignored, and `finish` is called on the tree as built so
+    /// far, without closing any open elements.
+    fn should_continue(&self) -> bool {
+        true
+    }
+
+    /// Called when the adoption agency algorithm finds mis-nested formatting
+    /// elements, as in `<b><i></b></i>`: `expected` is the element that
+    /// should have been closed first and `found` is the one that actually was.
+    ///
+    /// `expected_pos` is the source line of the start tag of `expected`, if
+    /// it is a formatting element like the `<i>` here.  It is `None` for
+    /// other elements, as in `<b><div></b>`.
+    fn misnested_tags(
+        &mut self,
+        _expected: &QualName,
+        _found: &QualName,
+        _expected_pos: Option<u64>,
+    ) {
+    }
+
     /// Get a handle to the `Document` node.
     fn get_document(&mut self) -> Self::Handle;
 
@@ -150,6 +264,27 @@ pub trait TreeSink {
         flags: ElementFlags,
     ) -> Self::Handle;
 
+    /// Create an element from the attribute list exactly as tokenized.
+    ///
+    /// The tree builder calls this instead of `create_element` only when it
+    /// is asked to preserve all attributes.  `attrs` may then contain
+    /// several attributes with the same name, in source order.  By default,
+    /// only the first attribute with each name is kept and the result is
+    /// passed on to `create_element`.
+    fn create_element_raw_attrs(
+        &mut self,
+        name: QualName,
+        mut attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Self::Handle {
+        if attrs.len() < 2 {
+            return self.create_element(name, attrs, flags);
+        }
+        let mut seen = HashSet::with_capacity(attrs.len());
+        attrs.retain(|attr| seen.insert(attr.name.clone()));
+        self.create_element(name, attrs, flags)
+    }
+
     /// Create a comment node.
     fn create_comment(&mut self, text: StrTendril) -> Self::Handle;
 
@@ -163,9 +298,68 @@ pub trait TreeSink {
     /// The child node will not already have a parent.
     fn append(&mut self, parent: &Self::Handle, child: NodeOrText<Self::Handle>);
 
-    /// When the insertion point is decided by the existence of a parent node of the
-    /// element, we consider both possibilities and send the element which will be used
-    /// if a parent node exists, along with the element to be used if there isn't one.
+    /// Append text as the last child of the given node, like `append`.
+    ///
+    /// `preserve_whitespace` is true when the text is inside an element whose
+    /// whitespace is preserved by default, such as `<pre>` or `<textarea>`.
+    /// CSS `white-space` is not known at parse time and is not considered.
+    ///
+    /// By default this forwards to `push_text` if `wants_borrowed_text`
+    /// returns true, and to `append` otherwise.
+    fn append_text_with_flags(
+        &mut self,
+        parent: &Self::Handle,
+        text: StrTendril,
+        preserve_whitespace: bool,
+    ) {
+        let _ = preserve_whitespace;
+        if self.wants_borrowed_text() {
+            self.push_text(parent, &text);
+        } else {
+            self.append(parent, NodeOrText::AppendText(text));
+        }
+    }
+
+    /// Whether text should be passed to `push_text` rather than `append`.
+    /// Sinks that implement `push_text` should return true.  Default: false
+    fn wants_borrowed_text(&self) -> bool {
+        false
+    }
+
+    /// Append text as the last child of the given node, like `append`, for
+    /// sinks that consume text as it is parsed instead of keeping it.
+    /// Only called when `wants_borrowed_text` returns true.
+    ///
+    /// `text` is only borrowed for the duration of the call; copy anything
+    /// that is needed later.  Text that is foster parented out of a table
+    /// still goes to `append_based_on_parent_node` or
+    /// `append_before_sibling`, as a tendril.
+    ///
+    /// By default this copies `text` into a new `StrTendril` and calls
+    /// `append`.
+    fn push_text(&mut self, parent: &Self::Handle, text: &str) {
+        self.append(parent, NodeOrText::AppendText(StrTendril::from_slice(text)));
+    }
+
+    /// Insert a node that is being foster parented out of a table.
+    ///
+    /// Content that is not allowed in a table, such as the stray text in
+    /// `<table>a<tr>`, is moved out of it.  `element` is the `<table>` and
+    /// `prev_element` is the element just above it on the stack of open
+    /// elements, usually its parent.  The tree builder cannot see the DOM, so
+    /// the sink finishes the decision:
+    ///
+    /// * if `element` has a parent, insert `child` into that parent just
+    ///   before `element`, as `append_before_sibling(element, child)` does;
+    /// * otherwise, for example after a script removed the table, append
+    ///   `child` as the last child of `prev_element`, as `append` does.
+    ///
+    /// As with those methods, text next to existing text should be merged
+    /// into it.  For `<table>a<tr>b` both `a` and `b` end up as one text node
+    /// before the table.
+    ///
+    /// See [foster parenting](https://html.spec.whatwg.org/multipage/#foster-parent)
+    /// in the spec.
     fn append_based_on_parent_node(
         &mut self,
         element: &Self::Handle,
@@ -173,6 +367,22 @@ pub trait TreeSink {
         child: NodeOrText<Self::Handle>,
     );
 
+    /// Insert text that is being foster parented out of a table, like
+    /// `append_based_on_parent_node`.  `preserve_whitespace` is as for
+    /// `append_text_with_flags`.
+    ///
+    /// By default this forwards to `append_based_on_parent_node`.
+    fn foster_parent_text_with_flags(
+        &mut self,
+        element: &Self::Handle,
+        prev_element: &Self::Handle,
+        text: StrTendril,
+        preserve_whitespace: bool,
+    ) {
+        let _ = preserve_whitespace;
+        self.append_based_on_parent_node(element, prev_element, NodeOrText::AppendText(text));
+    }
+
     /// Append a `DOCTYPE` element to the `Document` node.
     fn append_doctype_to_document(
         &mut self,
@@ -181,12 +391,183 @@ pub trait TreeSink {
         system_id: StrTendril,
     );
 
+    /// Append a `DOCTYPE` element to the `Document` node, with the
+    /// tokenizer's force-quirks flag.
+    ///
+    /// The flag is set when the declaration is too malformed to read, as in
+    /// `<!DOCTYPE>` with no name, which puts the document in quirks mode.
+    /// A missing `DOCTYPE` calls nothing.  Called instead of
+    /// `append_doctype_to_document`, except with the tokenizer's
+    /// `preserve_raw_doctype` option, when `append_doctype_raw` is called.
+    /// The default implementation drops `force_quirks` and calls
+    /// `append_doctype_to_document`.
+    fn append_doctype_full(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+        _force_quirks: bool,
+    ) {
+        self.append_doctype_to_document(name, public_id, system_id)
+    }
+
+    /// Append a `DOCTYPE` element to the `Document` node, with the
+    /// declaration as written in the source, like `<!doctype HTML>`.
+    ///
+    /// Called instead of `append_doctype_to_document` when the tokenizer's
+    /// `preserve_raw_doctype` option is set.  The default implementation
+    /// drops `raw` and calls `append_doctype_to_document`.
+    fn append_doctype_raw(
+        &mut self,
+        _raw: StrTendril,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.append_doctype_to_document(name, public_id, system_id)
+    }
+
+    /// Called for each start tag naming an obsolete element, when the
+    /// `flag_obsolete_elements` tree builder option is set.
+    fn obsolete_element(&mut self, _name: &QualName) {}
+
+    /// Called for each start tag that is dropped inside a `<select>`, where
+    /// only a few elements, such as `<option>` and `<optgroup>`, can be
+    /// inserted.  The text inside a dropped element is kept.  A `<select>`
+    /// start tag, which closes the open `<select>` instead of nesting in it,
+    /// is reported too.  Each of these is also a parse error.
+    fn select_content_dropped(&mut self, _name: &QualName) {}
+
+    /// Called for each `</template>` that is ignored because no `<template>`
+    /// is open.  Open templates are tracked apart from the other elements,
+    /// with their own stack of insertion modes, so this is a sign that the
+    /// templates in the source are unbalanced.  It is also a parse error.
+    fn stray_template_close(&mut self) {}
+
+    /// Called when the tree builder renames a start tag, as it does for
+    /// `<image>`, which becomes `<img>`.
+    fn tag_rewritten(&mut self, _from: &QualName, _to: &QualName) {}
+
+    /// Called for a start tag with the self-closing flag, like `<div/>`,
+    /// that the tree builder didn't acknowledge.  Only void elements such
+    /// as `<br/>` and SVG or MathML elements acknowledge it, so this is the
+    /// spec's parse error for a self-closing tag on any other element.
+    fn unacknowledged_self_closing(&mut self, _name: &QualName) {}
+
+    /// Receive a `DOCTYPE` that appeared after the start of the document.
+    ///
+    /// The spec ignores such a `DOCTYPE`; this is only called when the
+    /// `preserve_misplaced_doctype` tree builder option is set.
+    fn misplaced_doctype(
+        &mut self,
+        _name: StrTendril,
+        _public_id: StrTendril,
+        _system_id: StrTendril,
+    ) {
+    }
+
     /// Mark a HTML `<script>` as "already started".
     fn mark_script_already_started(&mut self, _node: &Self::Handle) {}
 
+    /// Called once for each element the tree builder creates, when its
+    /// attributes are set.
+    ///
+    /// Elements created for a start tag are reported right after they are
+    /// inserted, before any of their children.  Elements recreated by the
+    /// adoption agency algorithm are reported when created, before they are
+    /// inserted and before children are moved into them.  A duplicate
+    /// `<html>` or `<body>` tag can still add attributes later, through
+    /// `merge_attributes`.
+    fn element_finalized(&mut self, _element: &Self::Handle) {}
+
+    /// Indicate that the `<html>`, `<head>` or `<body>` element was implied:
+    /// the tree builder created it although its start tag is not in the
+    /// source, as for `<p>x`.  Called after `element_finalized`.
+    ///
+    /// A serializer can use this to omit the tag again.  Other implied
+    /// elements, such as `<tbody>`, and the root of a fragment are not
+    /// reported, except for the table wrappers created by the tree
+    /// builder's `wrap_orphaned_table_content` option.
+    fn element_synthesized(&mut self, _element: &Self::Handle, _name: &QualName) {}
+
+    /// Indicate that `clone` was created as a copy of the formatting
+    /// element `original`, like the second `<b>` in the tree for
+    /// `<p><b>x</p>y`, by the adoption agency algorithm or to reconstruct
+    /// the active formatting elements.  Called after `element_finalized`
+    /// for the copy.  `original` may itself be a copy.
+    ///
+    /// A sink that counts or extracts elements can use this to treat both
+    /// as one element from the source.
+    fn element_cloned(&mut self, _original: &Self::Handle, _clone: &Self::Handle) {}
+
+    /// Indicate that the `<head>` element was opened, from a `<head>` tag
+    /// or implied by the first thing that is not whitespace or a comment.
+    /// Called after `element_synthesized` for an implied head, and before
+    /// anything is put in the head.
+    fn head_opened(&mut self, _head: &Self::Handle) {}
+
+    /// Indicate that the `<head>` element was closed, by a `</head>` tag or
+    /// by the first thing that doesn't belong in it, like the `<p>` in
+    /// `<title>x</title><p>`.  Called before that thing is processed, so
+    /// before the `<body>` is opened.
+    ///
+    /// A `<title>` or other metadata that comes after the head is closed is
+    /// still put in the head, without calling `head_opened` or
+    /// `head_closed` again.  Neither is called when parsing a fragment.
+    fn head_closed(&mut self, _head: &Self::Handle) {}
+
+    /// Called for text that comes before the `<html>` element, like the
+    /// `text` in `text<html>`, just before it is moved into the `<body>`.
+    /// The `<html>`, `<head>` and `<body>` elements are then implied, so an
+    /// `<html>` or `<body>` start tag later in the source only adds its
+    /// attributes.  Whitespace before the text is dropped, as the spec
+    /// says, and not reported.
+    fn leading_text_relocated(&mut self, _text: StrTendril) {}
+
+    /// Indicate that the newline right after the start tag of `element`, a
+    /// `<pre>`, `<listing>` or `<textarea>`, was dropped, as the spec says.
+    /// `<pre>\nx` gives a `<pre>` with the text `x`, so a serializer that
+    /// wants the source back has to put the newline in again.  A `\r\n`
+    /// kept by the tokenizer's `normalize_newlines` option is one newline.
+    fn leading_newline_stripped(&mut self, _element: &Self::Handle) {}
+
+    /// Receive markup that parsers are known to disagree on, when the
+    /// tokenizer's `flag_ambiguous_constructs` option is set.
+    fn ambiguous_construct(&mut self, _construct: AmbiguousConstruct, _line_number: u64) {}
+
+    /// Receive the names of a duplicated attribute as written, before case
+    /// folding, when the tokenizer's `flag_ambiguous_constructs` option is
+    /// set.  `first` is the attribute that is kept and `duplicate` the later
+    /// one, so `<a HREF=1 href=2>` gives `"HREF"` and `"href"`.
+    fn duplicate_attribute(&mut self, _first: &str, _duplicate: &str, _line_number: u64) {}
+
+    /// Receive the counters collected during parsing.  Only called if
+    /// `TreeBuilderOpts::collect_stats` is set, just before `finish`.
+    fn parse_stats(&mut self, _stats: ParseStats) {}
+
+    /// Called when the tree builder gets to the end of the input, to tell
+    /// whether the document looks complete.  `open_elements` is the number
+    /// of elements still open that the spec does not allow to be closed by
+    /// the end of the input, like a `<div>` or a `<title>`, but unlike a
+    /// `<p>`, `<li>` or `<body>`.  `clean` is true if that number is zero
+    /// and the input did not end inside a tag, comment or doctype.
+    ///
+    /// A document that is not clean was perhaps cut off, as by a truncated
+    /// download.  Not called if the sink stopped parsing early.
+    fn eof_state(&mut self, _clean: bool, _open_elements: usize) {}
+
     /// Indicate that a node was popped off the stack of open elements.
     fn pop(&mut self, _node: &Self::Handle) {}
 
+    /// Indicate that an element left the stack of open elements.
+    ///
+    /// Unlike `pop`, this is called for every element that leaves the
+    /// stack: those closed by an end tag or implied end tag, those removed
+    /// from the middle of it by the adoption agency, and those still open
+    /// at the end of the input.  The HTML `<head>` element can be pushed
+    /// back and closed again.
+    fn element_closed(&mut self, _node: &Self::Handle) {}
+
     /// Get a handle to a template's template contents. The tree builder
     /// promises this will never be called with something else than
     /// a template element.
@@ -198,6 +579,33 @@ pub trait TreeSink {
     /// Set the document's quirks mode.
     fn set_quirks_mode(&mut self, mode: QuirksMode);
 
+    /// Called when quirks mode changes how the document is parsed, with a
+    /// short description of the decision taken.
+    ///
+    /// The tree builder has one such decision point: in quirks mode, a
+    /// `<table>` start tag does not close an open `<p>` element.  This is
+    /// only reported when a `<p>` is actually open.
+    fn quirks_behavior_applied(&mut self, _description: &'static str) {}
+
+    /// Called when the tree builder changes its [frameset-ok flag], with a
+    /// short description of what caused the change, like `"<pre>"` or
+    /// `"non-whitespace text"`.
+    ///
+    /// While the flag is set, a `<frameset>` start tag in the body replaces
+    /// the body.  It starts out set and is only ever cleared, so `ok` is
+    /// always false.  Nothing is reported when the flag is already clear.
+    ///
+    /// [frameset-ok flag]: https://html.spec.whatwg.org/multipage/#frameset-ok-flag
+    fn frameset_ok_changed(&mut self, _ok: bool, _reason: &'static str) {}
+
+    /// Report the `lang` and `dir` attributes of the root `<html>` element.
+    ///
+    /// Called once the root element is created, and again after each
+    /// duplicate `<html>` start tag merges its attributes onto it.  Values
+    /// set by an earlier tag win, as with `add_attrs_if_missing`.  Not called
+    /// when parsing a fragment.
+    fn set_document_metadata(&mut self, _lang: Option<StrTendril>, _dir: Option<StrTendril>) {}
+
     /// Append a node as the sibling immediately before the given node.
     ///
     /// The tree builder promises that `sibling` is not a text node.  However its
@@ -213,6 +621,21 @@ pub trait TreeSink {
     /// with something else than an element.
     fn add_attrs_if_missing(&mut self, target: &Self::Handle, attrs: Vec<Attribute>);
 
+    /// Merge the attributes of a duplicate `<html>` or `<body>` start tag,
+    /// named by `source_tag_name`, onto the existing element.
+    ///
+    /// By default this forwards to `add_attrs_if_missing`.  Override it to
+    /// observe the merge as a distinct event.
+    fn merge_attributes(
+        &mut self,
+        target: &Self::Handle,
+        source_tag_name: &QualName,
+        merged: &[Attribute],
+    ) {
+        let _ = source_tag_name;
+        self.add_attrs_if_missing(target, merged.to_vec());
+    }
+
     /// Associate the given form-associatable element with the form element
     fn associate_with_form(
         &mut self,
@@ -226,6 +649,12 @@ pub trait TreeSink {
     fn remove_from_parent(&mut self, target: &Self::Handle);
 
     /// Remove all the children from node and append them to new_parent.
+    ///
+    /// This is one bulk move: the tree builder makes no separate `append` or
+    /// `remove_from_parent` calls for the moved children, so a sink that
+    /// batches DOM mutations can apply it as a single operation.  Children
+    /// keep their order.  It is called by the adoption agency algorithm, with
+    /// a `new_parent` that was just created and has no children or parent.
     fn reparent_children(&mut self, node: &Self::Handle, new_parent: &Self::Handle);
 
     /// Returns true if the adjusted current node is an HTML integration point
@@ -41,6 +41,8 @@ pub enum SetResult {
 /// A queue of owned string buffers, which supports incrementally consuming characters.
 ///
 /// Internally it uses [`VecDeque`] and has the same complexity properties.
+/// There is no locking: like the tendrils it holds, a queue can't be sent
+/// to or shared with another thread.
 ///
 /// [`VecDeque`]: https://doc.rust-lang.org/std/collections/struct.VecDeque.html
 #[derive(Debug)]
@@ -64,6 +66,21 @@ impl BufferQueue {
         self.buffers.is_empty()
     }
 
+    /// The number of bytes in all the buffers in the queue.
+    pub fn total_len(&self) -> usize {
+        self.buffers.iter().map(|b| b.len()).sum()
+    }
+
+    /// Remove every buffer from the queue.
+    ///
+    /// A parser's `input_buffer` holds input that has been given to it but
+    /// not yet tokenized, as when the tree sink stopped or paused parsing.
+    /// Clearing it discards that input for good, so parsing can be aborted
+    /// or restarted with other input.
+    pub fn clear(&mut self) {
+        self.buffers.clear();
+    }
+
     /// Get the buffer at the beginning of the queue.
     #[inline]
     pub fn pop_front(&mut self) -> Option<StrTendril> {
@@ -261,6 +278,35 @@ mod test {
         assert_eq!(bq.next(), None);
     }
 
+    #[test]
+    fn total_len() {
+        let mut bq = BufferQueue::new();
+        assert_eq!(bq.total_len(), 0);
+        bq.push_back("abc".to_tendril());
+        bq.push_back("dé".to_tendril());
+        assert_eq!(bq.total_len(), 6);
+        assert_eq!(bq.next(), Some('a'));
+        assert_eq!(bq.total_len(), 5);
+        bq.push_front("x".to_tendril());
+        assert_eq!(bq.total_len(), 6);
+    }
+
+    #[test]
+    fn clear() {
+        let mut bq = BufferQueue::new();
+        bq.push_back("abc".to_tendril());
+        bq.push_back("def".to_tendril());
+        assert_eq!(bq.next(), Some('a'));
+        bq.clear();
+        assert!(bq.is_empty());
+        assert_eq!(bq.total_len(), 0);
+        assert_eq!(bq.peek(), None);
+        assert_eq!(bq.next(), None);
+
+        bq.push_back("g".to_tendril());
+        assert_eq!(bq.next(), Some('g'));
+    }
+
     #[test]
     fn can_unconsume() {
         let mut bq = BufferQueue::new();
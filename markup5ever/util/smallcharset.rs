@@ -11,6 +11,8 @@
 //!
 //! [`SmallCharSet`]: struct.SmallCharSet.html
 
+use std::convert::TryInto;
+
 /// Represents a set of "small characters", those with Unicode scalar
 /// values less than 64.
 ///
@@ -56,15 +58,35 @@ impl SmallCharSet {
     ///
     /// [`BufferQueue::pop_except_from`]: buffer_queue/struct.BufferQueue.html#method.pop_except_from
     pub fn nonmember_prefix_len(&self, buf: &str) -> u32 {
+        let bytes = buf.as_bytes();
         let mut n = 0;
-        for b in buf.bytes() {
+
+        // Look at eight bytes at a time.  Only bytes below 64 can be in the
+        // set, and in most text there are few of them, so find those first
+        // and look them up one by one.  Members are ASCII, so the prefix
+        // always ends on a character boundary.
+        while let Some(chunk) = bytes.get(n..n + 8) {
+            let word = u64::from_le_bytes(chunk.try_into().unwrap());
+            // The top bit of each byte whose top two bits are clear.
+            let mut small = !(word | word << 1) & 0x8080_8080_8080_8080;
+            while small != 0 {
+                let i = small.trailing_zeros() as usize / 8;
+                if self.contains(chunk[i]) {
+                    return (n + i) as u32;
+                }
+                small &= small - 1;
+            }
+            n += 8;
+        }
+
+        for &b in &bytes[n..] {
             if b >= 64 || !self.contains(b) {
                 n += 1;
             } else {
                 break;
             }
         }
-        n
+        n as u32
     }
 }
 
@@ -87,4 +109,31 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn nonmember_prefix_multibyte() {
+        // No byte of these is in the set, though the low six bits of some
+        // continuation bytes match members.
+        let text = "a\u{e9}\u{7c0}\u{20ac}\u{1f601}\u{3c00}";
+        let set = small_char_set!('\r' '\0' '&' '<' '\n');
+        for len in 0..40 {
+            let mut s = text.repeat(len / text.len() + 1);
+            let mut end = len;
+            while !s.is_char_boundary(end) {
+                end += 1;
+            }
+            s.truncate(end);
+            assert_eq!(end as u32, set.nonmember_prefix_len(&s));
+            s.push('<');
+            s.push_str(text);
+            assert_eq!(end as u32, set.nonmember_prefix_len(&s));
+        }
+    }
+
+    #[test]
+    fn nonmember_prefix_large_set() {
+        let set = small_char_set!('\t' '\n' '\x0C' '\r' ' ' '"' '&' '\'' '/' '<' '=' '>' '\0');
+        let s = "abcdefghijklmnopqrstuvwxyz0123456789 abc";
+        assert_eq!(36, set.nonmember_prefix_len(s));
+    }
 }
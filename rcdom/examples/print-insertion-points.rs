@@ -0,0 +1,33 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Print where the tree builder inserts each node of a malformed table,
+//! showing the text and the `<p>` that are foster-parented out of it.
+
+extern crate html5ever;
+extern crate markup5ever_rcdom as rcdom;
+
+use html5ever::parse_document;
+use html5ever::tendril::TendrilSink;
+use rcdom::RcDom;
+
+fn main() {
+    let html = "<table>a<tr><td>b</td>c<p>d</table>";
+    println!("{}\n", html);
+
+    let mut parser = parse_document(RcDom::default(), Default::default());
+    parser.set_on_insertion_point(Some(Box::new(|point| {
+        if point.foster_parented {
+            println!("before <{}> (foster-parented)", point.parent.local);
+        } else {
+            println!("into <{}>", point.parent.local);
+        }
+    })));
+    parser.one(html);
+}
@@ -33,6 +33,9 @@
 //! a new ref and then a node will outlive the document. Nodes own their children, but only have
 //! weak references to their parents.
 //!
+//! Each node also carries a user payload of type `T`, `()` by default.  Use
+//! `RcDom::with_node_ext` to compute it for each element as it is created.
+//!
 //! [tree structure]: https://en.wikipedia.org/wiki/Tree_(data_structure)
 //! [dom wiki]: https://en.wikipedia.org/wiki/Document_Object_Model
 
@@ -50,6 +53,7 @@ use std::rc::{Rc, Weak};
 
 use tendril::StrTendril;
 
+use html5ever::driver::{parse_document, resume_in_body, ParseOpts, Parser};
 use markup5ever::interface::tree_builder;
 use markup5ever::interface::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
 use markup5ever::serialize::TraversalScope;
@@ -58,10 +62,12 @@ use markup5ever::serialize::{Serialize, Serializer};
 use markup5ever::Attribute;
 use markup5ever::ExpandedName;
 use markup5ever::QualName;
+use markup5ever::{local_name, namespace_url, ns, LocalName};
+use tendril::TendrilSink;
 
 /// The different kinds of nodes in the DOM.
 #[derive(Debug)]
-pub enum NodeData {
+pub enum NodeData<T = ()> {
     /// The `Document` itself - the root node of a HTML document.
     Document,
 
@@ -89,7 +95,7 @@ pub enum NodeData {
         /// For HTML \<template\> elements, the [template contents].
         ///
         /// [template contents]: https://html.spec.whatwg.org/multipage/#template-contents
-        template_contents: RefCell<Option<Handle>>,
+        template_contents: RefCell<Option<Handle<T>>>,
 
         /// Whether the node is a [HTML integration point].
         ///
@@ -105,27 +111,126 @@ pub enum NodeData {
 }
 
 /// A DOM node.
-pub struct Node {
+pub struct Node<T = ()> {
     /// Parent node.
-    pub parent: Cell<Option<WeakHandle>>,
+    pub parent: Cell<Option<WeakHandle<T>>>,
     /// Child nodes of this node.
-    pub children: RefCell<Vec<Handle>>,
+    pub children: RefCell<Vec<Handle<T>>>,
     /// Represents this node's data.
-    pub data: NodeData,
+    pub data: NodeData<T>,
+    /// User data attached to this node.
+    pub ext: T,
 }
 
 impl Node {
     /// Create a new node from its contents
     pub fn new(data: NodeData) -> Rc<Self> {
+        Node::with_ext(data, ())
+    }
+}
+
+impl<T> Node<T> {
+    /// Create a new node from its contents and user data
+    pub fn with_ext(data: NodeData<T>, ext: T) -> Rc<Self> {
         Rc::new(Node {
             data,
             parent: Cell::new(None),
             children: RefCell::new(Vec::new()),
+            ext,
         })
     }
+
+    /// Iterate over this node and its descendants in document order.
+    ///
+    /// ```
+    /// use html5ever::driver::parse_document;
+    /// use html5ever::tendril::TendrilSink;
+    /// use markup5ever_rcdom::{NodeData, RcDom};
+    ///
+    /// let dom = parse_document(RcDom::default(), Default::default()).one("<p>a<b>b</b>");
+    /// let names: Vec<_> = dom
+    ///     .document
+    ///     .traverse()
+    ///     .filter_map(|node| match node.data {
+    ///         NodeData::Element { ref name, .. } => Some(name.local.to_string()),
+    ///         _ => None,
+    ///     })
+    ///     .collect();
+    /// assert_eq!(names, ["html", "head", "body", "p", "b"]);
+    /// ```
+    pub fn traverse(self: &Rc<Self>) -> NodeIter<T> {
+        NodeIter {
+            stack: vec![self.clone()],
+            expand: None,
+        }
+    }
+
+    /// Iterate over the descendants of this node in document order, without
+    /// the node itself.
+    pub fn descendants(self: &Rc<Self>) -> NodeIter<T> {
+        NodeIter {
+            stack: vec![],
+            expand: Some(self.clone()),
+        }
+    }
+
+    /// Iterate over the children of this node.
+    pub fn children(self: &Rc<Self>) -> Siblings<T> {
+        Siblings {
+            parent: Some(self.clone()),
+            prev: None,
+            next: 0,
+        }
+    }
+
+    /// Iterate over the siblings after this node.
+    pub fn following_siblings(self: &Rc<Self>) -> Siblings<T> {
+        match get_parent_and_index(self) {
+            Some((parent, i)) => Siblings {
+                parent: Some(parent),
+                prev: Some(self.clone()),
+                next: i + 1,
+            },
+            None => Siblings {
+                parent: None,
+                prev: None,
+                next: 0,
+            },
+        }
+    }
+
+    /// Serialize this node as HTML, as the [fragment serializing algorithm]
+    /// would for a parent holding just this node.  A document node is
+    /// serialized as its children.
+    ///
+    /// ```
+    /// use html5ever::driver::parse_document;
+    /// use html5ever::tendril::TendrilSink;
+    /// use markup5ever_rcdom::RcDom;
+    ///
+    /// let dom = parse_document(RcDom::default(), Default::default()).one("<p class=a>x &amp; y");
+    /// let body = dom.document.children.borrow()[0].children.borrow()[1].clone();
+    /// assert_eq!(body.serialize(), "<body><p class=\"a\">x &amp; y</p></body>");
+    /// ```
+    ///
+    /// [fragment serializing algorithm]: https://html.spec.whatwg.org/multipage/#serialising-html-fragments
+    pub fn serialize(self: &Rc<Self>) -> String {
+        let traversal_scope = match self.data {
+            NodeData::Document => ChildrenOnly(None),
+            _ => IncludeNode,
+        };
+        let opts = html5ever::serialize::SerializeOpts {
+            traversal_scope,
+            ..Default::default()
+        };
+        let mut bytes = vec![];
+        html5ever::serialize::serialize(&mut bytes, &SerializableHandle(self.clone()), opts)
+            .expect("writing to a Vec<u8> can't fail");
+        String::from_utf8(bytes).expect("serializer wrote invalid UTF-8")
+    }
 }
 
-impl Drop for Node {
+impl<T> Drop for Node<T> {
     fn drop(&mut self) {
         let mut nodes = mem::replace(&mut *self.children.borrow_mut(), vec![]);
         while let Some(node) = nodes.pop() {
@@ -140,7 +245,7 @@ impl Drop for Node {
     }
 }
 
-impl fmt::Debug for Node {
+impl<T: fmt::Debug> fmt::Debug for Node<T> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.debug_struct("Node")
             .field("data", &self.data)
@@ -150,13 +255,85 @@ impl fmt::Debug for Node {
 }
 
 /// Reference to a DOM node.
-pub type Handle = Rc<Node>;
+pub type Handle<T = ()> = Rc<Node<T>>;
 
 /// Weak reference to a DOM node, used for parent pointers.
-pub type WeakHandle = Weak<Node>;
+pub type WeakHandle<T = ()> = Weak<Node<T>>;
+
+/// Iterator over a node and its descendants in document order, created by
+/// `Node::traverse` and `Node::descendants`.  Template contents are not
+/// included.
+///
+/// No borrow is held between calls to `next`, so the tree may be changed
+/// while iterating.  The children of a node are read when the iterator moves
+/// past it, so changes to the children of the node returned last are seen:
+/// removing them skips them.  Nodes after it have already been queued, and
+/// are returned even if they are removed from the tree in the meantime.
+pub struct NodeIter<T = ()> {
+    /// Nodes still to return, the next one last.
+    stack: Vec<Handle<T>>,
+
+    /// The node returned last, if its children haven't been queued yet.
+    expand: Option<Handle<T>>,
+}
+
+impl<T> Iterator for NodeIter<T> {
+    type Item = Handle<T>;
+
+    fn next(&mut self) -> Option<Handle<T>> {
+        if let Some(node) = self.expand.take() {
+            self.stack
+                .extend(node.children.borrow().iter().rev().cloned());
+        }
+        let node = self.stack.pop()?;
+        self.expand = Some(node.clone());
+        Some(node)
+    }
+}
+
+/// Iterator over the children of a node, created by `Node::children` and
+/// `Node::following_siblings`.
+///
+/// No borrow is held between calls to `next`.  Each call returns the node
+/// after the one returned last, at the time of the call, so nodes may be
+/// inserted and removed while iterating.  Iteration stops if the node
+/// returned last is removed from the parent.
+pub struct Siblings<T = ()> {
+    parent: Option<Handle<T>>,
+
+    /// The node returned last.
+    prev: Option<Handle<T>>,
+
+    /// The index of the node after `prev`, when `prev` was returned.
+    next: usize,
+}
+
+impl<T> Iterator for Siblings<T> {
+    type Item = Handle<T>;
+
+    fn next(&mut self) -> Option<Handle<T>> {
+        let node = {
+            let children = self.parent.as_ref()?.children.borrow();
+            if let Some(ref prev) = self.prev {
+                let moved = self
+                    .next
+                    .checked_sub(1)
+                    .and_then(|i| children.get(i))
+                    .map_or(true, |child| !Rc::ptr_eq(child, prev));
+                if moved {
+                    self.next = children.iter().position(|child| Rc::ptr_eq(child, prev))? + 1;
+                }
+            }
+            children.get(self.next)?.clone()
+        };
+        self.prev = Some(node.clone());
+        self.next += 1;
+        Some(node)
+    }
+}
 
 /// Append a parentless node to another nodes' children
-fn append(new_parent: &Handle, child: Handle) {
+fn append<T>(new_parent: &Handle<T>, child: Handle<T>) {
     let previous_parent = child.parent.replace(Some(Rc::downgrade(new_parent)));
     // Invariant: child cannot have existing parent
     assert!(previous_parent.is_none());
@@ -164,7 +341,7 @@ fn append(new_parent: &Handle, child: Handle) {
 }
 
 /// If the node has a parent, get it and this node's position in its children
-fn get_parent_and_index(target: &Handle) -> Option<(Handle, usize)> {
+fn get_parent_and_index<T>(target: &Handle<T>) -> Option<(Handle<T>, usize)> {
     if let Some(weak) = target.parent.take() {
         let parent = weak.upgrade().expect("dangling weak pointer");
         target.parent.set(Some(weak));
@@ -184,17 +361,22 @@ fn get_parent_and_index(target: &Handle) -> Option<(Handle, usize)> {
     }
 }
 
-fn append_to_existing_text(prev: &Handle, text: &str) -> bool {
+fn append_to_existing_text<T>(prev: &Handle<T>, text: &StrTendril) -> bool {
     match prev.data {
         NodeData::Text { ref contents } => {
-            contents.borrow_mut().push_slice(text);
+            contents.borrow_mut().push_tendril(text);
             true
         },
         _ => false,
     }
 }
 
-fn remove_from_parent(target: &Handle) {
+/// Create a node with default user data
+fn new_node<T: Default>(data: NodeData<T>) -> Handle<T> {
+    Node::with_ext(data, T::default())
+}
+
+fn remove_from_parent<T>(target: &Handle<T>) {
     if let Some((parent, i)) = get_parent_and_index(target) {
         parent.children.borrow_mut().remove(i);
         target.parent.set(None);
@@ -202,34 +384,93 @@ fn remove_from_parent(target: &Handle) {
 }
 
 /// The DOM itself; the result of parsing.
-pub struct RcDom {
+pub struct RcDom<T = ()> {
     /// The `Document` itself.
-    pub document: Handle,
+    pub document: Handle<T>,
 
     /// Errors that occurred during parsing.
     pub errors: Vec<Cow<'static, str>>,
 
     /// The document's quirks mode.
     pub quirks_mode: QuirksMode,
+
+    /// Computes the user data of each new element.
+    node_ext: NodeExtFactory<T>,
+}
+
+type NodeExtFactory<T> = Box<dyn FnMut(&QualName, &[Attribute]) -> T>;
+
+impl<T: Default> RcDom<T> {
+    /// Create an empty DOM whose elements get their user data from
+    /// `node_ext`, called with each element's name and attributes in the order
+    /// the elements are created.  Other nodes get `T::default()`.
+    ///
+    /// ```
+    /// use html5ever::driver::parse_document;
+    /// use html5ever::tendril::TendrilSink;
+    /// use markup5ever_rcdom::RcDom;
+    ///
+    /// let mut count = 0;
+    /// let dom = parse_document(
+    ///     RcDom::with_node_ext(move |_, _| {
+    ///         count += 1;
+    ///         count
+    ///     }),
+    ///     Default::default(),
+    /// )
+    /// .one("<p>");
+    /// // The root `<html>` element is created first.
+    /// assert_eq!(dom.document.children.borrow()[0].ext, 1);
+    /// ```
+    pub fn with_node_ext<F>(node_ext: F) -> Self
+    where
+        F: FnMut(&QualName, &[Attribute]) -> T + 'static,
+    {
+        RcDom {
+            document: Node::with_ext(NodeData::Document, T::default()),
+            errors: vec![],
+            quirks_mode: tree_builder::NoQuirks,
+            node_ext: Box::new(node_ext),
+        }
+    }
+}
+
+impl<T> RcDom<T> {
+    /// Serialize the document as HTML.
+    ///
+    /// ```
+    /// use html5ever::driver::parse_document;
+    /// use html5ever::tendril::TendrilSink;
+    /// use markup5ever_rcdom::RcDom;
+    ///
+    /// let dom = parse_document(RcDom::default(), Default::default()).one("<title>a</title>b");
+    /// assert_eq!(
+    ///     dom.serialize(),
+    ///     "<html><head><title>a</title></head><body>b</body></html>"
+    /// );
+    /// ```
+    pub fn serialize(&self) -> String {
+        self.document.serialize()
+    }
 }
 
-impl TreeSink for RcDom {
+impl<T: Default> TreeSink for RcDom<T> {
     type Output = Self;
     fn finish(self) -> Self {
         self
     }
 
-    type Handle = Handle;
+    type Handle = Handle<T>;
 
     fn parse_error(&mut self, msg: Cow<'static, str>) {
         self.errors.push(msg);
     }
 
-    fn get_document(&mut self) -> Handle {
+    fn get_document(&mut self) -> Handle<T> {
         self.document.clone()
     }
 
-    fn get_template_contents(&mut self, target: &Handle) -> Handle {
+    fn get_template_contents(&mut self, target: &Handle<T>) -> Handle<T> {
         if let NodeData::Element {
             ref template_contents,
             ..
@@ -245,11 +486,11 @@ impl TreeSink for RcDom {
         self.quirks_mode = mode;
     }
 
-    fn same_node(&self, x: &Handle, y: &Handle) -> bool {
+    fn same_node(&self, x: &Handle<T>, y: &Handle<T>) -> bool {
         Rc::ptr_eq(x, y)
     }
 
-    fn elem_name<'a>(&self, target: &'a Handle) -> ExpandedName<'a> {
+    fn elem_name<'a>(&self, target: &'a Handle<T>) -> ExpandedName<'a> {
         return match target.data {
             NodeData::Element { ref name, .. } => name.expanded(),
             _ => panic!("not an element!"),
@@ -261,31 +502,36 @@ impl TreeSink for RcDom {
         name: QualName,
         attrs: Vec<Attribute>,
         flags: ElementFlags,
-    ) -> Handle {
-        Node::new(NodeData::Element {
-            name,
-            attrs: RefCell::new(attrs),
-            template_contents: RefCell::new(if flags.template {
-                Some(Node::new(NodeData::Document))
-            } else {
-                None
-            }),
-            mathml_annotation_xml_integration_point: flags.mathml_annotation_xml_integration_point,
-        })
+    ) -> Handle<T> {
+        let ext = (self.node_ext)(&name, &attrs);
+        Node::with_ext(
+            NodeData::Element {
+                name,
+                attrs: RefCell::new(attrs),
+                template_contents: RefCell::new(if flags.template {
+                    Some(new_node(NodeData::Document))
+                } else {
+                    None
+                }),
+                mathml_annotation_xml_integration_point: flags
+                    .mathml_annotation_xml_integration_point,
+            },
+            ext,
+        )
     }
 
-    fn create_comment(&mut self, text: StrTendril) -> Handle {
-        Node::new(NodeData::Comment { contents: text })
+    fn create_comment(&mut self, text: StrTendril) -> Handle<T> {
+        new_node(NodeData::Comment { contents: text })
     }
 
-    fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Handle {
-        Node::new(NodeData::ProcessingInstruction {
+    fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Handle<T> {
+        new_node(NodeData::ProcessingInstruction {
             target,
             contents: data,
         })
     }
 
-    fn append(&mut self, parent: &Handle, child: NodeOrText<Handle>) {
+    fn append(&mut self, parent: &Handle<T>, child: NodeOrText<Handle<T>>) {
         // Append to an existing Text node if we have one.
         match child {
             NodeOrText::AppendText(ref text) => match parent.children.borrow().last() {
@@ -302,7 +548,7 @@ impl TreeSink for RcDom {
         append(
             &parent,
             match child {
-                NodeOrText::AppendText(text) => Node::new(NodeData::Text {
+                NodeOrText::AppendText(text) => new_node(NodeData::Text {
                     contents: RefCell::new(text),
                 }),
                 NodeOrText::AppendNode(node) => node,
@@ -310,13 +556,13 @@ impl TreeSink for RcDom {
         );
     }
 
-    fn append_before_sibling(&mut self, sibling: &Handle, child: NodeOrText<Handle>) {
+    fn append_before_sibling(&mut self, sibling: &Handle<T>, child: NodeOrText<Handle<T>>) {
         let (parent, i) = get_parent_and_index(&sibling)
             .expect("append_before_sibling called on node without parent");
 
         let child = match (child, i) {
             // No previous node.
-            (NodeOrText::AppendText(text), 0) => Node::new(NodeData::Text {
+            (NodeOrText::AppendText(text), 0) => new_node(NodeData::Text {
                 contents: RefCell::new(text),
             }),
 
@@ -327,7 +573,7 @@ impl TreeSink for RcDom {
                 if append_to_existing_text(prev, &text) {
                     return;
                 }
-                Node::new(NodeData::Text {
+                new_node(NodeData::Text {
                     contents: RefCell::new(text),
                 })
             },
@@ -370,7 +616,7 @@ impl TreeSink for RcDom {
     ) {
         append(
             &self.document,
-            Node::new(NodeData::Doctype {
+            new_node(NodeData::Doctype {
                 name,
                 public_id,
                 system_id,
@@ -378,7 +624,7 @@ impl TreeSink for RcDom {
         );
     }
 
-    fn add_attrs_if_missing(&mut self, target: &Handle, attrs: Vec<Attribute>) {
+    fn add_attrs_if_missing(&mut self, target: &Handle<T>, attrs: Vec<Attribute>) {
         let mut existing = if let NodeData::Element { ref attrs, .. } = target.data {
             attrs.borrow_mut()
         } else {
@@ -396,11 +642,11 @@ impl TreeSink for RcDom {
         );
     }
 
-    fn remove_from_parent(&mut self, target: &Handle) {
+    fn remove_from_parent(&mut self, target: &Handle<T>) {
         remove_from_parent(&target);
     }
 
-    fn reparent_children(&mut self, node: &Handle, new_parent: &Handle) {
+    fn reparent_children(&mut self, node: &Handle<T>, new_parent: &Handle<T>) {
         let mut children = node.children.borrow_mut();
         let mut new_children = new_parent.children.borrow_mut();
         for child in children.iter() {
@@ -413,7 +659,7 @@ impl TreeSink for RcDom {
         new_children.extend(mem::replace(&mut *children, Vec::new()));
     }
 
-    fn is_mathml_annotation_xml_integration_point(&self, target: &Handle) -> bool {
+    fn is_mathml_annotation_xml_integration_point(&self, target: &Handle<T>) -> bool {
         if let NodeData::Element {
             mathml_annotation_xml_integration_point,
             ..
@@ -428,28 +674,208 @@ impl TreeSink for RcDom {
 
 impl Default for RcDom {
     fn default() -> RcDom {
-        RcDom {
-            document: Node::new(NodeData::Document),
-            errors: vec![],
-            quirks_mode: tree_builder::NoQuirks,
+        RcDom::with_node_ext(|_, _| ())
+    }
+}
+
+/// A place in a document's source where `RcDom::reparse_from` can restart
+/// parsing.  Found by `RcDom::parse_with_restart_points`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RestartPoint {
+    /// The byte offset in the source of the `<` of a start tag at the top
+    /// level of the `<body>`.
+    pub offset: usize,
+
+    // How much of the tree and the errors came from the source before
+    // `offset`.
+    document_children: usize,
+    html_children: usize,
+    body_children: usize,
+    errors: usize,
+}
+
+impl<T: Default> RcDom<T> {
+    /// Parse `source` into this DOM, which must be empty, like
+    /// `parse_document`, and find the places where `reparse_from` can
+    /// restart parsing after the source is edited.
+    ///
+    /// These are the start tags of the top-level elements of the `<body>`,
+    /// except where the parser has state that the start tag depends on,
+    /// such as an unclosed `<b>` or `<form>`, or a table.  Finding them
+    /// makes parsing slower, because the source is fed to the parser one
+    /// top-level tag at a time.
+    pub fn parse_with_restart_points(
+        self,
+        source: &str,
+        opts: ParseOpts,
+    ) -> (Self, Vec<RestartPoint>) {
+        let mut points = vec![];
+        let mut parser = parse_document(self, opts);
+        feed_with_restart_points(&mut parser, source, 0, &mut points);
+        (parser.finish(), points)
+    }
+
+    /// Update the DOM for an edit to its source, re-parsing as little as
+    /// this conservative scheme allows.
+    ///
+    /// `source` is the whole new source, which is the same as the one
+    /// last parsed before byte offset `change_offset`.  `points` are the
+    /// restart points of the last parse, from `parse_with_restart_points`
+    /// or an earlier call to this method, and are updated for the new
+    /// source.  `opts` should be the options the DOM was parsed with.
+    ///
+    /// Parsing restarts at the last restart point at or before the change:
+    /// the start of the top-level element containing it, or of one before.
+    /// Everything parsed from the source after that point is removed from
+    /// the tree, and the rest of the source is parsed again into the
+    /// existing `<body>`.  Nodes before the restart point are kept as they
+    /// are, so handles to them stay valid.  Without an earlier restart
+    /// point, the whole document is parsed again.
+    ///
+    /// The result is the tree a full parse of `source` would give, with
+    /// these limitations:
+    ///
+    /// * Everything after the restart point is re-parsed, to the end of the
+    ///   source, even if the edit only touched one element.
+    /// * A `<body>` or `<html>` start tag after the restart point adds its
+    ///   attributes to the existing element, and re-parsing does not
+    ///   remove them.  An edit that removes such a tag leaves them.
+    /// * Only the restart points are recorded, not positions of other
+    ///   nodes, so an edit inside a large table or a deeply misnested
+    ///   region restarts from the last clean point before it, which may
+    ///   be far back.
+    pub fn reparse_from(
+        &mut self,
+        points: &mut Vec<RestartPoint>,
+        source: &str,
+        change_offset: usize,
+        mut opts: ParseOpts,
+    ) {
+        let node_ext = mem::replace(&mut self.node_ext, Box::new(|_, _| T::default()));
+        let mut dom = RcDom {
+            document: self.document.clone(),
+            errors: mem::replace(&mut self.errors, vec![]),
+            quirks_mode: self.quirks_mode,
+            node_ext,
+        };
+
+        let restart = points.iter().rposition(|p| p.offset <= change_offset);
+        let roots = restart.and_then(|_| html_and_body(&self.document));
+        let (start, mut parser) = match (restart, roots) {
+            (Some(i), Some((html, body))) => {
+                let point = points[i];
+                points.truncate(i + 1);
+                truncate_children(&self.document, point.document_children);
+                truncate_children(&html, point.html_children);
+                truncate_children(&body, point.body_children);
+                dom.errors.truncate(point.errors);
+                let head = child_element(&html, local_name!("head"));
+                opts.tree_builder.quirks_mode = self.quirks_mode;
+                (point.offset, resume_in_body(dom, opts, html, head, body))
+            },
+            _ => {
+                points.clear();
+                truncate_children(&self.document, 0);
+                dom.errors.clear();
+                dom.quirks_mode = tree_builder::NoQuirks;
+                (0, parse_document(dom, opts))
+            },
+        };
+        feed_with_restart_points(&mut parser, source, start, points);
+
+        let dom = parser.finish();
+        self.errors = dom.errors;
+        self.quirks_mode = dom.quirks_mode;
+        self.node_ext = dom.node_ext;
+    }
+}
+
+/// Feed `source` from `start` to `parser`, a piece at a time, and add a
+/// restart point wherever the parser allows one.
+fn feed_with_restart_points<T: Default>(
+    parser: &mut Parser<RcDom<T>>,
+    source: &str,
+    start: usize,
+    points: &mut Vec<RestartPoint>,
+) {
+    let bytes = source.as_bytes();
+    let mut fed = start;
+    for i in start + 1..bytes.len() {
+        if bytes[i] != b'<' || !bytes.get(i + 1).map_or(false, u8::is_ascii_alphabetic) {
+            continue;
+        }
+        parser.process(StrTendril::from_slice(&source[fed..i]));
+        fed = i;
+        if parser.at_restart_point() {
+            let dom = &parser.tokenizer.sink.sink;
+            if let Some((html, body)) = html_and_body(&dom.document) {
+                points.push(RestartPoint {
+                    offset: i,
+                    document_children: dom.document.children.borrow().len(),
+                    html_children: html.children.borrow().len(),
+                    body_children: body.children.borrow().len(),
+                    errors: dom.errors.len(),
+                });
+            }
         }
     }
+    parser.process(StrTendril::from_slice(&source[fed..]));
 }
 
-enum SerializeOp {
-    Open(Handle),
+fn child_element<T>(parent: &Handle<T>, local: LocalName) -> Option<Handle<T>> {
+    parent
+        .children
+        .borrow()
+        .iter()
+        .find(|child| match child.data {
+            NodeData::Element { ref name, .. } => name.ns == ns!(html) && name.local == local,
+            _ => false,
+        })
+        .cloned()
+}
+
+fn html_and_body<T>(document: &Handle<T>) -> Option<(Handle<T>, Handle<T>)> {
+    let html = child_element(document, local_name!("html"))?;
+    let body = child_element(&html, local_name!("body"))?;
+    Some((html, body))
+}
+
+/// Remove all but the first `len` children of `parent`.
+fn truncate_children<T>(parent: &Handle<T>, len: usize) {
+    for child in parent.children.borrow_mut().drain(len..) {
+        child.parent.set(None);
+    }
+}
+
+enum SerializeOp<T> {
+    Open(Handle<T>),
     Close(QualName),
 }
 
-pub struct SerializableHandle(Handle);
+/// The node whose children are serialized as the children of `node`: its
+/// template contents for a `<template>`, otherwise the node itself.
+fn children_to_serialize<T>(node: &Handle<T>) -> Handle<T> {
+    match node.data {
+        NodeData::Element {
+            ref template_contents,
+            ..
+        } => template_contents
+            .borrow()
+            .clone()
+            .unwrap_or_else(|| node.clone()),
+        _ => node.clone(),
+    }
+}
+
+pub struct SerializableHandle<T = ()>(Handle<T>);
 
-impl From<Handle> for SerializableHandle {
-    fn from(h: Handle) -> SerializableHandle {
+impl<T> From<Handle<T>> for SerializableHandle<T> {
+    fn from(h: Handle<T>) -> SerializableHandle<T> {
         SerializableHandle(h)
     }
 }
 
-impl Serialize for SerializableHandle {
+impl<T> Serialize for SerializableHandle<T> {
     fn serialize<S>(&self, serializer: &mut S, traversal_scope: TraversalScope) -> io::Result<()>
     where
         S: Serializer,
@@ -457,12 +883,13 @@ impl Serialize for SerializableHandle {
         let mut ops = VecDeque::new();
         match traversal_scope {
             IncludeNode => ops.push_back(SerializeOp::Open(self.0.clone())),
-            ChildrenOnly(_) => ops.extend(self
-                .0
-                .children
-                .borrow()
-                .iter()
-                .map(|h| SerializeOp::Open(h.clone())))
+            ChildrenOnly(_) => ops.extend(
+                children_to_serialize(&self.0)
+                    .children
+                    .borrow()
+                    .iter()
+                    .map(|h| SerializeOp::Open(h.clone())),
+            ),
         }
 
         while let Some(op) = ops.pop_front() {
@@ -478,10 +905,11 @@ impl Serialize for SerializableHandle {
                             attrs.borrow().iter().map(|at| (&at.name, &at.value[..])),
                         )?;
 
-                        ops.reserve(1 + handle.children.borrow().len());
+                        let parent = children_to_serialize(&handle);
+                        ops.reserve(1 + parent.children.borrow().len());
                         ops.push_front(SerializeOp::Close(name.clone()));
 
-                        for child in handle.children.borrow().iter().rev() {
+                        for child in parent.children.borrow().iter().rev() {
                             ops.push_front(SerializeOp::Open(child.clone()));
                         }
                     },
@@ -1,18 +1,43 @@
 use html5ever::driver;
 use html5ever::serialize;
 use html5ever::tendril::TendrilSink;
-use markup5ever_rcdom::{RcDom, SerializableHandle};
+use markup5ever_rcdom::{Handle, NodeData, RcDom, SerializableHandle};
+
+/// Serialize the children of `node`.
+fn serialize_children(node: &Handle) -> String {
+    let mut serialized = Vec::new();
+    let node: SerializableHandle = node.clone().into();
+    serialize::serialize(&mut serialized, &node, Default::default()).unwrap();
+    String::from_utf8(serialized).unwrap()
+}
+
+/// The `<body>` of a parsed document, skipping any doctype before `<html>`.
+fn body(dom: &RcDom) -> Handle {
+    let document = dom.document.children.borrow();
+    let html = document
+        .iter()
+        .find(|node| match node.data {
+            NodeData::Element { .. } => true,
+            _ => false,
+        })
+        .unwrap();
+    let body = html.children.borrow()[1].clone();
+    body
+}
+
+/// Parse `input` as a document and serialize the children of its `<body>`.
+fn parse_body(input: &str, opts: driver::ParseOpts) -> String {
+    let dom = driver::parse_document(RcDom::default(), opts).one(input);
+    serialize_children(&body(&dom))
+}
 
 #[test]
 fn from_utf8() {
     let dom = driver::parse_document(RcDom::default(), Default::default())
         .from_utf8()
         .one("<title>Test".as_bytes());
-    let mut serialized = Vec::new();
-    let document: SerializableHandle = dom.document.clone().into();
-    serialize::serialize(&mut serialized, &document, Default::default()).unwrap();
     assert_eq!(
-        String::from_utf8(serialized).unwrap().replace(" ", ""),
+        serialize_children(&dom.document).replace(" ", ""),
         "<html><head><title>Test</title></head><body></body></html>"
     );
 }
@@ -27,3 +52,1540 @@ fn many_templates() {
         .from_utf8()
         .one(body.as_bytes());
 }
+
+#[test]
+fn insertion_mode() {
+    use html5ever::tendril::SliceExt;
+    use html5ever::tree_builder::InsertionMode;
+
+    let mut parser = driver::parse_document(RcDom::default(), Default::default());
+    assert_eq!(
+        parser.tokenizer.sink.current_insertion_mode(),
+        InsertionMode::Initial
+    );
+    parser.process("<table>".to_tendril());
+    assert_eq!(
+        parser.tokenizer.sink.current_insertion_mode(),
+        InsertionMode::InTable
+    );
+    parser.process("<tr><td>".to_tendril());
+    assert_eq!(
+        parser.tokenizer.sink.current_insertion_mode(),
+        InsertionMode::InCell
+    );
+}
+
+#[test]
+fn last_token_kind() {
+    use html5ever::tendril::SliceExt;
+    use html5ever::tokenizer::TokenKind;
+
+    let mut parser = driver::parse_document(RcDom::default(), Default::default());
+    assert_eq!(parser.tokenizer.sink.last_token_kind(), None);
+    let steps = [
+        ("<!DOCTYPE html>", TokenKind::Doctype),
+        ("<p>", TokenKind::StartTag),
+        ("text", TokenKind::Characters),
+        ("<!-- c -->", TokenKind::Comment),
+        ("</p>", TokenKind::EndTag),
+        // An implied close still leaves the start tag as the last token.
+        ("<p>a<div>", TokenKind::StartTag),
+        ("\0", TokenKind::NullCharacter),
+    ];
+    for &(input, kind) in &steps {
+        parser.process(input.to_tendril());
+        assert_eq!(
+            parser.tokenizer.sink.last_token_kind(),
+            Some(kind),
+            "{}",
+            input
+        );
+    }
+    parser.tokenizer.end();
+    assert_eq!(
+        parser.tokenizer.sink.last_token_kind(),
+        Some(TokenKind::Eof)
+    );
+}
+
+#[test]
+fn max_formatting_elements() {
+    use html5ever::tree_builder::TreeBuilderOpts;
+
+    fn b_depth(dom: &RcDom) -> usize {
+        fn first_element(node: &Handle) -> Option<Handle> {
+            node.children
+                .borrow()
+                .iter()
+                .find(|child| match child.data {
+                    NodeData::Element { .. } => true,
+                    _ => false,
+                })
+                .cloned()
+        }
+        let mut depth = 0;
+        let mut node = body(dom).children.borrow()[1].clone();
+        loop {
+            depth += 1;
+            let next = first_element(&node);
+            match next {
+                Some(child) => node = child,
+                None => return depth,
+            }
+        }
+    }
+
+    let mut input = String::from("<p>");
+    for i in 0..1000 {
+        input.push_str(&format!("<b x={}>", i));
+    }
+    input.push_str("</p>text");
+
+    let opts = driver::ParseOpts {
+        tree_builder: TreeBuilderOpts {
+            max_formatting_elements: Some(10),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let dom = driver::parse_document(RcDom::default(), opts).one(&*input);
+    assert_eq!(b_depth(&dom), 10);
+    assert!(dom
+        .errors
+        .iter()
+        .any(|e| e == "Too many active formatting elements"));
+
+    let dom = driver::parse_document(RcDom::default(), Default::default()).one(&*input);
+    assert_eq!(b_depth(&dom), 1000);
+
+    // Entries before the last marker don't count, so the <b> and <i> from
+    // outside the table are still reopened after it.
+    let input = "<p><b>1<i>2</p><table><tr><td><u>3<s>4<em>5</td></tr></table>6";
+    let opts = driver::ParseOpts {
+        tree_builder: TreeBuilderOpts {
+            max_formatting_elements: Some(2),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert_eq!(
+        parse_body(input, opts),
+        "<p><b>1<i>2</i></b></p><table><tbody><tr><td><u>3<s>4<em>5</em></s></u></td></tr>\
+         </tbody></table><b><i>6</i></b>"
+    );
+
+    // With no room at all, nothing is reopened, and a misnested end tag
+    // is just an end tag.
+    let opts = driver::ParseOpts {
+        tree_builder: TreeBuilderOpts {
+            max_formatting_elements: Some(0),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert_eq!(
+        parse_body("<p><b>1</p>2<i>3<p>4</i>5", opts),
+        "<p><b>1</b></p>2<i>3<p>45</p></i>"
+    );
+}
+
+#[test]
+fn check_document() {
+    use html5ever::conformance::{check_document, ConformanceError};
+    use html5ever::tree_builder::QuirksMode;
+
+    let report = check_document(b"<!DOCTYPE html><title>ok</title>");
+    assert!(report.is_conforming());
+    assert_eq!(report.quirks_mode, QuirksMode::NoQuirks);
+
+    let report = check_document(b"<p>no doctype");
+    assert_eq!(report.quirks_mode, QuirksMode::Quirks);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].line, 1);
+
+    let report = check_document(b"<!DOCTYPE html>\n<b><i>\n</b></i>\n<a x=1 x=2>");
+    let errors: Vec<(u64, &str)> = report
+        .errors
+        .iter()
+        .map(|ConformanceError { line, message }| (*line, &**message))
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            (3, "Formatting element not current node"),
+            (3, "Formatting element not open"),
+            (4, "Duplicate attribute"),
+            (
+                4,
+                "Unexpected open tag {http://www.w3.org/1999/xhtml}:a at end of body"
+            ),
+        ]
+    );
+}
+
+#[test]
+fn node_ext() {
+    use markup5ever_rcdom::Handle;
+
+    let mut count = 0;
+    let dom = driver::parse_document(
+        RcDom::with_node_ext(move |name, _| {
+            count += 1;
+            format!("{}{}", name.local, count)
+        }),
+        Default::default(),
+    )
+    .one("<b>1<p>2</b>3");
+
+    fn collect(node: &Handle<String>, out: &mut Vec<String>) {
+        if !node.ext.is_empty() {
+            out.push(node.ext.clone());
+        }
+        for child in node.children.borrow().iter() {
+            collect(child, out);
+        }
+    }
+    let mut exts = vec![];
+    collect(&dom.document, &mut exts);
+    // The adoption agency creates a second <b> inside the <p>.
+    assert_eq!(exts, vec!["html1", "head2", "body3", "b4", "p5", "b6"]);
+}
+
+#[test]
+fn disable_adoption_agency() {
+    use html5ever::tree_builder::TreeBuilderOpts;
+
+    fn parse(input: &str, disable_adoption_agency: bool) -> String {
+        let opts = driver::ParseOpts {
+            tree_builder: TreeBuilderOpts {
+                disable_adoption_agency,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let dom = driver::parse_document(RcDom::default(), opts).one(input);
+        serialize_children(&dom.document)
+    }
+
+    let input = "<b>1<p>2</b>3";
+    assert_eq!(
+        parse(input, false),
+        "<html><head></head><body><b>1</b><p><b>2</b>3</p></body></html>"
+    );
+    assert_eq!(
+        parse(input, true),
+        "<html><head></head><body><b>1<p>2</p></b>3</body></html>"
+    );
+
+    let input = "<b><i>1</b>2</i>";
+    assert_eq!(parse(input, false), parse(input, true));
+
+    // A formatting element that was already closed is forgotten, so it is
+    // not reopened for the text after it.
+    let input = "<p><b>1</p></b>3";
+    assert_eq!(
+        parse(input, true),
+        "<html><head></head><body><p><b>1</b></p>3</body></html>"
+    );
+    assert_eq!(parse(input, false), parse(input, true));
+}
+
+#[test]
+fn current_form_element() {
+    use html5ever::tendril::SliceExt;
+    use markup5ever_rcdom::NodeData;
+
+    let mut parser = driver::parse_document(RcDom::default(), Default::default());
+    parser.process("<form id=f><div><input>".to_tendril());
+    match parser.tokenizer.sink.current_form_element() {
+        Some(form) => match form.data {
+            NodeData::Element { ref name, .. } => assert_eq!(&*name.local, "form"),
+            _ => panic!("form element pointer is not an element"),
+        },
+        None => panic!("form element pointer not set"),
+    }
+    parser.process("</div></form><input>".to_tendril());
+    assert!(parser.tokenizer.sink.current_form_element().is_none());
+}
+
+#[test]
+fn current_namespace() {
+    use html5ever::tendril::SliceExt;
+    use html5ever::{namespace_url, ns};
+
+    let mut parser = driver::parse_document(RcDom::default(), Default::default());
+    assert_eq!(parser.tokenizer.sink.current_namespace(), ns!(html));
+    parser.process("<p>".to_tendril());
+    assert_eq!(parser.tokenizer.sink.current_namespace(), ns!(html));
+    parser.process("<svg><g>".to_tendril());
+    assert_eq!(parser.tokenizer.sink.current_namespace(), ns!(svg));
+    parser.process("<foreignObject><div>".to_tendril());
+    assert_eq!(parser.tokenizer.sink.current_namespace(), ns!(html));
+    parser.process("</div></foreignObject></g>".to_tendril());
+    assert_eq!(parser.tokenizer.sink.current_namespace(), ns!(svg));
+    parser.process("</svg>".to_tendril());
+    assert_eq!(parser.tokenizer.sink.current_namespace(), ns!(html));
+}
+
+#[test]
+fn has_element_in_scope() {
+    use html5ever::tendril::SliceExt;
+    use html5ever::tree_builder::Scope;
+    use html5ever::{local_name, namespace_url, ns, QualName};
+
+    let html = |local| QualName::new(None, ns!(html), local);
+    let in_scope = |input: &str, local, scope| {
+        let mut parser = driver::parse_document(RcDom::default(), Default::default());
+        parser.process(input.to_tendril());
+        parser
+            .tokenizer
+            .sink
+            .has_element_in_scope(&html(local), scope)
+    };
+
+    assert!(in_scope("<p>", local_name!("p"), Scope::Default));
+    assert!(!in_scope(
+        "<p><table><caption>",
+        local_name!("p"),
+        Scope::Default
+    ));
+    assert!(!in_scope(
+        "<p><svg><desc>",
+        local_name!("p"),
+        Scope::Default
+    ));
+    assert!(!in_scope("<p>", local_name!("div"), Scope::Default));
+
+    assert!(in_scope("<ul><li><p>", local_name!("li"), Scope::ListItem));
+    assert!(!in_scope("<li><ul><p>", local_name!("li"), Scope::ListItem));
+    assert!(in_scope("<li><ul><p>", local_name!("li"), Scope::Default));
+
+    assert!(in_scope("<p><span>", local_name!("p"), Scope::Button));
+    assert!(!in_scope(
+        "<p><button><span>",
+        local_name!("p"),
+        Scope::Button
+    ));
+    assert!(in_scope(
+        "<p><button><span>",
+        local_name!("p"),
+        Scope::Default
+    ));
+
+    assert!(in_scope(
+        "<table><tr><td><div>",
+        local_name!("tr"),
+        Scope::Table
+    ));
+    assert!(!in_scope(
+        "<table><tr><td><div>",
+        local_name!("tr"),
+        Scope::Default
+    ));
+    assert!(!in_scope(
+        "<table><tr><td><table>",
+        local_name!("tr"),
+        Scope::Table
+    ));
+
+    assert!(in_scope(
+        "<select><optgroup><option>",
+        local_name!("select"),
+        Scope::Select
+    ));
+    assert!(!in_scope(
+        "<div><select><option>",
+        local_name!("div"),
+        Scope::Select
+    ));
+    assert!(in_scope(
+        "<div><select><option>",
+        local_name!("div"),
+        Scope::Default
+    ));
+
+    // The namespace has to match too.
+    let mut parser = driver::parse_document(RcDom::default(), Default::default());
+    parser.process("<svg><title>".to_tendril());
+    let sink = &parser.tokenizer.sink;
+    assert!(sink.has_element_in_scope(
+        &QualName::new(None, ns!(svg), local_name!("svg")),
+        Scope::Table
+    ));
+    assert!(!sink.has_element_in_scope(&html(local_name!("title")), Scope::Table));
+}
+
+#[test]
+fn pi_as_processing_instruction() {
+    let opts = || {
+        driver::ParseOpts::builder()
+            .pi_as_processing_instruction(true)
+            .build()
+    };
+    let dom = driver::parse_document(RcDom::default(), opts()).one("<?php echo 1 ?><p>");
+    let document = dom.document.children.borrow();
+    match document[0].data {
+        NodeData::ProcessingInstruction {
+            ref target,
+            ref contents,
+        } => {
+            assert_eq!(&**target, "php");
+            assert_eq!(&**contents, "echo 1 ");
+        },
+        _ => panic!("expected a processing instruction"),
+    }
+
+    // In the body, it goes where a comment would.
+    assert_eq!(
+        parse_body("<p>a<?x y?>b<table><?z?><tr>", opts()),
+        "<p>a<?x y>b<table><?z ><tbody><tr></tr></tbody></table></p>"
+    );
+
+    let dom = driver::parse_document(RcDom::default(), Default::default()).one("<?php echo 1 ?>");
+    let document = dom.document.children.borrow();
+    match document[0].data {
+        NodeData::Comment { ref contents } => assert_eq!(&**contents, "?php echo 1 ?"),
+        _ => panic!("expected a comment"),
+    }
+}
+
+#[test]
+fn token_transform() {
+    use html5ever::tendril::StrTendril;
+    use html5ever::tokenizer::{CharacterTokens, EndTag, StartTag, Tag, TagToken};
+    use html5ever::tokenizer::{Token, TokenTransform};
+    use html5ever::LocalName;
+
+    struct Include;
+
+    impl TokenTransform for Include {
+        fn transform(&mut self, token: Token, output: &mut Vec<Token>) {
+            let tag = |kind, name: &str| {
+                TagToken(Tag {
+                    kind,
+                    name: LocalName::from(name),
+                    self_closing: false,
+                    attrs: Default::default(),
+                })
+            };
+            match token {
+                TagToken(ref t) if &*t.name == "my-include" => {
+                    if t.kind == StartTag {
+                        output.push(tag(StartTag, "p"));
+                        output.push(CharacterTokens(StrTendril::from("included")));
+                        output.push(tag(EndTag, "p"));
+                    }
+                },
+                token => output.push(token),
+            }
+        }
+    }
+
+    let dom = driver::parse_document_with_transform(RcDom::default(), Default::default(), Include)
+        .one("<div><my-include></my-include></div>");
+    assert_eq!(
+        serialize_children(&dom.document),
+        "<html><head></head><body><div><p>included</p></div></body></html>"
+    );
+}
+
+#[test]
+fn utf8_bom() {
+    let expected = "<html><head></head><body></body></html>";
+
+    let dom = driver::parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .one("\u{FEFF}<html>".as_bytes());
+    assert_eq!(serialize_children(&dom.document), expected);
+
+    let mut parser = driver::parse_document(RcDom::default(), Default::default()).from_utf8();
+    parser.process(b"\xEF\xBB"[..].into());
+    parser.process(b"\xBF<html>"[..].into());
+    assert_eq!(serialize_children(&parser.finish().document), expected);
+
+    // Only a leading BOM is discarded.
+    let mut parser = driver::parse_document(RcDom::default(), Default::default()).from_utf8();
+    parser.process(b"<p>"[..].into());
+    parser.process("\u{FEFF}x".as_bytes().into());
+    assert_eq!(
+        serialize_children(&parser.finish().document),
+        "<html><head></head><body><p>\u{FEFF}x</p></body></html>"
+    );
+}
+
+#[test]
+fn read_from_chunked_reader() {
+    use std::io::{self, Read};
+
+    /// Returns a few bytes per read, like a decompressor.
+    struct Chunked<'a>(&'a [u8]);
+
+    impl<'a> Read for Chunked<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(3);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    let input = "<title>caf\u{e9}</title><p>\u{2603}\u{1F600}";
+    let dom = driver::parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .read_from(&mut Chunked(input.as_bytes()))
+        .unwrap();
+    assert_eq!(
+        serialize_children(&dom.document),
+        "<html><head><title>caf\u{e9}</title></head><body><p>\u{2603}\u{1F600}</p></body></html>"
+    );
+}
+
+#[test]
+fn table_comment_positions() {
+    // Comments in table insertion modes are inserted at the current node,
+    // unlike text, which is foster parented.
+    let dom = driver::parse_document(RcDom::default(), Default::default())
+        .one("<table><!--x--><tr><!--y--><td>a</td><!--z-->b</tr></table>");
+    assert_eq!(
+        serialize_children(&dom.document),
+        "<html><head></head><body>b<table><!--x--><tbody><tr><!--y--><td>a</td><!--z--></tr>\
+         </tbody></table></body></html>"
+    );
+}
+
+#[test]
+fn element_categories() {
+    use html5ever::tree_builder::{is_formatting_element, is_scope_terminator, is_special_element};
+    use html5ever::{namespace_url, ns, LocalName, Namespace, QualName};
+
+    fn name(ns: Namespace, local: &str) -> QualName {
+        QualName::new(None, ns, LocalName::from(local))
+    }
+
+    assert!(is_special_element(&name(ns!(html), "address")));
+    assert!(is_special_element(&name(ns!(html), "table")));
+    assert!(is_special_element(&name(ns!(mathml), "mtext")));
+    assert!(is_special_element(&name(ns!(mathml), "annotation-xml")));
+    assert!(is_special_element(&name(ns!(svg), "foreignObject")));
+    assert!(!is_special_element(&name(ns!(html), "span")));
+    assert!(!is_special_element(&name(ns!(html), "b")));
+    assert!(!is_special_element(&name(ns!(svg), "table")));
+
+    assert!(is_scope_terminator(&name(ns!(html), "td")));
+    assert!(is_scope_terminator(&name(ns!(html), "template")));
+    assert!(is_scope_terminator(&name(ns!(mathml), "annotation-xml")));
+    assert!(is_scope_terminator(&name(ns!(svg), "desc")));
+    assert!(!is_scope_terminator(&name(ns!(html), "div")));
+    assert!(!is_scope_terminator(&name(ns!(svg), "svg")));
+
+    assert!(is_formatting_element(&name(ns!(html), "a")));
+    assert!(is_formatting_element(&name(ns!(html), "nobr")));
+    assert!(is_formatting_element(&name(ns!(html), "strong")));
+    assert!(!is_formatting_element(&name(ns!(html), "span")));
+    assert!(!is_formatting_element(&name(ns!(svg), "a")));
+}
+
+#[test]
+fn integration_points() {
+    use html5ever::tree_builder::{is_html_integration_point, is_mathml_text_integration_point};
+    use html5ever::{local_name, namespace_url, ns, Attribute, LocalName, Namespace, QualName};
+
+    fn name(ns: Namespace, local: &str) -> QualName {
+        QualName::new(None, ns, LocalName::from(local))
+    }
+
+    fn encoding(value: &str) -> Vec<Attribute> {
+        vec![Attribute {
+            name: QualName::new(None, ns!(), local_name!("encoding")),
+            value: value.into(),
+        }]
+    }
+
+    assert!(is_html_integration_point(
+        &name(ns!(svg), "foreignObject"),
+        &[]
+    ));
+    assert!(is_html_integration_point(&name(ns!(svg), "desc"), &[]));
+    assert!(is_html_integration_point(&name(ns!(svg), "title"), &[]));
+    assert!(!is_html_integration_point(
+        &name(ns!(svg), "foreignobject"),
+        &[]
+    ));
+    assert!(!is_html_integration_point(&name(ns!(svg), "rect"), &[]));
+    assert!(!is_html_integration_point(&name(ns!(html), "title"), &[]));
+
+    let annotation_xml = name(ns!(mathml), "annotation-xml");
+    assert!(is_html_integration_point(
+        &annotation_xml,
+        &encoding("text/html")
+    ));
+    assert!(is_html_integration_point(
+        &annotation_xml,
+        &encoding("TEXT/HTML")
+    ));
+    assert!(is_html_integration_point(
+        &annotation_xml,
+        &encoding("application/xhtml+xml")
+    ));
+    assert!(!is_html_integration_point(
+        &annotation_xml,
+        &encoding("text/html ")
+    ));
+    assert!(!is_html_integration_point(
+        &annotation_xml,
+        &encoding("image/svg+xml")
+    ));
+    assert!(!is_html_integration_point(&annotation_xml, &[]));
+    let mut xlink_encoding = encoding("text/html");
+    xlink_encoding[0].name.ns = ns!(xlink);
+    assert!(!is_html_integration_point(&annotation_xml, &xlink_encoding));
+
+    for local in &["mi", "mo", "mn", "ms", "mtext"] {
+        assert!(is_mathml_text_integration_point(&name(ns!(mathml), local)));
+        assert!(!is_html_integration_point(&name(ns!(mathml), local), &[]));
+    }
+    assert!(!is_mathml_text_integration_point(&name(
+        ns!(mathml),
+        "mrow"
+    )));
+    assert!(!is_mathml_text_integration_point(&annotation_xml));
+    assert!(!is_mathml_text_integration_point(&name(ns!(html), "mi")));
+    assert!(!is_mathml_text_integration_point(&name(ns!(svg), "desc")));
+}
+
+#[test]
+fn push_char() {
+    let input = "<!DOCTYPE html><p class=a>one <b>two</b><!-- c --> three";
+    let mut parser = driver::parse_document(RcDom::default(), Default::default());
+    for c in input.chars() {
+        parser.push_char(c);
+    }
+    let dom = parser.finish();
+    assert_eq!(
+        serialize_children(&dom.document),
+        "<!DOCTYPE html><html><head></head><body><p class=\"a\">one <b>two</b><!-- c --> three</p>\
+         </body></html>"
+    );
+}
+
+#[test]
+fn on_step() {
+    use html5ever::tokenizer::TagToken;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let steps = Rc::new(RefCell::new(vec![]));
+    let mut parser = driver::parse_document(RcDom::default(), Default::default());
+    let recorded = steps.clone();
+    parser.set_on_step(Some(Box::new(move |step| {
+        if let TagToken(ref tag) = *step.token {
+            let open: Vec<String> = step
+                .open_elements
+                .iter()
+                .map(|name| name.local.to_string())
+                .collect();
+            let step = format!("{} {:?}: {}", tag.name, step.mode, open.join(" "));
+            recorded.borrow_mut().push(step);
+        }
+    })));
+    parser.one("<title>x</title><p>");
+
+    assert_eq!(
+        *steps.borrow(),
+        vec![
+            "title Text: html head title",
+            "title InHead: html head",
+            "p InBody: html body p",
+        ]
+    );
+}
+
+#[test]
+fn parse_head_only() {
+    fn head_only(input: &str, opts: driver::ParseOpts) -> String {
+        let dom = driver::parse_head_only(RcDom::default(), opts).one(input);
+        serialize_children(&dom.document)
+    }
+
+    assert_eq!(
+        head_only(
+            "<!DOCTYPE html><html lang=en><head><title>T</title></head><!--c--> \
+             <meta name=a><body><p>never</p>",
+            Default::default()
+        ),
+        "<!DOCTYPE html><html lang=\"en\"><head><title>T</title><meta name=\"a\"></head>\
+         <!--c--> </html>"
+    );
+    assert_eq!(
+        head_only("<title>T</title>text", Default::default()),
+        "<html><head><title>T</title></head></html>"
+    );
+    assert_eq!(
+        head_only("<frameset><frame>", Default::default()),
+        "<html><head></head></html>"
+    );
+
+    let no_scripting = driver::ParseOpts::builder()
+        .scripting_enabled(false)
+        .build();
+    assert_eq!(
+        head_only(
+            "<noscript><link rel=a></noscript><meta name=b><p>x",
+            no_scripting
+        ),
+        "<html><head><noscript><link rel=\"a\"></noscript><meta name=\"b\"></head></html>"
+    );
+}
+
+#[test]
+fn max_input_bytes() {
+    fn parse_limited(chunks: &[&str], max: u64) -> (String, usize) {
+        let opts = driver::ParseOpts::builder()
+            .max_input_bytes(Some(max))
+            .build();
+        let mut parser = driver::parse_document(RcDom::default(), opts);
+        for chunk in chunks {
+            parser.process((*chunk).into());
+        }
+        let dom = parser.finish();
+        let errors = dom
+            .errors
+            .iter()
+            .filter(|e| *e == "Input exceeds the maximum size")
+            .count();
+        (serialize_children(&body(&dom)), errors)
+    }
+
+    // 13 bytes, fed in three chunks.
+    let chunks = ["<p>a", "bc</p", "><i>"];
+    assert_eq!(parse_limited(&chunks, 13), ("<p>abc</p><i></i>".into(), 0));
+    assert_eq!(parse_limited(&chunks, 12), ("<p>abc</p>".into(), 1));
+    assert_eq!(parse_limited(&chunks, 5), ("<p>ab</p>".into(), 1));
+
+    // A character that does not fit completely is dropped.
+    assert_eq!(parse_limited(&["<p>aé"], 5), ("<p>a</p>".into(), 1));
+}
+
+#[test]
+fn preserve_foreign_case() {
+    let parse = |input, preserve| {
+        let opts = driver::ParseOpts::builder()
+            .preserve_foreign_case(preserve)
+            .build();
+        parse_body(input, opts)
+    };
+
+    let input = "<svg><myCustomElement myAttr=1 viewbox=x><ClipPath/><RECT/>\
+                 </mycustomelement></svg><DIV ID=a></DIV>";
+    assert_eq!(
+        parse(input, false),
+        "<svg><mycustomelement myattr=\"1\" viewBox=\"x\"><clipPath></clipPath><rect></rect>\
+         </mycustomelement></svg><div id=\"a\"></div>"
+    );
+    assert_eq!(
+        parse(input, true),
+        "<svg><myCustomElement myAttr=\"1\" viewBox=\"x\"><clipPath></clipPath><RECT></RECT>\
+         </myCustomElement></svg><div id=\"a\"></div>"
+    );
+
+    // HTML breakout elements still leave foreign content.
+    assert_eq!(
+        parse("<math><myToken><P>x", true),
+        "<math><myToken></myToken></math><p>x</p>"
+    );
+
+    // Raw text in HTML elements ends at a lowercase end tag.
+    assert_eq!(
+        parse(
+            "<body><TITLE>a</title><Script>b</script><TEXTAREA>c</textarea><p>d",
+            true
+        ),
+        "<title>a</title><script>b</script><textarea>c</textarea><p>d</p>"
+    );
+    assert_eq!(
+        parse(
+            "<svg><desc><TEXTAREA>a</textarea></desc><myShape/></svg><p>b",
+            true
+        ),
+        "<svg><desc><textarea>a</textarea></desc><myShape></myShape></svg><p>b</p>"
+    );
+}
+
+#[test]
+fn null_handling() {
+    use html5ever::tokenizer::NullHandling;
+
+    let parse = |input, null_handling| {
+        let opts = driver::ParseOpts::builder()
+            .null_handling(null_handling)
+            .build();
+        parse_body(input, opts)
+    };
+
+    let body = "<p>a\0b</p><textarea>c\0d</textarea><i title=\"e\0f\"></i>";
+    assert_eq!(
+        parse(body, NullHandling::Spec),
+        "<p>ab</p><textarea>c\u{fffd}d</textarea><i title=\"e\u{fffd}f\"></i>"
+    );
+    assert_eq!(
+        parse(body, NullHandling::Replace),
+        "<p>a\u{fffd}b</p><textarea>c\u{fffd}d</textarea><i title=\"e\u{fffd}f\"></i>"
+    );
+    assert_eq!(
+        parse(body, NullHandling::Drop),
+        "<p>ab</p><textarea>cd</textarea><i title=\"ef\"></i>"
+    );
+
+    // The null is dropped in table text by the spec, and foster parented
+    // when replaced.
+    let table = "<table>\0<tr><td>g\0h</td></tr></table>";
+    assert_eq!(
+        parse(table, NullHandling::Spec),
+        "<table><tbody><tr><td>gh</td></tr></tbody></table>"
+    );
+    assert_eq!(
+        parse(table, NullHandling::Replace),
+        "\u{fffd}<table><tbody><tr><td>g\u{fffd}h</td></tr></tbody></table>"
+    );
+    assert_eq!(
+        parse(table, NullHandling::Drop),
+        "<table><tbody><tr><td>gh</td></tr></tbody></table>"
+    );
+
+    let foreign = "<svg><text>i\0j</text></svg>";
+    assert_eq!(
+        parse(foreign, NullHandling::Spec),
+        "<svg><text>i\u{fffd}j</text></svg>"
+    );
+    assert_eq!(
+        parse(foreign, NullHandling::Replace),
+        "<svg><text>i\u{fffd}j</text></svg>"
+    );
+    assert_eq!(
+        parse(foreign, NullHandling::Drop),
+        "<svg><text>ij</text></svg>"
+    );
+}
+
+#[test]
+fn foreign_fragments() {
+    use html5ever::{namespace_url, ns, Namespace};
+    use markup5ever_rcdom::NodeData;
+
+    fn elements(dom: RcDom) -> Vec<(Namespace, String)> {
+        // Skip the document and the `<html>` root of the fragment.
+        dom.document
+            .traverse()
+            .skip(2)
+            .filter_map(|node| match node.data {
+                NodeData::Element { ref name, .. } => {
+                    Some((name.ns.clone(), name.local.to_string()))
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    let dom = driver::parse_svg_fragment(RcDom::default(), Default::default())
+        .one("<rect/><clippath><foreignObject><div><p>x</div></foreignObject></clippath><p>");
+    assert_eq!(
+        elements(dom),
+        vec![
+            (ns!(svg), "rect".to_string()),
+            (ns!(svg), "clipPath".to_string()),
+            (ns!(svg), "foreignObject".to_string()),
+            (ns!(html), "div".to_string()),
+            (ns!(html), "p".to_string()),
+            (ns!(svg), "p".to_string()),
+        ]
+    );
+
+    let dom = driver::parse_mathml_fragment(RcDom::default(), Default::default())
+        .one("<mrow><mi>x<b>y</b></mi><mo>+</mo></mrow><p>z");
+    assert_eq!(
+        elements(dom),
+        vec![
+            (ns!(mathml), "mrow".to_string()),
+            (ns!(mathml), "mi".to_string()),
+            (ns!(html), "b".to_string()),
+            (ns!(mathml), "mo".to_string()),
+            // There's no HTML element to break out to.
+            (ns!(mathml), "p".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn sort_attributes() {
+    let parse = |input, sort| {
+        let opts = driver::ParseOpts::builder().sort_attributes(sort).build();
+        parse_body(input, opts)
+    };
+
+    assert_eq!(parse("<a z=1 a=2>", false), "<a z=\"1\" a=\"2\"></a>");
+    assert_eq!(parse("<a z=1 a=2>", true), "<a a=\"2\" z=\"1\"></a>");
+
+    // Attributes without a namespace come first.
+    assert_eq!(
+        parse("<svg xlink:href=x viewbox=y b=z></svg>", true),
+        "<svg b=\"z\" viewBox=\"y\" xlink:href=\"x\"></svg>"
+    );
+
+    // Duplicates are still dropped after the first.
+    assert_eq!(parse("<p c=1 b=2 c=3>", true), "<p b=\"2\" c=\"1\"></p>");
+}
+
+#[test]
+fn honor_self_closing() {
+    fn parse(input: &str, honor: bool) -> (String, Vec<String>) {
+        let opts = driver::ParseOpts::builder()
+            .honor_self_closing(honor)
+            .build();
+        let input = format!("<!DOCTYPE html>{}", input);
+        let dom = driver::parse_document(RcDom::default(), opts).one(input);
+        let errors = dom.errors.iter().map(|e| e.to_string()).collect();
+        (serialize_children(&body(&dom)), errors)
+    }
+
+    let (body, errors) = parse("<div/>text", false);
+    assert_eq!(body, "<div>text</div>");
+    assert!(errors.contains(&"Unacknowledged self-closing tag".to_string()));
+
+    let (body, errors) = parse("<div/>text", true);
+    assert_eq!(body, "<div></div>text");
+    assert_eq!(errors, ["Self-closing tag closed a non-void element"]);
+
+    // Formatting elements are not reopened, and a self-closing <p> closes
+    // the open one before it closes itself.
+    assert_eq!(parse("<b/>x<p>y<p/>z", true).0, "<b></b>x<p>y</p><p></p>z");
+
+    // Void elements and elements in foreign content are unchanged.
+    assert_eq!(
+        parse("<br/><svg><g/>x</svg>", true),
+        ("<br><svg><g></g>x</svg>".to_string(), vec![])
+    );
+}
+
+#[test]
+fn allowed_url_schemes() {
+    fn parse(input: &str) -> (String, usize) {
+        let opts = driver::ParseOpts::builder()
+            .allowed_url_schemes(Some(&["http", "HTTPS", "mailto"]))
+            .build();
+        let dom = driver::parse_document(RcDom::default(), opts).one(input);
+        let errors = dom
+            .errors
+            .iter()
+            .filter(|e| *e == "URL with a disallowed scheme")
+            .count();
+        (serialize_children(&body(&dom)), errors)
+    }
+
+    for payload in &[
+        "javascript:alert(1)",
+        "JaVaScRiPt:alert(1)",
+        " javascript:alert(1)",
+        "java\tscript:alert(1)",
+        "java\nscript:alert(1)",
+        "jav&#x09;ascript:alert(1)",
+        "&#x20;javascript:alert(1)",
+        "\x01javascript:alert(1)",
+        "javascript&colon;alert(1)",
+        "vbscript:msgbox(1)",
+        "data:text/html,<script>alert(1)</script>",
+    ] {
+        let input = format!("<a href=\"{}\" title=x>link</a>", payload);
+        assert_eq!(
+            parse(&input),
+            ("<a title=\"x\">link</a>".to_string(), 1),
+            "{:?}",
+            payload
+        );
+    }
+
+    assert_eq!(
+        parse(
+            "<form action='javascript:x'><button formaction=vbscript:y>b</button></form>\
+             <img src='data:image/png,z'><svg><a xlink:href='javascript:w'/></svg>"
+        ),
+        (
+            "<form><button>b</button></form><img><svg><a></a></svg>".to_string(),
+            4
+        )
+    );
+    assert_eq!(
+        parse(
+            "<a href='https://example.com/'>a</a><a href='/path:x'>b</a>\
+             <a href='mailto:a@example.com'>c</a><a title='javascript:t'>d</a>"
+        ),
+        (
+            "<a href=\"https://example.com/\">a</a><a href=\"/path:x\">b</a>\
+             <a href=\"mailto:a@example.com\">c</a><a title=\"javascript:t\">d</a>"
+                .to_string(),
+            0
+        )
+    );
+}
+
+#[test]
+fn reparse_from() {
+    use std::rc::Rc;
+
+    fn check(dom: &RcDom, source: &str) {
+        let full = driver::parse_document(RcDom::default(), Default::default()).one(source);
+        assert_eq!(
+            serialize_children(&dom.document),
+            serialize_children(&full.document)
+        );
+        assert_eq!(dom.errors, full.errors);
+        assert_eq!(dom.quirks_mode, full.quirks_mode);
+    }
+
+    let mut source =
+        "<!DOCTYPE html><title>t</title><p>one</p><div><b>two</b></div><ul><li>three</ul><p>four"
+            .to_string();
+    let (mut dom, mut points) =
+        RcDom::default().parse_with_restart_points(&source, Default::default());
+    check(&dom, &source);
+    let offsets: Vec<usize> = points.iter().map(|p| p.offset).collect();
+    assert_eq!(
+        offsets,
+        [
+            source.find("<div>").unwrap(),
+            source.find("<ul>").unwrap(),
+            source.rfind("<p>").unwrap(),
+        ]
+    );
+
+    // The first paragraph, parsed before any restart point.
+    let first_in_body = |dom: &RcDom| {
+        let first = body(dom).children.borrow()[0].clone();
+        first
+    };
+    let first = first_in_body(&dom);
+
+    let edits = [
+        ("three", "3<b>x"),
+        ("<p>four", "<table><tr><td>4<p>four"),
+        ("<ul>", ""),
+        ("<!DOCTYPE html>", ""),
+    ];
+    for &(from, to) in edits.iter() {
+        let new = source.replacen(from, to, 1);
+        let change_offset = source
+            .bytes()
+            .zip(new.bytes())
+            .take_while(|&(a, b)| a == b)
+            .count();
+        let restarts = points.first().map_or(false, |p| p.offset <= change_offset);
+        source = new;
+        dom.reparse_from(&mut points, &source, change_offset, Default::default());
+        check(&dom, &source);
+        assert_eq!(Rc::ptr_eq(&first, &first_in_body(&dom)), restarts);
+        assert!(points.iter().all(|p| source[p.offset..].starts_with('<')));
+    }
+}
+
+#[test]
+fn comment_filter() {
+    // The prefix could come from the command line.
+    let prefix = String::from("build:");
+    let mut parser = driver::parse_document(RcDom::default(), Default::default());
+    parser.set_comment_filter(Some(Box::new(move |text| {
+        text.trim_start().starts_with(&*prefix)
+    })));
+    let dom = parser.one(
+        "<!-- a --><!DOCTYPE html><!--build:css-->\
+         <p>x<!-- build:include  a.html -->y<!--\n-->z</p>\
+         <table><!-- b --><tr><td></table><!---->",
+    );
+    assert_eq!(
+        dom.serialize(),
+        "<!DOCTYPE html><!--build:css--><html><head></head><body>\
+         <p>x<!-- build:include  a.html -->yz</p>\
+         <table><tbody><tr><td></td></tr></tbody></table></body></html>"
+    );
+}
+
+#[test]
+fn normalize_unicode() {
+    use html5ever::tree_builder::NormalizationForm;
+
+    let parse = |form, input: &[&str]| {
+        let opts = driver::ParseOpts::builder().normalize_unicode(form).build();
+        driver::parse_document(RcDom::default(), opts)
+            .from_iter(input.iter().cloned())
+            .serialize()
+    };
+    // Each chunk after the first continues a run of text with characters
+    // that combine with the end of the chunk before it.
+    let input = [
+        "<title>Cafe",
+        "\u{301}</title><p title=e\u{301}>cafe&#x301;<!--e\u{301}--><table>e",
+        "\u{301}</table>\u{1100}",
+        "\u{1161}",
+    ];
+    assert_eq!(
+        parse(Some(NormalizationForm::Nfc), &input),
+        "<html><head><title>Caf\u{e9}</title></head><body>\
+         <p title=\"e\u{301}\">caf\u{e9}<!--\u{e9}-->\u{e9}<table></table>\u{ac00}</p>\
+         </body></html>"
+    );
+    assert_eq!(
+        parse(None, &input),
+        "<html><head><title>Cafe\u{301}</title></head><body>\
+         <p title=\"e\u{301}\">cafe\u{301}<!--e\u{301}-->e\u{301}<table></table>\u{1100}\u{1161}</p>\
+         </body></html>"
+    );
+    assert_eq!(
+        parse(Some(NormalizationForm::Nfd), &["<p>caf\u{e9}", "\u{ac00}"]),
+        "<html><head></head><body><p>cafe\u{301}\u{1100}\u{1161}</p></body></html>"
+    );
+}
+
+#[test]
+fn normalize_newlines() {
+    let body = |normalize_newlines, input: &str| {
+        let opts = driver::ParseOpts::builder()
+            .normalize_newlines(normalize_newlines)
+            .build();
+        let dom = driver::parse_document(RcDom::default(), opts).one(input);
+        let html = dom.document.children.borrow()[0].clone();
+        let body = html.children.borrow()[1].clone();
+        body.serialize()
+    };
+
+    let input = "<pre>\r\na\r\n</pre><div\rid=x>b\rc</div><pre>\r</pre>";
+    assert_eq!(
+        body(true, input),
+        "<body><pre>a\n</pre><div id=\"x\">b\nc</div><pre></pre></body>"
+    );
+    assert_eq!(
+        body(false, input),
+        "<body><pre>a\r\n</pre><div id=\"x\">b\rc</div><pre></pre></body>"
+    );
+}
+
+#[test]
+fn foreign_attribute_prefixes() {
+    use html5ever::{local_name, namespace_prefix, namespace_url, ns};
+    use markup5ever_rcdom::NodeData;
+
+    let dom = driver::parse_document(RcDom::default(), Default::default()).one(
+        "<svg xmlns:xlink='http://www.w3.org/1999/xlink'><use xlink:href='#x' xml:lang='en'/>",
+    );
+    let names: Vec<_> = dom
+        .document
+        .traverse()
+        .filter_map(|node| match node.data {
+            NodeData::Element { ref attrs, .. } => Some(
+                attrs
+                    .borrow()
+                    .iter()
+                    .map(|attr| (attr.name.clone(), attr.value.to_string()))
+                    .collect::<Vec<_>>(),
+            ),
+            _ => None,
+        })
+        .flatten()
+        .map(|(name, value)| (name.prefix, name.ns, name.local, value))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            (
+                Some(namespace_prefix!("xmlns")),
+                ns!(xmlns),
+                local_name!("xlink"),
+                "http://www.w3.org/1999/xlink".to_string()
+            ),
+            (
+                Some(namespace_prefix!("xlink")),
+                ns!(xlink),
+                local_name!("href"),
+                "#x".to_string()
+            ),
+            (
+                Some(namespace_prefix!("xml")),
+                ns!(xml),
+                local_name!("lang"),
+                "en".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn default_namespace() {
+    use html5ever::tree_builder::TreeBuilderOpts;
+    use html5ever::{local_name, namespace_url, ns, Namespace, QualName};
+    use markup5ever_rcdom::NodeData;
+
+    let custom = Namespace::from("urn:x-custom");
+    let opts = driver::ParseOpts {
+        tree_builder: TreeBuilderOpts {
+            default_namespace: Some("urn:x-custom"),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let dom = driver::parse_fragment(
+        RcDom::default(),
+        opts,
+        QualName::new(None, ns!(html), local_name!("body")),
+        vec![],
+    )
+    .one("<foo><p>a<div>b</div></foo><svg><rect/></svg>");
+    let names: Vec<_> = dom
+        .document
+        .traverse()
+        .filter_map(|node| match node.data {
+            NodeData::Element { ref name, .. } => Some((name.ns.clone(), name.local.to_string())),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            (custom.clone(), "html".to_string()),
+            (custom.clone(), "foo".to_string()),
+            (custom.clone(), "p".to_string()),
+            (custom.clone(), "div".to_string()),
+            (ns!(svg), "svg".to_string()),
+            (ns!(svg), "rect".to_string()),
+        ]
+    );
+
+    // The tags were still parsed as HTML: `<div>` closed the `<p>`.
+    let html = dom.document.children.borrow()[0].clone();
+    let foo = html.children.borrow()[0].clone();
+    assert_eq!(foo.children.borrow().len(), 2);
+}
+
+#[test]
+fn legacy_raw_text_elements() {
+    use markup5ever_rcdom::{Handle, NodeData};
+
+    fn body_children(input: &str) -> Vec<(String, String)> {
+        fn text(node: &Handle) -> String {
+            match node.data {
+                NodeData::Text { ref contents } => contents.borrow().to_string(),
+                _ => panic!("not text"),
+            }
+        }
+
+        let dom = driver::parse_document(RcDom::default(), Default::default()).one(input);
+        let body = body(&dom);
+        let children = body.children.borrow();
+        children
+            .iter()
+            .map(|child| match child.data {
+                NodeData::Element { ref name, .. } => {
+                    let contents = child.children.borrow().iter().map(text).collect();
+                    (name.local.to_string(), contents)
+                },
+                _ => ("#text".to_string(), text(child)),
+            })
+            .collect()
+    }
+
+    // The contents of `<xmp>` are raw text.
+    assert_eq!(
+        body_children("<xmp><b>x</b>&amp;</xmp>y"),
+        vec![
+            ("xmp".to_string(), "<b>x</b>&amp;".to_string()),
+            ("#text".to_string(), "y".to_string()),
+        ]
+    );
+    assert_eq!(
+        body_children("<xmp>\nx</xmp>"),
+        vec![("xmp".to_string(), "\nx".to_string())]
+    );
+
+    // `<listing>` drops a newline right after its start tag, like `<pre>`.
+    assert_eq!(
+        body_children("<listing>\ntext</listing>"),
+        vec![("listing".to_string(), "text".to_string())]
+    );
+    assert_eq!(
+        body_children("<listing>\n\ntext</listing>"),
+        vec![("listing".to_string(), "\ntext".to_string())]
+    );
+}
+
+#[test]
+fn strict_body_close() {
+    let parse = |strict: bool, input: &str| {
+        let opts = driver::ParseOpts::builder()
+            .strict_body_close(strict)
+            .build();
+        driver::parse_document(RcDom::default(), opts)
+            .one(input)
+            .serialize()
+    };
+
+    // By the spec, content after </body> and </html> goes where it would
+    // have gone without them.
+    let input = "<!DOCTYPE html><p>a<span>b</body>c</html>d";
+    assert_eq!(
+        parse(false, input),
+        "<!DOCTYPE html><html><head></head><body><p>a<span>bcd</span></p></body></html>"
+    );
+    assert_eq!(
+        parse(true, input),
+        "<!DOCTYPE html><html><head></head><body><p>a<span>b</span></p>cd</body></html>"
+    );
+
+    // Formatting elements are reopened for the content after them.
+    let input = "<!DOCTYPE html><p><b>a</body>b";
+    assert_eq!(
+        parse(false, input),
+        "<!DOCTYPE html><html><head></head><body><p><b>ab</b></p></body></html>"
+    );
+    assert_eq!(
+        parse(true, input),
+        "<!DOCTYPE html><html><head></head><body><p><b>a</b></p><b>b</b></body></html>"
+    );
+}
+
+#[test]
+fn strict_sink() {
+    use html5ever::conformance::StrictSink;
+
+    let parse = |input: &str| {
+        driver::parse_document(StrictSink::new(RcDom::default()), Default::default()).one(input)
+    };
+
+    let dom = parse("<!DOCTYPE html><title>x</title><p>ok</p>").unwrap();
+    assert_eq!(
+        dom.serialize(),
+        "<!DOCTYPE html><html><head><title>x</title></head><body><p>ok</p></body></html>"
+    );
+
+    match parse("<!DOCTYPE html><title>x</title>\n<p>a</b>\n<p>b</i>") {
+        Err(error) => assert_eq!(error.line, 2),
+        Ok(_) => panic!("no error"),
+    }
+    assert!(parse("<title>x</title>").is_err());
+}
+
+#[test]
+fn on_insertion_point() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let points = Rc::new(RefCell::new(vec![]));
+    let mut parser = driver::parse_document(RcDom::default(), Default::default());
+    let recorded = points.clone();
+    parser.set_on_insertion_point(Some(Box::new(move |point| {
+        let name = point.parent.local.to_string();
+        recorded.borrow_mut().push((name, point.foster_parented));
+    })));
+    let dom = parser.one("<body><table>a<tr><td>b</td></tr></table><template>c</template>");
+    assert_eq!(
+        dom.serialize(),
+        "<html><head></head><body>a<table><tbody><tr><td>b</td></tr></tbody></table>\
+         <template>c</template></body></html>"
+    );
+
+    let points = points.borrow();
+    let points: Vec<_> = points.iter().map(|(n, f)| (&**n, *f)).collect();
+    assert_eq!(
+        points,
+        vec![
+            ("html", false),
+            ("html", false),
+            ("body", false),
+            ("table", true),
+            ("table", false),
+            ("tbody", false),
+            ("tr", false),
+            ("td", false),
+            ("body", false),
+            ("template", false),
+        ]
+    );
+}
+
+#[test]
+fn mixed_content() {
+    use html5ever::mixed_content::MixedContentSink;
+
+    let parse = |base: &str, input: &str| {
+        let sink = MixedContentSink::new(RcDom::default(), base).unwrap();
+        let (_, found) = driver::parse_document(sink, Default::default()).one(input);
+        found
+            .into_iter()
+            .map(|m| format!("{} {} {}", m.element.local, m.attr.local, m.url))
+            .collect::<Vec<_>>()
+    };
+
+    let input = "<link rel=stylesheet href=http://cdn.example/a.css>\
+                 <link rel=icon href=https://cdn.example/icon.png>\
+                 <script src='HTTP://cdn.example/a.js'></script>\
+                 <img src=/logo.png><img src=//cdn.example/b.png>\
+                 <img src='https://cdn.example/c.png' src=http://ignored>\
+                 <iframe src=' http://frame.example/'></iframe>\
+                 <a href=http://example.org/>plain link</a>\
+                 <svg><image xlink:href=http://cdn.example/d.svg /></svg>\
+                 <base href=http://old.example/><img src=e.png>";
+    assert_eq!(
+        parse("https://example.com/page", input),
+        vec![
+            "link href http://cdn.example/a.css",
+            "script src HTTP://cdn.example/a.js",
+            "iframe src  http://frame.example/",
+            "image href http://cdn.example/d.svg",
+            "img src e.png",
+        ]
+    );
+
+    // Over http: nothing is mixed content.
+    assert!(parse("http://example.com/page", input).is_empty());
+    assert!(MixedContentSink::new(RcDom::default(), "/page").is_none());
+}
+
+#[test]
+fn immutable_dom() {
+    use html5ever::immutable::{self, FrozenDom, ImmutableDom, NodeId};
+    use markup5ever_rcdom::{Handle, NodeData};
+
+    fn assert_same(rc: &Handle, frozen: &FrozenDom, id: NodeId) {
+        match (&rc.data, &frozen[id].data) {
+            (NodeData::Document, immutable::NodeData::Document) => {},
+            (
+                NodeData::Doctype {
+                    name,
+                    public_id,
+                    system_id,
+                },
+                immutable::NodeData::Doctype {
+                    name: frozen_name,
+                    public_id: frozen_public_id,
+                    system_id: frozen_system_id,
+                },
+            ) => {
+                assert_eq!(&**name, frozen_name);
+                assert_eq!(&**public_id, frozen_public_id);
+                assert_eq!(&**system_id, frozen_system_id);
+            },
+            (NodeData::Text { contents }, immutable::NodeData::Text { contents: frozen }) => {
+                assert_eq!(&**contents.borrow(), frozen)
+            },
+            (NodeData::Comment { contents }, immutable::NodeData::Comment { contents: frozen }) => {
+                assert_eq!(&**contents, frozen)
+            },
+            (
+                NodeData::Element {
+                    name,
+                    attrs,
+                    template_contents,
+                    ..
+                },
+                immutable::NodeData::Element {
+                    name: frozen_name,
+                    attrs: frozen_attrs,
+                    template_contents: frozen_contents,
+                    ..
+                },
+            ) => {
+                assert_eq!(name, frozen_name);
+                let attrs: Vec<_> = attrs
+                    .borrow()
+                    .iter()
+                    .map(|attr| (attr.name.clone(), attr.value.to_string()))
+                    .collect();
+                let frozen_attrs: Vec<_> = frozen_attrs
+                    .iter()
+                    .map(|attr| (attr.name.clone(), attr.value.clone()))
+                    .collect();
+                assert_eq!(attrs, frozen_attrs);
+                match (&*template_contents.borrow(), frozen_contents) {
+                    (Some(contents), Some(frozen_contents)) => {
+                        assert_eq!(frozen[*frozen_contents].parent, None);
+                        assert_same(contents, frozen, *frozen_contents);
+                    },
+                    (None, None) => {},
+                    _ => panic!("template contents differ for {:?}", name),
+                }
+            },
+            (rc, frozen) => panic!("{:?} is not {:?}", rc, frozen),
+        }
+
+        let children = rc.children.borrow();
+        assert_eq!(children.len(), frozen[id].children.len());
+        for (child, &frozen_child) in children.iter().zip(&frozen[id].children) {
+            assert_eq!(frozen[frozen_child].parent, Some(id));
+            assert_same(child, frozen, frozen_child);
+        }
+    }
+
+    for input in &[
+        "<!DOCTYPE html PUBLIC '-//W3C//DTD HTML 4.01//EN'><title>a &amp; b</title>",
+        "<p>one <b>two <i>three</b> four</i> five",
+        "<table>a<tr>b<td>c</td></tr>d</table>e",
+        "<html lang=en><body class=x><html dir=rtl><body id=y>text",
+        "<template><p>a<template><td>b</template></template><!--c-->",
+        "<div></div><frameset><frame></frameset><!--after-->",
+        "<svg viewBox='0 0 1 1'><foreignObject><p>x</p></foreignObject></svg><math><mi>y",
+        "<pre>\n\nz</pre><textarea>\r\nw</textarea><select><option>1<option>2</select>",
+        "<a href=1>x<p>y<a href=2>z</a></p></a>",
+    ] {
+        let rc = driver::parse_document(RcDom::default(), Default::default()).one(*input);
+        let frozen =
+            driver::parse_document(ImmutableDom::default(), Default::default()).one(*input);
+        assert_same(&rc.document, &frozen, frozen.document());
+        assert_eq!(rc.errors, frozen.errors, "{}", input);
+        assert_eq!(rc.quirks_mode, frozen.quirks_mode, "{}", input);
+
+        let rc_count = rc.document.descendants().count();
+        assert_eq!(frozen.descendants(frozen.document()).count(), rc_count);
+    }
+}
+
+#[test]
+fn parse_fragment_keep_context() {
+    use html5ever::{local_name, namespace_url, ns, Attribute, QualName};
+    use markup5ever_rcdom::NodeData;
+    use std::rc::Rc;
+
+    let attrs = vec![Attribute {
+        name: QualName::new(None, ns!(), local_name!("class")),
+        value: "row".into(),
+    }];
+    let (dom, tr) = driver::parse_fragment_keep_context(
+        RcDom::default(),
+        Default::default(),
+        QualName::html("tr"),
+        attrs,
+    )
+    .one("<td>a<td>b</tr><p>");
+
+    // The handle is the context element, with the fragment in it.
+    match tr.data {
+        NodeData::Element { ref name, .. } => assert_eq!(*name, QualName::html("tr")),
+        _ => panic!("not an element"),
+    }
+    assert!(tr.parent.take().is_none());
+    for child in tr.children.borrow().iter() {
+        let parent = child.parent.take().unwrap();
+        assert!(Rc::ptr_eq(&parent.upgrade().unwrap(), &tr));
+        child.parent.set(Some(parent));
+    }
+    assert_eq!(
+        tr.serialize(),
+        "<tr class=\"row\"><td>a</td><td>b<p></p></td></tr>"
+    );
+    assert!(dom.document.children.borrow().is_empty());
+    assert_eq!(dom.errors.len(), 1);
+
+    // parse_fragment gives the same children, in an <html> element.
+    let dom = driver::parse_fragment(
+        RcDom::default(),
+        Default::default(),
+        QualName::html("tr"),
+        vec![],
+    )
+    .one("<td>a<td>b</tr><p>");
+    assert_eq!(dom.serialize(), "<html><td>a</td><td>b<p></p></td></html>");
+}
@@ -259,3 +259,37 @@ fn deep_tree() {
     serialize(&mut ret_val, &document, opts)
         .expect("Writing to a string shouldn't fail (expect on OOM)");
 }
+
+#[test]
+fn dom_serialize_round_trip() {
+    let inputs = [
+        "<!DOCTYPE html><html><head><title>a &lt; b</title></head><body></body></html>",
+        "<html><head></head><body><p class=\"x\" title=\"&quot;&amp;\">a<br>b</p></body></html>",
+        "<html><head><style>p > a { }</style></head><body><script>if (a < b) {}</script></body></html>",
+        "<html><head></head><body><template><td>a</td></template><!-- c --></body></html>",
+        "<html><head></head><body><svg><path d=\"M 0\"></path></svg><img src=\"x\"></body></html>",
+    ];
+    for input in inputs.iter() {
+        let dom = parse_document(RcDom::default(), ParseOpts::default()).one(*input);
+        assert_eq!(dom.serialize(), *input);
+        let reparsed = parse_document(RcDom::default(), ParseOpts::default()).one(dom.serialize());
+        assert_eq!(reparsed.serialize(), *input);
+    }
+}
+
+#[test]
+fn node_serialize() {
+    let dom = parse_document(RcDom::default(), ParseOpts::default())
+        .one("<p id=a>x<br>&amp;<textarea>&lt;y</textarea></p>");
+    let html = dom.document.children.borrow()[0].clone();
+    let body = html.children.borrow()[1].clone();
+    let p = body.children.borrow()[0].clone();
+    assert_eq!(
+        p.serialize(),
+        "<p id=\"a\">x<br>&amp;<textarea>&lt;y</textarea></p>"
+    );
+    let text = p.children.borrow()[0].clone();
+    assert_eq!(text.serialize(), "x");
+    assert_eq!(dom.document.serialize(), dom.serialize());
+    assert_eq!(html.serialize(), dom.serialize());
+}
@@ -210,6 +210,7 @@ fn json_to_token(js: &Value) -> Token {
             public_id: args[1].get_nullable_tendril(),
             system_id: args[2].get_nullable_tendril(),
             force_quirks: !args[3].get_bool(),
+            raw: None,
         }),
 
         "StartTag" => TagToken(Tag {
@@ -1,9 +1,12 @@
-use html5ever::driver;
+use html5ever::driver::{self, ParseOpts};
 use html5ever::tendril::stream::TendrilSink;
 use html5ever::tendril::StrTendril;
+use html5ever::tokenizer::{PauseHandle, Position};
+use html5ever::tree_builder::TreeBuilderOpts;
 use html5ever::ExpandedName;
 use html5ever::QualName;
-use markup5ever::interface::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use markup5ever::interface::{AmbiguousConstruct, ElementFlags, NodeOrText, ParseStats};
+use markup5ever::interface::{QuirksMode, TreeSink};
 use markup5ever::{local_name, namespace_url, ns, Attribute};
 use markup5ever_rcdom::{Handle, RcDom};
 use std::borrow::Cow;
@@ -11,6 +14,7 @@ use std::borrow::Cow;
 pub struct LineCountingDOM {
     pub line_vec: Vec<(QualName, u64)>,
     pub current_line: u64,
+    pub error_lines: Vec<u64>,
     pub rcdom: RcDom,
 }
 
@@ -24,6 +28,7 @@ impl TreeSink for LineCountingDOM {
     type Handle = Handle;
 
     fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.error_lines.push(self.current_line);
         self.rcdom.parse_error(msg);
     }
 
@@ -120,6 +125,7 @@ fn check_four_lines() {
     let sink = LineCountingDOM {
         line_vec: vec![],
         current_line: 1,
+        error_lines: vec![],
         rcdom: RcDom::default(),
     };
     let mut result_tok = driver::parse_document(sink, Default::default());
@@ -140,3 +146,1462 @@ fn check_four_lines() {
     // Assertion
     assert_eq!(actual.line_vec, expected);
 }
+
+#[test]
+fn position_offset() {
+    let sink = LineCountingDOM {
+        line_vec: vec![],
+        current_line: 1,
+        error_lines: vec![],
+        rcdom: RcDom::default(),
+    };
+    let opts = ParseOpts::builder()
+        .position_offset(Position {
+            line: 10,
+            column: 5,
+        })
+        .build();
+    let actual = driver::parse_document(sink, opts).one("<a>\n<b>\n</c>");
+    assert_eq!(
+        actual.line_vec,
+        vec![
+            (QualName::new(None, ns!(html), local_name!("html")), 10),
+            (QualName::new(None, ns!(html), local_name!("head")), 10),
+            (QualName::new(None, ns!(html), local_name!("body")), 10),
+            (QualName::new(None, ns!(html), local_name!("a")), 10),
+            (QualName::new(None, ns!(html), local_name!("b")), 11),
+        ]
+    );
+    // The missing doctype is reported on the first line, the stray end tag
+    // on the third.
+    assert_eq!(actual.error_lines.first(), Some(&10));
+    assert!(actual.error_lines.contains(&12));
+    assert!(actual.error_lines.iter().all(|&line| line >= 10));
+}
+
+/// Delegates to an `RcDom`, recording the optional hooks it receives.
+#[derive(Default)]
+pub struct RecordingDOM {
+    pub created: Vec<QualName>,
+    pub raw_attrs: Vec<(QualName, Vec<Attribute>)>,
+    pub merged_attrs: Vec<(QualName, Vec<Attribute>)>,
+    pub misnested: Vec<(QualName, QualName, Option<u64>)>,
+    pub texts: Vec<(String, bool)>,
+    pub misplaced_doctypes: Vec<String>,
+    pub raw_doctypes: Vec<(String, String)>,
+    pub doctypes: Vec<(String, bool)>,
+    pub reparented: usize,
+    pub quirks_behaviors: Vec<&'static str>,
+    pub frameset_ok_changes: Vec<(bool, &'static str)>,
+    pub rewritten_tags: Vec<(QualName, QualName)>,
+    pub unacknowledged: Vec<QualName>,
+    pub leading_text: Vec<String>,
+    pub newline_stripped: Vec<String>,
+    pub head_events: Vec<(&'static str, usize)>,
+    pub popped: Vec<String>,
+    pub closed: Vec<String>,
+    pub obsolete: Vec<String>,
+    pub select_dropped: Vec<String>,
+    pub stray_template_closes: usize,
+    pub metadata: Vec<(Option<String>, Option<String>)>,
+    pub finalized: Vec<(String, bool, usize)>,
+    pub fostered: Vec<(String, String, String)>,
+    pub synthesized: Vec<String>,
+    pub stats: Option<ParseStats>,
+    pub eof: Option<(bool, usize)>,
+    pub ambiguous: Vec<(AmbiguousConstruct, u64)>,
+    pub duplicate_attrs: Vec<(String, String, u64)>,
+    pub stop_after: Option<QualName>,
+    pub stopped: bool,
+    pub pause: Option<PauseHandle>,
+    pub pause_after_script: Option<PauseHandle>,
+    pub rcdom: RcDom,
+}
+
+impl TreeSink for RecordingDOM {
+    type Output = Self;
+
+    fn finish(self) -> Self {
+        self
+    }
+
+    type Handle = Handle;
+
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.rcdom.parse_error(msg);
+    }
+
+    fn get_document(&mut self) -> Handle {
+        self.rcdom.get_document()
+    }
+
+    fn get_template_contents(&mut self, target: &Handle) -> Handle {
+        self.rcdom.get_template_contents(target)
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.rcdom.set_quirks_mode(mode)
+    }
+
+    fn quirks_behavior_applied(&mut self, description: &'static str) {
+        self.quirks_behaviors.push(description);
+    }
+
+    fn frameset_ok_changed(&mut self, ok: bool, reason: &'static str) {
+        self.frameset_ok_changes.push((ok, reason));
+    }
+
+    fn set_document_metadata(&mut self, lang: Option<StrTendril>, dir: Option<StrTendril>) {
+        self.metadata
+            .push((lang.map(|s| s.to_string()), dir.map(|s| s.to_string())));
+    }
+
+    fn same_node(&self, x: &Handle, y: &Handle) -> bool {
+        self.rcdom.same_node(x, y)
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a Handle) -> ExpandedName<'a> {
+        self.rcdom.elem_name(target)
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Handle {
+        self.created.push(name.clone());
+        if self.stop_after.as_ref() == Some(&name) {
+            self.stopped = true;
+        }
+        if let Some(ref pause) = self.pause {
+            pause.pause();
+        }
+        self.rcdom.create_element(name, attrs, flags)
+    }
+
+    fn create_element_raw_attrs(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Handle {
+        self.raw_attrs.push((name.clone(), attrs.clone()));
+        self.create_element(name, attrs, flags)
+    }
+
+    fn should_continue(&self) -> bool {
+        !self.stopped
+    }
+
+    fn head_opened(&mut self, _head: &Handle) {
+        self.head_events.push(("opened", self.created.len()));
+    }
+
+    fn head_closed(&mut self, _head: &Handle) {
+        self.head_events.push(("closed", self.created.len()));
+    }
+
+    fn pop(&mut self, node: &Handle) {
+        self.popped
+            .push(self.rcdom.elem_name(node).local.to_string());
+        if let Some(ref pause) = self.pause_after_script {
+            if *self.rcdom.elem_name(node).local == local_name!("script") {
+                pause.pause();
+            }
+        }
+    }
+
+    fn element_closed(&mut self, node: &Handle) {
+        self.closed
+            .push(self.rcdom.elem_name(node).local.to_string());
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> Handle {
+        self.rcdom.create_comment(text)
+    }
+
+    fn create_pi(&mut self, target: StrTendril, content: StrTendril) -> Handle {
+        self.rcdom.create_pi(target, content)
+    }
+
+    fn append(&mut self, parent: &Handle, child: NodeOrText<Handle>) {
+        self.rcdom.append(parent, child)
+    }
+
+    fn append_before_sibling(&mut self, sibling: &Handle, child: NodeOrText<Handle>) {
+        self.rcdom.append_before_sibling(sibling, child)
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &Handle,
+        prev_element: &Handle,
+        child: NodeOrText<Handle>,
+    ) {
+        let child_desc = match child {
+            NodeOrText::AppendText(ref text) => text.to_string(),
+            NodeOrText::AppendNode(ref node) => format!("<{}>", self.elem_name(node).local),
+        };
+        self.fostered.push((
+            self.elem_name(element).local.to_string(),
+            self.elem_name(prev_element).local.to_string(),
+            child_desc,
+        ));
+        self.rcdom
+            .append_based_on_parent_node(element, prev_element, child)
+    }
+
+    fn append_doctype_to_document(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.rcdom
+            .append_doctype_to_document(name, public_id, system_id);
+    }
+
+    fn append_doctype_full(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+        force_quirks: bool,
+    ) {
+        self.doctypes.push((name.to_string(), force_quirks));
+        self.append_doctype_to_document(name, public_id, system_id);
+    }
+
+    fn append_doctype_raw(
+        &mut self,
+        raw: StrTendril,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.raw_doctypes.push((raw.to_string(), name.to_string()));
+        self.append_doctype_to_document(name, public_id, system_id);
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &Handle, attrs: Vec<Attribute>) {
+        self.rcdom.add_attrs_if_missing(target, attrs);
+    }
+
+    fn merge_attributes(
+        &mut self,
+        target: &Handle,
+        source_tag_name: &QualName,
+        merged: &[Attribute],
+    ) {
+        self.merged_attrs
+            .push((source_tag_name.clone(), merged.to_vec()));
+        self.rcdom.add_attrs_if_missing(target, merged.to_vec());
+    }
+
+    fn misnested_tags(&mut self, expected: &QualName, found: &QualName, expected_pos: Option<u64>) {
+        self.misnested
+            .push((expected.clone(), found.clone(), expected_pos));
+    }
+
+    fn append_text_with_flags(
+        &mut self,
+        parent: &Handle,
+        text: StrTendril,
+        preserve_whitespace: bool,
+    ) {
+        self.texts.push((text.to_string(), preserve_whitespace));
+        self.rcdom.append(parent, NodeOrText::AppendText(text));
+    }
+
+    fn foster_parent_text_with_flags(
+        &mut self,
+        element: &Handle,
+        prev_element: &Handle,
+        text: StrTendril,
+        preserve_whitespace: bool,
+    ) {
+        self.texts.push((text.to_string(), preserve_whitespace));
+        self.append_based_on_parent_node(element, prev_element, NodeOrText::AppendText(text));
+    }
+
+    fn obsolete_element(&mut self, name: &QualName) {
+        self.obsolete.push(name.local.to_string());
+    }
+
+    fn select_content_dropped(&mut self, name: &QualName) {
+        self.select_dropped.push(name.local.to_string());
+    }
+
+    fn stray_template_close(&mut self) {
+        self.stray_template_closes += 1;
+    }
+
+    fn tag_rewritten(&mut self, from: &QualName, to: &QualName) {
+        self.rewritten_tags.push((from.clone(), to.clone()));
+    }
+
+    fn unacknowledged_self_closing(&mut self, name: &QualName) {
+        self.unacknowledged.push(name.clone());
+    }
+
+    fn leading_text_relocated(&mut self, text: StrTendril) {
+        self.leading_text.push(text.to_string());
+    }
+
+    fn leading_newline_stripped(&mut self, element: &Handle) {
+        let name = self.elem_name(element).local.to_string();
+        self.newline_stripped.push(name);
+    }
+
+    fn misplaced_doctype(
+        &mut self,
+        name: StrTendril,
+        _public_id: StrTendril,
+        _system_id: StrTendril,
+    ) {
+        self.misplaced_doctypes.push(name.to_string());
+    }
+
+    fn remove_from_parent(&mut self, target: &Handle) {
+        self.rcdom.remove_from_parent(target);
+    }
+
+    fn reparent_children(&mut self, node: &Handle, new_parent: &Handle) {
+        self.reparented += 1;
+        self.rcdom.reparent_children(node, new_parent);
+    }
+
+    fn element_finalized(&mut self, element: &Handle) {
+        let name = self.elem_name(element).local.to_string();
+        let inserted = element.parent.take().map_or(false, |parent| {
+            element.parent.set(Some(parent));
+            true
+        });
+        let children = element.children.borrow().len();
+        self.finalized.push((name, inserted, children));
+    }
+
+    fn element_synthesized(&mut self, element: &Handle, name: &QualName) {
+        assert_eq!(self.elem_name(element), name.expanded());
+        self.synthesized.push(name.local.to_string());
+    }
+
+    fn parse_stats(&mut self, stats: ParseStats) {
+        assert!(self.stats.is_none());
+        self.stats = Some(stats);
+    }
+
+    fn eof_state(&mut self, clean: bool, open_elements: usize) {
+        assert!(self.eof.is_none());
+        self.eof = Some((clean, open_elements));
+    }
+
+    fn ambiguous_construct(&mut self, construct: AmbiguousConstruct, line_number: u64) {
+        self.ambiguous.push((construct, line_number));
+    }
+
+    fn duplicate_attribute(&mut self, first: &str, duplicate: &str, line_number: u64) {
+        self.duplicate_attrs
+            .push((first.to_string(), duplicate.to_string(), line_number));
+    }
+}
+
+fn parse_recording(input: &str, opts: ParseOpts) -> RecordingDOM {
+    driver::parse_document(RecordingDOM::default(), opts).one(input)
+}
+
+#[test]
+fn preserve_all_attributes() {
+    let opts = ParseOpts::builder().preserve_all_attributes(true).build();
+    let dom = parse_recording("<a x=1 x=2>", opts);
+    let (_, attrs) = dom
+        .raw_attrs
+        .iter()
+        .find(|(name, _)| name.local == local_name!("a"))
+        .unwrap();
+    let values: Vec<&str> = attrs.iter().map(|a| &*a.value).collect();
+    assert_eq!(values, vec!["1", "2"]);
+
+    // Without the option, elements are created through `create_element`,
+    // with the duplicates already dropped.
+    let dom = parse_recording("<a x=1 x=2>", ParseOpts::default());
+    assert!(dom.raw_attrs.is_empty());
+    assert_eq!(dom.created.len(), 4);
+}
+
+#[test]
+fn merge_body_attributes() {
+    let dom = parse_recording(
+        "<body class=a><p>x<body id=b class=c>",
+        ParseOpts::default(),
+    );
+    assert_eq!(dom.merged_attrs.len(), 1);
+    let (name, attrs) = &dom.merged_attrs[0];
+    assert_eq!(name.local, local_name!("body"));
+    let merged: Vec<(&str, &str)> = attrs.iter().map(|a| (&*a.name.local, &*a.value)).collect();
+    assert_eq!(merged, vec![("id", "b"), ("class", "c")]);
+}
+
+#[test]
+fn misnested_tags() {
+    let dom = parse_recording("<b>\n<i></b></i>\n<u>\n<div></u>", ParseOpts::default());
+    let names: Vec<(&str, &str, Option<u64>)> = dom
+        .misnested
+        .iter()
+        .map(|(expected, found, pos)| (&*expected.local, &*found.local, *pos))
+        .collect();
+    assert_eq!(names, vec![("i", "b", Some(2)), ("div", "u", None)]);
+}
+
+#[test]
+fn text_whitespace_preservation() {
+    let dom = parse_recording(
+        "<p>a</p><pre> b <i>c</i></pre><div>d</div><pre><table>e<tr></table></pre>f",
+        ParseOpts::default(),
+    );
+    let texts: Vec<(&str, bool)> = dom.texts.iter().map(|(t, p)| (&**t, *p)).collect();
+    assert_eq!(
+        texts,
+        vec![
+            ("a", false),
+            (" b ", true),
+            ("c", true),
+            ("d", false),
+            // Foster parented out of the table, into the <pre>.
+            ("e", true),
+            ("f", false)
+        ]
+    );
+}
+
+#[test]
+fn coalesced_text() {
+    // A run of text split into several tokens, by newlines or character
+    // references, is appended at once.
+    let dom = parse_recording(
+        "<p>a\nb &amp; c\nd<b>e\nf</b>\n<table>g\nh<tr>",
+        ParseOpts::default(),
+    );
+    let texts: Vec<(&str, bool)> = dom.texts.iter().map(|(t, p)| (&**t, *p)).collect();
+    assert_eq!(
+        texts,
+        vec![
+            ("a\nb & c\nd", false),
+            ("e\nf", false),
+            ("\n", false),
+            ("g\nh", false)
+        ]
+    );
+    assert_eq!(
+        dom.rcdom.serialize(),
+        "<html><head></head><body><p>a\nb &amp; c\nd<b>e\nf</b>\ng\nh\
+         <table><tbody><tr></tr></tbody></table></p></body></html>"
+    );
+
+    // The text given to the parser so far is appended before it returns.
+    let mut parser = driver::parse_document(RecordingDOM::default(), ParseOpts::default());
+    parser.process(StrTendril::from("<p>a\nb"));
+    assert_eq!(parser.tokenizer.sink.sink.texts.len(), 1);
+    parser.process(StrTendril::from("\nc</p>"));
+    let dom = parser.finish();
+    assert_eq!(dom.texts.len(), 2);
+    assert_eq!(
+        dom.rcdom.serialize(),
+        "<html><head></head><body><p>a\nb\nc</p></body></html>"
+    );
+
+    // The text is processed before the tokenizer checks whether a CDATA
+    // section is allowed.  Here it reopens an HTML `<b>`, so it is not.
+    for &(root, leaf) in &[("svg", "desc"), ("math", "mi")] {
+        let input = format!("<{0}><{1}><a><b></a>x<![CDATA[y]]></{1}></{0}>", root, leaf);
+        let dom = parse_recording(&input, ParseOpts::default());
+        assert_eq!(
+            dom.rcdom.serialize(),
+            format!(
+                "<html><head></head><body><{0}><{1}><a><b></b></a><b>x<!--[CDATA[y]]--></b></{1}></{0}>\
+                 </body></html>",
+                root, leaf
+            )
+        );
+    }
+}
+
+#[test]
+fn preserve_misplaced_doctype() {
+    let input = "<body><!DOCTYPE html>";
+    let dom = parse_recording(input, ParseOpts::default());
+    assert!(dom.misplaced_doctypes.is_empty());
+
+    let opts = ParseOpts {
+        tree_builder: TreeBuilderOpts {
+            preserve_misplaced_doctype: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let dom = parse_recording(input, opts);
+    assert_eq!(dom.misplaced_doctypes, vec!["html"]);
+}
+
+#[test]
+fn reparent_children_in_adoption_agency() {
+    use html5ever::serialize::{serialize, SerializeOpts};
+    use markup5ever_rcdom::SerializableHandle;
+
+    let dom = parse_recording("<b>1<p>2<i>3</i>4</b>5</p>", ParseOpts::default());
+    assert_eq!(dom.reparented, 1);
+
+    let mut serialized = Vec::new();
+    let document: SerializableHandle = dom.rcdom.document.clone().into();
+    serialize(&mut serialized, &document, SerializeOpts::default()).unwrap();
+    assert_eq!(
+        String::from_utf8(serialized).unwrap(),
+        "<html><head></head><body><b>1</b><p><b>2<i>3</i>4</b>5</p></body></html>"
+    );
+}
+
+#[test]
+fn quirks_behavior_applied() {
+    let dom = parse_recording("<p><table>", ParseOpts::default());
+    assert_eq!(
+        dom.quirks_behaviors,
+        vec!["<table> does not close an open <p>"]
+    );
+
+    let dom = parse_recording("<!DOCTYPE html><p><table>", ParseOpts::default());
+    assert!(dom.quirks_behaviors.is_empty());
+}
+
+#[test]
+fn frameset_ok_changed() {
+    use markup5ever_rcdom::NodeData;
+
+    fn parse(input: &str) -> (Vec<(bool, &'static str)>, Vec<String>) {
+        let dom = parse_recording(input, ParseOpts::default());
+        let html = dom.rcdom.document.children.borrow()[0].clone();
+        let children = html
+            .children()
+            .map(|child| match child.data {
+                NodeData::Element { ref name, .. } => name.local.to_string(),
+                _ => panic!("unexpected node"),
+            })
+            .collect();
+        (dom.frameset_ok_changes, children)
+    }
+
+    assert_eq!(
+        parse("<p>text<frameset>"),
+        (
+            vec![(false, "non-whitespace text")],
+            vec!["head".to_string(), "body".to_string()]
+        )
+    );
+    // Only the first change is reported.
+    assert_eq!(parse("<pre>a<img>").0, vec![(false, "<pre> or <listing>")]);
+    // Whitespace and hidden inputs leave the flag set, so the frameset
+    // replaces the body.
+    assert_eq!(
+        parse("<p> <input type=hidden><frameset>"),
+        (vec![], vec!["head".to_string(), "frameset".to_string()])
+    );
+}
+
+#[test]
+fn rewrite_image_tag() {
+    use markup5ever_rcdom::NodeData;
+
+    fn body_child_name(dom: &RecordingDOM) -> String {
+        let html = dom.rcdom.document.children.borrow()[0].clone();
+        let body = html.children.borrow()[1].clone();
+        let child = body.children.borrow()[0].clone();
+        match child.data {
+            NodeData::Element { ref name, .. } => name.local.to_string(),
+            _ => panic!("not an element"),
+        }
+    }
+
+    let input = "<image src=x>";
+    let dom = parse_recording(input, ParseOpts::default());
+    assert_eq!(body_child_name(&dom), "img");
+    let rewritten: Vec<(&str, &str)> = dom
+        .rewritten_tags
+        .iter()
+        .map(|(from, to)| (&*from.local, &*to.local))
+        .collect();
+    assert_eq!(rewritten, vec![("image", "img")]);
+
+    let opts = ParseOpts {
+        tree_builder: TreeBuilderOpts {
+            rewrite_image_tag: false,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let dom = parse_recording(input, opts);
+    assert_eq!(body_child_name(&dom), "image");
+    assert!(dom.rewritten_tags.is_empty());
+}
+
+#[test]
+fn unacknowledged_self_closing() {
+    let unacknowledged = |input| parse_recording(input, ParseOpts::default()).unacknowledged;
+
+    assert_eq!(
+        unacknowledged("<div/>"),
+        vec![QualName::new(None, ns!(html), local_name!("div"))]
+    );
+    assert!(unacknowledged("<br/>").is_empty());
+    assert!(unacknowledged("<svg/>").is_empty());
+    assert!(unacknowledged("<math><mi/></math>").is_empty());
+    assert_eq!(
+        unacknowledged("<svg><foreignObject><p/></foreignObject></svg><span/>"),
+        vec![
+            QualName::new(None, ns!(html), local_name!("p")),
+            QualName::new(None, ns!(html), local_name!("span")),
+        ]
+    );
+}
+
+#[test]
+fn leading_text_relocated() {
+    let dom = parse_recording("text<html><body>x</body></html>", ParseOpts::default());
+    assert_eq!(dom.leading_text, vec!["text"]);
+    let html = dom.rcdom.document.children.borrow()[0].clone();
+    assert_eq!(
+        html.serialize(),
+        "<html><head></head><body>textx</body></html>"
+    );
+
+    let leading_text = |input| parse_recording(input, ParseOpts::default()).leading_text;
+    assert_eq!(leading_text("<!DOCTYPE html> \n a b<p>"), vec!["a b"]);
+    assert!(leading_text(" <!-- c --> <html>text").is_empty());
+    assert!(leading_text("<p>text").is_empty());
+}
+
+#[test]
+fn leading_newline_stripped() {
+    let stripped = |input| parse_recording(input, ParseOpts::default()).newline_stripped;
+    assert_eq!(stripped("<pre>\nx</pre>"), vec!["pre"]);
+    assert!(stripped("<pre>text</pre>").is_empty());
+    assert!(stripped("<pre></pre>\n<p>\n").is_empty());
+    assert_eq!(
+        stripped("<textarea>\n\n</textarea><listing>\nx</listing>"),
+        vec!["textarea", "listing"]
+    );
+
+    // A "\r\n" split between chunks is reported once.
+    let opts = ParseOpts::builder().normalize_newlines(false).build();
+    let mut parser = driver::parse_document(RecordingDOM::default(), opts.clone());
+    parser.process(StrTendril::from("<pre>\r"));
+    parser.process(StrTendril::from("\nx</pre>"));
+    let dom = parser.finish();
+    assert_eq!(dom.newline_stripped, vec!["pre"]);
+    assert_eq!(
+        dom.rcdom.serialize(),
+        "<html><head></head><body><pre>x</pre></body></html>"
+    );
+
+    // The second "\r" of a "\r\r" is a newline of its own, however the
+    // input is split.
+    for split in 5..7 {
+        let input = "<pre>\r\rx</pre>";
+        let mut parser = driver::parse_document(RecordingDOM::default(), opts.clone());
+        parser.process(StrTendril::from(&input[..split]));
+        parser.process(StrTendril::from(&input[split..]));
+        assert_eq!(
+            parser.finish().rcdom.serialize(),
+            "<html><head></head><body><pre>\rx</pre></body></html>"
+        );
+    }
+}
+
+#[test]
+fn flag_obsolete_elements() {
+    let input = "<center><font>a</font><b>b</b></center><marquee>";
+    let dom = parse_recording(input, ParseOpts::default());
+    assert!(dom.obsolete.is_empty());
+
+    let opts = ParseOpts {
+        tree_builder: TreeBuilderOpts {
+            flag_obsolete_elements: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let dom = parse_recording(input, opts.clone());
+    assert_eq!(dom.obsolete, vec!["center", "font", "marquee"]);
+
+    // SVG elements are not reported, even when they share a name with an
+    // obsolete HTML element, and nor are reopened formatting elements.
+    let input = "<image><svg><image/><font/></svg><svg><font color=red></font></svg>\
+                 <p><font>a<p>b";
+    let dom = parse_recording(input, opts);
+    assert_eq!(dom.obsolete, vec!["image", "font", "font"]);
+}
+
+#[test]
+fn select_content_dropped() {
+    use html5ever::serialize::{serialize, SerializeOpts};
+    use markup5ever_rcdom::SerializableHandle;
+
+    fn parse(input: &str) -> (Vec<String>, String) {
+        let dom = parse_recording(input, ParseOpts::default());
+        let body = dom.rcdom.document.children.borrow()[0].children.borrow()[1].clone();
+        let mut serialized = Vec::new();
+        let body: SerializableHandle = body.into();
+        serialize(&mut serialized, &body, SerializeOpts::default()).unwrap();
+        (dom.select_dropped, String::from_utf8(serialized).unwrap())
+    }
+
+    assert_eq!(
+        parse("<select><div>x</div><option>y</option></select>"),
+        (
+            vec!["div".to_string()],
+            "<select>x<option>y</option></select>".to_string()
+        )
+    );
+    // A nested `<select>` closes the open one.
+    assert_eq!(
+        parse("<select><b><select><i>z"),
+        (
+            vec!["b".to_string(), "select".to_string()],
+            "<select></select><i>z</i>".to_string()
+        )
+    );
+    // `<input>` closes the select and is inserted after it.
+    assert_eq!(
+        parse("<table><tr><td><select><option>a<input>"),
+        (
+            vec![],
+            "<table><tbody><tr><td><select><option>a</option></select><input>\
+             </td></tr></tbody></table>"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn stop_parsing() {
+    use markup5ever_rcdom::NodeData;
+
+    let sink = RecordingDOM {
+        stop_after: Some(QualName::new(None, ns!(html), local_name!("head"))),
+        ..Default::default()
+    };
+    let mut parser = driver::parse_document(sink, ParseOpts::default());
+    parser.process(StrTendril::from("<html><head><title>x</title></head>"));
+    parser.process(StrTendril::from("<body><p>y"));
+    let dom = parser.finish();
+    assert!(dom.stopped);
+
+    let html = dom.rcdom.document.children.borrow()[0].clone();
+    let children: Vec<String> = html
+        .children
+        .borrow()
+        .iter()
+        .map(|child| match child.data {
+            NodeData::Element { ref name, .. } => name.local.to_string(),
+            _ => panic!("not an element"),
+        })
+        .collect();
+    assert_eq!(children, vec!["head"]);
+    assert!(html.children.borrow()[0].children.borrow().is_empty());
+}
+
+#[test]
+fn pause_parsing() {
+    let handle = PauseHandle::default();
+    let sink = RecordingDOM {
+        pause: Some(handle.clone()),
+        ..Default::default()
+    };
+    let mut parser = driver::parse_document(sink, ParseOpts::default()).pausable(handle);
+    parser.process(StrTendril::from("<p>a<b>b</b><i>c"));
+
+    // Pauses fall between tokens, so the implied html, head and body
+    // elements arrive together with the first <p>.
+    let mut counts = vec![];
+    while parser.is_paused() {
+        counts.push(parser.sink().created.len());
+        parser.resume();
+    }
+    assert_eq!(counts, vec![4, 5, 6]);
+
+    let dom = parser.finish();
+    assert_eq!(dom.created.len(), 6);
+}
+
+#[test]
+fn head_opened_and_closed() {
+    // With the number of elements created at the time: html, head, title.
+    let dom = parse_recording("<title>x</title><p>y", ParseOpts::default());
+    assert_eq!(dom.head_events, vec![("opened", 2), ("closed", 3)]);
+
+    let dom = parse_recording(
+        "<!DOCTYPE html><html><head><meta charset=utf-8></head>\
+         <link rel=icon href=x><body>",
+        ParseOpts::default(),
+    );
+    assert_eq!(dom.head_events, vec![("opened", 2), ("closed", 3)]);
+
+    let dom = parse_recording("<html>", ParseOpts::default());
+    assert_eq!(dom.head_events, vec![("opened", 2), ("closed", 2)]);
+}
+
+#[test]
+fn doctype_force_quirks() {
+    let doctypes = |input| parse_recording(input, ParseOpts::default()).doctypes;
+    assert_eq!(
+        doctypes("<!DOCTYPE html><p>x"),
+        vec![("html".into(), false)]
+    );
+    assert_eq!(doctypes("<!DOCTYPE><p>x"), vec![("".into(), true)]);
+    assert_eq!(
+        doctypes("<!DOCTYPE html PUBLIC>"),
+        vec![("html".into(), true)]
+    );
+    assert_eq!(
+        doctypes("<!DOCTYPE html SYSTEM 'about:legacy-compat'>"),
+        vec![("html".into(), false)]
+    );
+    // Only a malformed doctype sets the flag; a quirky one does not.
+    assert_eq!(
+        doctypes("<!DOCTYPE html PUBLIC '-//W3C//DTD HTML 3.2 Final//EN'>"),
+        vec![("html".into(), false)]
+    );
+    assert!(doctypes("<p>x").is_empty());
+}
+
+#[test]
+fn stray_template_close() {
+    let dom = parse_recording(
+        "<!DOCTYPE html><template><p>a</template></template><p>b</p>",
+        ParseOpts::default(),
+    );
+    assert_eq!(dom.stray_template_closes, 1);
+    assert_eq!(
+        dom.rcdom.serialize(),
+        "<!DOCTYPE html><html><head><template><p>a</p></template></head>\
+         <body><p>b</p></body></html>"
+    );
+
+    // Before <head>, and inside a table, where end tags are handled by other
+    // insertion modes.
+    let dom = parse_recording(
+        "</template><table></template><tr><td>x</td></tr></table>",
+        ParseOpts::default(),
+    );
+    assert_eq!(dom.stray_template_closes, 2);
+    assert_eq!(
+        dom.rcdom.serialize(),
+        "<html><head></head><body><table><tbody><tr><td>x</td></tr></tbody></table>\
+         </body></html>"
+    );
+
+    let dom = parse_recording(
+        "<template><template></template></template>",
+        ParseOpts::default(),
+    );
+    assert_eq!(dom.stray_template_closes, 0);
+}
+
+#[test]
+fn document_write() {
+    let handle = PauseHandle::default();
+    let sink = RecordingDOM {
+        pause_after_script: Some(handle.clone()),
+        ..Default::default()
+    };
+    let mut parser = driver::parse_document(sink, ParseOpts::default()).pausable(handle);
+    parser.process(StrTendril::from(
+        "<!DOCTYPE html><p>a<script>document.write('<b>')</script>c</p>d",
+    ));
+
+    // The written <b> goes before the input that follows the script, so it
+    // wraps the rest of the paragraph.  Later writes follow earlier ones.
+    assert!(parser.is_paused());
+    parser.document_write(StrTendril::from("<b>"));
+    parser.document_write(StrTendril::from("b"));
+    parser.resume();
+    let dom = parser.finish();
+    assert_eq!(
+        dom.rcdom.serialize(),
+        "<!DOCTYPE html><html><head></head><body>\
+         <p>a<script>document.write('<b>')</script><b>bc</b></p><b>d</b></body></html>"
+    );
+}
+
+#[test]
+fn set_document_metadata() {
+    fn some(s: &str) -> Option<String> {
+        Some(s.to_string())
+    }
+
+    let dom = parse_recording("<html lang=en dir=ltr><p>", ParseOpts::default());
+    assert_eq!(dom.metadata, vec![(some("en"), some("ltr"))]);
+
+    let dom = parse_recording("<p>", ParseOpts::default());
+    assert_eq!(dom.metadata, vec![(None, None)]);
+
+    let dom = parse_recording(
+        "<html lang=en><body><html lang=fr dir=rtl>",
+        ParseOpts::default(),
+    );
+    assert_eq!(
+        dom.metadata,
+        vec![(some("en"), None), (some("en"), some("rtl"))]
+    );
+}
+
+#[test]
+fn element_finalized() {
+    fn finalized(input: &str) -> Vec<(String, bool, usize)> {
+        parse_recording(input, ParseOpts::default()).finalized
+    }
+
+    fn event(name: &str, inserted: bool) -> (String, bool, usize) {
+        (name.to_string(), inserted, 0)
+    }
+
+    assert_eq!(
+        finalized("<p id=a><b>x</b><script>y</script>"),
+        vec![
+            event("html", true),
+            event("head", true),
+            event("body", true),
+            event("p", true),
+            event("b", true),
+            event("script", true),
+        ]
+    );
+
+    // The adoption agency creates a second <b> and reports it before
+    // moving the text into it and inserting it.
+    assert_eq!(
+        finalized("<b>1<p>2</b>3"),
+        vec![
+            event("html", true),
+            event("head", true),
+            event("body", true),
+            event("b", true),
+            event("p", true),
+            event("b", false),
+        ]
+    );
+}
+
+#[test]
+fn eof_state() {
+    let eof = |input| parse_recording(input, ParseOpts::default()).eof;
+
+    assert_eq!(
+        eof("<!DOCTYPE html><html><head><title>t</title></head>\
+             <body><div>x</div></body></html>"),
+        Some((true, 0))
+    );
+    // These elements may be left open.
+    assert_eq!(eof("<p>a<p>b"), Some((true, 0)));
+    assert_eq!(eof("<ul><li>a<li>b"), Some((false, 1)));
+    assert_eq!(eof("<table><tr><td>x"), Some((false, 1)));
+    assert_eq!(eof("<div><span>x"), Some((false, 2)));
+    assert_eq!(eof("<title>x"), Some((false, 2)));
+
+    // The input ends inside a token.
+    assert_eq!(eof("<p>x</p><a href=\"y"), Some((false, 0)));
+    assert_eq!(eof("<p>x<!-- y"), Some((false, 0)));
+    assert_eq!(eof("<p>x</"), Some((false, 0)));
+}
+
+#[test]
+fn parse_stats() {
+    let input = "<!DOCTYPE html><table>a<tr></table><p><b><i>x</b>";
+    let opts = ParseOpts::builder().collect_stats(true).build();
+    assert_eq!(
+        parse_recording(input, opts).stats,
+        Some(ParseStats {
+            tokens: 11,
+            elements_created: 9,
+            parse_errors: 2,
+            adoption_agency_runs: 1,
+            foster_parented: 1,
+            max_depth: 5,
+        })
+    );
+
+    assert_eq!(parse_recording(input, ParseOpts::default()).stats, None);
+}
+
+#[test]
+fn preserve_raw_doctype() {
+    let opts = ParseOpts::builder().preserve_raw_doctype(true).build();
+    for (input, raw) in &[
+        ("<!DOCTYPE html><p>x", "<!DOCTYPE html>"),
+        ("<!doctype HTML><p>x", "<!doctype HTML>"),
+        ("<!DocType Html>", "<!DocType Html>"),
+    ] {
+        let dom = parse_recording(input, opts.clone());
+        assert_eq!(
+            dom.raw_doctypes,
+            vec![(raw.to_string(), "html".to_string())]
+        );
+    }
+
+    // Without the option, the sink only sees the parsed doctype.
+    let dom = parse_recording("<!DOCTYPE html>", ParseOpts::default());
+    assert!(dom.raw_doctypes.is_empty());
+}
+
+#[test]
+fn element_synthesized() {
+    fn synthesized(input: &str) -> Vec<String> {
+        parse_recording(input, ParseOpts::default()).synthesized
+    }
+
+    assert_eq!(synthesized("<p>x"), vec!["html", "head", "body"]);
+    assert_eq!(
+        synthesized("<html><title>x</title><p>"),
+        vec!["head", "body"]
+    );
+    assert!(synthesized("<!DOCTYPE html><html><head></head><body><p>x</body></html>").is_empty());
+}
+
+#[test]
+fn wrap_orphaned_table_content() {
+    fn parse(input: &str, wrap: bool) -> (Vec<String>, String) {
+        let opts = ParseOpts {
+            tree_builder: TreeBuilderOpts {
+                wrap_orphaned_table_content: wrap,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let dom = parse_recording(input, opts);
+        (dom.synthesized, dom.rcdom.serialize())
+    }
+
+    assert_eq!(
+        parse("<td>x<td>y", true),
+        (
+            vec!["html", "head", "body", "table", "tbody", "tr"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            "<html><head></head><body><table><tbody><tr><td>x</td><td>y</td></tr>\
+             </tbody></table></body></html>"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        parse("<td>x<td>y", false).1,
+        "<html><head></head><body>xy</body></html>"
+    );
+
+    // Only the missing wrappers are created.
+    assert_eq!(
+        parse("<!DOCTYPE html><p>a<tr><th>b", true).1,
+        "<!DOCTYPE html><html><head></head><body><p>a</p><table><tbody><tr><th>b</th>\
+         </tr></tbody></table></body></html>"
+    );
+    assert_eq!(
+        parse("<thead><tr><td>c", true).1,
+        "<html><head></head><body><table><thead><tr><td>c</td></tr></thead></table>\
+         </body></html>"
+    );
+}
+
+#[test]
+fn foster_parenting() {
+    use html5ever::serialize::{serialize, SerializeOpts};
+    use markup5ever_rcdom::SerializableHandle;
+
+    fn fostered(input: &str) -> (Vec<(String, String, String)>, String) {
+        let dom = parse_recording(input, ParseOpts::default());
+        let mut serialized = Vec::new();
+        let document: SerializableHandle = dom.rcdom.document.clone().into();
+        serialize(&mut serialized, &document, SerializeOpts::default()).unwrap();
+        (dom.fostered, String::from_utf8(serialized).unwrap())
+    }
+
+    fn call(element: &str, prev_element: &str, child: &str) -> (String, String, String) {
+        (
+            element.to_string(),
+            prev_element.to_string(),
+            child.to_string(),
+        )
+    }
+
+    // Stray text is moved in front of the table and merged.
+    assert_eq!(
+        fostered("<div><table>a<tr>b</table></div>"),
+        (
+            vec![call("table", "div", "a"), call("table", "div", "b")],
+            "<html><head></head><body><div>ab<table><tbody><tr></tr></tbody></table></div>\
+             </body></html>"
+                .to_string()
+        )
+    );
+
+    // Whitespace stays in the table.  The stray element is fostered, and the
+    // text inside it is then appended to it normally.
+    assert_eq!(
+        fostered("<table> <b>x</b></table>"),
+        (
+            vec![call("table", "body", "<b>")],
+            "<html><head></head><body><b>x</b><table> </table></body></html>".to_string()
+        )
+    );
+}
+
+#[test]
+fn append_based_on_parent_node_without_parent() {
+    use markup5ever_rcdom::NodeData;
+
+    fn text_children(node: &Handle) -> Vec<String> {
+        node.children
+            .borrow()
+            .iter()
+            .map(|child| match child.data {
+                NodeData::Text { ref contents } => contents.borrow().to_string(),
+                NodeData::Element { ref name, .. } => format!("<{}>", name.local),
+                _ => panic!("unexpected node"),
+            })
+            .collect()
+    }
+
+    let mut dom = RcDom::default();
+    let name = |local| QualName::new(None, ns!(html), local);
+    let body = dom.create_element(name(local_name!("body")), vec![], Default::default());
+    let table = dom.create_element(name(local_name!("table")), vec![], Default::default());
+
+    // A table that was removed from the document: append to `prev_element`.
+    let text = NodeOrText::AppendText(StrTendril::from("a"));
+    dom.append_based_on_parent_node(&table, &body, text);
+    assert_eq!(text_children(&body), vec!["a"]);
+
+    // A table in the document: insert before it.
+    dom.append(&body, NodeOrText::AppendNode(table.clone()));
+    let text = NodeOrText::AppendText(StrTendril::from("b"));
+    dom.append_based_on_parent_node(&table, &body, text);
+    assert_eq!(text_children(&body), vec!["ab", "<table>"]);
+}
+
+#[test]
+fn flag_ambiguous_constructs() {
+    use AmbiguousConstruct::*;
+
+    fn flagged(input: &str) -> Vec<AmbiguousConstruct> {
+        let opts = ParseOpts::builder().flag_ambiguous_constructs(true).build();
+        let dom = parse_recording(input, opts);
+        dom.ambiguous.into_iter().map(|(c, _)| c).collect()
+    }
+
+    assert_eq!(
+        flagged("<a href=\"x\"/onclick=alert(1)>"),
+        vec![SolidusInTag]
+    );
+    assert_eq!(
+        flagged("<a href=\"x\"onclick=alert(1)>"),
+        vec![MissingWhitespaceBetweenAttributes]
+    );
+    assert_eq!(
+        flagged("<img alt=\"</noscript><img src=x onerror=alert(1)>\">"),
+        vec![LessThanSignInAttributeValue]
+    );
+    assert_eq!(
+        flagged("<img alt=&lt;script&gt;>"),
+        vec![LessThanSignInAttributeValue]
+    );
+    assert_eq!(
+        flagged("<!--><img src=x onerror=alert(1)>-->"),
+        vec![AbruptClosingOfEmptyComment]
+    );
+    assert_eq!(
+        flagged("<!-- --!><img src=x onerror=alert(1)>-->"),
+        vec![IncorrectlyClosedComment]
+    );
+    assert_eq!(flagged("<!-- <!-- -->"), vec![NestedComment]);
+    assert_eq!(
+        flagged("<a title=x`y href=a=b>"),
+        vec![
+            UnexpectedCharacterInUnquotedAttributeValue,
+            UnexpectedCharacterInUnquotedAttributeValue
+        ]
+    );
+    assert_eq!(flagged("<a =x>"), vec![EqualsSignBeforeAttributeName]);
+    assert_eq!(flagged("<a href=a href=b>"), vec![DuplicateAttribute]);
+    assert_eq!(flagged("<a></a href=x>"), vec![EndTagWithAttributes]);
+    assert_eq!(flagged("<img src=x onerror=alert(1)//"), vec![EofInTag]);
+
+    // `/>` only matters on non-void HTML elements.
+    assert_eq!(
+        flagged("<div/><br/><svg/><svg><path/></svg><math><mi/></math>"),
+        vec![SelfClosingNonVoidElement]
+    );
+
+    // The line is reported with each construct.
+    let opts = ParseOpts::builder().flag_ambiguous_constructs(true).build();
+    let dom = parse_recording("<p>\n\n<a href=\"x\"/>", opts);
+    assert_eq!(dom.ambiguous, vec![(SelfClosingNonVoidElement, 3)]);
+
+    // Nothing is reported by default.
+    let dom = parse_recording("<div/><a href=\"x\"/onclick=y>", ParseOpts::default());
+    assert!(dom.ambiguous.is_empty());
+}
+
+#[test]
+fn duplicate_attribute_case() {
+    let duplicates = |input| {
+        let opts = ParseOpts::builder().flag_ambiguous_constructs(true).build();
+        parse_recording(input, opts).duplicate_attrs
+    };
+
+    // A duplicate that differs only in case is told apart by the names as
+    // written.
+    assert_eq!(
+        duplicates("<a HREF=1 href=2>"),
+        vec![("HREF".into(), "href".into(), 1)]
+    );
+    assert_eq!(
+        duplicates("<a href=1 href=2>"),
+        vec![("href".into(), "href".into(), 1)]
+    );
+    assert_eq!(
+        duplicates("<p>\n<a hRef=1 title=t HREF=2 Href=3>"),
+        vec![
+            ("hRef".into(), "HREF".into(), 2),
+            ("hRef".into(), "Href".into(), 2),
+        ]
+    );
+    // In SVG the names are reported as written, not as the tree builder
+    // adjusts them.
+    assert_eq!(
+        duplicates("<svg viewBox='0 0 1 1' VIEWBOX=x>"),
+        vec![("viewBox".into(), "VIEWBOX".into(), 1)]
+    );
+
+    // The first is kept, under its lowercased name.
+    let dom = parse_recording("<a HREF=1 href=2>", ParseOpts::default());
+    assert!(dom.duplicate_attrs.is_empty());
+    assert_eq!(
+        dom.rcdom.serialize(),
+        "<html><head></head><body><a href=\"1\"></a></body></html>"
+    );
+}
+
+#[test]
+fn flag_script_data_constructs() {
+    use markup5ever_rcdom::NodeData;
+    use AmbiguousConstruct::*;
+
+    fn flagged(input: &str) -> (Vec<AmbiguousConstruct>, String) {
+        let opts = ParseOpts::builder().flag_ambiguous_constructs(true).build();
+        let dom = parse_recording(input, opts);
+        let script = dom
+            .rcdom
+            .document
+            .traverse()
+            .find(|node| match node.data {
+                NodeData::Element { ref name, .. } => name.local == local_name!("script"),
+                _ => false,
+            })
+            .unwrap();
+        let text = script
+            .children()
+            .map(|child| match child.data {
+                NodeData::Text { ref contents } => contents.borrow().to_string(),
+                _ => panic!("script with a child element"),
+            })
+            .collect();
+        let constructs = dom.ambiguous.into_iter().map(|(c, _)| c).collect();
+        (constructs, text)
+    }
+
+    // The first `</script>` only ends the nested script.
+    assert_eq!(
+        flagged("<script><!--<script>alert(1)</script>--></script><p>"),
+        (
+            vec![ScriptHtmlCommentLikeText, NestedScriptStart],
+            "<!--<script>alert(1)</script>-->".to_string()
+        )
+    );
+    assert_eq!(
+        flagged("<script><!--<script></script><img src=x onerror=alert(1)>"),
+        (
+            vec![ScriptHtmlCommentLikeText, NestedScriptStart],
+            "<!--<script></script><img src=x onerror=alert(1)>".to_string()
+        )
+    );
+    assert_eq!(
+        flagged("<script><!-- x --></script>"),
+        (vec![ScriptHtmlCommentLikeText], "<!-- x -->".to_string())
+    );
+    assert_eq!(
+        flagged("<script><!--<scripts></script>"),
+        (vec![ScriptHtmlCommentLikeText], "<!--<scripts>".to_string())
+    );
+
+    // Without `<!--`, `<script>` is plain text.
+    assert_eq!(
+        flagged("<script>a<script>b</script>"),
+        (vec![], "a<script>b".to_string())
+    );
+}
+
+/// Delegates to an `RcDom`, receiving text through `push_text`.
+#[derive(Default)]
+pub struct PushTextDOM {
+    pub pushed: Vec<String>,
+    pub rcdom: RcDom,
+}
+
+impl TreeSink for PushTextDOM {
+    type Output = Self;
+
+    fn finish(self) -> Self {
+        self
+    }
+
+    type Handle = Handle;
+
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.rcdom.parse_error(msg);
+    }
+
+    fn get_document(&mut self) -> Handle {
+        self.rcdom.get_document()
+    }
+
+    fn get_template_contents(&mut self, target: &Handle) -> Handle {
+        self.rcdom.get_template_contents(target)
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.rcdom.set_quirks_mode(mode)
+    }
+
+    fn same_node(&self, x: &Handle, y: &Handle) -> bool {
+        self.rcdom.same_node(x, y)
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a Handle) -> ExpandedName<'a> {
+        self.rcdom.elem_name(target)
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Handle {
+        self.rcdom.create_element(name, attrs, flags)
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> Handle {
+        self.rcdom.create_comment(text)
+    }
+
+    fn create_pi(&mut self, target: StrTendril, content: StrTendril) -> Handle {
+        self.rcdom.create_pi(target, content)
+    }
+
+    fn append(&mut self, parent: &Handle, child: NodeOrText<Handle>) {
+        if let NodeOrText::AppendText(_) = child {
+            panic!("text appended without push_text");
+        }
+        self.rcdom.append(parent, child)
+    }
+
+    fn wants_borrowed_text(&self) -> bool {
+        true
+    }
+
+    fn push_text(&mut self, parent: &Handle, text: &str) {
+        self.pushed.push(text.to_string());
+        self.rcdom
+            .append(parent, NodeOrText::AppendText(StrTendril::from(text)))
+    }
+
+    fn append_before_sibling(&mut self, sibling: &Handle, child: NodeOrText<Handle>) {
+        self.rcdom.append_before_sibling(sibling, child)
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &Handle,
+        prev_element: &Handle,
+        child: NodeOrText<Handle>,
+    ) {
+        self.rcdom
+            .append_based_on_parent_node(element, prev_element, child)
+    }
+
+    fn append_doctype_to_document(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.rcdom
+            .append_doctype_to_document(name, public_id, system_id);
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &Handle, attrs: Vec<Attribute>) {
+        self.rcdom.add_attrs_if_missing(target, attrs);
+    }
+
+    fn remove_from_parent(&mut self, target: &Handle) {
+        self.rcdom.remove_from_parent(target);
+    }
+
+    fn reparent_children(&mut self, node: &Handle, new_parent: &Handle) {
+        self.rcdom.reparent_children(node, new_parent);
+    }
+}
+
+#[test]
+fn push_text() {
+    use html5ever::serialize::{serialize, SerializeOpts};
+    use markup5ever_rcdom::SerializableHandle;
+
+    fn serialize_dom(dom: &RcDom) -> String {
+        let mut serialized = Vec::new();
+        let document: SerializableHandle = dom.document.clone().into();
+        serialize(&mut serialized, &document, SerializeOpts::default()).unwrap();
+        String::from_utf8(serialized).unwrap()
+    }
+
+    let inputs = [
+        "<title>a &amp; b</title><p>one <b>two</b>\nthree",
+        "<pre>\n\nx</pre><textarea>\ny</textarea><script>if (a < b) {}</script>",
+        "<svg><text>\0c</text></svg><template>d<p>e</template>",
+    ];
+    for input in &inputs {
+        let expected = driver::parse_document(RcDom::default(), Default::default()).one(*input);
+        let dom = driver::parse_document(PushTextDOM::default(), Default::default()).one(*input);
+        assert_eq!(serialize_dom(&dom.rcdom), serialize_dom(&expected));
+        assert!(!dom.pushed.is_empty());
+    }
+
+    // Foster parented text still arrives as a tendril.
+    let dom = driver::parse_document(PushTextDOM::default(), Default::default())
+        .one("<table>a<tr><td>b</td></tr></table>");
+    assert_eq!(dom.pushed, vec!["b"]);
+    assert_eq!(
+        serialize_dom(&dom.rcdom),
+        "<html><head></head><body>a<table><tbody><tr><td>b</td></tr></tbody></table>\
+         </body></html>"
+    );
+}
+
+#[test]
+fn element_closed() {
+    let dom = parse_recording("<p>a<b>b</p>c<ul><li>d<li>e</ul>f", ParseOpts::default());
+    // Closing several elements at once, as `</p>` and `</ul>` do here,
+    // doesn't call `pop`.
+    assert_eq!(dom.popped, vec!["head", "li", "b", "body", "html"]);
+    assert_eq!(
+        dom.closed,
+        vec!["head", "b", "p", "li", "li", "ul", "b", "body", "html"]
+    );
+}
@@ -0,0 +1,133 @@
+use html5ever::driver::parse_document;
+use html5ever::tendril::TendrilSink;
+use markup5ever::interface::TreeSink;
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+
+fn parse(input: &str) -> RcDom {
+    parse_document(RcDom::default(), Default::default()).one(input)
+}
+
+fn describe(node: &Handle) -> String {
+    match node.data {
+        NodeData::Document => "#document".to_string(),
+        NodeData::Doctype { ref name, .. } => format!("!{}", name),
+        NodeData::Text { ref contents } => format!("\"{}\"", contents.borrow()),
+        NodeData::Comment { ref contents } => format!("<!--{}-->", contents),
+        NodeData::Element { ref name, .. } => name.local.to_string(),
+        NodeData::ProcessingInstruction { ref target, .. } => format!("?{}", target),
+    }
+}
+
+fn describe_all<I: Iterator<Item = Handle>>(nodes: I) -> Vec<String> {
+    nodes.map(|node| describe(&node)).collect()
+}
+
+fn find(dom: &RcDom, name: &str) -> Handle {
+    dom.document
+        .traverse()
+        .find(|node| describe(node) == name)
+        .unwrap()
+}
+
+const INPUT: &str = "<!DOCTYPE html><title>t</title><p>a<b>b</b>c</p><!--d--><ul><li>e</ul>";
+
+#[test]
+fn traverse() {
+    let dom = parse(INPUT);
+    assert_eq!(
+        describe_all(dom.document.traverse()),
+        [
+            "#document",
+            "!html",
+            "html",
+            "head",
+            "title",
+            "\"t\"",
+            "body",
+            "p",
+            "\"a\"",
+            "b",
+            "\"b\"",
+            "\"c\"",
+            "<!--d-->",
+            "ul",
+            "li",
+            "\"e\"",
+        ]
+    );
+    assert_eq!(
+        describe_all(find(&dom, "p").traverse()),
+        ["p", "\"a\"", "b", "\"b\"", "\"c\""]
+    );
+}
+
+#[test]
+fn descendants() {
+    let dom = parse(INPUT);
+    assert_eq!(
+        describe_all(find(&dom, "p").descendants()),
+        ["\"a\"", "b", "\"b\"", "\"c\""]
+    );
+    assert_eq!(
+        describe_all(find(&dom, "\"a\"").descendants()),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn children_and_siblings() {
+    let dom = parse(INPUT);
+    let body = find(&dom, "body");
+    assert_eq!(describe_all(body.children()), ["p", "<!--d-->", "ul"]);
+    assert_eq!(
+        describe_all(find(&dom, "\"a\"").following_siblings()),
+        ["b", "\"c\""]
+    );
+    assert_eq!(
+        describe_all(find(&dom, "ul").following_siblings()),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        describe_all(dom.document.following_siblings()),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn mutation_while_traversing() {
+    let mut dom = parse(INPUT);
+
+    // Removing the children of the node just returned skips them.
+    let mut seen = vec![];
+    for node in find(&dom, "body").descendants() {
+        if describe(&node) == "p" {
+            let children: Vec<_> = node.children().collect();
+            for child in children {
+                dom.remove_from_parent(&child);
+            }
+        }
+        seen.push(describe(&node));
+    }
+    assert_eq!(seen, ["p", "<!--d-->", "ul", "li", "\"e\""]);
+
+    // Removing siblings before the current node doesn't lose our place.
+    let body = find(&dom, "body");
+    let mut seen = vec![];
+    for node in body.children() {
+        if describe(&node) == "<!--d-->" {
+            dom.remove_from_parent(&find(&dom, "p"));
+        }
+        seen.push(describe(&node));
+    }
+    assert_eq!(seen, ["p", "<!--d-->", "ul"]);
+    assert_eq!(describe_all(body.children()), ["<!--d-->", "ul"]);
+
+    // Removing the current node ends the iteration.
+    let mut seen = vec![];
+    for node in body.children() {
+        dom.remove_from_parent(&node);
+        seen.push(describe(&node));
+    }
+    assert_eq!(seen, ["<!--d-->"]);
+    assert_eq!(describe_all(body.children()), ["ul"]);
+}
@@ -429,6 +429,7 @@ where
     fn end(&mut self) {
         for node in self.open_elems.drain(..).rev() {
             self.sink.pop(&node);
+            self.sink.element_closed(&node);
         }
     }
 
@@ -466,6 +467,7 @@ where
         let child = create_element(&mut self.sink, tag.name, tag.attrs);
         self.insert_appropriately(AppendNode(child.clone()));
         self.sink.pop(&child);
+        self.sink.element_closed(&child);
         Done
     }
 
@@ -587,6 +589,7 @@ where
         self.namespace_stack.pop();
         let node = self.open_elems.pop().expect("no current element");
         self.sink.pop(&node);
+        self.sink.element_closed(&node);
         node
     }
 
@@ -654,6 +657,7 @@ where
                     self.phase = EndPhase;
                     let handle = self.append_tag_to_doc(tag);
                     self.sink.pop(&handle);
+                    self.sink.element_closed(&handle);
                     Done
                 },
                 CommentToken(comment) => self.append_comment_to_doc(comment),